@@ -0,0 +1,70 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use q1tsim::circuit::Circuit;
+use q1tsim::gates::{Gate, CCX, CCZ, CU1, CX, H, QFT, RZ, T, Unitary};
+use q1tsim::qustate::QuState;
+
+/// Apply `gate` on `bits` to a `nr_bits` state with `nr_branches` separate
+/// branches, created by applying Hadamard gates conditionally.
+fn apply_gate<G: Gate>(gate: &G, bits: &[usize], nr_bits: usize, nr_branches: usize)
+    -> QuState
+{
+    let mut state = QuState::new(nr_bits, nr_branches).unwrap();
+    let control: Vec<bool> = (0..nr_branches).map(|i| i % 2 == 1).collect();
+    state.apply_conditional_gate(&control, &q1tsim::gates::H::new(), &[nr_bits-1]).unwrap();
+    for _ in 0..10
+    {
+        state.apply_gate(gate, bits).unwrap();
+    }
+    state
+}
+
+/// Create a circuit on `nr_bits` qubits, consisting of `nr_layers` layers of
+/// single-qubit rotations followed by a chain of entangling CX gates, with
+/// gate fusion limited to gates on at most `max_fusion_bits` qubits.
+fn layered_circuit(nr_bits: usize, nr_layers: usize, max_fusion_bits: Option<usize>)
+    -> Circuit
+{
+    let mut circuit = Circuit::new(nr_bits, 0).unwrap();
+    circuit.set_gate_fusion(max_fusion_bits);
+    for layer in 0..nr_layers
+    {
+        for bit in 0..nr_bits
+        {
+            circuit.add_gate(H::new(), &[bit]).unwrap();
+            circuit.add_gate(RZ::new(0.1 * (layer + bit) as f64), &[bit]).unwrap();
+        }
+        for bit in 0..nr_bits-1
+        {
+            circuit.add_gate(CX::new(), &[bit, bit+1]).unwrap();
+            circuit.add_gate(T::new(), &[bit+1]).unwrap();
+        }
+    }
+    circuit
+}
+
+fn criterion_benchmark(c: &mut Criterion)
+{
+    let o = q1tsim::cmatrix::COMPLEX_ONE;
+    let mut mat = q1tsim::cmatrix::CMatrix::zeros((8, 8));
+    for i in 0..8
+    {
+        mat[[(i + 3) % 8, i]] = if i % 2 == 0 { o } else { -o };
+    }
+    let unitary = Unitary::new("U", mat).unwrap();
+
+    c.bench_function("cx 16", |b| b.iter(|| apply_gate(&CX::new(), &[3, 11], 16, 1)));
+    c.bench_function("cx 12, 16 branches", |b| b.iter(|| apply_gate(&CX::new(), &[9, 2], 12, 16)));
+    c.bench_function("ccx 16", |b| b.iter(|| apply_gate(&CCX::new(), &[14, 0, 7], 16, 1)));
+    c.bench_function("cu1 16", |b| b.iter(|| apply_gate(&CU1::new(0.3), &[4, 10], 16, 1)));
+    c.bench_function("ccz 16", |b| b.iter(|| apply_gate(&CCZ::new(), &[14, 0, 7], 16, 1)));
+    c.bench_function("unitary 16", move |b| b.iter(|| apply_gate(&unitary, &[5, 1, 12], 16, 1)));
+    c.bench_function("qft 16", |b| b.iter(|| apply_gate(&QFT::new(4, true), &[2, 9, 6, 13], 16, 1)));
+
+    let mut unfused = layered_circuit(16, 4, None);
+    c.bench_function("layers 16, no fusion", move |b| b.iter(|| unfused.execute(1).unwrap()));
+    let mut fused = layered_circuit(16, 4, Some(3));
+    c.bench_function("layers 16, fusion", move |b| b.iter(|| fused.execute(1).unwrap()));
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
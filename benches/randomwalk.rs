@@ -1,42 +1,20 @@
 use criterion::{criterion_group, criterion_main, Criterion};
-use q1tsim::{declare_controlled, declare_controlled_cost, declare_controlled_type,
-    declare_controlled_impl, declare_controlled_impl_gate, declare_controlled_latex,
-    declare_controlled_qasm};
 use q1tsim::circuit::Circuit;
-use q1tsim::gates::{CCX};
+use q1tsim::gates::{MultiControlled, X};
 
 use rand_core::SeedableRng;
 
-declare_controlled!(C3X, CCX);
-declare_controlled!(C4X, C3X);
-declare_controlled!(C5X, C4X);
-declare_controlled!(C6X, C5X);
-declare_controlled!(C7X, C6X);
-declare_controlled!(C8X, C7X);
-declare_controlled!(C9X, C8X);
-
 fn add_cnx(circuit: &mut Circuit, i: usize, nr_pos_bits: usize)
     -> q1tsim::error::Result<()>
 {
     let n = nr_pos_bits+1-i;
     let bits: Vec<usize> = (0..=n).collect();
-    match n
-    {
-        9 => circuit.add_gate(C9X::new(), &bits),
-        8 => circuit.add_gate(C8X::new(), &bits),
-        7 => circuit.add_gate(C7X::new(), &bits),
-        6 => circuit.add_gate(C6X::new(), &bits),
-        5 => circuit.add_gate(C5X::new(), &bits),
-        4 => circuit.add_gate(C4X::new(), &bits),
-        3 => circuit.add_gate(C3X::new(), &bits),
-        2 => circuit.add_gate(CCX::new(), &bits),
-        _ => panic!("Unable to add C{}X gate", n)
-    }
+    circuit.add_gate(MultiControlled::new(Box::new(X::new()), n), &bits)
 }
 
 fn build_randomwalk_circuit(nr_pos_bits: usize, measure: bool) -> q1tsim::error::Result<Circuit>
 {
-    let mut circuit = Circuit::new(nr_pos_bits+1, nr_pos_bits);
+    let mut circuit = Circuit::new(nr_pos_bits+1, nr_pos_bits)?;
 
     for _ in 0..((1 << nr_pos_bits) - 1)
     {
@@ -68,7 +46,7 @@ fn quantum_random_walk(nr_pos_bits: usize, nr_shots: usize, measure: bool)
 
     match build_randomwalk_circuit(nr_pos_bits, measure)
     {
-        Ok(mut circuit) => { circuit.execute_with_rng(nr_shots, &mut rng); }
+        Ok(mut circuit) => { circuit.execute_with_rng(nr_shots, &mut rng).unwrap(); }
         Err(err) => { panic!("Failed to build circuit: {}", err); }
     }
 }
@@ -0,0 +1,563 @@
+// Copyright 2019 Q1t BV
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Grover search.
+//!
+//! In this module, an item `x` in a search space of `n` qubits corresponds to
+//! the basis state in which qubit `i` has the value of bit `i` of `x`. This
+//! is the same as the value of the classical register, when qubit `i` is
+//! measured into classical bit `i`.
+
+/// Boolean expression in the qubit values
+#[derive(Debug)]
+enum BoolExpr
+{
+    Const(bool),
+    Var(usize),
+    Not(Box<BoolExpr>),
+    And(Box<BoolExpr>, Box<BoolExpr>),
+    Or(Box<BoolExpr>, Box<BoolExpr>),
+    Xor(Box<BoolExpr>, Box<BoolExpr>)
+}
+
+impl BoolExpr
+{
+    /// Parse a boolean expression.
+    ///
+    /// Parse the boolean expression in `text`. Variables are written as `x`
+    /// followed by the qubit index, constants as `0` or `1`. Supported
+    /// operators are, in order of decreasing precedence, negation (`!` or
+    /// `~`), conjunction (`&`), exclusive or (`^`), and disjunction (`|`).
+    fn parse(text: &str) -> crate::error::ParseResult<Self>
+    {
+        let tokens: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+        let mut pos = 0;
+        let expr = Self::parse_or(text, &tokens, &mut pos)?;
+        if pos < tokens.len()
+        {
+            let rest: String = tokens[pos..].iter().collect();
+            Err(crate::error::ParseError::TrailingText(rest))
+        }
+        else
+        {
+            Ok(expr)
+        }
+    }
+
+    fn parse_or(text: &str, tokens: &[char], pos: &mut usize)
+        -> crate::error::ParseResult<Self>
+    {
+        let mut expr = Self::parse_xor(text, tokens, pos)?;
+        while tokens.get(*pos) == Some(&'|')
+        {
+            *pos += 1;
+            let rhs = Self::parse_xor(text, tokens, pos)?;
+            expr = BoolExpr::Or(Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    fn parse_xor(text: &str, tokens: &[char], pos: &mut usize)
+        -> crate::error::ParseResult<Self>
+    {
+        let mut expr = Self::parse_and(text, tokens, pos)?;
+        while tokens.get(*pos) == Some(&'^')
+        {
+            *pos += 1;
+            let rhs = Self::parse_and(text, tokens, pos)?;
+            expr = BoolExpr::Xor(Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(text: &str, tokens: &[char], pos: &mut usize)
+        -> crate::error::ParseResult<Self>
+    {
+        let mut expr = Self::parse_unary(text, tokens, pos)?;
+        while tokens.get(*pos) == Some(&'&')
+        {
+            *pos += 1;
+            let rhs = Self::parse_unary(text, tokens, pos)?;
+            expr = BoolExpr::And(Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(text: &str, tokens: &[char], pos: &mut usize)
+        -> crate::error::ParseResult<Self>
+    {
+        match tokens.get(*pos)
+        {
+            Some('!') | Some('~') => {
+                *pos += 1;
+                let expr = Self::parse_unary(text, tokens, pos)?;
+                Ok(BoolExpr::Not(Box::new(expr)))
+            },
+            Some('(') => {
+                *pos += 1;
+                let expr = Self::parse_or(text, tokens, pos)?;
+                if tokens.get(*pos) != Some(&')')
+                {
+                    return Err(crate::error::ParseError::UnclosedParentheses(String::from(text)));
+                }
+                *pos += 1;
+                Ok(expr)
+            },
+            Some('0') => {
+                *pos += 1;
+                Ok(BoolExpr::Const(false))
+            },
+            Some('1') => {
+                *pos += 1;
+                Ok(BoolExpr::Const(true))
+            },
+            Some('x') => {
+                let start = *pos + 1;
+                let mut end = start;
+                while end < tokens.len() && tokens[end].is_ascii_digit()
+                {
+                    end += 1;
+                }
+                let index: String = tokens[start..end].iter().collect();
+                let bit = index.parse::<usize>().map_err(|_| {
+                    crate::error::ParseError::InvalidBit(String::from(text))
+                })?;
+                *pos = end;
+                Ok(BoolExpr::Var(bit))
+            },
+            _ => {
+                Err(crate::error::ParseError::InvalidBooleanExpression(String::from(text)))
+            }
+        }
+    }
+
+    /// The largest variable index in this expression, if any.
+    fn max_bit(&self) -> Option<usize>
+    {
+        match *self
+        {
+            BoolExpr::Const(_) => None,
+            BoolExpr::Var(bit) => Some(bit),
+            BoolExpr::Not(ref expr) => expr.max_bit(),
+            BoolExpr::And(ref lhs, ref rhs)
+            | BoolExpr::Or(ref lhs, ref rhs)
+            | BoolExpr::Xor(ref lhs, ref rhs) => lhs.max_bit().max(rhs.max_bit())
+        }
+    }
+
+    /// Evaluate this expression for item `x`.
+    fn evaluate(&self, x: u64) -> bool
+    {
+        match *self
+        {
+            BoolExpr::Const(value) => value,
+            BoolExpr::Var(bit) => (x >> bit) & 1 == 1,
+            BoolExpr::Not(ref expr) => !expr.evaluate(x),
+            BoolExpr::And(ref lhs, ref rhs) => lhs.evaluate(x) && rhs.evaluate(x),
+            BoolExpr::Or(ref lhs, ref rhs) => lhs.evaluate(x) || rhs.evaluate(x),
+            BoolExpr::Xor(ref lhs, ref rhs) => lhs.evaluate(x) != rhs.evaluate(x)
+        }
+    }
+}
+
+/// Add a sign flip.
+///
+/// Add gates to `composite`, operating on its first `nr_bits` bits, that flip
+/// the sign of the basis state for item `x`, leaving all other basis states
+/// unchanged.
+fn add_sign_flip(composite: &mut crate::gates::Composite, nr_bits: usize, x: u64)
+{
+    let target = nr_bits - 1;
+    let target_set = (x >> target) & 1 == 1;
+    if !target_set
+    {
+        composite.add_gate(crate::gates::X::new(), &[target]);
+    }
+
+    if nr_bits == 1
+    {
+        composite.add_gate(crate::gates::Z::new(), &[target]);
+    }
+    else
+    {
+        let negated: Vec<bool> = (0..target).map(|bit| (x >> bit) & 1 == 0).collect();
+        let gate = crate::gates::MultiControlled::new_negated(
+            Box::new(crate::gates::Z::new()), &negated);
+        composite.add_gate(gate, &(0..nr_bits).collect::<Vec<usize>>());
+    }
+
+    if !target_set
+    {
+        composite.add_gate(crate::gates::X::new(), &[target]);
+    }
+}
+
+/// Create a phase oracle.
+///
+/// Create a gate on `nr_bits` qubits, that flips the sign of the basis
+/// states for all items `x` for which `predicate(x)` is `true`.
+pub fn phase_oracle<F>(nr_bits: usize, predicate: F) -> crate::gates::Composite
+where F: Fn(u64) -> bool
+{
+    let mut oracle = crate::gates::Composite::new("O", nr_bits);
+    for x in (0..1 << nr_bits).filter(|&x| predicate(x))
+    {
+        add_sign_flip(&mut oracle, nr_bits, x);
+    }
+    oracle
+}
+
+/// Create a diffusion operator.
+///
+/// Create the Grover diffusion operator on `nr_bits` qubits, reflecting
+/// the state about the uniform superposition `|s⟩` of all basis states.
+/// Up to a global phase, the operator is `2|s⟩⟨s| - I`.
+pub fn diffusion(nr_bits: usize) -> crate::gates::Composite
+{
+    let mut composite = crate::gates::Composite::new("D", nr_bits);
+    for bit in 0..nr_bits
+    {
+        composite.add_gate(crate::gates::H::new(), &[bit]);
+    }
+    add_sign_flip(&mut composite, nr_bits, 0);
+    for bit in 0..nr_bits
+    {
+        composite.add_gate(crate::gates::H::new(), &[bit]);
+    }
+    composite
+}
+
+/// Optimal number of Grover iterations.
+///
+/// Compute the number of Grover iterations that maximizes the probability
+/// of finding a marked item, when `nr_marked` out of `2`<sup>`nr_bits`</sup>
+/// items are marked.
+pub fn optimal_nr_iterations(nr_bits: usize, nr_marked: usize) -> usize
+{
+    let nr_items = (1u64 << nr_bits) as f64;
+    if nr_marked == 0 || nr_marked as f64 >= nr_items
+    {
+        0
+    }
+    else
+    {
+        let theta = (nr_marked as f64 / nr_items).sqrt().asin();
+        (::std::f64::consts::PI / (4.0 * theta) - 0.5).round() as usize
+    }
+}
+
+/// Result of a Grover search
+#[derive(Clone, Debug)]
+pub struct GroverResult
+{
+    /// The number of Grover iterations performed
+    pub nr_iterations: usize,
+    /// The marked items that were found, together with the number of times
+    /// each was measured, most frequently measured item first
+    pub items: Vec<(u64, usize)>
+}
+
+/// Grover search
+///
+/// Struct `Grover` searches for the marked items in a space of
+/// `2`<sup>`n`</sup> items, by repeatedly applying a phase oracle, flipping
+/// the sign of the marked items, followed by the diffusion operator. The
+/// iterations are packaged in a single static loop.
+pub struct Grover
+{
+    /// The number of qubits in the search space
+    nr_bits: usize,
+    /// The marked items
+    marked: Vec<u64>
+}
+
+impl Grover
+{
+    /// Create a new Grover search.
+    ///
+    /// Create a new search over `nr_bits` qubits, for the items `x` for
+    /// which `predicate(x)` is `true`.
+    pub fn new<F>(nr_bits: usize, predicate: F) -> Self
+    where F: Fn(u64) -> bool
+    {
+        let marked = (0..1 << nr_bits).filter(|&x| predicate(x)).collect();
+        Grover { nr_bits: nr_bits, marked: marked }
+    }
+
+    /// Create a new Grover search from a truth table.
+    ///
+    /// Create a new search for the items `x` for which `table[x]` is `true`.
+    /// The length of `table` must be a power of two, the number of qubits in
+    /// the search space is its base-2 logarithm. Returns an
+    /// `InvalidTruthTable` error if the length is not a power of two.
+    pub fn from_truth_table(table: &[bool]) -> crate::error::Result<Self>
+    {
+        if !table.len().is_power_of_two()
+        {
+            Err(crate::error::Error::InvalidTruthTable(table.len()))
+        }
+        else
+        {
+            let nr_bits = table.len().trailing_zeros() as usize;
+            Ok(Self::new(nr_bits, |x| table[x as usize]))
+        }
+    }
+
+    /// Create a new Grover search from a boolean expression.
+    ///
+    /// Create a new search over `nr_bits` qubits, for the items for which
+    /// boolean expression `expr` is true. In the expression, the value of
+    /// qubit `i` is written as `x`<i>`i`</i>, and the constants as `0` and `1`.
+    /// The operators are, in order of decreasing precedence, negation (`!` or
+    /// `~`), conjunction (`&`), exclusive or (`^`), and disjunction (`|`),
+    /// and parentheses can be used for grouping. For example, the expression
+    /// `"x0 & !(x1 | x2)"` marks the single item 1. Returns a `ParseError`
+    /// if the expression is invalid, or an `InvalidQBit` error if it refers
+    /// to a qubit outside of the search space.
+    pub fn from_expression(nr_bits: usize, expr: &str) -> crate::error::Result<Self>
+    {
+        let expr = BoolExpr::parse(expr)?;
+        match expr.max_bit()
+        {
+            Some(bit) if bit >= nr_bits => Err(crate::error::Error::InvalidQBit(bit)),
+            _                           => Ok(Self::new(nr_bits, |x| expr.evaluate(x)))
+        }
+    }
+
+    /// The number of qubits in the search space.
+    pub fn nr_bits(&self) -> usize
+    {
+        self.nr_bits
+    }
+
+    /// The marked items, in increasing order.
+    pub fn marked(&self) -> &[u64]
+    {
+        &self.marked
+    }
+
+    /// The phase oracle, flipping the sign of the marked items.
+    pub fn oracle(&self) -> crate::gates::Composite
+    {
+        phase_oracle(self.nr_bits, |x| self.marked.binary_search(&x).is_ok())
+    }
+
+    /// A single Grover iteration, i.e. the oracle followed by the diffusion
+    /// operator.
+    pub fn iteration(&self) -> crate::gates::Composite
+    {
+        let bits: Vec<usize> = (0..self.nr_bits).collect();
+        let mut composite = crate::gates::Composite::new("G", self.nr_bits);
+        composite.add_gate(self.oracle(), &bits);
+        composite.add_gate(diffusion(self.nr_bits), &bits);
+        composite
+    }
+
+    /// The number of iterations that maximizes the probability of finding
+    /// a marked item.
+    pub fn optimal_nr_iterations(&self) -> usize
+    {
+        optimal_nr_iterations(self.nr_bits, self.marked.len())
+    }
+
+    /// Build the search circuit.
+    ///
+    /// Create a circuit that prepares the uniform superposition of all
+    /// items, applies `nr_iterations` Grover iterations, and measures qubit
+    /// `i` into classical bit `i`.
+    pub fn circuit(&self, nr_iterations: usize) -> crate::error::Result<crate::circuit::Circuit>
+    {
+        let bits: Vec<usize> = (0..self.nr_bits).collect();
+        let mut circuit = crate::circuit::Circuit::new(self.nr_bits, self.nr_bits)?;
+        for &bit in bits.iter()
+        {
+            circuit.h(bit)?;
+        }
+        if nr_iterations > 0
+        {
+            circuit.add_gate(crate::gates::Loop::new("grover", nr_iterations, self.iteration()),
+                &bits)?;
+        }
+        circuit.measure_all(&bits)?;
+        Ok(circuit)
+    }
+
+    /// Search for marked items.
+    ///
+    /// Run the search circuit with the optimal number of iterations
+    /// `nr_shots` times, and return the marked items that were found.
+    pub fn search(&self, nr_shots: usize) -> crate::error::Result<GroverResult>
+    {
+        self.search_with_rng(nr_shots, &mut rand::thread_rng())
+    }
+
+    /// Search for marked items.
+    ///
+    /// Run the search circuit with the optimal number of iterations
+    /// `nr_shots` times, and return the marked items that were found. Use
+    /// random number generator `rng` for sampling the measurements.
+    pub fn search_with_rng<R: rand::RngCore>(&self, nr_shots: usize, rng: &mut R)
+        -> crate::error::Result<GroverResult>
+    {
+        let nr_iterations = self.optimal_nr_iterations();
+        let mut circuit = self.circuit(nr_iterations)?;
+        circuit.execute_with_rng(nr_shots, rng)?;
+
+        let mut items: Vec<(u64, usize)> = circuit.histogram()?.into_iter()
+            .filter(|(x, _)| self.marked.binary_search(x).is_ok())
+            .collect();
+        items.sort_by(|&(x0, c0), &(x1, c1)| c1.cmp(&c0).then(x0.cmp(&x1)));
+
+        Ok(GroverResult
+        {
+            nr_iterations: nr_iterations,
+            items: items
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{diffusion, optimal_nr_iterations, phase_oracle, Grover};
+    use crate::gates::Gate;
+
+    /// Index in a gate matrix of the basis state for item `x`
+    fn matrix_index(x: usize, nr_bits: usize) -> usize
+    {
+        (0..nr_bits).fold(0, |idx, bit| (idx << 1) | ((x >> bit) & 1))
+    }
+
+    fn rng() -> rand_hc::Hc128Rng
+    {
+        <rand_hc::Hc128Rng as rand_core::SeedableRng>::seed_from_u64(0x6d2f_0e15)
+    }
+
+    #[test]
+    fn test_oracle()
+    {
+        for nr_bits in 1..4
+        {
+            let oracle = phase_oracle(nr_bits, |x| x % 3 == 1);
+            let mut expected = crate::cmatrix::CMatrix::eye(1 << nr_bits);
+            for x in (0..1 << nr_bits).filter(|x| x % 3 == 1)
+            {
+                let idx = matrix_index(x, nr_bits);
+                expected[[idx, idx]] = -crate::cmatrix::COMPLEX_ONE;
+            }
+            assert_complex_matrix_eq!(oracle.matrix(), &expected);
+            assert_complex_matrix_eq!(Grover::new(nr_bits, |x| x % 3 == 1).oracle().matrix(),
+                &expected);
+        }
+    }
+
+    #[test]
+    fn test_diffusion()
+    {
+        for nr_bits in 1..4
+        {
+            let size = 1 << nr_bits;
+            // I - 2|s⟩⟨s|, i.e. minus the reflection about |s⟩
+            let expected = crate::cmatrix::CMatrix::eye(size)
+                - crate::cmatrix::CMatrix::from_elem((size, size),
+                    num_complex::Complex::new(2.0 / size as f64, 0.0));
+            assert_complex_matrix_eq!(diffusion(nr_bits).matrix(), &expected);
+        }
+    }
+
+    #[test]
+    fn test_optimal_nr_iterations()
+    {
+        assert_eq!(optimal_nr_iterations(2, 1), 1);
+        assert_eq!(optimal_nr_iterations(3, 1), 2);
+        assert_eq!(optimal_nr_iterations(4, 1), 3);
+        assert_eq!(optimal_nr_iterations(10, 1), 25);
+        assert_eq!(optimal_nr_iterations(4, 4), 1);
+        assert_eq!(optimal_nr_iterations(4, 0), 0);
+        assert_eq!(optimal_nr_iterations(2, 4), 0);
+    }
+
+    #[test]
+    fn test_from_truth_table()
+    {
+        let grover = Grover::from_truth_table(&[false, true, false, false, false, false, true, false])
+            .unwrap();
+        assert_eq!(grover.nr_bits(), 3);
+        assert_eq!(grover.marked(), &[1, 6]);
+
+        assert!(matches!(Grover::from_truth_table(&[false, true, true]),
+            Err(crate::error::Error::InvalidTruthTable(3))));
+    }
+
+    #[test]
+    fn test_from_expression()
+    {
+        let grover = Grover::from_expression(3, "x0 & !(x1 | x2)").unwrap();
+        assert_eq!(grover.marked(), &[1]);
+
+        let grover = Grover::from_expression(3, "x0 & ~x2 | (x1 ^ x2) & 1").unwrap();
+        let expected: Vec<u64> = (0..8)
+            .filter(|x| (x & 1 == 1 && x & 4 == 0) || ((x >> 1) & 1 != (x >> 2) & 1))
+            .collect();
+        assert_eq!(grover.marked(), expected.as_slice());
+
+        let grover = Grover::from_expression(2, "0").unwrap();
+        assert!(grover.marked().is_empty());
+
+        assert!(matches!(Grover::from_expression(3, "x0 &"),
+            Err(crate::error::Error::ParseError(crate::error::ParseError::InvalidBooleanExpression(_)))));
+        assert!(matches!(Grover::from_expression(3, "(x0 | x1"),
+            Err(crate::error::Error::ParseError(crate::error::ParseError::UnclosedParentheses(_)))));
+        assert!(matches!(Grover::from_expression(3, "x0 x1"),
+            Err(crate::error::Error::ParseError(crate::error::ParseError::TrailingText(_)))));
+        assert!(matches!(Grover::from_expression(3, "x & x1"),
+            Err(crate::error::Error::ParseError(crate::error::ParseError::InvalidBit(_)))));
+        assert!(matches!(Grover::from_expression(3, "x0 & x3"),
+            Err(crate::error::Error::InvalidQBit(3))));
+    }
+
+    #[test]
+    fn test_circuit()
+    {
+        let grover = Grover::new(3, |x| x == 5);
+        let circuit = grover.circuit(2).unwrap();
+        assert_eq!(circuit.nr_qbits(), 3);
+        assert!(circuit.c_qasm().unwrap().contains(".grover(2)"));
+        assert!(circuit.open_qasm().is_ok());
+    }
+
+    #[test]
+    fn test_search()
+    {
+        let nr_shots = 1024;
+
+        let grover = Grover::new(4, |x| x == 11);
+        let result = grover.search_with_rng(nr_shots, &mut rng()).unwrap();
+        assert_eq!(result.nr_iterations, 3);
+        assert_eq!(result.items.len(), 1);
+        assert_eq!(result.items[0].0, 11);
+        assert!(result.items[0].1 as f64 > 0.9 * nr_shots as f64);
+
+        let grover = Grover::from_expression(5, "x0 & x1 & !x4 & (x2 ^ x3)").unwrap();
+        assert_eq!(grover.marked(), &[7, 11]);
+        let result = grover.search_with_rng(nr_shots, &mut rng()).unwrap();
+        assert_eq!(result.nr_iterations, 3);
+        let mut found: Vec<u64> = result.items.iter().map(|&(x, _)| x).collect();
+        found.sort();
+        assert_eq!(found, vec![7, 11]);
+        assert!(result.items[0].1 >= result.items[1].1);
+        let total: usize = result.items.iter().map(|&(_, count)| count).sum();
+        assert!(total as f64 > 0.9 * nr_shots as f64);
+    }
+}
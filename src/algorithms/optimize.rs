@@ -0,0 +1,465 @@
+// Copyright 2019 Q1t BV
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Classical optimizers for variational quantum algorithms.
+
+use rand::Rng;
+
+/// Function to be minimized
+///
+/// Trait Objective describes a (possibly noisy) real function of a number of
+/// real parameters, that can be minimized by an `Optimizer`. Evaluation may
+/// involve sampling, for which the random number generator passed to the
+/// methods should be used.
+pub trait Objective
+{
+    /// Evaluate this function for parameter values `params`.
+    fn value(&mut self, params: &[f64], rng: &mut dyn rand::RngCore)
+        -> crate::error::Result<f64>;
+
+    /// Compute the gradient of this function at parameter values `params`.
+    fn gradient(&mut self, params: &[f64], rng: &mut dyn rand::RngCore)
+        -> crate::error::Result<Vec<f64>>;
+}
+
+/// Result of a minimization
+///
+/// Struct OptimizeResult holds the lowest function value that was found in
+/// any evaluation of the objective function during the minimization, and the
+/// parameters for which it was found. For a noisy objective function, this
+/// value is itself a noisy estimate. The history holds one entry for every
+/// iteration performed by the optimizer, which is the lowest function value
+/// found up to and including that iteration, so that the last entry equals
+/// `value` if any iteration was performed.
+#[derive(Clone, Debug)]
+pub struct OptimizeResult
+{
+    /// The lowest function value found
+    pub value: f64,
+    /// The parameters for which this value was found
+    pub params: Vec<f64>,
+    /// The lowest function value found after each iteration
+    pub history: Vec<f64>
+}
+
+/// Tracker of the best point found during a minimization
+struct BestPoint
+{
+    /// The lowest function value found so far
+    value: f64,
+    /// The parameters for which this value was found
+    params: Vec<f64>,
+    /// The lowest function value found after each iteration
+    history: Vec<f64>
+}
+
+impl BestPoint
+{
+    /// Create a new tracker, starting with function value `value` at
+    /// parameters `params`.
+    fn new(value: f64, params: &[f64]) -> Self
+    {
+        BestPoint { value: value, params: params.to_vec(), history: vec![] }
+    }
+
+    /// Record an evaluation of the objective function, with value `value`
+    /// at parameters `params`.
+    fn update(&mut self, value: f64, params: &[f64])
+    {
+        if value < self.value
+        {
+            self.value = value;
+            self.params = params.to_vec();
+        }
+    }
+
+    /// Add the lowest value found so far to the history, at the end of an
+    /// iteration.
+    fn end_iteration(&mut self)
+    {
+        self.history.push(self.value);
+    }
+
+    /// Convert this tracker into the result of the minimization.
+    fn into_result(self) -> OptimizeResult
+    {
+        OptimizeResult { value: self.value, params: self.params, history: self.history }
+    }
+}
+
+/// Classical optimizer
+///
+/// Trait Optimizer describes an algorithm for finding the minimum of an
+/// objective function.
+pub trait Optimizer
+{
+    /// Minimize a function.
+    ///
+    /// Find the minimum of function `objective`, starting from parameter
+    /// values `initial`. Random number generator `rng` is used both by the
+    /// optimizer itself (if needed), and for evaluating the function.
+    fn minimize(&mut self, objective: &mut dyn Objective, initial: &[f64],
+        rng: &mut dyn rand::RngCore) -> crate::error::Result<OptimizeResult>;
+}
+
+/// Nelder-Mead simplex optimizer
+///
+/// Derivative free optimizer, that iteratively moves a simplex of `n+1`
+/// points through the `n`-dimensional parameter space.
+pub struct NelderMead
+{
+    /// The maximum number of iterations
+    max_iter: usize,
+    /// The size of the initial simplex
+    initial_step: f64,
+    /// Stop when the spread in function values over the simplex falls below this value
+    tolerance: f64
+}
+
+impl NelderMead
+{
+    /// Create a new Nelder-Mead optimizer, performing at most `max_iter`
+    /// iterations.
+    pub fn new(max_iter: usize) -> Self
+    {
+        NelderMead { max_iter: max_iter, initial_step: 0.5, tolerance: 1.0e-6 }
+    }
+
+    /// Set the size of the initial simplex around the starting point.
+    pub fn with_initial_step(mut self, initial_step: f64) -> Self
+    {
+        self.initial_step = initial_step;
+        self
+    }
+
+    /// Stop when the difference between the highest and lowest function value
+    /// in the simplex is smaller than `tolerance`.
+    pub fn with_tolerance(mut self, tolerance: f64) -> Self
+    {
+        self.tolerance = tolerance;
+        self
+    }
+}
+
+/// Return the point `x0 + t·(x1 - x0)`.
+fn interpolate(x0: &[f64], x1: &[f64], t: f64) -> Vec<f64>
+{
+    x0.iter().zip(x1.iter()).map(|(&a, &b)| a + t * (b - a)).collect()
+}
+
+impl Optimizer for NelderMead
+{
+    fn minimize(&mut self, objective: &mut dyn Objective, initial: &[f64],
+        rng: &mut dyn rand::RngCore) -> crate::error::Result<OptimizeResult>
+    {
+        let n = initial.len();
+
+        let mut simplex = vec![];
+        simplex.push((objective.value(initial, rng)?, initial.to_vec()));
+        let mut best = BestPoint::new(simplex[0].0, initial);
+        for i in 0..n
+        {
+            let mut x = initial.to_vec();
+            x[i] += self.initial_step;
+            let f = objective.value(&x, rng)?;
+            best.update(f, &x);
+            simplex.push((f, x));
+        }
+
+        for _ in 0..self.max_iter
+        {
+            simplex.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(::std::cmp::Ordering::Equal));
+            if n == 0 || simplex[n].0 - simplex[0].0 < self.tolerance
+            {
+                break;
+            }
+
+            let mut centroid = vec![0.0; n];
+            for (_, x) in simplex[..n].iter()
+            {
+                for (c, &xi) in centroid.iter_mut().zip(x.iter())
+                {
+                    *c += xi / n as f64;
+                }
+            }
+
+            let (f_worst, x_worst) = simplex[n].clone();
+            let x_refl = interpolate(&centroid, &x_worst, -1.0);
+            let f_refl = objective.value(&x_refl, rng)?;
+            best.update(f_refl, &x_refl);
+            if f_refl < simplex[0].0
+            {
+                let x_exp = interpolate(&centroid, &x_worst, -2.0);
+                let f_exp = objective.value(&x_exp, rng)?;
+                best.update(f_exp, &x_exp);
+                simplex[n] = if f_exp < f_refl { (f_exp, x_exp) } else { (f_refl, x_refl) };
+            }
+            else if f_refl < simplex[n-1].0
+            {
+                simplex[n] = (f_refl, x_refl);
+            }
+            else
+            {
+                let (f_base, x_base) = if f_refl < f_worst { (f_refl, x_refl) } else { (f_worst, x_worst) };
+                let x_contr = interpolate(&centroid, &x_base, 0.5);
+                let f_contr = objective.value(&x_contr, rng)?;
+                best.update(f_contr, &x_contr);
+                if f_contr < f_base
+                {
+                    simplex[n] = (f_contr, x_contr);
+                }
+                else
+                {
+                    let x_best = simplex[0].1.clone();
+                    for point in simplex[1..].iter_mut()
+                    {
+                        let x = interpolate(&x_best, &point.1, 0.5);
+                        let f = objective.value(&x, rng)?;
+                        best.update(f, &x);
+                        *point = (f, x);
+                    }
+                }
+            }
+
+            best.end_iteration();
+        }
+
+        Ok(best.into_result())
+    }
+}
+
+/// Simultaneous perturbation stochastic approximation
+///
+/// Optimizer that estimates the gradient from only two function evaluations
+/// per iteration, by perturbing all parameters simultaneously in a random
+/// direction. This makes it well suited for noisy objective functions.
+pub struct Spsa
+{
+    /// The number of iterations
+    max_iter: usize,
+    /// Scale of the step size
+    a: f64,
+    /// Scale of the perturbation size
+    c: f64,
+    /// Decay exponent of the step size
+    alpha: f64,
+    /// Decay exponent of the perturbation size
+    gamma: f64,
+    /// Stability constant for the step size
+    stability: f64
+}
+
+impl Spsa
+{
+    /// Create a new SPSA optimizer, performing `max_iter` iterations, using
+    /// the standard decay exponents `α = 0.602` and `γ = 0.101`.
+    pub fn new(max_iter: usize) -> Self
+    {
+        Spsa
+        {
+            max_iter: max_iter,
+            a: 0.2,
+            c: 0.1,
+            alpha: 0.602,
+            gamma: 0.101,
+            stability: 0.1 * max_iter as f64
+        }
+    }
+
+    /// Set the gains.
+    ///
+    /// Set the scale `a` of the step size and the scale `c` of the
+    /// perturbation. In iteration `k`, the step size is `a/(k+1+A)`<sup>`α`</sup>,
+    /// and the perturbation size is `c/(k+1)`<sup>`γ`</sup>.
+    pub fn with_gains(mut self, a: f64, c: f64) -> Self
+    {
+        self.a = a;
+        self.c = c;
+        self
+    }
+}
+
+impl Optimizer for Spsa
+{
+    fn minimize(&mut self, objective: &mut dyn Objective, initial: &[f64],
+        rng: &mut dyn rand::RngCore) -> crate::error::Result<OptimizeResult>
+    {
+        let mut x = initial.to_vec();
+        let mut best = BestPoint::new(objective.value(&x, rng)?, &x);
+        for k in 0..self.max_iter
+        {
+            let ak = self.a / (k as f64 + 1.0 + self.stability).powf(self.alpha);
+            let ck = self.c / (k as f64 + 1.0).powf(self.gamma);
+
+            let delta: Vec<f64> = (0..x.len())
+                .map(|_| if rng.gen::<bool>() { 1.0 } else { -1.0 })
+                .collect();
+            let x_plus: Vec<f64> = x.iter().zip(delta.iter()).map(|(&xi, &d)| xi + ck*d).collect();
+            let x_minus: Vec<f64> = x.iter().zip(delta.iter()).map(|(&xi, &d)| xi - ck*d).collect();
+            let f_plus = objective.value(&x_plus, rng)?;
+            let f_minus = objective.value(&x_minus, rng)?;
+            best.update(f_plus, &x_plus);
+            best.update(f_minus, &x_minus);
+
+            let scale = (f_plus - f_minus) / (2.0 * ck);
+            for (xi, &d) in x.iter_mut().zip(delta.iter())
+            {
+                *xi -= ak * scale / d;
+            }
+            if k + 1 == self.max_iter
+            {
+                // Evaluate the final iterate, so that it can be returned
+                best.update(objective.value(&x, rng)?, &x);
+            }
+
+            best.end_iteration();
+        }
+
+        Ok(best.into_result())
+    }
+}
+
+/// Gradient descent optimizer
+///
+/// Optimizer that takes steps in the direction of the negative gradient,
+/// using the gradient computed by the objective function.
+pub struct GradientDescent
+{
+    /// The maximum number of iterations
+    max_iter: usize,
+    /// The size of a step relative to the gradient
+    learning_rate: f64,
+    /// Stop when the norm of the gradient falls below this value
+    tolerance: f64
+}
+
+impl GradientDescent
+{
+    /// Create a new gradient descent optimizer, performing at most `max_iter`
+    /// steps of size `learning_rate` times the gradient.
+    pub fn new(max_iter: usize, learning_rate: f64) -> Self
+    {
+        GradientDescent { max_iter: max_iter, learning_rate: learning_rate, tolerance: 1.0e-6 }
+    }
+
+    /// Stop when the norm of the gradient is smaller than `tolerance`.
+    pub fn with_tolerance(mut self, tolerance: f64) -> Self
+    {
+        self.tolerance = tolerance;
+        self
+    }
+}
+
+impl Optimizer for GradientDescent
+{
+    fn minimize(&mut self, objective: &mut dyn Objective, initial: &[f64],
+        rng: &mut dyn rand::RngCore) -> crate::error::Result<OptimizeResult>
+    {
+        let mut x = initial.to_vec();
+        let mut best = BestPoint::new(objective.value(&x, rng)?, &x);
+        for _ in 0..self.max_iter
+        {
+            let grad = objective.gradient(&x, rng)?;
+            if grad.iter().map(|g| g * g).sum::<f64>().sqrt() < self.tolerance
+            {
+                break;
+            }
+            for (xi, &gi) in x.iter_mut().zip(grad.iter())
+            {
+                *xi -= self.learning_rate * gi;
+            }
+            best.update(objective.value(&x, rng)?, &x);
+            best.end_iteration();
+        }
+
+        Ok(best.into_result())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{GradientDescent, NelderMead, Objective, Optimizer, Spsa};
+
+    /// Shifted quadratic function (x-1)² + 2(y+0.5)² - 1
+    struct Quadratic {}
+
+    impl Objective for Quadratic
+    {
+        fn value(&mut self, params: &[f64], _rng: &mut dyn rand::RngCore)
+            -> crate::error::Result<f64>
+        {
+            Ok((params[0] - 1.0).powi(2) + 2.0 * (params[1] + 0.5).powi(2) - 1.0)
+        }
+
+        fn gradient(&mut self, params: &[f64], _rng: &mut dyn rand::RngCore)
+            -> crate::error::Result<Vec<f64>>
+        {
+            Ok(vec![2.0 * (params[0] - 1.0), 4.0 * (params[1] + 0.5)])
+        }
+    }
+
+    fn check_minimum<O: Optimizer>(optimizer: &mut O, tol: f64)
+    {
+        let mut rng = <rand_hc::Hc128Rng as rand_core::SeedableRng>::seed_from_u64(0x5eed);
+        let res = optimizer.minimize(&mut Quadratic {}, &[0.0, 0.0], &mut rng).unwrap();
+        assert!((res.value + 1.0).abs() < tol, "{}", res.value);
+        assert!((res.params[0] - 1.0).abs() < tol.sqrt());
+        assert!((res.params[1] + 0.5).abs() < tol.sqrt());
+        assert!(!res.history.is_empty());
+        assert_eq!(res.history.last(), Some(&res.value));
+        assert!(res.history.windows(2).all(|w| w[1] <= w[0]));
+    }
+
+    #[test]
+    fn test_nelder_mead()
+    {
+        check_minimum(&mut NelderMead::new(200).with_tolerance(1.0e-10), 1.0e-8);
+    }
+
+    #[test]
+    fn test_spsa()
+    {
+        check_minimum(&mut Spsa::new(500).with_gains(0.5, 0.1), 1.0e-4);
+    }
+
+    #[test]
+    fn test_gradient_descent()
+    {
+        check_minimum(&mut GradientDescent::new(200, 0.1), 1.0e-8);
+    }
+
+    #[test]
+    fn test_history_length()
+    {
+        let mut rng = <rand_hc::Hc128Rng as rand_core::SeedableRng>::seed_from_u64(0x5eed);
+        let optimizers: Vec<Box<dyn Optimizer>> = vec![
+            Box::new(NelderMead::new(5)),
+            Box::new(Spsa::new(5)),
+            Box::new(GradientDescent::new(5, 0.1))
+        ];
+        for mut optimizer in optimizers
+        {
+            let res = optimizer.minimize(&mut Quadratic {}, &[0.0, 0.0], &mut rng).unwrap();
+            assert_eq!(res.history.len(), 5);
+            assert_eq!(res.history.last(), Some(&res.value));
+        }
+
+        let res = GradientDescent::new(0, 0.1)
+            .minimize(&mut Quadratic {}, &[0.0, 0.0], &mut rng).unwrap();
+        assert!(res.history.is_empty());
+        assert_eq!(res.value, 0.5);
+        assert_eq!(res.params, vec![0.0, 0.0]);
+    }
+}
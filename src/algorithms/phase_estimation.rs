@@ -0,0 +1,476 @@
+// Copyright 2019 Q1t BV
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Quantum phase estimation and amplitude estimation.
+
+use crate::gates::Gate;
+
+/// Shared gate
+///
+/// Struct `SharedGate` allows a single gate to be added to a circuit multiple
+/// times, by sharing ownership of the gate between all occurrences. All
+/// operations are delegated to the shared gate.
+#[derive(Clone)]
+struct SharedGate
+{
+    gate: ::std::sync::Arc<dyn crate::export::CircuitGate>
+}
+
+impl crate::gates::Gate for SharedGate
+{
+    fn cost(&self) -> f64
+    {
+        self.gate.cost()
+    }
+
+    fn description(&self) -> &str
+    {
+        self.gate.description()
+    }
+
+    fn nr_affected_bits(&self) -> usize
+    {
+        self.gate.nr_affected_bits()
+    }
+
+    fn matrix(&self) -> crate::cmatrix::CMatrix
+    {
+        self.gate.matrix()
+    }
+
+    fn apply_slice(&self, state: crate::cmatrix::CVecSliceMut)
+    {
+        self.gate.apply_slice(state);
+    }
+
+    fn apply_mat_slice(&self, state: crate::cmatrix::CMatSliceMut)
+    {
+        self.gate.apply_mat_slice(state);
+    }
+}
+
+impl crate::export::OpenQasm for SharedGate
+{
+    fn open_qasm(&self, bit_names: &[String], bits: &[usize])
+        -> crate::error::Result<String>
+    {
+        self.gate.open_qasm(bit_names, bits)
+    }
+
+    fn conditional_open_qasm(&self, condition: &str, bit_names: &[String],
+        bits: &[usize]) -> crate::error::Result<String>
+    {
+        self.gate.conditional_open_qasm(condition, bit_names, bits)
+    }
+}
+
+impl crate::export::CQasm for SharedGate
+{
+    fn c_qasm(&self, bit_names: &[String], bits: &[usize])
+        -> crate::error::Result<String>
+    {
+        self.gate.c_qasm(bit_names, bits)
+    }
+
+    fn conditional_c_qasm(&self, condition: &str, bit_names: &[String],
+        bits: &[usize]) -> crate::error::Result<String>
+    {
+        self.gate.conditional_c_qasm(condition, bit_names, bits)
+    }
+}
+
+impl crate::export::Latex for SharedGate
+{
+    fn latex(&self, bits: &[usize], state: &mut crate::export::LatexExportState)
+        -> crate::error::Result<()>
+    {
+        self.gate.latex(bits, state)
+    }
+}
+
+/// How to implement powers of the unitary
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PowerMethod
+{
+    /// Add a controlled unitary gate to the circuit for every application
+    Repeat,
+    /// Add a static loop over a single controlled unitary to the circuit
+    Loop
+}
+
+/// Result of a phase estimation
+#[derive(Clone, Debug)]
+pub struct PhaseEstimationResult
+{
+    /// The estimated phase `φ`, in the range `[0, 1)`, where the eigenvalue is `exp(2πiφ)`
+    pub phase: f64,
+    /// The fraction of runs in which the estimated phase was measured
+    pub confidence: f64,
+    /// The number of times each value of the counting register was measured
+    pub histogram: Vec<usize>
+}
+
+/// Quantum phase estimation
+///
+/// Struct `PhaseEstimation` estimates the phase `φ` of the eigenvalue
+/// `exp(2πiφ)` of a unitary gate, for an eigenstate prepared by a state
+/// preparation gate. The phase is determined in a register of counting qubits,
+/// using controlled powers of the unitary followed by an inverse quantum
+/// Fourier transform. With `n` counting qubits, the phase is found with a
+/// precision of `2`<sup>`-n`</sup>.
+///
+/// In the circuit, the counting qubits come first, followed by the qubits
+/// the unitary operates on. Counting qubit `i` holds bit `i` of the
+/// counting register, i.e. the last counting qubit holds the most significant
+/// bit of the phase, and it is measured into classical bit `i`.
+pub struct PhaseEstimation
+{
+    /// The unitary whose phase is estimated
+    unitary: SharedGate,
+    /// The number of qubits in the counting register
+    nr_counting_bits: usize,
+    /// Gate preparing the state on which the unitary operates
+    state_prep: Option<SharedGate>,
+    /// How to compute the powers of the unitary
+    power_method: PowerMethod
+}
+
+impl PhaseEstimation
+{
+    /// Create a new phase estimation.
+    ///
+    /// Create a new phase estimation for the phase of gate `unitary`, using
+    /// `nr_counting_bits` counting qubits. By default, the unitary operates
+    /// on the `|0...0⟩` state, and powers of the unitary are implemented using
+    /// static loops.
+    pub fn new<G>(unitary: G, nr_counting_bits: usize) -> Self
+    where G: 'static + crate::export::CircuitGate
+    {
+        PhaseEstimation
+        {
+            unitary: SharedGate { gate: ::std::sync::Arc::new(unitary) },
+            nr_counting_bits: nr_counting_bits,
+            state_prep: None,
+            power_method: PowerMethod::Loop
+        }
+    }
+
+    /// Set the state preparation.
+    ///
+    /// Prepare the state on which the unitary operates, by applying gate
+    /// `state_prep` on the `|0...0⟩` state. Ideally, this is an eigenstate
+    /// of the unitary.
+    pub fn with_state_prep<G>(mut self, state_prep: G) -> Self
+    where G: 'static + crate::export::CircuitGate
+    {
+        self.state_prep = Some(SharedGate { gate: ::std::sync::Arc::new(state_prep) });
+        self
+    }
+
+    /// Set how to compute powers of the unitary.
+    pub fn with_power_method(mut self, power_method: PowerMethod) -> Self
+    {
+        self.power_method = power_method;
+        self
+    }
+
+    /// The number of qubits in the counting register.
+    pub fn nr_counting_bits(&self) -> usize
+    {
+        self.nr_counting_bits
+    }
+
+    /// Build the phase estimation circuit.
+    ///
+    /// Create the circuit estimating the phase, including the measurement
+    /// of the counting register. Returns an `InvalidNrBits` error if the
+    /// state preparation operates on a different number of qubits than the
+    /// unitary.
+    pub fn circuit(&self) -> crate::error::Result<crate::circuit::Circuit>
+    {
+        let nr_count = self.nr_counting_bits;
+        let nr_bits = self.unitary.nr_affected_bits();
+        let target_bits: Vec<usize> = (nr_count..nr_count+nr_bits).collect();
+
+        let mut circuit = crate::circuit::Circuit::new(nr_count + nr_bits, nr_count)?;
+        if let Some(ref state_prep) = self.state_prep
+        {
+            state_prep.check_nr_bits(&target_bits)?;
+            circuit.add_gate(state_prep.clone(), &target_bits)?;
+        }
+
+        for bit in 0..nr_count
+        {
+            circuit.h(bit)?;
+        }
+        for bit in 0..nr_count
+        {
+            let power = 1 << bit;
+            let mut bits = vec![bit];
+            bits.extend_from_slice(&target_bits);
+            let cu = crate::gates::MultiControlled::new(Box::new(self.unitary.clone()), 1);
+            match self.power_method
+            {
+                PowerMethod::Repeat => {
+                    circuit.add_gate(cu, &bits)?;
+                    for _ in 1..power
+                    {
+                        let cu = crate::gates::MultiControlled::new(
+                            Box::new(self.unitary.clone()), 1);
+                        circuit.add_gate(cu, &bits)?;
+                    }
+                },
+                PowerMethod::Loop => {
+                    let mut body = crate::gates::Composite::new(cu.description(), nr_bits+1);
+                    body.add_gate(cu, &(0..nr_bits+1).collect::<Vec<usize>>());
+                    let label = format!("qpe_{}", bit);
+                    circuit.add_gate(crate::gates::Loop::new(&label, power, body), &bits)?;
+                }
+            }
+        }
+
+        circuit.add_gate(crate::gates::QFT::new_inverse(nr_count, true),
+            &(0..nr_count).rev().collect::<Vec<usize>>())?;
+        for bit in 0..nr_count
+        {
+            circuit.measure(bit, bit)?;
+        }
+
+        Ok(circuit)
+    }
+
+    /// Estimate the phase.
+    ///
+    /// Estimate the phase of the unitary by running the phase estimation
+    /// circuit `nr_shots` times, and taking the most frequently measured
+    /// value of the counting register.
+    pub fn estimate(&self, nr_shots: usize) -> crate::error::Result<PhaseEstimationResult>
+    {
+        self.estimate_with_rng(nr_shots, &mut rand::thread_rng())
+    }
+
+    /// Estimate the phase.
+    ///
+    /// Estimate the phase of the unitary by running the phase estimation
+    /// circuit `nr_shots` times, and taking the most frequently measured
+    /// value of the counting register. Use random number generator `rng` for
+    /// sampling the measurements.
+    pub fn estimate_with_rng<R: rand::RngCore>(&self, nr_shots: usize, rng: &mut R)
+        -> crate::error::Result<PhaseEstimationResult>
+    {
+        let mut circuit = self.circuit()?;
+        circuit.execute_with_rng(nr_shots, rng)?;
+        let histogram = circuit.histogram_vec()?;
+
+        let (best, &count) = histogram.iter().enumerate()
+            .max_by_key(|&(_, &count)| count)
+            .unwrap();
+        Ok(PhaseEstimationResult
+        {
+            phase: best as f64 / histogram.len() as f64,
+            confidence: count as f64 / nr_shots as f64,
+            histogram: histogram
+        })
+    }
+}
+
+/// Estimate a phase.
+///
+/// Estimate the phase `φ` of the eigenvalue `exp(2πiφ)` of gate `unitary`,
+/// for the eigenstate prepared by `state_prep`, using `nr_counting_bits`
+/// counting qubits, and `nr_shots` runs of the phase estimation circuit.
+pub fn phase_estimation<G, P>(unitary: G, nr_counting_bits: usize,
+    state_prep: P, nr_shots: usize) -> crate::error::Result<PhaseEstimationResult>
+where G: 'static + crate::export::CircuitGate, P: 'static + crate::export::CircuitGate
+{
+    PhaseEstimation::new(unitary, nr_counting_bits)
+        .with_state_prep(state_prep)
+        .estimate(nr_shots)
+}
+
+/// Result of an amplitude estimation
+#[derive(Clone, Debug)]
+pub struct AmplitudeEstimationResult
+{
+    /// The estimated probability of finding the system in a good state
+    pub amplitude: f64,
+    /// The fraction of runs in which the estimated amplitude was measured
+    pub confidence: f64
+}
+
+/// Amplitude estimation
+///
+/// Estimate the probability `a` that measuring the state `A|0...0⟩`,
+/// prepared by gate `state_prep`, results in a good basis state. A basis
+/// state `|x⟩` is good when `is_good(x)` returns `true`, with the first
+/// qubit being the most significant bit of `x`. The estimate is found using
+/// phase estimation, with `nr_counting_bits` counting qubits, on the
+/// Grover operator `Q = A S₀ A† Sᵪ`, where `Sᵪ` flips the sign of the good
+/// states and `S₀` reflects about `|0...0⟩`. The eigenvalues of `Q` are
+/// `exp(±2iθ)`, with `a = sin²θ`.
+pub fn amplitude_estimation<G, F, R>(state_prep: G, is_good: F, nr_counting_bits: usize,
+    nr_shots: usize, rng: &mut R) -> crate::error::Result<AmplitudeEstimationResult>
+where G: crate::export::CircuitGate, F: Fn(u64) -> bool, R: rand::RngCore
+{
+    let a = state_prep.matrix();
+    let size = a.rows();
+    let a_adj = a.t().mapv(|c| c.conj());
+
+    let mut s_0 = -crate::cmatrix::CMatrix::eye(size);
+    s_0[[0, 0]] = crate::cmatrix::COMPLEX_ONE;
+
+    // Multiplying from the right by Sᵪ flips the sign of the columns of good states
+    let mut q = a.dot(&s_0).dot(&a_adj);
+    for (x, mut column) in q.gencolumns_mut().into_iter().enumerate()
+    {
+        if is_good(x as u64)
+        {
+            column.mapv_inplace(|c| -c);
+        }
+    }
+    let grover = crate::gates::Unitary::new("Q", q)?;
+
+    let estimation = PhaseEstimation::new(grover, nr_counting_bits)
+        .with_state_prep(crate::gates::Unitary::new(state_prep.description(), a)?);
+    let result = estimation.estimate_with_rng(nr_shots, rng)?;
+
+    // Phases φ and 1-φ result in the same amplitude
+    let histogram = &result.histogram;
+    let nr_outcomes = histogram.len();
+    let (best, count) = (0..nr_outcomes/2+1)
+        .map(|y| {
+            let count = if y == 0 || 2*y == nr_outcomes
+                {
+                    histogram[y]
+                }
+                else
+                {
+                    histogram[y] + histogram[nr_outcomes-y]
+                };
+            (y, count)
+        })
+        .max_by_key(|&(_, count)| count)
+        .unwrap();
+
+    let theta = ::std::f64::consts::PI * best as f64 / nr_outcomes as f64;
+    Ok(AmplitudeEstimationResult
+    {
+        amplitude: theta.sin().powi(2),
+        confidence: count as f64 / nr_shots as f64
+    })
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{amplitude_estimation, phase_estimation, PhaseEstimation, PowerMethod};
+    use crate::gates::{Composite, H, RY, U1, X};
+
+    fn rng() -> rand_hc::Hc128Rng
+    {
+        <rand_hc::Hc128Rng as rand_core::SeedableRng>::seed_from_u64(0x3ab7_91c4)
+    }
+
+    #[test]
+    fn test_exact_phase()
+    {
+        let unitary = U1::new(2.0 * ::std::f64::consts::PI * 5.0 / 16.0);
+        for &method in [PowerMethod::Loop, PowerMethod::Repeat].iter()
+        {
+            let estimation = PhaseEstimation::new(U1::new(2.0 * ::std::f64::consts::PI * 5.0 / 16.0), 4)
+                .with_state_prep(X::new())
+                .with_power_method(method);
+            let result = estimation.estimate_with_rng(100, &mut rng()).unwrap();
+            assert_eq!(result.phase, 0.3125);
+            assert_eq!(result.confidence, 1.0);
+            assert_eq!(result.histogram[5], 100);
+        }
+
+        let result = phase_estimation(unitary, 4, X::new(), 100).unwrap();
+        assert_eq!(result.phase, 0.3125);
+    }
+
+    #[test]
+    fn test_inexact_phase()
+    {
+        let nr_shots = 1024;
+        let estimation = PhaseEstimation::new(U1::new(2.0 * ::std::f64::consts::PI / 3.0), 6)
+            .with_state_prep(X::new());
+        assert_eq!(estimation.nr_counting_bits(), 6);
+        let result = estimation.estimate_with_rng(nr_shots, &mut rng()).unwrap();
+        assert!((result.phase - 1.0 / 3.0).abs() < 1.0 / 64.0);
+        assert!(result.confidence > 0.4 && result.confidence < 1.0);
+        assert_eq!(result.histogram.iter().sum::<usize>(), nr_shots);
+    }
+
+    #[test]
+    fn test_multi_qubit()
+    {
+        // Eigenstate |11⟩ of the two-qubit gate with eigenvalue exp(2πi·3/8)
+        let mut unitary = Composite::new("U", 2);
+        unitary.add_gate(crate::gates::CU1::new(2.0 * ::std::f64::consts::PI * 3.0 / 8.0), &[0, 1]);
+        unitary.add_gate(X::new(), &[0]);
+        unitary.add_gate(X::new(), &[0]);
+        let mut prep = Composite::new("P", 2);
+        prep.add_gate(X::new(), &[0]);
+        prep.add_gate(X::new(), &[1]);
+
+        let result = PhaseEstimation::new(unitary, 3)
+            .with_state_prep(prep)
+            .estimate_with_rng(50, &mut rng())
+            .unwrap();
+        assert_eq!(result.phase, 0.375);
+        assert_eq!(result.confidence, 1.0);
+    }
+
+    #[test]
+    fn test_circuit()
+    {
+        let estimation = PhaseEstimation::new(U1::new(::std::f64::consts::PI), 2)
+            .with_state_prep(X::new());
+        let circuit = estimation.circuit().unwrap();
+        assert_eq!(circuit.nr_qbits(), 3);
+        assert_eq!(circuit.nr_cbits(), 2);
+        assert!(circuit.open_qasm().is_ok());
+        assert!(circuit.c_qasm().unwrap().contains(".qpe_1(2)"));
+
+    }
+
+    #[test]
+    fn test_errors()
+    {
+        let estimation = PhaseEstimation::new(U1::new(::std::f64::consts::PI), 2)
+            .with_state_prep(crate::gates::CX::new());
+        assert!(matches!(estimation.circuit(), Err(crate::error::Error::InvalidNrBits(1, 2, _))));
+    }
+
+    #[test]
+    fn test_amplitude_estimation()
+    {
+        // Amplitude exactly representable in the counting register
+        let theta = 3.0 * ::std::f64::consts::PI / 16.0;
+        let result = amplitude_estimation(RY::new(2.0 * theta), |x| x == 1, 4, 100, &mut rng())
+            .unwrap();
+        assert!((result.amplitude - theta.sin().powi(2)).abs() < 1.0e-12);
+        assert!((result.confidence - 1.0).abs() < 1.0e-12);
+
+        // Probability of finding |11⟩ in a uniform superposition
+        let mut prep = Composite::new("A", 2);
+        prep.add_gate(H::new(), &[0]);
+        prep.add_gate(H::new(), &[1]);
+        let result = amplitude_estimation(prep, |x| x == 3, 6, 1024, &mut rng()).unwrap();
+        assert!((result.amplitude - 0.25).abs() < 0.03);
+        assert!(result.confidence > 0.5);
+    }
+}
@@ -0,0 +1,533 @@
+// Copyright 2019 Q1t BV
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Quantum approximate optimization algorithm.
+
+use crate::algorithms::optimize::{Objective, Optimizer};
+
+/// Combinatorial optimization problem in Ising form
+///
+/// Struct Ising describes the minimization of a cost function
+/// `C(s) = c + ∑`<sub>`i`</sub>` h`<sub>`i`</sub>`s`<sub>`i`</sub>` + ∑`<sub>`i<j`</sub>` J`<sub>`ij`</sub>`s`<sub>`i`</sub>`s`<sub>`j`</sub>
+/// over spins `s`<sub>`i`</sub>` = ±1`. Spin `i` is represented by qubit `i`,
+/// where `|0⟩` corresponds to `s`<sub>`i`</sub>` = 1` and `|1⟩` to
+/// `s`<sub>`i`</sub>` = -1`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ising
+{
+    /// The number of spins
+    nr_bits: usize,
+    /// The constant term `c`
+    offset: f64,
+    /// The local fields `h`<sub>`i`</sub>
+    fields: Vec<f64>,
+    /// The couplings `(i, j, J`<sub>`ij`</sub>`)`
+    couplings: Vec<(usize, usize, f64)>
+}
+
+impl Ising
+{
+    /// Create a new problem.
+    ///
+    /// Create a new problem on `nr_bits` spins, with constant term `offset`,
+    /// local fields `fields`, and couplings `(i, j, J`<sub>`ij`</sub>`)` in
+    /// `couplings`. If the number of fields does not match the number of
+    /// spins, an `InvalidNrBits` error is returned, if a coupling refers to
+    /// an invalid spin, or couples a spin to itself, an `InvalidQBit` error
+    /// is returned.
+    pub fn new(nr_bits: usize, offset: f64, fields: Vec<f64>,
+        couplings: Vec<(usize, usize, f64)>) -> crate::error::Result<Self>
+    {
+        if fields.len() != nr_bits
+        {
+            return Err(crate::error::Error::InvalidNrBits(fields.len(), nr_bits,
+                String::from("Ising fields")));
+        }
+        for &(i, j, _) in couplings.iter()
+        {
+            if i >= nr_bits || i == j
+            {
+                return Err(crate::error::Error::InvalidQBit(i));
+            }
+            if j >= nr_bits
+            {
+                return Err(crate::error::Error::InvalidQBit(j));
+            }
+        }
+
+        Ok(Ising { nr_bits: nr_bits, offset: offset, fields: fields, couplings: couplings })
+    }
+
+    /// Create a MaxCut problem.
+    ///
+    /// Create the problem of finding a maximum cut in the graph with
+    /// `nr_nodes` nodes, and weighted edges `(i, j, w`<sub>`ij`</sub>`)` in
+    /// `edges`. Since the problem is formulated as a minimization, the cost
+    /// is minus the total weight of the cut edges.
+    pub fn maxcut(nr_nodes: usize, edges: &[(usize, usize, f64)]) -> crate::error::Result<Self>
+    {
+        // An edge is cut when s_i s_j = -1, so -w(1 - s_i s_j)/2 is minus
+        // the weight of the edge if it is cut, and zero otherwise.
+        let offset = -0.5 * edges.iter().map(|&(_, _, w)| w).sum::<f64>();
+        let couplings = edges.iter().map(|&(i, j, w)| (i, j, 0.5 * w)).collect();
+        Self::new(nr_nodes, offset, vec![0.0; nr_nodes], couplings)
+    }
+
+    /// Create a problem from a QUBO.
+    ///
+    /// Create the problem of minimizing `x`<sup>`T`</sup>`Qx` over binary
+    /// variables `x`<sub>`i`</sub>` ∈ {0, 1}`, where variable `x`<sub>`i`</sub>
+    /// is the value of qubit `i`. If `q` is not a square matrix, an
+    /// `InvalidMatrixSize` error is returned.
+    pub fn from_qubo(q: &ndarray::Array2<f64>) -> crate::error::Result<Self>
+    {
+        let n = q.rows();
+        if q.cols() != n
+        {
+            return Err(crate::error::Error::InvalidMatrixSize(q.rows(), q.cols()));
+        }
+
+        // Substitute x_i = (1 - s_i)/2
+        let mut offset = 0.0;
+        let mut fields = vec![0.0; n];
+        let mut couplings = vec![];
+        for i in 0..n
+        {
+            offset += 0.5 * q[[i, i]];
+            fields[i] -= 0.5 * q[[i, i]];
+            for j in i+1..n
+            {
+                let w = q[[i, j]] + q[[j, i]];
+                if w != 0.0
+                {
+                    offset += 0.25 * w;
+                    fields[i] -= 0.25 * w;
+                    fields[j] -= 0.25 * w;
+                    couplings.push((i, j, 0.25 * w));
+                }
+            }
+        }
+
+        Self::new(n, offset, fields, couplings)
+    }
+
+    /// The number of spins in this problem
+    pub fn nr_bits(&self) -> usize
+    {
+        self.nr_bits
+    }
+
+    /// Evaluate the cost function.
+    ///
+    /// Compute the cost for the spin configuration in `key`, where bit `i`
+    /// in `key` is the value of qubit `i`, as measured into classical bit `i`.
+    pub fn cost(&self, key: u64) -> f64
+    {
+        let spin = |i: usize| if (key >> i) & 1 == 0 { 1.0 } else { -1.0 };
+        self.offset
+            + self.fields.iter().enumerate().map(|(i, &h)| h * spin(i)).sum::<f64>()
+            + self.couplings.iter().map(|&(i, j, w)| w * spin(i) * spin(j)).sum::<f64>()
+    }
+
+    /// Compute the average cost.
+    ///
+    /// Compute the average cost over the measurement results in histogram
+    /// `hist`, as returned by `Circuit::histogram()`.
+    pub fn expected_cost(&self, hist: &crate::idhash::U64HashMap<usize>) -> f64
+    {
+        let total: usize = hist.values().sum();
+        if total == 0
+        {
+            0.0
+        }
+        else
+        {
+            hist.iter().map(|(&key, &count)| count as f64 * self.cost(key)).sum::<f64>()
+                / total as f64
+        }
+    }
+
+    /// Find the best sampled solution.
+    ///
+    /// Return the measurement result in histogram `hist` with the lowest cost,
+    /// together with that cost, or `None` if the histogram is empty.
+    pub fn best_solution(&self, hist: &crate::idhash::U64HashMap<usize>) -> Option<(u64, f64)>
+    {
+        hist.keys()
+            .map(|&key| (key, self.cost(key)))
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(::std::cmp::Ordering::Equal)
+                .then(a.0.cmp(&b.0)))
+    }
+}
+
+/// Implementation of the two-qubit interaction in the cost layer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Coupling
+{
+    /// Implement `exp(-iθZ⊗Z/2)` as a `CX`, `RZ`, `CX` sequence
+    CXRZ,
+    /// Implement `exp(-iθZ⊗Z/2)` as a single `RZZ` Pauli rotation gate
+    ZZ
+}
+
+/// Add a `ZZ` rotation over angle `angle` on qubits `i` and `j` to circuit
+/// `circuit`, using the implementation in `coupling`.
+fn add_zz_rotation(circuit: &mut crate::circuit::Circuit, coupling: Coupling,
+    angle: &str, i: usize, j: usize) -> crate::error::Result<()>
+{
+    match coupling
+    {
+        Coupling::CXRZ => {
+            circuit.cx(i, j)?;
+            circuit.rz_param(angle, j)?;
+            circuit.cx(i, j)
+        },
+        Coupling::ZZ => {
+            circuit.add_parametric_gate("RZZ", &[angle],
+                |args| crate::gates::PauliRotation::rzz(args[0]), &[i, j])
+        }
+    }
+}
+
+/// Build a QAOA circuit.
+///
+/// Build the circuit for the quantum approximate optimization algorithm of
+/// depth `depth`, for problem `problem`. The circuit starts with a uniform
+/// superposition, followed by `depth` layers, each consisting of the cost
+/// unitary `exp(-iγ`<sub>`k`</sub>`C)` and the mixer
+/// `exp(-iβ`<sub>`k`</sub>`∑X`<sub>`i`</sub>`)`. The angles are parameters
+/// `gamma_k` and `beta_k` of the circuit, for `k = 0, ..., depth-1`. The
+/// couplings are implemented as given by `coupling`. Finally, all qubits are
+/// measured, qubit `i` into classical bit `i`.
+pub fn qaoa_circuit(problem: &Ising, depth: usize, coupling: Coupling)
+    -> crate::error::Result<crate::circuit::Circuit>
+{
+    let n = problem.nr_bits();
+    let mut circuit = crate::circuit::Circuit::new(n, n)?;
+    for i in 0..n
+    {
+        circuit.h(i)?;
+    }
+
+    for k in 0..depth
+    {
+        for (i, &h) in problem.fields.iter().enumerate()
+        {
+            if h != 0.0
+            {
+                circuit.rz_param(&format!("{}*gamma_{}", 2.0 * h, k), i)?;
+            }
+        }
+        for &(i, j, w) in problem.couplings.iter()
+        {
+            if w != 0.0
+            {
+                add_zz_rotation(&mut circuit, coupling, &format!("{}*gamma_{}", 2.0 * w, k), i, j)?;
+            }
+        }
+        for i in 0..n
+        {
+            circuit.rx_param(&format!("2*beta_{}", k), i)?;
+        }
+    }
+
+    let bits: Vec<usize> = (0..n).collect();
+    circuit.measure_all(&bits)?;
+
+    Ok(circuit)
+}
+
+/// Result of a QAOA optimization
+#[derive(Clone, Debug)]
+pub struct QAOAResult
+{
+    /// The lowest average cost found
+    pub cost: f64,
+    /// The optimal cost layer angles `γ`<sub>`k`</sub>
+    pub gammas: Vec<f64>,
+    /// The optimal mixer angles `β`<sub>`k`</sub>
+    pub betas: Vec<f64>,
+    /// The best average cost after each iteration of the optimizer
+    pub history: Vec<f64>,
+    /// The solution with the lowest cost, sampled at the optimal angles
+    pub best: Option<(u64, f64)>
+}
+
+/// Quantum approximate optimization algorithm
+///
+/// Struct QAOA optimizes the angles in a QAOA circuit for an optimization
+/// problem in Ising form, by minimizing the average cost of the measured
+/// solutions.
+pub struct QAOA<'a>
+{
+    /// The problem to solve
+    problem: &'a Ising,
+    /// The number of layers in the circuit
+    depth: usize,
+    /// The QAOA circuit
+    circuit: crate::circuit::Circuit,
+    /// The number of runs for each estimate of the cost
+    nr_shots: usize
+}
+
+impl<'a> QAOA<'a>
+{
+    /// Create a new QAOA optimizer.
+    ///
+    /// Create a new optimizer for problem `problem`, using a circuit of depth
+    /// `depth` with couplings implemented as in `coupling`. The average cost
+    /// is estimated from `nr_shots` runs of the circuit.
+    pub fn new(problem: &'a Ising, depth: usize, coupling: Coupling, nr_shots: usize)
+        -> crate::error::Result<Self>
+    {
+        let circuit = qaoa_circuit(problem, depth, coupling)?;
+        Ok(QAOA { problem: problem, depth: depth, circuit: circuit, nr_shots: nr_shots })
+    }
+
+    /// The QAOA circuit.
+    pub fn circuit(&self) -> &crate::circuit::Circuit
+    {
+        &self.circuit
+    }
+
+    /// Bind the angles in `params`, given as `[γ`<sub>`0`</sub>`, ...,
+    /// γ`<sub>`p-1`</sub>`, β`<sub>`0`</sub>`, ..., β`<sub>`p-1`</sub>`]`, to
+    /// the circuit.
+    fn bind(&mut self, params: &[f64])
+    {
+        let mut values = ::std::collections::HashMap::new();
+        for k in 0..self.depth
+        {
+            values.insert(format!("gamma_{}", k), params[k]);
+            values.insert(format!("beta_{}", k), params[self.depth + k]);
+        }
+        self.circuit.bind(&values);
+    }
+
+    /// Estimate the average cost.
+    ///
+    /// Estimate the average cost of the solutions produced by the QAOA circuit
+    /// for angles `gammas` and `betas`, using random number generator `rng`
+    /// for sampling. If the number of angles does not match the depth of the
+    /// circuit, an `InvalidNrBits` error is returned.
+    pub fn expected_cost<R: rand::RngCore>(&mut self, gammas: &[f64], betas: &[f64],
+        rng: &mut R) -> crate::error::Result<f64>
+    {
+        let params = self.join_angles(gammas, betas)?;
+        self.value(&params, &mut &mut *rng as &mut dyn rand::RngCore)
+    }
+
+    /// Combine the angles for the cost and mixing layers in a single vector.
+    fn join_angles(&self, gammas: &[f64], betas: &[f64]) -> crate::error::Result<Vec<f64>>
+    {
+        if gammas.len() != self.depth
+        {
+            Err(crate::error::Error::InvalidNrBits(gammas.len(), self.depth,
+                String::from("gamma")))
+        }
+        else if betas.len() != self.depth
+        {
+            Err(crate::error::Error::InvalidNrBits(betas.len(), self.depth,
+                String::from("beta")))
+        }
+        else
+        {
+            Ok(gammas.iter().chain(betas.iter()).cloned().collect())
+        }
+    }
+
+    /// Optimize the angles.
+    ///
+    /// Minimize the average cost using optimizer `optimizer`, starting from
+    /// angles `gammas` and `betas`. After optimization, the circuit is run
+    /// once more at the optimal angles, to find the best sampled solution.
+    pub fn minimize<O, R>(&mut self, optimizer: &mut O, gammas: &[f64], betas: &[f64],
+        rng: &mut R) -> crate::error::Result<QAOAResult>
+    where O: Optimizer + ?Sized, R: rand::RngCore
+    {
+        let x0 = self.join_angles(gammas, betas)?;
+        let res = optimizer.minimize(self, &x0, rng)?;
+
+        self.bind(&res.params);
+        self.circuit.execute_with_rng(self.nr_shots, rng)?;
+        let best = self.problem.best_solution(&self.circuit.histogram()?);
+
+        Ok(QAOAResult
+        {
+            cost: res.value,
+            gammas: res.params[..self.depth].to_vec(),
+            betas: res.params[self.depth..].to_vec(),
+            history: res.history,
+            best: best
+        })
+    }
+}
+
+impl<'a> Objective for QAOA<'a>
+{
+    fn value(&mut self, params: &[f64], rng: &mut dyn rand::RngCore)
+        -> crate::error::Result<f64>
+    {
+        self.bind(params);
+        self.circuit.execute_with_rng(self.nr_shots, &mut &mut *rng)?;
+        Ok(self.problem.expected_cost(&self.circuit.histogram()?))
+    }
+
+    fn gradient(&mut self, params: &[f64], rng: &mut dyn rand::RngCore)
+        -> crate::error::Result<Vec<f64>>
+    {
+        self.bind(params);
+        let problem = self.problem;
+        let grad = self.circuit.gradient_with_rng(self.nr_shots, |key| problem.cost(key),
+            &mut &mut *rng)?;
+        let gammas = (0..self.depth).map(|k| grad[&format!("gamma_{}", k)]);
+        let betas = (0..self.depth).map(|k| grad[&format!("beta_{}", k)]);
+        Ok(gammas.chain(betas).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{qaoa_circuit, Coupling, Ising, QAOA};
+    use crate::algorithms::optimize::{GradientDescent, NelderMead};
+    use crate::error::Error;
+
+    fn rng() -> rand_hc::Hc128Rng
+    {
+        <rand_hc::Hc128Rng as rand_core::SeedableRng>::seed_from_u64(0xdeadbeef)
+    }
+
+    #[test]
+    fn test_maxcut_cost()
+    {
+        // Triangle with a pendant node
+        let problem = Ising::maxcut(4, &[(0, 1, 1.0), (1, 2, 1.0), (0, 2, 1.0), (2, 3, 2.0)])
+            .unwrap();
+        assert_eq!(problem.cost(0b0000), 0.0);
+        assert_eq!(problem.cost(0b1111), 0.0);
+        assert_eq!(problem.cost(0b0001), -2.0);
+        assert_eq!(problem.cost(0b0100), -4.0);
+        assert_eq!(problem.cost(0b1011), -4.0);
+        assert_eq!(problem.cost(0b1001), -4.0);
+
+        let mut hist = crate::idhash::new_u64_hash_map();
+        hist.insert(0b0000, 2);
+        hist.insert(0b0001, 1);
+        hist.insert(0b0100, 1);
+        assert_eq!(problem.expected_cost(&hist), -1.5);
+        assert_eq!(problem.best_solution(&hist), Some((0b0100, -4.0)));
+
+        assert_eq!(Ising::maxcut(2, &[(0, 2, 1.0)]), Err(Error::InvalidQBit(2)));
+        assert_eq!(Ising::maxcut(2, &[(1, 1, 1.0)]), Err(Error::InvalidQBit(1)));
+    }
+
+    #[test]
+    fn test_qubo_cost()
+    {
+        let q = array![
+            [-1.0,  2.0, 0.0],
+            [ 0.0, -1.0, 1.5],
+            [-0.5,  0.0, 3.0]
+        ];
+        let problem = Ising::from_qubo(&q).unwrap();
+        for key in 0..8
+        {
+            let x: Vec<f64> = (0..3).map(|i| ((key >> i) & 1) as f64).collect();
+            let mut expected = 0.0;
+            for i in 0..3
+            {
+                for j in 0..3
+                {
+                    expected += x[i] * q[[i, j]] * x[j];
+                }
+            }
+            assert!((problem.cost(key) - expected).abs() < 1.0e-12);
+        }
+
+        assert_eq!(Ising::from_qubo(&ndarray::Array2::zeros((2, 3))),
+            Err(Error::InvalidMatrixSize(2, 3)));
+    }
+
+    #[test]
+    fn test_circuit()
+    {
+        let problem = Ising::new(2, 0.0, vec![0.5, 0.0], vec![(0, 1, -1.0)]).unwrap();
+        let circuit = qaoa_circuit(&problem, 1, Coupling::CXRZ).unwrap();
+        assert_eq!(circuit.parameters(),
+            vec![String::from("gamma_0"), String::from("beta_0")]);
+        assert_eq!(circuit.latex(), Ok(String::from(
+r#"\Qcircuit @C=1em @R=.7em {
+    \lstick{\ket{0}} & \gate{H} & \gate{RZ(gamma_0)} & \ctrl{1} & \qw & \ctrl{1} & \gate{RX(2*beta_0)} & \meter & \qw & \qw \\
+    \lstick{\ket{0}} & \gate{H} & \qw & \targ & \gate{RZ(-2*gamma_0)} & \targ & \gate{RX(2*beta_0)} & \qw & \meter & \qw \\
+    \lstick{0} & \cw & \cw & \cw & \cw & \cw & \cw & \cw \cwx[-2] & \cw & \cw \\
+    \lstick{0} & \cw & \cw & \cw & \cw & \cw & \cw & \cw & \cw \cwx[-2] & \cw \\
+}
+"#)));
+
+        let mut circuit = qaoa_circuit(&problem, 2, Coupling::ZZ).unwrap();
+        assert_eq!(circuit.parameters(), vec![String::from("gamma_0"), String::from("beta_0"),
+            String::from("gamma_1"), String::from("beta_1")]);
+        let values = [("gamma_0", 0.3), ("beta_0", 0.2), ("gamma_1", 0.6), ("beta_1", 0.1)]
+            .iter()
+            .map(|&(n, v)| (String::from(n), v))
+            .collect();
+        circuit.bind(&values);
+        assert!(circuit.open_qasm().is_ok());
+    }
+
+    #[test]
+    fn test_coupling()
+    {
+        // Both implementations of the coupling should produce the same distribution
+        let problem = Ising::maxcut(3, &[(0, 1, 1.0), (1, 2, 0.5)]).unwrap();
+        let nr_shots = 20000;
+        let mut rng = rng();
+        let mut cxrz = QAOA::new(&problem, 1, Coupling::CXRZ, nr_shots).unwrap();
+        let mut zz = QAOA::new(&problem, 1, Coupling::ZZ, nr_shots).unwrap();
+        let c0 = cxrz.expected_cost(&[0.7], &[0.4], &mut rng).unwrap();
+        let c1 = zz.expected_cost(&[0.7], &[0.4], &mut rng).unwrap();
+        assert!((c0 - c1).abs() < 0.05, "{} != {}", c0, c1);
+        // Uniform superposition for γ = β = 0
+        let c = cxrz.expected_cost(&[0.0], &[0.0], &mut rng).unwrap();
+        assert!((c + 0.75).abs() < 0.05);
+
+        assert!(matches!(cxrz.expected_cost(&[0.0, 1.0], &[0.0], &mut rng),
+            Err(Error::InvalidNrBits(2, 1, _))));
+    }
+
+    #[test]
+    fn test_qaoa_maxcut()
+    {
+        // Square graph, the maximum cut (0101 or 1010) cuts all four edges
+        let problem = Ising::maxcut(4, &[(0, 1, 1.0), (1, 2, 1.0), (2, 3, 1.0), (3, 0, 1.0)])
+            .unwrap();
+        let mut rng = rng();
+
+        let mut qaoa = QAOA::new(&problem, 1, Coupling::CXRZ, 1000).unwrap();
+        let res = qaoa.minimize(&mut NelderMead::new(40).with_initial_step(0.3), &[0.5], &[0.5],
+            &mut rng).unwrap();
+        // For p = 1, the optimal expected cut on a ring is 3/4 of the edges
+        assert!(res.cost < -2.7, "{}", res.cost);
+        assert_eq!(res.gammas.len(), 1);
+        assert_eq!(res.betas.len(), 1);
+        let (key, cost) = res.best.unwrap();
+        assert!(key == 0b0101 || key == 0b1010);
+        assert_eq!(cost, -4.0);
+
+        let res = qaoa.minimize(&mut GradientDescent::new(20, 0.1), &[0.5], &[0.5], &mut rng)
+            .unwrap();
+        assert!(res.cost < -2.7, "{}", res.cost);
+    }
+}
@@ -0,0 +1,588 @@
+// Copyright 2019 Q1t BV
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Shor's factoring algorithm.
+
+use crate::gates::Gate;
+
+/// Controlled modular multiplication
+///
+/// Gate multiplying the value `x` in a register of `n` qubits in place by a
+/// constant, modulo a modulus, when a control bit is set. The gate operates
+/// on the control bit, the `n` bits of `x` (most significant bit first), and
+/// `n+2` ancilla bits. When applied to a state, the gate directly permutes
+/// the basis states, leaving the ancillas untouched; when exported, it is
+/// expanded into the controlled modular multiplier from `gates::arith`,
+/// which uses the ancillas.
+struct ControlledMultiplier
+{
+    /// The number of bits in the register
+    nr_bits: usize,
+    /// The constant to multiply with
+    value: u64,
+    /// The modulus
+    modulus: u64,
+    /// The circuit implementing this gate
+    composite: crate::gates::Composite
+}
+
+impl ControlledMultiplier
+{
+    /// Create a new controlled multiplication of a `nr_bits`-bit number by
+    /// `value`, modulo `modulus`.
+    fn new(nr_bits: usize, value: u64, modulus: u64) -> crate::error::Result<Self>
+    {
+        let composite = crate::gates::arith::controlled_modular_multiplier(nr_bits,
+            value, modulus)?;
+        Ok(ControlledMultiplier
+        {
+            nr_bits: nr_bits,
+            value: value % modulus,
+            modulus: modulus,
+            composite: composite
+        })
+    }
+
+    /// The basis state to which `x` is mapped.
+    fn product(&self, x: u64) -> u64
+    {
+        if x < self.modulus
+        {
+            ((x as u128 * self.value as u128) % self.modulus as u128) as u64
+        }
+        else
+        {
+            x
+        }
+    }
+}
+
+impl crate::gates::Gate for ControlledMultiplier
+{
+    fn cost(&self) -> f64
+    {
+        self.composite.cost()
+    }
+
+    fn description(&self) -> &str
+    {
+        self.composite.description()
+    }
+
+    fn nr_affected_bits(&self) -> usize
+    {
+        self.composite.nr_affected_bits()
+    }
+
+    fn matrix(&self) -> crate::cmatrix::CMatrix
+    {
+        let mut res = crate::cmatrix::CMatrix::eye(1 << self.nr_affected_bits());
+        self.apply_mat_slice(res.view_mut());
+        res
+    }
+
+    fn apply_slice(&self, state: crate::cmatrix::CVecSliceMut)
+    {
+        self.apply_mat_slice(state.insert_axis(ndarray::Axis(1)));
+    }
+
+    fn apply_mat_slice(&self, mut state: crate::cmatrix::CMatSliceMut)
+    {
+        let half = state.rows() / 2;
+        let block_size = half >> self.nr_bits;
+        let mut active = state.slice_mut(s![half.., ..]);
+        let orig = active.to_owned();
+        for x in 0..(1 << self.nr_bits)
+        {
+            let y = self.product(x) as usize;
+            if y != x as usize
+            {
+                let src = x as usize * block_size;
+                let dst = y * block_size;
+                active.slice_mut(s![dst..dst+block_size, ..])
+                    .assign(&orig.slice(s![src..src+block_size, ..]));
+            }
+        }
+    }
+}
+
+impl crate::export::OpenQasm for ControlledMultiplier
+{
+    fn open_qasm(&self, bit_names: &[String], bits: &[usize])
+        -> crate::error::Result<String>
+    {
+        self.composite.open_qasm(bit_names, bits)
+    }
+
+    fn conditional_open_qasm(&self, condition: &str, bit_names: &[String],
+        bits: &[usize]) -> crate::error::Result<String>
+    {
+        self.composite.conditional_open_qasm(condition, bit_names, bits)
+    }
+}
+
+impl crate::export::CQasm for ControlledMultiplier
+{
+    fn c_qasm(&self, bit_names: &[String], bits: &[usize])
+        -> crate::error::Result<String>
+    {
+        self.composite.c_qasm(bit_names, bits)
+    }
+
+    fn conditional_c_qasm(&self, condition: &str, bit_names: &[String],
+        bits: &[usize]) -> crate::error::Result<String>
+    {
+        self.composite.conditional_c_qasm(condition, bit_names, bits)
+    }
+}
+
+impl crate::export::Latex for ControlledMultiplier
+{
+    fn latex(&self, bits: &[usize], state: &mut crate::export::LatexExportState)
+        -> crate::error::Result<()>
+    {
+        self.check_nr_bits(bits)?;
+        state.add_block_gate(bits, &format!(r"\times {}", self.value))
+    }
+}
+
+/// Greatest common divisor of `a` and `b`.
+fn gcd(a: u64, b: u64) -> u64
+{
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// Compute `base`<sup>`exp`</sup> modulo `modulus`.
+fn pow_mod(base: u64, mut exp: u64, modulus: u64) -> u64
+{
+    let modulus = modulus as u128;
+    let mut base = base as u128 % modulus;
+    let mut res = 1 % modulus;
+    while exp > 0
+    {
+        if exp & 1 == 1
+        {
+            res = (res * base) % modulus;
+        }
+        base = (base * base) % modulus;
+        exp >>= 1;
+    }
+    res as u64
+}
+
+/// Continued fraction convergents.
+///
+/// Return the numerators and denominators of the convergents of the continued
+/// fraction expansion of `num/den`.
+pub fn convergents(num: u64, den: u64) -> Vec<(u64, u64)>
+{
+    let mut res = vec![];
+    let (mut num, mut den) = (num as u128, den as u128);
+    let (mut p0, mut p1) = (0u128, 1u128);
+    let (mut q0, mut q1) = (1u128, 0u128);
+    while den != 0
+    {
+        let a = num / den;
+        let (p, q) = (a*p1 + p0, a*q1 + q0);
+        res.push((p as u64, q as u64));
+        p0 = p1;
+        p1 = p;
+        q0 = q1;
+        q1 = q;
+        let rem = num - a*den;
+        num = den;
+        den = rem;
+    }
+    res
+}
+
+/// The number of bits needed to store numbers below `modulus`.
+fn nr_bits_for(modulus: u64) -> usize
+{
+    (64 - (modulus - 1).leading_zeros()) as usize
+}
+
+/// Build an order finding circuit.
+///
+/// Create a circuit estimating the order of `base` modulo `modulus`, i.e. the
+/// smallest positive integer `r` such that `base`<sup>`r`</sup>` = 1 (mod modulus)`.
+/// The circuit performs phase estimation on the modular multiplication by
+/// `base`, with `2n` bits of precision, where `n` is the number of bits
+/// needed to store `modulus`. Instead of a register of counting qubits, a
+/// single control qubit is used, that is measured and reset after every
+/// controlled multiplication, with the inverse quantum Fourier transform
+/// performed using classically controlled phase gates. Bit `k` of the phase
+/// estimate is measured into classical bit `k`.
+///
+/// The first qubit of the circuit is the control qubit, the next `n` hold the
+/// work register, initialized to 1, and the last `n+2` are ancillas for the
+/// modular multiplications. Since the phase corrections are conditioned on
+/// single classical bits, the circuit cannot be exported to OpenQasm. Returns
+/// an `InvalidModulus` error if `modulus < 3`, or a `NotInvertible` error if
+/// `base` and `modulus` are not coprime.
+pub fn order_finding_circuit(base: u64, modulus: u64)
+    -> crate::error::Result<crate::circuit::Circuit>
+{
+    build_order_finding_circuit(base, modulus, ControlledMultiplier::new)
+}
+
+/// Build an order finding circuit.
+///
+/// Create the order finding circuit described in `order_finding_circuit()`,
+/// using `multiplier(n, factor, modulus)` to create the gate for the
+/// controlled multiplication of an `n`-bit number by `factor`.
+fn build_order_finding_circuit<G, F>(base: u64, modulus: u64, multiplier: F)
+    -> crate::error::Result<crate::circuit::Circuit>
+where G: 'static + crate::export::CircuitGate, F: Fn(usize, u64, u64) -> crate::error::Result<G>
+{
+    let nr_bits = if modulus > 0 { nr_bits_for(modulus) } else { 0 };
+    if modulus < 3
+    {
+        return Err(crate::error::Error::InvalidModulus(modulus, nr_bits));
+    }
+    if gcd(base, modulus) != 1
+    {
+        return Err(crate::error::Error::NotInvertible(base, modulus));
+    }
+
+    let nr_count = 2 * nr_bits;
+    let mut circuit = crate::circuit::Circuit::new(2*nr_bits + 3, nr_count)?;
+    let bits: Vec<usize> = (0..2*nr_bits+3).collect();
+
+    circuit.x(nr_bits)?;
+    for k in 0..nr_count
+    {
+        // Round k determines bit k of the phase, using the modular
+        // multiplication raised to the power 2^(t-1-k).
+        let factor = pow_mod(base, 1 << (nr_count - 1 - k), modulus);
+        circuit.h(0)?;
+        circuit.add_gate(multiplier(nr_bits, factor, modulus)?, &bits)?;
+        for j in 0..k
+        {
+            let angle = -2.0 * ::std::f64::consts::PI / (1u64 << (k - j + 1)) as f64;
+            circuit.add_conditional_gate(&[j], 1, crate::gates::U1::new(angle), &[0])?;
+        }
+        circuit.h(0)?;
+        circuit.measure(0, k)?;
+        circuit.reset(0)?;
+    }
+
+    Ok(circuit)
+}
+
+/// Find the order of a number.
+///
+/// Estimate the order of `base` modulo `modulus`, using `nr_shots` runs of
+/// the order finding circuit. The measured phases are converted to candidate
+/// orders using continued fractions, most frequently measured phase first.
+/// Since the denominator of a convergent may be a divisor of the order, small
+/// multiples of it are tried as well. The first candidate that is verified
+/// classically is returned, or `None` if the order is not found.
+pub fn find_order<R: rand::RngCore>(base: u64, modulus: u64, nr_shots: usize, rng: &mut R)
+    -> crate::error::Result<Option<u64>>
+{
+    let mut circuit = order_finding_circuit(base, modulus)?;
+    circuit.execute_with_rng(nr_shots, rng)?;
+    order_from_histogram(base, modulus, circuit.histogram()?)
+}
+
+/// Find the order of a number from measured phases.
+///
+/// Find the order of `base` modulo `modulus` from the histogram `hist` of
+/// phase estimates, as measured by the order finding circuit. See
+/// `find_order()` for details.
+fn order_from_histogram(base: u64, modulus: u64, hist: crate::idhash::U64HashMap<usize>)
+    -> crate::error::Result<Option<u64>>
+{
+    let nr_count = 2 * nr_bits_for(modulus);
+    let mut outcomes: Vec<(u64, usize)> = hist.into_iter().collect();
+    outcomes.sort_by(|&(y0, c0), &(y1, c1)| c1.cmp(&c0).then(y0.cmp(&y1)));
+    for (y, _) in outcomes.into_iter().filter(|&(y, _)| y != 0)
+    {
+        for (_, den) in convergents(y, 1 << nr_count)
+        {
+            if den < 2 || den >= modulus
+            {
+                continue;
+            }
+            let max_order = modulus.min(den * nr_count as u64 / 2);
+            let mut order = den;
+            while order <= max_order
+            {
+                if pow_mod(base, order, modulus) == 1
+                {
+                    return Ok(Some(order));
+                }
+                order += den;
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Result of a factorization
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShorResult
+{
+    /// The two non-trivial factors found, smallest first
+    pub factors: (u64, u64),
+    /// The base for which the order was found, if the factors were found
+    /// using order finding
+    pub base: Option<u64>,
+    /// The order of the base, if the factors were found using order finding
+    pub order: Option<u64>
+}
+
+impl ShorResult
+{
+    fn new(p: u64, q: u64, base: Option<u64>, order: Option<u64>) -> Self
+    {
+        ShorResult
+        {
+            factors: (p.min(q), p.max(q)),
+            base: base,
+            order: order
+        }
+    }
+}
+
+/// Factor a number.
+///
+/// Find two non-trivial factors of `number`, using Shor's algorithm. Even
+/// numbers and perfect powers are handled classically. Otherwise, random
+/// bases are tried: if a base happens to share a factor with `number`, this
+/// factor is returned, else the order `r` of the base is found using
+/// `nr_shots` runs of the order finding circuit. If `r` is even, and
+/// `base`<sup>`r/2`</sup>` ≠ -1 (mod number)`, the factors are found as
+/// the greatest common divisors of `base`<sup>`r/2`</sup>` ± 1` and `number`.
+/// After `max_attempts` bases have been tried without success, or if `number`
+/// is prime or smaller than 4, a `FactoringFailed` error is returned.
+pub fn factor<R: rand::Rng>(number: u64, nr_shots: usize, max_attempts: usize, rng: &mut R)
+    -> crate::error::Result<ShorResult>
+{
+    if number < 4 || (2..).take_while(|d| d * d <= number).all(|d| number % d != 0)
+    {
+        return Err(crate::error::Error::FactoringFailed(number));
+    }
+    if number % 2 == 0
+    {
+        return Ok(ShorResult::new(2, number / 2, None, None));
+    }
+    for exp in 2..64
+    {
+        let root = (number as f64).powf(1.0 / exp as f64).round() as u64;
+        if root < 2
+        {
+            break;
+        }
+        if (root as u128).pow(exp) == number as u128
+        {
+            return Ok(ShorResult::new(root, number / root, None, None));
+        }
+    }
+
+    for _ in 0..max_attempts
+    {
+        let base = rng.gen_range(2, number);
+        if let Some(result) = factor_with_base(number, base, nr_shots, rng)?
+        {
+            return Ok(result);
+        }
+    }
+
+    Err(crate::error::Error::FactoringFailed(number))
+}
+
+/// Factor a number using a given base.
+///
+/// Try to find two non-trivial factors of `number`, using the order of
+/// `base` modulo `number`, found using `nr_shots` runs of the order finding
+/// circuit. If `base` shares a factor with `number`, this factor is returned
+/// without order finding. Returns `None` if no factors were found, i.e. when
+/// the order was not found, when the order is odd, or when
+/// `base`<sup>`r/2`</sup>` = -1 (mod number)`.
+pub fn factor_with_base<R: rand::RngCore>(number: u64, base: u64, nr_shots: usize,
+    rng: &mut R) -> crate::error::Result<Option<ShorResult>>
+{
+    let divisor = gcd(base, number);
+    if divisor > 1
+    {
+        return Ok(if divisor < number
+            {
+                Some(ShorResult::new(divisor, number / divisor, Some(base), None))
+            }
+            else
+            {
+                None
+            });
+    }
+
+    if let Some(order) = find_order(base, number, nr_shots, rng)?
+    {
+        if order % 2 == 0
+        {
+            let half = pow_mod(base, order / 2, number);
+            if half != number - 1
+            {
+                for &candidate in [half - 1, half + 1].iter()
+                {
+                    let divisor = gcd(candidate, number);
+                    if divisor > 1 && divisor < number
+                    {
+                        return Ok(Some(ShorResult::new(divisor, number / divisor,
+                            Some(base), Some(order))));
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{build_order_finding_circuit, convergents, factor, factor_with_base, find_order,
+        gcd, order_finding_circuit, order_from_histogram, pow_mod, ControlledMultiplier,
+        ShorResult};
+    use crate::gates::Gate;
+
+    fn rng() -> rand_hc::Hc128Rng
+    {
+        <rand_hc::Hc128Rng as rand_core::SeedableRng>::seed_from_u64(0x5a0e_41d3)
+    }
+
+    #[test]
+    fn test_controlled_multiplier()
+    {
+        // The permutation should match the circuit on the valid inputs
+        let n = 2;
+        let modulus = 3;
+        let gate = ControlledMultiplier::new(n, 2, modulus).unwrap();
+        assert_eq!(gate.nr_affected_bits(), 2*n + 3);
+        assert_eq!(gate.description(), "CMUL(2) mod 3");
+        let bit_names: Vec<String> = (0..2*n+3).map(|i| format!("q[{}]", i)).collect();
+        let bits: Vec<usize> = (0..2*n+3).collect();
+        assert!(crate::export::OpenQasm::open_qasm(&gate, &bit_names, &bits).is_ok());
+
+        let mat = gate.matrix();
+        let expected = crate::gates::arith::controlled_modular_multiplier(n, 2, modulus)
+            .unwrap().matrix();
+        for c in 0..2
+        {
+            for x in 0..modulus as usize
+            {
+                let col = ((c << n) | x) << (n+2);
+                for row in 0..mat.rows()
+                {
+                    assert!((mat[[row, col]] - expected[[row, col]]).norm() < 1.0e-10);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_convergents()
+    {
+        assert_eq!(convergents(3, 8), vec![(0, 1), (1, 2), (1, 3), (3, 8)]);
+        assert_eq!(convergents(192, 256), vec![(0, 1), (1, 1), (3, 4)]);
+        assert_eq!(convergents(0, 256), vec![(0, 1)]);
+    }
+
+    #[test]
+    fn test_order_finding_circuit()
+    {
+        let circuit = order_finding_circuit(7, 15).unwrap();
+        assert_eq!(circuit.nr_qbits(), 11);
+        assert_eq!(circuit.nr_cbits(), 8);
+        assert!(circuit.latex().is_ok());
+        // The phase corrections are conditioned on single classical bits,
+        // which cannot be expressed in OpenQasm
+        assert!(matches!(circuit.open_qasm(), Err(crate::error::Error::ExportError(
+            crate::error::ExportError::IncompleteConditionRegister))));
+
+        assert!(matches!(order_finding_circuit(2, 2),
+            Err(crate::error::Error::InvalidModulus(2, _))));
+        assert!(matches!(order_finding_circuit(6, 15),
+            Err(crate::error::Error::NotInvertible(6, 15))));
+        assert!(matches!(find_order(5, 35, 16, &mut rng()),
+            Err(crate::error::Error::NotInvertible(5, 35))));
+    }
+
+    #[test]
+    fn test_find_order()
+    {
+        let mut rng = rng();
+        assert_eq!(find_order(7, 15, 64, &mut rng), Ok(Some(4)));
+        assert_eq!(find_order(4, 15, 64, &mut rng), Ok(Some(2)));
+        assert_eq!(find_order(2, 21, 64, &mut rng), Ok(Some(6)));
+    }
+
+    #[test]
+    fn test_factor_with_composite()
+    {
+        // Factor 15 using the circuit that is exported, rather than the
+        // permutation used when simulating
+        let mut rng = rng();
+        let (number, base) = (15, 7);
+        let mut circuit = build_order_finding_circuit(base, number,
+            crate::gates::arith::controlled_modular_multiplier).unwrap();
+        circuit.execute_with_rng(16, &mut rng).unwrap();
+        let order = order_from_histogram(base, number, circuit.histogram().unwrap()).unwrap();
+        assert_eq!(order, Some(4));
+
+        let half = pow_mod(base, 2, number);
+        assert_eq!((gcd(half - 1, number), gcd(half + 1, number)), (3, 5));
+    }
+
+    #[test]
+    fn test_factor()
+    {
+        let mut rng = rng();
+        for &(number, p, q) in [(15, 3, 5), (21, 3, 7), (35, 5, 7)].iter()
+        {
+            let result = factor(number, 32, 10, &mut rng).unwrap();
+            assert_eq!(result.factors, (p, q));
+        }
+
+        assert_eq!(factor(22, 32, 10, &mut rng).map(|r| r.factors), Ok((2, 11)));
+        assert_eq!(factor(49, 32, 10, &mut rng).map(|r| r.factors), Ok((7, 7)));
+        assert!(matches!(factor(13, 32, 10, &mut rng),
+            Err(crate::error::Error::FactoringFailed(13))));
+    }
+
+    #[test]
+    fn test_factor_with_base()
+    {
+        // Make sure the quantum part of the algorithm is exercised
+        let mut rng = rng();
+        for &(number, base, p, q, order) in [(15, 7, 3, 5, 4), (21, 2, 3, 7, 6), (35, 2, 5, 7, 12)].iter()
+        {
+            assert_eq!(factor_with_base(number, base, 32, &mut rng), Ok(Some(ShorResult
+            {
+                factors: (p, q),
+                base: Some(base),
+                order: Some(order)
+            })));
+        }
+
+        // Shared factor
+        assert_eq!(factor_with_base(15, 6, 32, &mut rng).map(|r| r.map(|r| r.factors)),
+            Ok(Some((3, 5))));
+        // Order 2, but 14 ≡ -1 mod 15
+        assert_eq!(factor_with_base(15, 14, 32, &mut rng), Ok(None));
+    }
+}
@@ -0,0 +1,399 @@
+// Copyright 2019 Q1t BV
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Hamiltonian simulation using Trotter-Suzuki product formulas.
+
+/// Add a Pauli rotation.
+///
+/// Append the rotation `exp(-iθP/2)` over angle `angle` for Pauli string
+/// `string` to composite gate `composite`. The qubits in the support of the
+/// string are rotated to the `Z` basis (using `H` for `X`, and
+/// `R`<sub>`X`</sub>`(π/2)` for `Y`), after which the parity of these
+/// qubits is computed into the last of them using a ladder of `CX` gates,
+/// and rotated using a `R`<sub>`Z`</sub> gate. Finally, the parity
+/// computation and basis changes are undone. Nothing is added for the
+/// identity string, which only adds a global phase.
+pub fn add_pauli_rotation(composite: &mut crate::gates::Composite,
+    string: &crate::pauli::PauliString, angle: f64)
+{
+    crate::gates::add_rotation_gadget(composite, string, angle);
+}
+
+/// Compute the exponential of a matrix.
+///
+/// Compute `exp(A)` for square matrix `a` by scaling and squaring: the
+/// matrix is scaled by a power of two such that its norm is at most one half,
+/// the exponential of the scaled matrix is computed from its Taylor series,
+/// and the result is squared repeatedly.
+fn expm(a: &crate::cmatrix::CMatrix) -> crate::cmatrix::CMatrix
+{
+    let n = a.rows();
+    let norm = a.genrows().into_iter()
+        .map(|row| row.iter().map(|x| x.norm()).sum::<f64>())
+        .fold(0.0, f64::max);
+    let mut nr_squarings = 0;
+    while norm > 0.5 * (1u64 << nr_squarings) as f64
+    {
+        nr_squarings += 1;
+    }
+    let scaled = a * crate::cmatrix::COMPLEX_ONE / (1u64 << nr_squarings) as f64;
+
+    let mut res = crate::cmatrix::CMatrix::eye(n);
+    let mut term = crate::cmatrix::CMatrix::eye(n);
+    for k in 1..30
+    {
+        term = term.dot(&scaled) / k as f64;
+        res += &term;
+        if term.iter().all(|x| x.norm() < 1.0e-17)
+        {
+            break;
+        }
+    }
+
+    for _ in 0..nr_squarings
+    {
+        res = res.dot(&res);
+    }
+
+    res
+}
+
+/// Compute the exact time evolution operator.
+///
+/// Compute the `2`<sup>`n`</sup>`×2`<sup>`n`</sup> matrix `exp(-iHt)`
+/// of the time evolution for time `time` under Hamiltonian `hamiltonian`,
+/// acting on `n` qubits, from the full matrix of the Hamiltonian. Use this
+/// only for small numbers of qubits.
+pub fn exact_evolution(hamiltonian: &crate::pauli::PauliSum, time: f64)
+    -> crate::cmatrix::CMatrix
+{
+    expm(&(hamiltonian.matrix() * (-crate::cmatrix::COMPLEX_I * time)))
+}
+
+/// Order of the product formula
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrotterOrder
+{
+    /// First order formula `∏`<sub>`k`</sub>`exp(-ic`<sub>`k`</sub>`P`<sub>`k`</sub>`Δt)`,
+    /// with an error of order `Δt`<sup>`2`</sup> per step
+    First,
+    /// Symmetric second order formula, applying the first order formula for
+    /// half a time step, followed by the same terms in reverse order for the
+    /// other half. The error is of order `Δt`<sup>`3`</sup> per step.
+    Second
+}
+
+/// Trotter-Suzuki product formula
+///
+/// Struct Trotter builds gates approximating the time evolution operator
+/// `exp(-iHt)` for a Hamiltonian `H = ∑`<sub>`k`</sub>`c`<sub>`k`</sub>`P`<sub>`k`</sub>,
+/// given as a sum of Pauli strings. The evolution time is divided into a
+/// number of equal steps, and in each step the evolution under the separate
+/// terms is applied in turn, using the Pauli rotations from
+/// `add_pauli_rotation()`. Identity terms in the Hamiltonian are left out,
+/// so that the gates implement the evolution up to a global phase
+/// `exp(-ict)`, where `c` is the sum of the coefficients of the identity
+/// terms.
+#[derive(Clone, Debug)]
+pub struct Trotter
+{
+    /// The Hamiltonian
+    hamiltonian: crate::pauli::PauliSum,
+    /// The total evolution time
+    time: f64,
+    /// The number of time steps
+    nr_steps: usize,
+    /// The order of the product formula
+    order: TrotterOrder
+}
+
+impl Trotter
+{
+    /// Create a new product formula.
+    ///
+    /// Create a new first order product formula for the evolution under
+    /// Hamiltonian `hamiltonian` for time `time`, in `nr_steps` equal time
+    /// steps. If `nr_steps` is zero, an `InvalidNrSteps` error is returned.
+    pub fn new(hamiltonian: &crate::pauli::PauliSum, time: f64, nr_steps: usize)
+        -> crate::error::Result<Self>
+    {
+        if nr_steps == 0
+        {
+            Err(crate::error::Error::InvalidNrSteps(nr_steps))
+        }
+        else
+        {
+            Ok(Trotter
+            {
+                hamiltonian: hamiltonian.clone(),
+                time: time,
+                nr_steps: nr_steps,
+                order: TrotterOrder::First
+            })
+        }
+    }
+
+    /// Set the order of the product formula.
+    pub fn with_order(mut self, order: TrotterOrder) -> Self
+    {
+        self.order = order;
+        self
+    }
+
+    /// The number of qubits the evolution acts on
+    pub fn nr_bits(&self) -> usize
+    {
+        self.hamiltonian.nr_bits()
+    }
+
+    /// The number of time steps
+    pub fn nr_steps(&self) -> usize
+    {
+        self.nr_steps
+    }
+
+    /// The order of the product formula
+    pub fn order(&self) -> TrotterOrder
+    {
+        self.order
+    }
+
+    /// Add the rotations for a single time step to composite gate `composite`.
+    fn add_step(&self, composite: &mut crate::gates::Composite)
+    {
+        let dt = self.time / self.nr_steps as f64;
+        let terms: Vec<&(f64, crate::pauli::PauliString)> = self.hamiltonian.terms().iter()
+            .filter(|(coef, string)| *coef != 0.0 && !string.is_identity())
+            .collect();
+
+        match self.order
+        {
+            TrotterOrder::First => {
+                for &&(coef, ref string) in terms.iter()
+                {
+                    add_pauli_rotation(composite, string, 2.0 * coef * dt);
+                }
+            },
+            TrotterOrder::Second => {
+                if let Some((&&(coef, ref string), rest)) = terms.split_last()
+                {
+                    // The two half steps for the last term are merged
+                    for &&(coef, ref string) in rest.iter()
+                    {
+                        add_pauli_rotation(composite, string, coef * dt);
+                    }
+                    add_pauli_rotation(composite, string, 2.0 * coef * dt);
+                    for &&(coef, ref string) in rest.iter().rev()
+                    {
+                        add_pauli_rotation(composite, string, coef * dt);
+                    }
+                }
+            }
+        }
+    }
+
+    /// A single time step.
+    ///
+    /// Return a composite gate named `trotter_step` implementing a single
+    /// step of the product formula.
+    pub fn step(&self) -> crate::gates::Composite
+    {
+        let mut composite = crate::gates::Composite::new("trotter_step", self.nr_bits());
+        self.add_step(&mut composite);
+        composite
+    }
+
+    /// The full evolution, as a composite gate.
+    ///
+    /// Return a composite gate named `trotter`, in which the rotations for
+    /// all time steps are written out.
+    pub fn composite(&self) -> crate::gates::Composite
+    {
+        let mut composite = crate::gates::Composite::new("trotter", self.nr_bits());
+        for _ in 0..self.nr_steps
+        {
+            self.add_step(&mut composite);
+        }
+        composite
+    }
+
+    /// The full evolution, as a static loop.
+    ///
+    /// Return a static loop with label `label`, that repeats the gate from
+    /// `step()` for every time step.
+    pub fn static_loop(&self, label: &str) -> crate::gates::Loop
+    {
+        crate::gates::Loop::new(label, self.nr_steps, self.step())
+    }
+
+    /// The exact time evolution operator.
+    ///
+    /// Return the matrix of the exact evolution `exp(-iHt)`, computed by
+    /// `exact_evolution()`.
+    pub fn exact(&self) -> crate::cmatrix::CMatrix
+    {
+        exact_evolution(&self.hamiltonian, self.time)
+    }
+
+    /// The error of the product formula.
+    ///
+    /// Compute the Frobenius norm of the difference between the matrix of the
+    /// product formula, corrected for the global phase of the identity terms,
+    /// and the exact evolution operator. This is an upper bound for the
+    /// operator norm of the difference. Use this only for small numbers of
+    /// qubits.
+    pub fn error(&self) -> f64
+    {
+        use crate::gates::Gate;
+
+        let phase = num_complex::Complex::from_polar(&1.0, &(-self.hamiltonian.constant() * self.time));
+        let diff = self.composite().matrix() * phase - self.exact();
+        diff.iter().map(|x| x.norm_sqr()).sum::<f64>().sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{add_pauli_rotation, exact_evolution, Trotter, TrotterOrder};
+    use crate::error::Error;
+    use crate::gates::{Composite, Gate};
+    use crate::pauli::{PauliString, PauliSum};
+
+    fn max_diff(a: &crate::cmatrix::CMatrix, b: &crate::cmatrix::CMatrix) -> f64
+    {
+        (a - b).iter().map(|x| x.norm()).fold(0.0, f64::max)
+    }
+
+    fn heisenberg() -> PauliSum
+    {
+        PauliSum::from_terms(3, &[
+            (1.0, "XXI"), (1.0, "YYI"), (1.0, "ZZI"),
+            (0.5, "IXX"), (0.5, "IYY"), (0.5, "IZZ"),
+            (0.3, "ZII"), (-0.2, "IIY")
+        ]).unwrap()
+    }
+
+    #[test]
+    fn test_pauli_rotation()
+    {
+        let angle = 0.7;
+        for &desc in ["ZII", "IXI", "IIY", "XYZ", "YIX", "ZIZ"].iter()
+        {
+            let string = PauliString::parse(desc).unwrap();
+            let mut composite = Composite::new("rot", 3);
+            add_pauli_rotation(&mut composite, &string, angle);
+            let expected = exact_evolution(&PauliSum::from_terms(3, &[(0.5 * angle, desc)]).unwrap(), 1.0);
+            assert_complex_matrix_eq!(&composite.matrix(), &expected);
+        }
+
+        let mut composite = Composite::new("rot", 2);
+        add_pauli_rotation(&mut composite, &PauliString::parse("II").unwrap(), angle);
+        assert_complex_matrix_eq!(&composite.matrix(), &crate::cmatrix::CMatrix::eye(4));
+    }
+
+    #[test]
+    fn test_exact_evolution()
+    {
+        let z = crate::cmatrix::COMPLEX_ZERO;
+        let t = 2.5;
+        let h = PauliSum::from_terms(1, &[(0.5, "X"), (1.5, "I")]).unwrap();
+        let c = num_complex::Complex::new((0.5f64 * t).cos(), 0.0);
+        let s = num_complex::Complex::new(0.0, -(0.5f64 * t).sin());
+        let p = num_complex::Complex::from_polar(&1.0, &(-1.5 * t));
+        assert_complex_matrix_eq!(&exact_evolution(&h, t), &(array![[c, s], [s, c]] * p));
+
+        let h = PauliSum::from_terms(1, &[(-3.0, "Z")]).unwrap();
+        let p = num_complex::Complex::from_polar(&1.0, &(3.0 * t));
+        assert_complex_matrix_eq!(&exact_evolution(&h, t), &array![[p, z], [z, p.conj()]]);
+    }
+
+    #[test]
+    fn test_commuting()
+    {
+        // For commuting terms, the product formula is exact
+        let h = PauliSum::from_terms(3, &[(0.4, "ZZI"), (-1.1, "IZZ"), (0.7, "ZIZ"), (0.3, "XXX"),
+            (2.0, "III")]).unwrap();
+        for &order in [TrotterOrder::First, TrotterOrder::Second].iter()
+        {
+            let trotter = Trotter::new(&h, 1.3, 1).unwrap().with_order(order);
+            assert!(trotter.error() < 1.0e-10);
+        }
+    }
+
+    #[test]
+    fn test_error_scaling()
+    {
+        let h = heisenberg();
+        let t = 1.0;
+
+        // First order: error decreases linearly with the number of steps
+        let e1 = Trotter::new(&h, t, 8).unwrap().error();
+        let e2 = Trotter::new(&h, t, 16).unwrap().error();
+        assert!(e1 < 0.3);
+        assert!((e1 / e2 - 2.0).abs() < 0.2);
+
+        // Second order: error decreases quadratically
+        let e1 = Trotter::new(&h, t, 8).unwrap().with_order(TrotterOrder::Second).error();
+        let e2 = Trotter::new(&h, t, 16).unwrap().with_order(TrotterOrder::Second).error();
+        assert!(e1 < 0.02);
+        assert!((e1 / e2 - 4.0).abs() < 0.4);
+    }
+
+    #[test]
+    fn test_loop()
+    {
+        let h = heisenberg();
+        for &order in [TrotterOrder::First, TrotterOrder::Second].iter()
+        {
+            let trotter = Trotter::new(&h, 0.8, 5).unwrap().with_order(order);
+            assert_eq!(trotter.nr_bits(), 3);
+            assert_eq!(trotter.nr_steps(), 5);
+            assert_eq!(trotter.order(), order);
+
+            let lp = trotter.static_loop("evolve");
+            assert_eq!(lp.nr_affected_bits(), 3);
+            assert!(max_diff(&lp.matrix(), &trotter.composite().matrix()) < 1.0e-12);
+
+            let step = trotter.step().matrix();
+            let mut expected = crate::cmatrix::CMatrix::eye(8);
+            for _ in 0..5
+            {
+                expected = step.dot(&expected);
+            }
+            assert!(max_diff(&trotter.composite().matrix(), &expected) < 1.0e-12);
+        }
+    }
+
+    #[test]
+    fn test_export()
+    {
+        let h = PauliSum::from_terms(2, &[(0.5, "XY")]).unwrap();
+        let trotter = Trotter::new(&h, 1.0, 2).unwrap();
+        let mut circuit = crate::circuit::Circuit::new(2, 0).unwrap();
+        circuit.add_gate(trotter.static_loop("evolve"), &[0, 1]).unwrap();
+        let qasm = circuit.open_qasm().unwrap();
+        assert!(qasm.contains("h q[0]"));
+        assert!(qasm.contains("rx(1.5707963267948966) q[1]"));
+        assert!(qasm.contains("cx q[0], q[1]"));
+        assert!(qasm.contains("rz(0.5) q[1]"));
+    }
+
+    #[test]
+    fn test_errors()
+    {
+        let h = heisenberg();
+        assert!(matches!(Trotter::new(&h, 1.0, 0), Err(Error::InvalidNrSteps(0))));
+    }
+}
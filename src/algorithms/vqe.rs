@@ -0,0 +1,345 @@
+// Copyright 2019 Q1t BV
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Variational quantum eigensolver.
+
+use crate::algorithms::optimize::{Objective, Optimizer};
+
+/// Append measurements.
+///
+/// Append measurements of all qubits in `basis` to circuit `circuit`, in the
+/// basis given by the Pauli operator for the qubit. Qubit `i` is measured
+/// into classical bit `i`.
+pub(crate) fn add_measurements(circuit: &mut crate::circuit::Circuit,
+    basis: &crate::pauli::PauliString) -> crate::error::Result<()>
+{
+    for (bit, &op) in basis.ops().iter().enumerate()
+    {
+        match op
+        {
+            crate::pauli::Pauli::I => { },
+            crate::pauli::Pauli::X => { circuit.measure_x(bit, bit)?; },
+            crate::pauli::Pauli::Y => { circuit.measure_y(bit, bit)?; },
+            crate::pauli::Pauli::Z => { circuit.measure_z(bit, bit)?; }
+        }
+    }
+    Ok(())
+}
+
+/// Result of a variational quantum eigensolver run
+#[derive(Clone, Debug)]
+pub struct VQEResult
+{
+    /// The lowest energy found
+    pub energy: f64,
+    /// The parameter values for which this energy was found
+    pub params: ::std::collections::HashMap<String, f64>,
+    /// The best energy after each iteration of the optimizer
+    pub history: Vec<f64>
+}
+
+/// Variational quantum eigensolver
+///
+/// Struct VQE estimates the ground state energy of a Hamiltonian, given as
+/// a sum of Pauli strings, by minimizing the energy of the state prepared by
+/// a parametrized ansatz circuit. The energy is estimated from measurements:
+/// the terms in the Hamiltonian are divided into groups of qubit-wise
+/// commuting terms, and for each group the ansatz circuit is executed with
+/// measurements in the appropriate basis appended.
+///
+/// The ansatz should contain only gates, and have at least as many classical
+/// bits as quantum bits; measurement of qubit `i` is done into classical bit
+/// `i`. The free parameters of the ansatz are the parameters to be optimized.
+pub struct VQE<'a>
+{
+    /// The circuit preparing the trial state
+    ansatz: &'a mut crate::circuit::Circuit,
+    /// The Hamiltonian
+    hamiltonian: &'a crate::pauli::PauliSum,
+    /// The names of the parameters of the ansatz
+    param_names: Vec<String>,
+    /// Groups of simultaneously measurable terms in the Hamiltonian
+    groups: Vec<(crate::pauli::PauliString, Vec<usize>)>,
+    /// The number of runs for each energy estimate
+    nr_shots: usize
+}
+
+impl<'a> VQE<'a>
+{
+    /// Create a new eigensolver.
+    ///
+    /// Create a new eigensolver that estimates the ground state energy of
+    /// `hamiltonian`, using trial states prepared by circuit `ansatz`. Each
+    /// measurement basis is sampled using `nr_shots` runs of the circuit. If
+    /// the number of qubits in `ansatz` and `hamiltonian` differ, an
+    /// `InvalidNrBits` error is returned.
+    pub fn new(ansatz: &'a mut crate::circuit::Circuit, hamiltonian: &'a crate::pauli::PauliSum,
+        nr_shots: usize) -> crate::error::Result<Self>
+    {
+        if hamiltonian.nr_bits() != ansatz.nr_qbits()
+        {
+            return Err(crate::error::Error::InvalidNrBits(hamiltonian.nr_bits(),
+                ansatz.nr_qbits(), String::from("Hamiltonian")));
+        }
+
+        let param_names = ansatz.parameters();
+        let groups = hamiltonian.measurement_groups();
+        Ok(VQE
+        {
+            ansatz: ansatz,
+            hamiltonian: hamiltonian,
+            param_names: param_names,
+            groups: groups,
+            nr_shots: nr_shots
+        })
+    }
+
+    /// The names of the parameters, in the order used for parameter vectors.
+    pub fn parameters(&self) -> &[String]
+    {
+        &self.param_names
+    }
+
+    /// Bind the values in `params` to the parameters of the ansatz.
+    fn bind(&mut self, params: &[f64])
+    {
+        let values = self.param_names.iter().cloned()
+            .zip(params.iter().cloned())
+            .collect();
+        self.ansatz.bind(&values);
+    }
+
+    /// Estimate the energy.
+    ///
+    /// Estimate the energy of the trial state for parameter values `params`,
+    /// in the order given by `parameters()`, using random number generator
+    /// `rng` for sampling.
+    pub fn energy<R: rand::RngCore>(&mut self, params: &[f64], rng: &mut R)
+        -> crate::error::Result<f64>
+    {
+        self.bind(params);
+
+        let ansatz = &mut *self.ansatz;
+        let hamiltonian = self.hamiltonian;
+        let nr_shots = self.nr_shots;
+
+        let mut energy = hamiltonian.constant();
+        let nr_ops = ansatz.nr_ops();
+        for (basis, idxs) in self.groups.iter()
+        {
+            let res = add_measurements(ansatz, basis)
+                .and_then(|_| ansatz.execute_with_rng(nr_shots, rng));
+            ansatz.truncate_ops(nr_ops);
+            res?;
+
+            let c_state = ansatz.cstate().ok_or(crate::error::Error::NotExecuted)?.values()?;
+            energy += hamiltonian.measured_value(idxs, c_state);
+        }
+
+        Ok(energy)
+    }
+
+    /// Estimate the energy gradient.
+    ///
+    /// Estimate the gradient of the energy of the trial state with respect
+    /// to the parameters, at parameter values `params`, using the parameter
+    /// shift rule. See `Circuit::gradient()` for the gates that are
+    /// supported.
+    pub fn energy_gradient<R: rand::RngCore>(&mut self, params: &[f64], rng: &mut R)
+        -> crate::error::Result<Vec<f64>>
+    {
+        self.bind(params);
+
+        let ansatz = &mut *self.ansatz;
+        let hamiltonian = self.hamiltonian;
+        let nr_shots = self.nr_shots;
+
+        let mut grad = vec![0.0; params.len()];
+        let nr_ops = ansatz.nr_ops();
+        for (basis, idxs) in self.groups.iter()
+        {
+            let observable = |key: u64| hamiltonian.outcome_value(idxs, key);
+            let res = add_measurements(ansatz, basis)
+                .and_then(|_| ansatz.gradient_with_rng(nr_shots, observable, rng));
+            ansatz.truncate_ops(nr_ops);
+            let group_grad = res?;
+            for (g, name) in grad.iter_mut().zip(self.param_names.iter())
+            {
+                *g += group_grad[name];
+            }
+        }
+
+        Ok(grad)
+    }
+
+    /// Find the ground state energy.
+    ///
+    /// Minimize the energy of the trial state using optimizer `optimizer`,
+    /// starting from the parameter values in `initial`. If no value for
+    /// a parameter of the ansatz is given, an `UnboundParameter` error is
+    /// returned. On return, the optimal parameter values are bound to the
+    /// ansatz circuit.
+    pub fn minimize<O, R>(&mut self, optimizer: &mut O,
+        initial: &::std::collections::HashMap<String, f64>, rng: &mut R)
+        -> crate::error::Result<VQEResult>
+    where O: Optimizer + ?Sized, R: rand::RngCore
+    {
+        let x0 = self.param_names.iter()
+            .map(|name| initial.get(name).cloned()
+                .ok_or_else(|| crate::error::Error::UnboundParameter(name.clone())))
+            .collect::<crate::error::Result<Vec<f64>>>()?;
+
+        let res = optimizer.minimize(self, &x0, rng)?;
+        self.bind(&res.params);
+        let params = self.param_names.iter().cloned()
+            .zip(res.params.iter().cloned())
+            .collect();
+
+        Ok(VQEResult { energy: res.value, params: params, history: res.history })
+    }
+}
+
+impl<'a> Objective for VQE<'a>
+{
+    fn value(&mut self, params: &[f64], rng: &mut dyn rand::RngCore)
+        -> crate::error::Result<f64>
+    {
+        self.energy(params, &mut &mut *rng)
+    }
+
+    fn gradient(&mut self, params: &[f64], rng: &mut dyn rand::RngCore)
+        -> crate::error::Result<Vec<f64>>
+    {
+        self.energy_gradient(params, &mut &mut *rng)
+    }
+}
+
+/// Find the ground state energy.
+///
+/// Estimate the ground state energy of `hamiltonian`, by minimizing the
+/// energy of the trial states prepared by circuit `ansatz` using optimizer
+/// `optimizer`. Optimization starts at parameter values `initial`, and the
+/// energy for every set of parameter values is estimated from `nr_shots`
+/// runs of the circuit for each measurement basis. See the description of
+/// `VQE` for details.
+pub fn vqe<O, R>(ansatz: &mut crate::circuit::Circuit, hamiltonian: &crate::pauli::PauliSum,
+    optimizer: &mut O, initial: &::std::collections::HashMap<String, f64>,
+    nr_shots: usize, rng: &mut R) -> crate::error::Result<VQEResult>
+where O: Optimizer + ?Sized, R: rand::RngCore
+{
+    VQE::new(ansatz, hamiltonian, nr_shots)?.minimize(optimizer, initial, rng)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{vqe, VQE};
+    use crate::algorithms::optimize::{GradientDescent, NelderMead, Spsa};
+    use crate::circuit::Circuit;
+    use crate::error::Error;
+    use crate::pauli::PauliSum;
+
+    fn rng() -> rand_hc::Hc128Rng
+    {
+        <rand_hc::Hc128Rng as rand_core::SeedableRng>::seed_from_u64(0xc0ffee)
+    }
+
+    fn initial(names: &[&str], values: &[f64]) -> ::std::collections::HashMap<String, f64>
+    {
+        names.iter().map(|&n| String::from(n)).zip(values.iter().cloned()).collect()
+    }
+
+    #[test]
+    fn test_energy()
+    {
+        let nr_shots = 2000;
+        let tol = 0.06;
+
+        // Bell state preparation, rotated by an angle theta
+        let mut ansatz = circuit!(2, 2, {
+            ry_param("theta", 0);
+            cx(0, 1);
+        }).unwrap();
+        let hamiltonian = PauliSum::from_terms(2, &[
+            (1.0, "ZZ"), (0.5, "XX"), (-0.25, "YY"), (0.5, "ZI"), (2.0, "II")
+        ]).unwrap();
+        let mut rng = rng();
+        let mut solver = VQE::new(&mut ansatz, &hamiltonian, nr_shots).unwrap();
+        assert_eq!(solver.parameters(), &[String::from("theta")]);
+
+        for &theta in [0.0f64, 0.7, 2.0].iter()
+        {
+            // cos(θ/2)|00⟩ + sin(θ/2)|11⟩
+            // ⟨ZZ⟩ = 1, ⟨XX⟩ = sin θ, ⟨YY⟩ = -sin θ, ⟨ZI⟩ = cos θ
+            let expected = 1.0 + 0.75 * theta.sin() + 0.5 * theta.cos() + 2.0;
+            let energy = solver.energy(&[theta], &mut rng).unwrap();
+            assert!((energy - expected).abs() < tol, "{} != {}", energy, expected);
+
+            // dE/dθ = 0.75 cos θ - 0.5 sin θ
+            let expected = 0.75 * theta.cos() - 0.5 * theta.sin();
+            let grad = solver.energy_gradient(&[theta], &mut rng).unwrap();
+            assert!((grad[0] - expected).abs() < tol, "{} != {}", grad[0], expected);
+        }
+
+        // The measurements should have been removed again
+        assert_eq!(ansatz.nr_ops(), 2);
+    }
+
+    #[test]
+    fn test_vqe()
+    {
+        let nr_shots = 2000;
+        let tol = 0.1;
+
+        // Minimum energy is -sqrt(1 + 0.5²) = -1.118...
+        let hamiltonian = PauliSum::from_terms(1, &[(1.0, "Z"), (0.5, "X")]).unwrap();
+        let expected = -1.25f64.sqrt();
+
+        let mut ansatz = circuit!(1, 1, { ry_param("theta", 0); }).unwrap();
+        let mut rng = rng();
+        let res = vqe(&mut ansatz, &hamiltonian, &mut NelderMead::new(40).with_tolerance(0.01),
+            &initial(&["theta"], &[1.0]), nr_shots, &mut rng).unwrap();
+        assert!((res.energy - expected).abs() < tol, "{}", res.energy);
+        assert!(!res.history.is_empty());
+        // θ = π + atan(0.5)
+        assert!((res.params["theta"] - 3.605).abs() < 0.3);
+
+        let res = vqe(&mut ansatz, &hamiltonian, &mut Spsa::new(100).with_gains(0.5, 0.2),
+            &initial(&["theta"], &[1.0]), nr_shots, &mut rng).unwrap();
+        assert!((res.energy - expected).abs() < tol, "{}", res.energy);
+
+        let res = vqe(&mut ansatz, &hamiltonian, &mut GradientDescent::new(30, 0.5),
+            &initial(&["theta"], &[1.0]), nr_shots, &mut rng).unwrap();
+        assert!((res.energy - expected).abs() < tol, "{}", res.energy);
+        assert_eq!(res.history.len(), 30);
+    }
+
+    #[test]
+    fn test_vqe_errors()
+    {
+        let hamiltonian = PauliSum::from_terms(2, &[(1.0, "ZZ")]).unwrap();
+        let mut ansatz = circuit!(1, 1, { ry_param("theta", 0); }).unwrap();
+        assert!(matches!(VQE::new(&mut ansatz, &hamiltonian, 10),
+            Err(Error::InvalidNrBits(2, 1, _))));
+
+        let mut ansatz = Circuit::new(2, 1).unwrap();
+        ansatz.ry_param("theta", 0).unwrap();
+        let mut rng = rng();
+        assert!(matches!(vqe(&mut ansatz, &hamiltonian, &mut NelderMead::new(10),
+            &initial(&["phi"], &[1.0]), 10, &mut rng), Err(Error::UnboundParameter(_))));
+        let mut solver = VQE::new(&mut ansatz, &hamiltonian, 10).unwrap();
+        assert!(matches!(solver.energy(&[0.0], &mut rng), Err(Error::InvalidCBit(1))));
+        assert_eq!(ansatz.nr_ops(), 1);
+    }
+}
@@ -30,9 +30,11 @@ pub enum Basis
 enum CircuitOp
 {
     /// Apply a gate to the state
-    Gate(Box<CircuitGate>, Vec<usize>),
+    Gate(Box<dyn CircuitGate>, Vec<usize>),
+    /// Apply a gate with symbolic arguments to the state
+    ParametricGate(crate::parameter::ParametricGate, Vec<usize>),
     /// Conditionally apply a gate, depending on classical bits
-    ConditionalGate(Vec<usize>, u64, Box<CircuitGate>, Vec<usize>),
+    ConditionalGate(Vec<usize>, u64, Box<dyn CircuitGate>, Vec<usize>),
     /// Reset a qubit to |0⟩
     Reset(usize),
     /// Reset the quantum state to |00...0⟩
@@ -59,12 +61,28 @@ pub struct Circuit
     nr_qbits: usize,
     /// The number of classical bit in the system
     nr_cbits: usize,
+    /// The named registers of quantum bits
+    qregs: Vec<crate::register::Register>,
+    /// The named registers of classical bits
+    cregs: Vec<crate::register::Register>,
     /// The quantum state of the system
     q_state: Option<crate::qustate::QuState>,
-    /// The classial state of the system
-    c_state: Option<ndarray::Array1<u64>>,
+    /// The classial state of the system, shared with the result of the last
+    /// execution
+    c_state: Option<::std::sync::Arc<crate::cstate::CState>>,
+    /// The seed of the shuffle that put the runs in the classical state in
+    /// shot order, if they are not in the order of the quantum state
+    shot_order_seed: Option<u64>,
     /// The operations to perform on the state
-    ops: Vec<CircuitOp>
+    ops: Vec<CircuitOp>,
+    /// The values bound to the parameters in the circuit
+    param_values: ::std::collections::HashMap<String, f64>,
+    /// The maximum number of qubits in a fused gate, 0 to disable gate fusion
+    max_fusion_bits: usize,
+    /// Whether to store the quantum state as a sparse vector while possible
+    sparse_state: bool,
+    /// The maximum number of bytes used in executing the circuit, if any
+    memory_budget: Option<usize>
 }
 
 impl Circuit
@@ -72,17 +90,44 @@ impl Circuit
     /// Create a new circuit.
     ///
     /// Create a new (empty) quantum circuit, with `nr_qbits` quantum bits and
-    /// `nr_cbits` classical bits.
-    pub fn new(nr_qbits: usize, nr_cbits: usize) -> Self
+    /// `nr_cbits` classical bits. The quantum bits form a register named `q`,
+    /// and the classical bits a register named `b`; more registers can be
+    /// added using `add_qreg()` and `add_creg()`. If the number of classical
+    /// bits exceeds `cstate::MAX_NR_BITS`, a `TooManyCBits` error is returned.
+    pub fn new(nr_qbits: usize, nr_cbits: usize) -> crate::error::Result<Self>
     {
-        Circuit
+        if nr_cbits > crate::cstate::MAX_NR_BITS
+        {
+            return Err(crate::error::Error::TooManyCBits(nr_cbits,
+                crate::cstate::MAX_NR_BITS));
+        }
+
+        let mut qregs = vec![];
+        if nr_qbits > 0
+        {
+            qregs.push(crate::register::Register::new("q", 0, nr_qbits)?);
+        }
+        let mut cregs = vec![];
+        if nr_cbits > 0
+        {
+            cregs.push(crate::register::Register::new("b", 0, nr_cbits)?);
+        }
+
+        Ok(Circuit
         {
             nr_qbits: nr_qbits,
             nr_cbits: nr_cbits,
+            qregs: qregs,
+            cregs: cregs,
             q_state: None,
             c_state: None,
-            ops: vec![]
-        }
+            shot_order_seed: None,
+            ops: vec![],
+            param_values: ::std::collections::HashMap::new(),
+            max_fusion_bits: 0,
+            sparse_state: false,
+            memory_budget: None
+        })
     }
 
     /// The number of quantum bits in this circuit
@@ -97,32 +142,297 @@ impl Circuit
         self.nr_cbits
     }
 
-    /// The classical register.
+    /// Check if `name` can be used as the name of a new register.
+    fn check_register_name(&self, name: &str) -> crate::error::Result<()>
+    {
+        if self.qregs.iter().chain(self.cregs.iter()).any(|reg| reg.name() == name)
+        {
+            Err(crate::error::Error::DuplicateRegister(String::from(name)))
+        }
+        else
+        {
+            Ok(())
+        }
+    }
+
+    /// Add a quantum register.
     ///
-    /// Return a reference to the classical bit register, containing the results
-    /// of any measurements made on the system. If no experiment has been run
-    /// yet, `None` is returned.
-    pub fn cstate(&self) -> Option<&ndarray::Array1<u64>>
+    /// Add a register of `size` new quantum bits called `name` to this circuit.
+    /// The new qubits are placed after the existing ones, and can be addressed
+    /// either by their index in the circuit, or as `(name, idx)` with `idx`
+    /// the index of the qubit in the register. The name must be a valid
+    /// OpenQasm identifier that is not used yet for another quantum or
+    /// classical register. Any results of a previous execution of the circuit
+    /// are cleared.
+    pub fn add_qreg(&mut self, name: &str, size: usize) -> crate::error::Result<()>
+    {
+        self.check_register_name(name)?;
+        self.qregs.push(crate::register::Register::new(name, self.nr_qbits, size)?);
+        self.nr_qbits += size;
+        self.q_state = None;
+        self.c_state = None;
+        Ok(())
+    }
+
+    /// Add a classical register.
+    ///
+    /// Add a register of `size` new classical bits called `name` to this
+    /// circuit. The new bits are placed after the existing ones, and can be
+    /// addressed either by their index in the circuit, or as `(name, idx)`
+    /// with `idx` the index of the bit in the register. The name must be a
+    /// valid OpenQasm identifier that is not used yet for another quantum or
+    /// classical register. Any results of a previous execution of the circuit
+    /// are cleared.
+    pub fn add_creg(&mut self, name: &str, size: usize) -> crate::error::Result<()>
+    {
+        self.check_register_name(name)?;
+        let nr_cbits = self.nr_cbits + size;
+        if nr_cbits > crate::cstate::MAX_NR_BITS
+        {
+            return Err(crate::error::Error::TooManyCBits(nr_cbits,
+                crate::cstate::MAX_NR_BITS));
+        }
+        self.cregs.push(crate::register::Register::new(name, self.nr_cbits, size)?);
+        self.nr_cbits = nr_cbits;
+        self.q_state = None;
+        self.c_state = None;
+        Ok(())
+    }
+
+    /// The quantum registers in this circuit, in the order of their bits
+    pub fn qregs(&self) -> &[crate::register::Register]
     {
-        self.c_state.as_ref()
+        &self.qregs
     }
 
-    /// Add a gate.
+    /// The classical registers in this circuit, in the order of their bits
+    pub fn cregs(&self) -> &[crate::register::Register]
+    {
+        &self.cregs
+    }
+
+    /// Find a quantum register.
     ///
-    /// Append a `n`-ary gate `gate`, operating on the `n` qubits in `bits`, to
-    /// this circuit.
-    pub fn add_gate<G: 'static>(&mut self, gate: G, bits: &[usize]) -> crate::error::Result<()>
-    where G: CircuitGate
+    /// Return the quantum register called `name`, or an `UnknownRegister`
+    /// error if no such register exists.
+    pub fn qreg(&self, name: &str) -> crate::error::Result<&crate::register::Register>
     {
-        if let Some(&bit) = bits.iter().find(|&&b| b >= self.nr_qbits)
+        self.qregs.iter().find(|reg| reg.name() == name)
+            .ok_or_else(|| crate::error::Error::UnknownRegister(String::from(name)))
+    }
+
+    /// Find a classical register.
+    ///
+    /// Return the classical register called `name`, or an `UnknownRegister`
+    /// error if no such register exists.
+    pub fn creg(&self, name: &str) -> crate::error::Result<&crate::register::Register>
+    {
+        self.cregs.iter().find(|reg| reg.name() == name)
+            .ok_or_else(|| crate::error::Error::UnknownRegister(String::from(name)))
+    }
+
+    /// Find the index of the quantum bit with address `qbit` in this circuit.
+    fn qbit_index<Q>(&self, qbit: &Q) -> crate::error::Result<usize>
+    where Q: crate::register::BitIndex
+    {
+        let idx = qbit.bit_index(&self.qregs)?;
+        if idx < self.nr_qbits
         {
-            Err(crate::error::Error::InvalidQBit(bit))
+            Ok(idx)
         }
         else
         {
-            self.ops.push(CircuitOp::Gate(Box::new(gate), bits.to_owned()));
-            Ok(())
+            Err(crate::error::Error::InvalidQBit(idx))
+        }
+    }
+
+    /// Find the indices of the quantum bits with addresses `qbits` in this
+    /// circuit.
+    fn qbit_indices<Q>(&self, qbits: &[Q]) -> crate::error::Result<Vec<usize>>
+    where Q: crate::register::BitIndex
+    {
+        qbits.iter().map(|qbit| self.qbit_index(qbit)).collect()
+    }
+
+    /// Find the index of the classical bit with address `cbit` in this circuit.
+    fn cbit_index<C>(&self, cbit: &C) -> crate::error::Result<usize>
+    where C: crate::register::BitIndex
+    {
+        let idx = cbit.bit_index(&self.cregs)?;
+        if idx < self.nr_cbits
+        {
+            Ok(idx)
+        }
+        else
+        {
+            Err(crate::error::Error::InvalidCBit(idx))
+        }
+    }
+
+    /// Find the indices of the classical bits with addresses `cbits` in this
+    /// circuit.
+    fn cbit_indices<C>(&self, cbits: &[C]) -> crate::error::Result<Vec<usize>>
+    where C: crate::register::BitIndex
+    {
+        cbits.iter().map(|cbit| self.cbit_index(cbit)).collect()
+    }
+
+    /// The maximum number of qubits in a fused gate.
+    ///
+    /// Return the maximum number of qubits a gate may act on, when consecutive
+    /// gates in this circuit are fused into a single gate on execution, or
+    /// `None` if gate fusion is disabled.
+    pub fn gate_fusion(&self) -> Option<usize>
+    {
+        if self.max_fusion_bits == 0 { None } else { Some(self.max_fusion_bits) }
+    }
+
+    /// Set gate fusion.
+    ///
+    /// When executing the circuit, runs of consecutive gates that together
+    /// act on at most `max_bits` qubits are combined into a single gate, so
+    /// that the quantum state is updated only once for the entire run. Gate
+    /// runs are ended by any operation that is not an unconditional gate,
+    /// like a measurement, reset, conditional gate, or barrier. Setting
+    /// `max_bits` to `None` disables gate fusion, which is the default.
+    pub fn set_gate_fusion(&mut self, max_bits: Option<usize>)
+    {
+        self.max_fusion_bits = max_bits.unwrap_or(0);
+    }
+
+    /// Whether the quantum state is stored sparsely.
+    ///
+    /// Return whether the quantum state of this circuit is stored as a sparse
+    /// vector on execution, for as long as it remains sparse.
+    pub fn sparse_state(&self) -> bool
+    {
+        self.sparse_state
+    }
+
+    /// Set sparse storage of the quantum state.
+    ///
+    /// When `sparse` is `true`, the quantum state is stored as a sparse
+    /// vector on execution, keeping only the amplitudes of basis states that
+    /// actually occur. This is much more efficient for circuits that keep the
+    /// state in a superposition of only a few basis states, like reversible
+    /// arithmetic, and allows for simulating circuits of up to 64 qubits. When
+    /// the state fills up, it is automatically converted to a dense state. By
+    /// default, the state is stored densely.
+    pub fn set_sparse_state(&mut self, sparse: bool)
+    {
+        self.sparse_state = sparse;
+    }
+
+    /// The memory budget.
+    ///
+    /// Return the maximum number of bytes that may be used for the quantum and
+    /// classical states when executing this circuit, or `None` if memory use
+    /// is not limited.
+    pub fn memory_budget(&self) -> Option<usize>
+    {
+        self.memory_budget
+    }
+
+    /// Set the memory budget.
+    ///
+    /// Limit the memory used for storing the quantum and classical states when
+    /// executing this circuit to `budget` bytes, or remove the limit if
+    /// `budget` is `None`. Before execution starts, it is checked that the
+    /// initial state fits within the budget. During execution, operations
+    /// that increase the memory use, like measurements that split the state
+    /// into separate branches, check the memory they need before allocating
+    /// it. If the budget would be exceeded, execution stops with a
+    /// `MemoryBudgetExceeded` error. By default, memory use is not limited.
+    pub fn set_memory_budget(&mut self, budget: Option<usize>)
+    {
+        self.memory_budget = budget;
+    }
+
+    /// Estimate the memory needed for execution.
+    ///
+    /// Estimate the peak number of bytes used for the quantum and classical
+    /// states when executing this circuit with `nr_shots` runs. The estimate
+    /// assumes the state is stored densely, and that every measurement
+    /// splits each branch of the state in two, up to a maximum of one branch
+    /// per run. Since branches that become equal are merged again during
+    /// execution, the actual memory use is usually smaller. If the required
+    /// memory is too large to be represented, `usize::MAX` is returned.
+    pub fn estimated_memory(&self, nr_shots: usize) -> usize
+    {
+        let branch_size = match crate::qustate::dense_state_size(self.nr_qbits)
+            {
+                Some(size) => size,
+                None => { return usize::MAX; }
+            };
+
+        let mut nr_branches = 1usize;
+        let mut max_branches = 1usize;
+        for op in self.ops.iter()
+        {
+            let new_nr_branches = match *op
+                {
+                    CircuitOp::ConditionalGate(..)
+                    | CircuitOp::Reset(_)
+                    | CircuitOp::Measure(..) => nr_branches.saturating_mul(2),
+                    CircuitOp::MeasureAll(..) => {
+                        nr_branches.saturating_mul(
+                            1usize.checked_shl(self.nr_qbits as u32).unwrap_or(usize::MAX))
+                    },
+                    CircuitOp::ResetAll => 1,
+                    _ => nr_branches
+                }.min(nr_shots.max(1));
+
+            // Both the old and new branches are kept while splitting the state
+            if new_nr_branches != nr_branches
+            {
+                max_branches = max_branches.max(nr_branches.saturating_add(new_nr_branches));
+            }
+            nr_branches = new_nr_branches;
         }
+
+        max_branches.saturating_mul(branch_size)
+            .saturating_add(crate::cstate::CState::memory_size(self.nr_cbits, nr_shots))
+    }
+
+    /// The classical register.
+    ///
+    /// Return a reference to the classical bit register, containing the results
+    /// of any measurements made on the system. After `execute()`, the runs are
+    /// in the same order as the shots in the execution result. If no
+    /// experiment has been run yet, `None` is returned.
+    pub fn cstate(&self) -> Option<&crate::cstate::CState>
+    {
+        self.c_state.as_deref()
+    }
+
+    /// The number of operations in this circuit
+    pub(crate) fn nr_ops(&self) -> usize
+    {
+        self.ops.len()
+    }
+
+    /// Remove operations.
+    ///
+    /// Remove all operations from this circuit, except for the first `len`
+    /// operations.
+    pub(crate) fn truncate_ops(&mut self, len: usize)
+    {
+        self.ops.truncate(len);
+    }
+
+    /// Add a gate.
+    ///
+    /// Append a `n`-ary gate `gate`, operating on the `n` qubits in `bits`, to
+    /// this circuit. Here, as in all other methods adding operations to the
+    /// circuit, bits can be given either by their index in the circuit, or
+    /// by a register name and an index in that register (see
+    /// [BitIndex](../register/trait.BitIndex.html)).
+    pub fn add_gate<G, Q>(&mut self, gate: G, bits: &[Q]) -> crate::error::Result<()>
+    where G: 'static + CircuitGate, Q: crate::register::BitIndex
+    {
+        let bits = self.qbit_indices(bits)?;
+        self.ops.push(CircuitOp::Gate(Box::new(gate), bits));
+        Ok(())
     }
 
     /// Add a conditional gate.
@@ -131,47 +441,124 @@ impl Circuit
     /// `bits` to this circuit. The gate will only be applied only when the
     /// classical bits with indices from `control` form the target word `target`.
     /// The bit at the position of the first index in `control` is interpreted
-    /// as the most significant bit to check.
-    pub fn add_conditional_gate<G: 'static>(&mut self, control: &[usize],
-        target: u64, gate: G, qbits: &[usize]) -> crate::error::Result<()>
-    where G: CircuitGate
-    {
-        if let Some(&bit) = control.iter().find(|&&b| b >= self.nr_cbits)
+    /// as the least significant bit to check. Since the target is a 64-bit
+    /// word, at most 64 control bits can be used.
+    pub fn add_conditional_gate<G, C, Q>(&mut self, control: &[C],
+        target: u64, gate: G, qbits: &[Q]) -> crate::error::Result<()>
+    where G: 'static + CircuitGate, C: crate::register::BitIndex, Q: crate::register::BitIndex
+    {
+        let control = self.cbit_indices(control)?;
+        if control.len() > 64
         {
-            Err(crate::error::Error::InvalidCBit(bit))
+            return Err(crate::error::Error::TooManyCBits(control.len(), 64));
         }
-        else if let Some(&bit) = qbits.iter().find(|&&b| b >= self.nr_qbits)
+        let qbits = self.qbit_indices(qbits)?;
+        self.ops.push(CircuitOp::ConditionalGate(control, target, Box::new(gate), qbits));
+        Ok(())
+    }
+
+    /// Add a gate with symbolic arguments.
+    ///
+    /// Append a gate with name `name`, operating on the qubits in `bits`, to
+    /// this circuit. The arguments of the gate are given by the expressions
+    /// in `args`, which may contain named parameters (see
+    /// [Expression::parse()](../parameter/struct.Expression.html#method.parse)).
+    /// When values for all parameters in `args` are known, the gate is
+    /// created by calling `build` with the values of the arguments. Parameter
+    /// values are set using the `bind()` method.
+    pub fn add_parametric_gate<G, F, Q>(&mut self, name: &str, args: &[&str],
+        build: F, bits: &[Q]) -> crate::error::Result<()>
+    where G: 'static + CircuitGate, F: 'static + Fn(&[f64]) -> G, Q: crate::register::BitIndex
+    {
+        let exprs = args.iter()
+            .map(|arg| crate::parameter::Expression::parse(arg))
+            .collect::<crate::error::ParseResult<Vec<_>>>()?;
+        let mut gate = crate::parameter::ParametricGate::new(name, exprs, build);
+
+        let bits = self.qbit_indices(bits)?;
+        if bits.len() != gate.nr_affected_bits()
         {
-            Err(crate::error::Error::InvalidQBit(bit))
+            Err(crate::error::Error::InvalidNrBits(bits.len(),
+                gate.nr_affected_bits(), String::from(name)))
         }
         else
         {
-            self.ops.push(CircuitOp::ConditionalGate(control.to_owned(), target,
-                Box::new(gate), qbits.to_owned()));
+            gate.bind(&self.param_values);
+            self.ops.push(CircuitOp::ParametricGate(gate, bits));
             Ok(())
         }
     }
 
-    /// Add a measurement
+    /// Bind parameter values.
     ///
-    /// Add measurement of qubit `qbit` in basis `basis`, into classical bit
-    /// `cbit`, to this circuit.
-    pub fn measure_basis(&mut self, qbit: usize, cbit: usize, basis: Basis)
-         -> crate::error::Result<()>
-    {
-        if qbit >= self.nr_qbits
+    /// Set the values of the parameters in this circuit to the values in
+    /// `values`. Parameters not occurring in `values` keep the value they
+    /// were previously bound to, if any. The circuit can only be executed
+    /// when all its parameters are bound, but it can be bound to different
+    /// values any number of times.
+    pub fn bind(&mut self, values: &::std::collections::HashMap<String, f64>)
+    {
+        self.param_values.extend(values.iter().map(|(k, &v)| (k.clone(), v)));
+        for op in self.ops.iter_mut()
         {
-            Err(crate::error::Error::InvalidQBit(qbit))
+            if let CircuitOp::ParametricGate(ref mut gate, _) = *op
+            {
+                gate.bind(&self.param_values);
+            }
         }
-        else if cbit >= self.nr_cbits
+    }
+
+    /// The parameters in this circuit.
+    ///
+    /// Return the names of all parameters occurring in the arguments of the
+    /// gates in this circuit, in order of first appearance.
+    pub fn parameters(&self) -> Vec<String>
+    {
+        let mut res: Vec<String> = vec![];
+        for op in self.ops.iter()
         {
-            Err(crate::error::Error::InvalidCBit(cbit))
+            if let CircuitOp::ParametricGate(ref gate, _) = *op
+            {
+                for name in gate.args().iter().flat_map(|arg| arg.parameters())
+                {
+                    if !res.iter().any(|n| n == name)
+                    {
+                        res.push(String::from(name));
+                    }
+                }
+            }
         }
-        else
+        res
+    }
+
+    /// Check that all parameters are bound.
+    ///
+    /// Return `Error::UnboundParameter` with the name of the first parameter
+    /// in the circuit that has no value bound to it, if any.
+    fn check_bound(&self) -> crate::error::Result<()>
+    {
+        for op in self.ops.iter()
         {
-            self.ops.push(CircuitOp::Measure(qbit, cbit, basis));
-            Ok(())
+            if let CircuitOp::ParametricGate(ref gate, _) = *op
+            {
+                gate.gate()?;
+            }
         }
+        Ok(())
+    }
+
+    /// Add a measurement
+    ///
+    /// Add measurement of qubit `qbit` in basis `basis`, into classical bit
+    /// `cbit`, to this circuit.
+    pub fn measure_basis<Q, C>(&mut self, qbit: Q, cbit: C, basis: Basis)
+         -> crate::error::Result<()>
+    where Q: crate::register::BitIndex, C: crate::register::BitIndex
+    {
+        let qbit = self.qbit_index(&qbit)?;
+        let cbit = self.cbit_index(&cbit)?;
+        self.ops.push(CircuitOp::Measure(qbit, cbit, basis));
+        Ok(())
     }
 
     /// Add a measurement.
@@ -179,7 +566,8 @@ impl Circuit
     /// Add measurement of qubit `qbit` in the Pauli `X` basis, into classical
     /// bit `cbit` to this circuit.
     #[inline(always)]
-    pub fn measure_x(&mut self, qbit: usize, cbit: usize) -> crate::error::Result<()>
+    pub fn measure_x<Q, C>(&mut self, qbit: Q, cbit: C) -> crate::error::Result<()>
+    where Q: crate::register::BitIndex, C: crate::register::BitIndex
     {
         self.measure_basis(qbit, cbit, Basis::X)
     }
@@ -189,7 +577,8 @@ impl Circuit
     /// Add measurement of qubit `qbit` in the Pauli `Y` basis, into classical
     /// bit `cbit` to this circuit.
     #[inline(always)]
-    pub fn measure_y(&mut self, qbit: usize, cbit: usize) -> crate::error::Result<()>
+    pub fn measure_y<Q, C>(&mut self, qbit: Q, cbit: C) -> crate::error::Result<()>
+    where Q: crate::register::BitIndex, C: crate::register::BitIndex
     {
         self.measure_basis(qbit, cbit, Basis::Y)
     }
@@ -199,7 +588,8 @@ impl Circuit
     /// Add measurement of qubit `qbit` in the Pauli `Z` basis, into classical
     /// bit `cbit` to this circuit.
     #[inline(always)]
-    pub fn measure_z(&mut self, qbit: usize, cbit: usize) -> crate::error::Result<()>
+    pub fn measure_z<Q, C>(&mut self, qbit: Q, cbit: C) -> crate::error::Result<()>
+    where Q: crate::register::BitIndex, C: crate::register::BitIndex
     {
         self.measure_basis(qbit, cbit, Basis::Z)
     }
@@ -209,35 +599,53 @@ impl Circuit
     /// Add measurement of qubit `qbit` into classical bit `cbit` to this circuit.
     /// This is an alias for `measure_z()`.
     #[inline(always)]
-    pub fn measure(&mut self, qbit: usize, cbit: usize) -> crate::error::Result<()>
+    pub fn measure<Q, C>(&mut self, qbit: Q, cbit: C) -> crate::error::Result<()>
+    where Q: crate::register::BitIndex, C: crate::register::BitIndex
     {
         self.measure_basis(qbit, cbit, Basis::Z)
     }
 
-    /// Add a measurement.
+    /// Check the number of classical bits in a measurement of all qubits.
     ///
-    /// Add the measurement of all qubits in the quantum state into the classical
-    /// bits `cbits`. Measurement is done in basis `basis`.
-    pub fn measure_all_basis(&mut self, cbits: &[usize], basis: Basis)
-         -> crate::error::Result<()>
+    /// Check that `cbits` holds a classical bit for every qubit in this
+    /// circuit. If not, an `InvalidNrBits` error for operation `desc` is
+    /// returned.
+    fn check_nr_measured_bits(&self, cbits: &[usize], desc: &str) -> crate::error::Result<()>
     {
-        if let Some(&bit) = cbits.iter().find(|&&b| b >= self.nr_cbits)
+        if cbits.len() != self.nr_qbits
         {
-            Err(crate::error::Error::InvalidCBit(bit))
+            Err(crate::error::Error::InvalidNrBits(cbits.len(), self.nr_qbits,
+                String::from(desc)))
         }
         else
         {
-            self.ops.push(CircuitOp::MeasureAll(cbits.to_owned(), basis));
             Ok(())
         }
     }
 
+    /// Add a measurement.
+    ///
+    /// Add the measurement of all qubits in the quantum state into the classical
+    /// bits `cbits`. Measurement is done in basis `basis`. If the number of
+    /// bits in `cbits` is not equal to the number of qubits, an
+    /// `InvalidNrBits` error is returned.
+    pub fn measure_all_basis<C>(&mut self, cbits: &[C], basis: Basis)
+         -> crate::error::Result<()>
+    where C: crate::register::BitIndex
+    {
+        let cbits = self.cbit_indices(cbits)?;
+        self.check_nr_measured_bits(&cbits, "measure all")?;
+        self.ops.push(CircuitOp::MeasureAll(cbits, basis));
+        Ok(())
+    }
+
     /// Add a measurement.
     ///
     /// Add the measurement of all qubits in the quantum state into the classical
     /// bits `cbits`. Measurement is done in the Pauli `Z` basis.
     #[inline(always)]
-    pub fn measure_all(&mut self, cbits: &[usize])-> crate::error::Result<()>
+    pub fn measure_all<C>(&mut self, cbits: &[C])-> crate::error::Result<()>
+    where C: crate::register::BitIndex
     {
         self.measure_all_basis(cbits, Basis::Z)
     }
@@ -249,22 +657,14 @@ impl Circuit
     /// collapsing the quantum state.
     /// NOTE: this is not a physical process, and cannot be reproduced on a real
     /// quantum computer.
-    pub fn peek_basis(&mut self, qbit: usize, cbit: usize, basis: Basis)
+    pub fn peek_basis<Q, C>(&mut self, qbit: Q, cbit: C, basis: Basis)
         -> crate::error::Result<()>
+    where Q: crate::register::BitIndex, C: crate::register::BitIndex
     {
-        if qbit >= self.nr_qbits
-        {
-            Err(crate::error::Error::InvalidQBit(qbit))
-        }
-        else if cbit >= self.nr_cbits
-        {
-            Err(crate::error::Error::InvalidCBit(cbit))
-        }
-        else
-        {
-            self.ops.push(CircuitOp::Peek(qbit, cbit, basis));
-            Ok(())
-        }
+        let qbit = self.qbit_index(&qbit)?;
+        let cbit = self.cbit_index(&cbit)?;
+        self.ops.push(CircuitOp::Peek(qbit, cbit, basis));
+        Ok(())
     }
 
     /// Add a measurement.
@@ -274,7 +674,8 @@ impl Circuit
     /// NOTE: this is not a physical process, and cannot be reproduced on a real
     /// quantum computer.
     #[inline(always)]
-    pub fn peek_x(&mut self, qbit: usize, cbit: usize) -> crate::error::Result<()>
+    pub fn peek_x<Q, C>(&mut self, qbit: Q, cbit: C) -> crate::error::Result<()>
+    where Q: crate::register::BitIndex, C: crate::register::BitIndex
     {
         self.peek_basis(qbit, cbit, Basis::X)
     }
@@ -286,7 +687,8 @@ impl Circuit
     /// NOTE: this is not a physical process, and cannot be reproduced on a real
     /// quantum computer.
     #[inline(always)]
-    pub fn peek_y(&mut self, qbit: usize, cbit: usize) -> crate::error::Result<()>
+    pub fn peek_y<Q, C>(&mut self, qbit: Q, cbit: C) -> crate::error::Result<()>
+    where Q: crate::register::BitIndex, C: crate::register::BitIndex
     {
         self.peek_basis(qbit, cbit, Basis::Y)
     }
@@ -298,7 +700,8 @@ impl Circuit
     /// NOTE: this is not a physical process, and cannot be reproduced on a real
     /// quantum computer.
     #[inline(always)]
-    pub fn peek_z(&mut self, qbit: usize, cbit: usize) -> crate::error::Result<()>
+    pub fn peek_z<Q, C>(&mut self, qbit: Q, cbit: C) -> crate::error::Result<()>
+    where Q: crate::register::BitIndex, C: crate::register::BitIndex
     {
         self.peek_basis(qbit, cbit, Basis::Z)
     }
@@ -310,7 +713,8 @@ impl Circuit
     /// NOTE: this is not a physical process, and cannot be reproduced on a real
     /// quantum computer.
     #[inline(always)]
-    pub fn peek(&mut self, qbit: usize, cbit: usize) -> crate::error::Result<()>
+    pub fn peek<Q, C>(&mut self, qbit: Q, cbit: C) -> crate::error::Result<()>
+    where Q: crate::register::BitIndex, C: crate::register::BitIndex
     {
         self.peek_basis(qbit, cbit, Basis::Z)
     }
@@ -319,21 +723,18 @@ impl Circuit
     ///
     /// Add the measurement of all qubits in the quantum state into the classical
     /// bits `cbits`. Measurement is done in basis `basis`, without
-    /// collapsing the quantum state.
+    /// collapsing the quantum state. If the number of bits in `cbits` is not
+    /// equal to the number of qubits, an `InvalidNrBits` error is returned.
     /// NOTE: this is not a physical process, and cannot be reproduced on a real
     /// quantum computer.
-    pub fn peek_all_basis(&mut self, cbits: &[usize], basis: Basis)
+    pub fn peek_all_basis<C>(&mut self, cbits: &[C], basis: Basis)
         -> crate::error::Result<()>
+    where C: crate::register::BitIndex
     {
-        if let Some(&bit) = cbits.iter().find(|&&b| b >= self.nr_cbits)
-        {
-            Err(crate::error::Error::InvalidCBit(bit))
-        }
-        else
-        {
-            self.ops.push(CircuitOp::PeekAll(cbits.to_owned(), basis));
-            Ok(())
-        }
+        let cbits = self.cbit_indices(cbits)?;
+        self.check_nr_measured_bits(&cbits, "peek all")?;
+        self.ops.push(CircuitOp::PeekAll(cbits, basis));
+        Ok(())
     }
 
 
@@ -345,7 +746,8 @@ impl Circuit
     /// NOTE: this is not a physical process, and cannot be reproduced on a real
     /// quantum computer.
     #[inline(always)]
-    pub fn peek_all(&mut self, cbits: &[usize]) -> crate::error::Result<()>
+    pub fn peek_all<C>(&mut self, cbits: &[C]) -> crate::error::Result<()>
+    where C: crate::register::BitIndex
     {
         self.peek_all_basis(cbits, Basis::Z)
     }
@@ -355,17 +757,12 @@ impl Circuit
     /// Reset the qubit `qbit` to |0⟩. This is done by measuring the bit, and
     /// flipping it if the result is `1`, so this is potentially an expensive
     /// operation.
-    pub fn reset(&mut self, qbit: usize) -> crate::error::Result<()>
+    pub fn reset<Q>(&mut self, qbit: Q) -> crate::error::Result<()>
+    where Q: crate::register::BitIndex
     {
-        if qbit >= self.nr_qbits
-        {
-            Err(crate::error::Error::InvalidQBit(qbit))
-        }
-        else
-        {
-            self.ops.push(CircuitOp::Reset(qbit));
-            Ok(())
-        }
+        let qbit = self.qbit_index(&qbit)?;
+        self.ops.push(CircuitOp::Reset(qbit));
+        Ok(())
     }
 
     /// Reset all qubits
@@ -380,7 +777,8 @@ impl Circuit
     /// Add a Hadamard gate.
     ///
     /// Add a Hadamard operating on qubit `qbit`, to this circuit.
-    pub fn h(&mut self, qbit: usize) -> crate::error::Result<()>
+    pub fn h<Q>(&mut self, qbit: Q) -> crate::error::Result<()>
+    where Q: crate::register::BitIndex
     {
         self.add_gate(crate::gates::H::new(), &[qbit])
     }
@@ -388,7 +786,8 @@ impl Circuit
     /// Add a Pauli X gate.
     ///
     /// Add a Pauli X gate operating on qubit `bit`, to this circuit.
-    pub fn x(&mut self, bit: usize) -> crate::error::Result<()>
+    pub fn x<Q>(&mut self, bit: Q) -> crate::error::Result<()>
+    where Q: crate::register::BitIndex
     {
         self.add_gate(crate::gates::X::new(), &[bit])
     }
@@ -396,7 +795,8 @@ impl Circuit
     /// Add a Pauli Y gate.
     ///
     /// Add a Pauli Y gate operating on qubit `bit`, to this circuit.
-    pub fn y(&mut self, bit: usize) -> crate::error::Result<()>
+    pub fn y<Q>(&mut self, bit: Q) -> crate::error::Result<()>
+    where Q: crate::register::BitIndex
     {
         self.add_gate(crate::gates::Y::new(), &[bit])
     }
@@ -404,7 +804,8 @@ impl Circuit
     /// Add a Pauli Z gate.
     ///
     /// Add a Pauli Z gate operating on qubit `bit`, to this circuit.
-    pub fn z(&mut self, bit: usize) -> crate::error::Result<()>
+    pub fn z<Q>(&mut self, bit: Q) -> crate::error::Result<()>
+    where Q: crate::register::BitIndex
     {
         self.add_gate(crate::gates::Z::new(), &[bit])
     }
@@ -412,7 +813,8 @@ impl Circuit
     /// Add a phase gate
     ///
     /// Add an `S` phase gate operating on qubit `bit`, to this circuit.
-    pub fn s(&mut self, bit: usize) -> crate::error::Result<()>
+    pub fn s<Q>(&mut self, bit: Q) -> crate::error::Result<()>
+    where Q: crate::register::BitIndex
     {
         self.add_gate(crate::gates::S::new(), &[bit])
     }
@@ -420,7 +822,8 @@ impl Circuit
     /// Add a phase gate
     ///
     /// Add an `S`<sup>`\dagger`</sup> phase gate operating on qubit `bit`, to this circuit.
-    pub fn sdg(&mut self, bit: usize) -> crate::error::Result<()>
+    pub fn sdg<Q>(&mut self, bit: Q) -> crate::error::Result<()>
+    where Q: crate::register::BitIndex
     {
         self.add_gate(crate::gates::Sdg::new(), &[bit])
     }
@@ -428,7 +831,8 @@ impl Circuit
     /// Add a R<sub>X</sub> gate.
     ///
     /// Add a `R`<sub>`X`</sub>`(θ)` gate operating on qubit `bit`, to this circuit.
-    pub fn rx(&mut self, theta: f64, bit: usize) -> crate::error::Result<()>
+    pub fn rx<Q>(&mut self, theta: f64, bit: Q) -> crate::error::Result<()>
+    where Q: crate::register::BitIndex
     {
         self.add_gate(crate::gates::RX::new(theta), &[bit])
     }
@@ -436,7 +840,8 @@ impl Circuit
     /// Add a R<sub>Y</sub> gate.
     ///
     /// Add a `R`<sub>`Y`</sub>`(θ)` gate operating on qubit `bit`, to this circuit.
-    pub fn ry(&mut self, theta: f64, bit: usize) -> crate::error::Result<()>
+    pub fn ry<Q>(&mut self, theta: f64, bit: Q) -> crate::error::Result<()>
+    where Q: crate::register::BitIndex
     {
         self.add_gate(crate::gates::RY::new(theta), &[bit])
     }
@@ -444,7 +849,8 @@ impl Circuit
     /// Add a R<sub>Z</sub> gate.
     ///
     /// Add a `R`<sub>`Z`</sub>`(λ)` gate operating on qubit `bit`, to this circuit.
-    pub fn rz(&mut self, lambda: f64, bit: usize) -> crate::error::Result<()>
+    pub fn rz<Q>(&mut self, lambda: f64, bit: Q) -> crate::error::Result<()>
+    where Q: crate::register::BitIndex
     {
         self.add_gate(crate::gates::RZ::new(lambda), &[bit])
     }
@@ -452,7 +858,8 @@ impl Circuit
     /// Add a U<sub>1</sub> gate.
     ///
     /// Add a `U`<sub>`1`</sub>`(λ)` gate operating on qubit `bit`, to this circuit.
-    pub fn u1(&mut self, lambda: f64, bit: usize) -> crate::error::Result<()>
+    pub fn u1<Q>(&mut self, lambda: f64, bit: Q) -> crate::error::Result<()>
+    where Q: crate::register::BitIndex
     {
         self.add_gate(crate::gates::U1::new(lambda), &[bit])
     }
@@ -460,7 +867,8 @@ impl Circuit
     /// Add a U<sub>2</sub> gate.
     ///
     /// Add a `U`<sub>`2`</sub>`(ϕ, λ)` gate operating on qubit `bit`, to this circuit.
-    pub fn u2(&mut self, phi: f64, lambda: f64, bit: usize) -> crate::error::Result<()>
+    pub fn u2<Q>(&mut self, phi: f64, lambda: f64, bit: Q) -> crate::error::Result<()>
+    where Q: crate::register::BitIndex
     {
         self.add_gate(crate::gates::U2::new(phi, lambda), &[bit])
     }
@@ -468,8 +876,9 @@ impl Circuit
     /// Add a U<sub>3</sub> gate.
     ///
     /// Add a `U`<sub>`3`</sub>`(θ, ϕ, λ)` gate operating on qubit `bit`, to this circuit.
-    pub fn u3(&mut self, theta: f64, phi: f64, lambda: f64, bit: usize)
+    pub fn u3<Q>(&mut self, theta: f64, phi: f64, lambda: f64, bit: Q)
          -> crate::error::Result<()>
+    where Q: crate::register::BitIndex
     {
         self.add_gate(crate::gates::U3::new(theta, phi, lambda), &[bit])
     }
@@ -478,26 +887,115 @@ impl Circuit
     ///
     /// Add a `C`<sub>`X`</sub> gate, controlled by qubit `control` and
     /// operating on qubit `target`, to this circuit.
-    pub fn cx(&mut self, control: usize, target: usize) -> crate::error::Result<()>
+    pub fn cx<C, T>(&mut self, control: C, target: T) -> crate::error::Result<()>
+    where C: crate::register::BitIndex, T: crate::register::BitIndex
+    {
+        let bits = [self.qbit_index(&control)?, self.qbit_index(&target)?];
+        self.add_gate(crate::gates::CX::new(), &bits)
+    }
+
+    /// Add a parametric R<sub>X</sub> gate.
+    ///
+    /// Add a `R`<sub>`X`</sub>`(θ)` gate operating on qubit `bit`, to this
+    /// circuit, where `θ` is given by the parameter expression `theta`.
+    pub fn rx_param<Q>(&mut self, theta: &str, bit: Q) -> crate::error::Result<()>
+    where Q: crate::register::BitIndex
+    {
+        self.add_parametric_gate("RX", &[theta], |args| crate::gates::RX::new(args[0]), &[bit])
+    }
+
+    /// Add a parametric R<sub>Y</sub> gate.
+    ///
+    /// Add a `R`<sub>`Y`</sub>`(θ)` gate operating on qubit `bit`, to this
+    /// circuit, where `θ` is given by the parameter expression `theta`.
+    pub fn ry_param<Q>(&mut self, theta: &str, bit: Q) -> crate::error::Result<()>
+    where Q: crate::register::BitIndex
+    {
+        self.add_parametric_gate("RY", &[theta], |args| crate::gates::RY::new(args[0]), &[bit])
+    }
+
+    /// Add a parametric R<sub>Z</sub> gate.
+    ///
+    /// Add a `R`<sub>`Z`</sub>`(λ)` gate operating on qubit `bit`, to this
+    /// circuit, where `λ` is given by the parameter expression `lambda`.
+    pub fn rz_param<Q>(&mut self, lambda: &str, bit: Q) -> crate::error::Result<()>
+    where Q: crate::register::BitIndex
+    {
+        self.add_parametric_gate("RZ", &[lambda], |args| crate::gates::RZ::new(args[0]), &[bit])
+    }
+
+    /// Add a parametric U<sub>1</sub> gate.
+    ///
+    /// Add a `U`<sub>`1`</sub>`(λ)` gate operating on qubit `bit`, to this
+    /// circuit, where `λ` is given by the parameter expression `lambda`.
+    pub fn u1_param<Q>(&mut self, lambda: &str, bit: Q) -> crate::error::Result<()>
+    where Q: crate::register::BitIndex
+    {
+        self.add_parametric_gate("U1", &[lambda], |args| crate::gates::U1::new(args[0]), &[bit])
+    }
+
+    /// Add a parametric U<sub>3</sub> gate.
+    ///
+    /// Add a `U`<sub>`3`</sub>`(θ, ϕ, λ)` gate operating on qubit `bit`, to
+    /// this circuit, where the arguments are given by the parameter
+    /// expressions `theta`, `phi`, and `lambda`.
+    pub fn u3_param<Q>(&mut self, theta: &str, phi: &str, lambda: &str, bit: Q)
+        -> crate::error::Result<()>
+    where Q: crate::register::BitIndex
+    {
+        self.add_parametric_gate("U3", &[theta, phi, lambda],
+            |args| crate::gates::U3::new(args[0], args[1], args[2]), &[bit])
+    }
+
+    /// Add a parametric controlled R<sub>X</sub> gate.
+    ///
+    /// Add a controlled `R`<sub>`X`</sub>`(θ)` gate, with control qubit
+    /// `control` and target qubit `target`, to this circuit, where `θ` is
+    /// given by the parameter expression `theta`.
+    pub fn crx_param<C, T>(&mut self, theta: &str, control: C, target: T)
+        -> crate::error::Result<()>
+    where C: crate::register::BitIndex, T: crate::register::BitIndex
+    {
+        let bits = [self.qbit_index(&control)?, self.qbit_index(&target)?];
+        self.add_parametric_gate("CRX", &[theta], |args| crate::gates::CRX::new(args[0]), &bits)
+    }
+
+    /// Add a parametric controlled R<sub>Y</sub> gate.
+    ///
+    /// Add a controlled `R`<sub>`Y`</sub>`(θ)` gate, with control qubit
+    /// `control` and target qubit `target`, to this circuit, where `θ` is
+    /// given by the parameter expression `theta`.
+    pub fn cry_param<C, T>(&mut self, theta: &str, control: C, target: T)
+        -> crate::error::Result<()>
+    where C: crate::register::BitIndex, T: crate::register::BitIndex
+    {
+        let bits = [self.qbit_index(&control)?, self.qbit_index(&target)?];
+        self.add_parametric_gate("CRY", &[theta], |args| crate::gates::CRY::new(args[0]), &bits)
+    }
+
+    /// Add a parametric controlled R<sub>Z</sub> gate.
+    ///
+    /// Add a controlled `R`<sub>`Z`</sub>`(λ)` gate, with control qubit
+    /// `control` and target qubit `target`, to this circuit, where `λ` is
+    /// given by the parameter expression `lambda`.
+    pub fn crz_param<C, T>(&mut self, lambda: &str, control: C, target: T)
+        -> crate::error::Result<()>
+    where C: crate::register::BitIndex, T: crate::register::BitIndex
     {
-        self.add_gate(crate::gates::CX::new(), &[control, target])
+        let bits = [self.qbit_index(&control)?, self.qbit_index(&target)?];
+        self.add_parametric_gate("CRZ", &[lambda], |args| crate::gates::CRZ::new(args[0]), &bits)
     }
 
     /// Add a barrier
     ///
     /// Add a barrier on the bits in `bits`. No transformations on these bits
     /// are allowed across this barrier.
-    pub fn barrier(&mut self, qbits: &[usize]) -> crate::error::Result<()>
+    pub fn barrier<Q>(&mut self, qbits: &[Q]) -> crate::error::Result<()>
+    where Q: crate::register::BitIndex
     {
-        if let Some(&bit) = qbits.iter().find(|&&b| b >= self.nr_qbits)
-        {
-            Err(crate::error::Error::InvalidQBit(bit))
-        }
-        else
-        {
-            self.ops.push(CircuitOp::Barrier(qbits.to_vec()));
-            Ok(())
-        }
+        let qbits = self.qbit_indices(qbits)?;
+        self.ops.push(CircuitOp::Barrier(qbits));
+        Ok(())
     }
 
     /// Execute this circuit
@@ -505,9 +1003,29 @@ impl Circuit
     /// Execute this circuit, performing its operations and measurements.
     /// Measurements are made over `nr_shots` executions of the circuit. This
     /// function clears any previous states of the system (quantum or classical).
+    /// Execution fails if a parameter in the circuit is unbound, or if the
+    /// memory budget is exceeded. On success, the measurement results of all
+    /// runs are returned in an `ExecutionResult`, together with the randomly
+    /// chosen seed that can be passed to `execute_with_seed()` to reproduce
+    /// them.
     pub fn execute(&mut self, nr_shots: usize)
+        -> crate::error::Result<crate::execution::ExecutionResult>
+    {
+        self.execute_with_seed(nr_shots, rand::random())
+    }
+
+    /// Execute this circuit
+    ///
+    /// Execute this circuit, performing its operations and measurements.
+    /// Measurements are made over `nr_shots` executions of the circuit, using
+    /// an HC-128 random number generator seeded with `seed` for sampling, so
+    /// that executions with the same seed give the same results. Otherwise,
+    /// this function is the same as `execute()`.
+    pub fn execute_with_seed(&mut self, nr_shots: usize, seed: u64)
+        -> crate::error::Result<crate::execution::ExecutionResult>
     {
-        self.execute_with_rng(nr_shots, &mut rand::thread_rng());
+        let mut rng = <rand_hc::Hc128Rng as rand::SeedableRng>::seed_from_u64(seed);
+        self.execute_helper(nr_shots, Some(seed), &mut rng)
     }
 
     /// Execute this circuit
@@ -515,14 +1033,79 @@ impl Circuit
     /// Execute this circuit, performing its operations and measurements.
     /// Measurements are made over `nr_shots` executions of the circuit, using
     /// random number generator `rng` for sampling. This function clears any
-    /// previous states of the system (quantum or classical).
+    /// previous states of the system (quantum or classical). Execution fails
+    /// if a parameter in the circuit is unbound, or if the memory budget is
+    /// exceeded. On success, the measurement results of all runs are returned
+    /// in an `ExecutionResult`, without a seed.
     pub fn execute_with_rng<R: rand::RngCore>(&mut self, nr_shots: usize, rng: &mut R)
+        -> crate::error::Result<crate::execution::ExecutionResult>
+    {
+        self.execute_helper(nr_shots, None, rng)
+    }
+
+    /// Execute this circuit with `nr_shots` runs, using random number
+    /// generator `rng`, which was seeded with `seed` if known.
+    fn execute_helper<R: rand::RngCore>(&mut self, nr_shots: usize, seed: Option<u64>,
+        rng: &mut R) -> crate::error::Result<crate::execution::ExecutionResult>
+    {
+        let start = ::std::time::Instant::now();
+
+        self.q_state = None;
+        self.c_state = None;
+        self.shot_order_seed = None;
+
+        let c_state_size = crate::cstate::CState::memory_size(self.nr_cbits, nr_shots);
+        let q_state_size = if self.sparse_state
+            {
+                0
+            }
+            else
+            {
+                crate::qustate::dense_state_size(self.nr_qbits).unwrap_or(usize::MAX)
+            };
+        let required = q_state_size.saturating_add(c_state_size);
+        let budget = self.memory_budget.unwrap_or(usize::MAX);
+        if required == usize::MAX || required > budget
+        {
+            return Err(crate::error::Error::MemoryBudgetExceeded(required, budget));
+        }
+
+        let mut q_state = if self.sparse_state
+            {
+                crate::qustate::QuState::new_sparse(self.nr_qbits, nr_shots)?
+            }
+            else
+            {
+                crate::qustate::QuState::new(self.nr_qbits, nr_shots)?
+            };
+        q_state.set_memory_budget(self.memory_budget.map(|budget| budget - c_state_size));
+        self.q_state = Some(q_state);
+        let c_state = crate::cstate::CState::new(self.nr_cbits, nr_shots);
+        self.c_state = Some(::std::sync::Arc::new(c_state));
+        let peak_nr_branches = self.run(rng)?;
+
+        // Runs that end up in the same branch of the quantum state are stored
+        // consecutively. Shuffle them, so that the order of the shots does not
+        // depend on the measured outcomes.
+        let order_seed = rng.next_u64();
+        let c_state = self.c_state.as_mut().unwrap();
+        ::std::sync::Arc::make_mut(c_state).permute(&Self::shot_order(nr_shots, order_seed));
+        self.shot_order_seed = Some(order_seed);
+        let memory = ::std::sync::Arc::clone(c_state);
+
+        Ok(crate::execution::ExecutionResult::new(memory, self.cregs.clone(), seed,
+            start.elapsed(), peak_nr_branches))
+    }
+
+    /// Return the order of the runs in the shots of an execution result, as a
+    /// random permutation of `nr_shots` runs drawn with a generator seeded
+    /// with `seed`.
+    fn shot_order(nr_shots: usize, seed: u64) -> Vec<usize>
     {
-        self.q_state = Some(crate::qustate::QuState::new(self.nr_qbits, nr_shots));
-        self.c_state = Some(ndarray::Array::zeros(nr_shots));
-        // It shpuld not be possible to get an error from reexecute, so
-        // ignore it for now.
-        self.reexecute_with_rng(rng).unwrap();
+        let mut rng = <rand_hc::Hc128Rng as rand::SeedableRng>::seed_from_u64(seed);
+        let mut order: Vec<usize> = (0..nr_shots).collect();
+        rand::seq::SliceRandom::shuffle(order.as_mut_slice(), &mut rng);
+        order
     }
 
     /// Execute a circuit again.
@@ -543,129 +1126,168 @@ impl Circuit
     pub fn reexecute_with_rng<R: rand::Rng>(&mut self, rng: &mut R)
         -> crate::error::Result<()>
     {
+        self.run(rng).map(|_| ())
+    }
+
+    /// Perform the operations in this circuit on the current state, using
+    /// random number generator `rng` for sampling. Return the largest number
+    /// of branches of the quantum state after any of the operations.
+    fn run<R: rand::Rng>(&mut self, rng: &mut R) -> crate::error::Result<usize>
+    {
+        self.check_bound()?;
         if self.q_state.is_none() || self.c_state.is_none()
         {
             return Err(crate::error::Error::NotExecuted);
         }
 
+        let c_state = ::std::sync::Arc::make_mut(self.c_state.as_mut().unwrap());
+        if let Some(seed) = self.shot_order_seed.take()
+        {
+            // Put the runs back in the order of the quantum state
+            let order = Self::shot_order(c_state.nr_shots(), seed);
+            let mut inverse = vec![0; order.len()];
+            for (shot, &run) in order.iter().enumerate()
+            {
+                inverse[run] = shot;
+            }
+            c_state.permute(&inverse);
+        }
+
         let q_state = self.q_state.as_mut().unwrap();
-        let c_state = self.c_state.as_mut().unwrap();
+        let mut fusion = crate::fusion::GateFusion::new(self.max_fusion_bits);
+        let mut peak_nr_branches = q_state.nr_branches();
 
         for op in self.ops.iter()
         {
             match *op
             {
                 CircuitOp::Gate(ref gate, ref bits) => {
-                    q_state.apply_gate(&**gate, bits.as_slice());
+                    fusion.add(&**gate, bits.as_slice(), &mut |fop| fop.apply(q_state))?;
+                },
+                CircuitOp::ParametricGate(ref gate, ref bits) => {
+                    fusion.add(gate.gate()?, bits.as_slice(), &mut |fop| fop.apply(q_state))?;
                 },
                 CircuitOp::ConditionalGate(ref control, target, ref gate, ref bits) => {
-                    let mut cbits = vec![0; c_state.len()];
-                    for (idst, &isrc) in control.iter().enumerate()
-                    {
-                        for (db, &sb) in cbits.iter_mut().zip(c_state.iter())
-                        {
-                            *db |= ((sb >> isrc) & 1) << idst;
-                        }
-                    }
-                    let apply_gate: Vec<bool> = cbits.iter()
-                        .map(|&b| b == target)
-                        .collect();
+                    fusion.flush(&mut |fop| fop.apply(q_state))?;
+                    let apply_gate = c_state.matches(control, target);
                     q_state.apply_conditional_gate(&apply_gate, &**gate,
-                        bits.as_slice());
+                        bits.as_slice())?;
                 },
                 CircuitOp::Measure(qbit, cbit, basis) => {
+                    fusion.flush(&mut |fop| fop.apply(q_state))?;
                     match basis
                     {
                         Basis::X => {
-                            q_state.apply_gate(&crate::gates::H::new(), &[qbit]);
-                            q_state.measure_into(qbit, cbit, c_state, rng);
-                            q_state.apply_gate(&crate::gates::H::new(), &[qbit]);
+                            q_state.apply_gate(&crate::gates::H::new(), &[qbit])?;
+                            c_state.store_measurement(&[cbit],
+                                |res, cbits| q_state.measure_into(qbit, cbits[0], res, rng))?;
+                            q_state.apply_gate(&crate::gates::H::new(), &[qbit])?;
                         },
                         Basis::Y => {
-                            q_state.apply_gate(&crate::gates::Sdg::new(), &[qbit]);
-                            q_state.apply_gate(&crate::gates::H::new(), &[qbit]);
-                            q_state.measure_into(qbit, cbit, c_state, rng);
-                            q_state.apply_gate(&crate::gates::H::new(), &[qbit]);
-                            q_state.apply_gate(&crate::gates::S::new(), &[qbit]);
+                            q_state.apply_gate(&crate::gates::Sdg::new(), &[qbit])?;
+                            q_state.apply_gate(&crate::gates::H::new(), &[qbit])?;
+                            c_state.store_measurement(&[cbit],
+                                |res, cbits| q_state.measure_into(qbit, cbits[0], res, rng))?;
+                            q_state.apply_gate(&crate::gates::H::new(), &[qbit])?;
+                            q_state.apply_gate(&crate::gates::S::new(), &[qbit])?;
                         },
                         Basis::Z => {
-                            q_state.measure_into(qbit, cbit, c_state, rng);
+                            c_state.store_measurement(&[cbit],
+                                |res, cbits| q_state.measure_into(qbit, cbits[0], res, rng))?;
                         }
                     }
                 }
                 CircuitOp::MeasureAll(ref cbits, basis) => {
+                    fusion.flush(&mut |fop| fop.apply(q_state))?;
                     match basis
                     {
                         Basis::X => {
-                            q_state.apply_unary_gate_all(&crate::gates::H::new());
-                            q_state.measure_all_into(cbits, c_state, rng);
-                            q_state.apply_unary_gate_all(&crate::gates::H::new());
+                            q_state.apply_unary_gate_all(&crate::gates::H::new())?;
+                            c_state.store_measurement(cbits,
+                                |res, cbits| q_state.measure_all_into(cbits, res, rng))?;
+                            q_state.apply_unary_gate_all(&crate::gates::H::new())?;
                         },
                         Basis::Y => {
-                            q_state.apply_unary_gate_all(&crate::gates::Sdg::new());
-                            q_state.apply_unary_gate_all(&crate::gates::H::new());
-                            q_state.measure_all_into(cbits, c_state, rng);
-                            q_state.apply_unary_gate_all(&crate::gates::H::new());
-                            q_state.apply_unary_gate_all(&crate::gates::S::new());
+                            q_state.apply_unary_gate_all(&crate::gates::Sdg::new())?;
+                            q_state.apply_unary_gate_all(&crate::gates::H::new())?;
+                            c_state.store_measurement(cbits,
+                                |res, cbits| q_state.measure_all_into(cbits, res, rng))?;
+                            q_state.apply_unary_gate_all(&crate::gates::H::new())?;
+                            q_state.apply_unary_gate_all(&crate::gates::S::new())?;
                         },
                         Basis::Z => {
-                            q_state.measure_all_into(cbits, c_state, rng);
+                            c_state.store_measurement(cbits,
+                                |res, cbits| q_state.measure_all_into(cbits, res, rng))?;
                         }
                     }
                 },
                 CircuitOp::Peek(qbit, cbit, basis) => {
+                    fusion.flush(&mut |fop| fop.apply(q_state))?;
                     match basis
                     {
                         Basis::X => {
-                            q_state.apply_gate(&crate::gates::H::new(), &[qbit]);
-                            q_state.peek_into(qbit, cbit, c_state, rng);
-                            q_state.apply_gate(&crate::gates::H::new(), &[qbit]);
+                            q_state.apply_gate(&crate::gates::H::new(), &[qbit])?;
+                            c_state.store_measurement(&[cbit],
+                                |res, cbits| q_state.peek_into(qbit, cbits[0], res, rng))?;
+                            q_state.apply_gate(&crate::gates::H::new(), &[qbit])?;
                         },
                         Basis::Y => {
-                            q_state.apply_gate(&crate::gates::Sdg::new(), &[qbit]);
-                            q_state.apply_gate(&crate::gates::H::new(), &[qbit]);
-                            q_state.peek_into(qbit, cbit, c_state, rng);
-                            q_state.apply_gate(&crate::gates::H::new(), &[qbit]);
-                            q_state.apply_gate(&crate::gates::S::new(), &[qbit]);
+                            q_state.apply_gate(&crate::gates::Sdg::new(), &[qbit])?;
+                            q_state.apply_gate(&crate::gates::H::new(), &[qbit])?;
+                            c_state.store_measurement(&[cbit],
+                                |res, cbits| q_state.peek_into(qbit, cbits[0], res, rng))?;
+                            q_state.apply_gate(&crate::gates::H::new(), &[qbit])?;
+                            q_state.apply_gate(&crate::gates::S::new(), &[qbit])?;
                         },
                         Basis::Z => {
-                            q_state.peek_into(qbit, cbit, c_state, rng);
+                            c_state.store_measurement(&[cbit],
+                                |res, cbits| q_state.peek_into(qbit, cbits[0], res, rng))?;
                         }
                     }
                 },
                 CircuitOp::PeekAll(ref cbits, basis) => {
+                    fusion.flush(&mut |fop| fop.apply(q_state))?;
                     match basis
                     {
                         Basis::X => {
-                            q_state.apply_unary_gate_all(&crate::gates::H::new());
-                            q_state.peek_all_into(cbits, c_state, rng);
-                            q_state.apply_unary_gate_all(&crate::gates::H::new());
+                            q_state.apply_unary_gate_all(&crate::gates::H::new())?;
+                            c_state.store_measurement(cbits,
+                                |res, cbits| q_state.peek_all_into(cbits, res, rng))?;
+                            q_state.apply_unary_gate_all(&crate::gates::H::new())?;
                         },
                         Basis::Y => {
-                            q_state.apply_unary_gate_all(&crate::gates::Sdg::new());
-                            q_state.apply_unary_gate_all(&crate::gates::H::new());
-                            q_state.peek_all_into(cbits, c_state, rng);
-                            q_state.apply_unary_gate_all(&crate::gates::H::new());
-                            q_state.apply_unary_gate_all(&crate::gates::S::new());
+                            q_state.apply_unary_gate_all(&crate::gates::Sdg::new())?;
+                            q_state.apply_unary_gate_all(&crate::gates::H::new())?;
+                            c_state.store_measurement(cbits,
+                                |res, cbits| q_state.peek_all_into(cbits, res, rng))?;
+                            q_state.apply_unary_gate_all(&crate::gates::H::new())?;
+                            q_state.apply_unary_gate_all(&crate::gates::S::new())?;
                         },
                         Basis::Z => {
-                            q_state.peek_all_into(cbits, c_state, rng);
+                            c_state.store_measurement(cbits,
+                                |res, cbits| q_state.peek_all_into(cbits, res, rng))?;
                         }
                     }
                 },
                 CircuitOp::Reset(bit) => {
-                    q_state.reset(bit, rng);
+                    fusion.flush(&mut |fop| fop.apply(q_state))?;
+                    q_state.reset(bit, rng)?;
                 },
                 CircuitOp::ResetAll => {
+                    fusion.flush(&mut |fop| fop.apply(q_state))?;
                     q_state.reset_all();
                 },
                 CircuitOp::Barrier(_) => {
-                    /* Nothing to be done */
+                    // Gates are not fused across a barrier
+                    fusion.flush(&mut |fop| fop.apply(q_state))?;
                 }
             }
+
+            peak_nr_branches = peak_nr_branches.max(q_state.nr_branches());
         }
+        fusion.flush(&mut |fop| fop.apply(q_state))?;
 
-        Ok(())
+        Ok(peak_nr_branches)
     }
 
     /// Create a histogram of measurements.
@@ -675,19 +1297,14 @@ impl Circuit
     /// first bit in the classical register (at index 0) corresponds to the
     /// least significant bit in the key; the last classical bit (at index `n-1`)
     /// to the most significant bit in the key. This function of course only works
-    /// when there are at most 64 bits in the register. If there are more, use
-    /// `histogram_string()`.
+    /// when there are at most 64 bits in the register. If there are more, a
+    /// `TooManyCBits` error is returned, and `histogram_string()` should be
+    /// used instead.
     pub fn histogram(&self) -> crate::error::Result<crate::idhash::U64HashMap<usize>>
     {
         if let Some(ref c_state) = self.c_state
         {
-            let mut res = crate::idhash::new_u64_hash_map();
-            for &key in c_state
-            {
-                let count = res.entry(key).or_insert(0);
-                *count += 1;
-            }
-            Ok(res)
+            c_state.histogram()
         }
         else
         {
@@ -703,112 +1320,415 @@ impl Circuit
     /// is the same as in the `histogram()` function. The vector is of length
     /// `2`<sub>`n`</sub>, so use this function only for reasonably small
     /// numbers of `n`. For sparse collections, using `histogram()` or
-    /// `histogram_string` may be better.
+    /// `histogram_string` may be better. If the length of the vector can not
+    /// be represented, a `TooManyCBits` error is returned.
     pub fn histogram_vec(&self) -> crate::error::Result<Vec<usize>>
     {
         if let Some(ref c_state) = self.c_state
         {
-            let mut res = vec![0; 1 << self.nr_cbits];
-            for &key in c_state
+            c_state.histogram_vec()
+        }
+        else
+        {
+            Err(crate::error::Error::NotExecuted)
+        }
+    }
+
+    /// Create a histogram of measurements.
+    ///
+    /// Create a histogram of the measured classical bits. The `n` bits in the
+    /// classical register are collected in a string key, with the last character
+    /// in the key corresponding to the first bit (at index 0) in the classical
+    /// register and vice versa. Unlike `histogram()`, this works for classical
+    /// registers of any width.
+    pub fn histogram_string(&self)
+        -> crate::error::Result<::std::collections::HashMap<String, usize>>
+    {
+        if let Some(ref c_state) = self.c_state
+        {
+            Ok(c_state.histogram_string())
+        }
+        else
+        {
+            Err(crate::error::Error::NotExecuted)
+        }
+    }
+
+    /// Create a histogram of measurements in a register.
+    ///
+    /// Create a histogram of the measured values of the bits in the classical
+    /// register called `name`. As in `histogram_string()`, the bits are
+    /// collected in a string key, with the last character in the key
+    /// corresponding to the first bit in the register and vice versa. If no
+    /// register with this name exists, an `UnknownRegister` error is returned.
+    pub fn register_histogram(&self, name: &str)
+        -> crate::error::Result<::std::collections::HashMap<String, usize>>
+    {
+        let reg = self.creg(name)?;
+        if let Some(ref c_state) = self.c_state
+        {
+            Ok(c_state.marginal(&reg.bits())?.histogram_string())
+        }
+        else
+        {
+            Err(crate::error::Error::NotExecuted)
+        }
+    }
+
+    /// Compute an expectation value.
+    ///
+    /// Compute the average of `observable` over the classical register values
+    /// of all runs of the last execution of this circuit. Function
+    /// `observable` maps the value of the classical register, in the same
+    /// bit order as used in `histogram()`, to the value of the observable.
+    /// If this circuit has not been run yet, a `NotExecuted` error is returned,
+    /// and if it has more than 64 classical bits, a `TooManyCBits` error.
+    pub fn expectation<F>(&self, observable: F) -> crate::error::Result<f64>
+    where F: Fn(u64) -> f64
+    {
+        if let Some(ref c_state) = self.c_state
+        {
+            let c_state = c_state.values()?;
+            if c_state.is_empty()
+            {
+                Ok(0.0)
+            }
+            else
+            {
+                let sum: f64 = c_state.iter().map(|&key| observable(key)).sum();
+                Ok(sum / c_state.len() as f64)
+            }
+        }
+        else
+        {
+            Err(crate::error::Error::NotExecuted)
+        }
+    }
+
+    /// Compute a gradient.
+    ///
+    /// Compute the gradient of the expectation value of `observable` (see
+    /// `expectation()`) with respect to all parameters in this circuit, using
+    /// the parameter shift rule. For every argument of a parametric gate that
+    /// depends on a parameter, the circuit is executed with `nr_shots` runs
+    /// for each of the shifted argument values. Only the `RX`, `RY`, `RZ`,
+    /// `U1`, `U3`, `CRX`, `CRY`, and `CRZ` gates are supported; for other
+    /// parametric gates, a `NoGradient` error is returned. The arguments of
+    /// the gates, and the results of any previous execution of this circuit,
+    /// are left unchanged, also when an error occurs.
+    pub fn gradient<F>(&mut self, nr_shots: usize, observable: F)
+        -> crate::error::Result<::std::collections::HashMap<String, f64>>
+    where F: Fn(u64) -> f64
+    {
+        self.gradient_with_rng(nr_shots, observable, &mut rand::thread_rng())
+    }
+
+    /// Compute a gradient.
+    ///
+    /// Compute the gradient of the expectation value of `observable` with
+    /// respect to all parameters in this circuit, using the parameter shift
+    /// rule, and random number generator `rng` for sampling. See `gradient()`
+    /// for details.
+    pub fn gradient_with_rng<F, R>(&mut self, nr_shots: usize, observable: F, rng: &mut R)
+        -> crate::error::Result<::std::collections::HashMap<String, f64>>
+    where F: Fn(u64) -> f64, R: rand::RngCore
+    {
+        self.check_bound()?;
+
+        // Keep the results of a previous execution out of the way, so that
+        // they can be restored afterwards
+        let q_state = self.q_state.take();
+        let c_state = self.c_state.take();
+        let shot_order_seed = self.shot_order_seed.take();
+        let res = self.gradient_helper(nr_shots, &observable, rng);
+        self.q_state = q_state;
+        self.c_state = c_state;
+        self.shot_order_seed = shot_order_seed;
+
+        res
+    }
+
+    /// Compute a gradient.
+    ///
+    /// Compute the gradient of the expectation value of `observable` with
+    /// respect to all parameters in this circuit, executing the circuit with
+    /// `nr_shots` runs for every shifted argument, and using random number
+    /// generator `rng` for sampling.
+    fn gradient_helper<F, R>(&mut self, nr_shots: usize, observable: &F, rng: &mut R)
+        -> crate::error::Result<::std::collections::HashMap<String, f64>>
+    where F: Fn(u64) -> f64, R: rand::RngCore
+    {
+        let mut grad: ::std::collections::HashMap<String, f64> = self.parameters()
+            .into_iter()
+            .map(|name| (name, 0.0))
+            .collect();
+        for iop in 0..self.ops.len()
+        {
+            let (rule, args, exprs) = match self.ops[iop]
+            {
+                CircuitOp::ParametricGate(ref gate, _) if gate.args().iter().any(|a| !a.is_constant()) => {
+                    let rule = crate::gradient::shift_rule(gate)?;
+                    (rule, gate.arg_values(&self.param_values)?, gate.args().to_vec())
+                },
+                _ => continue
+            };
+
+            for (iarg, expr) in exprs.iter().enumerate().filter(|(_, e)| !e.is_constant())
+            {
+                let mut deriv = 0.0;
+                for &(shift, weight) in rule
+                {
+                    let mut shifted = args.clone();
+                    shifted[iarg] += shift;
+                    deriv += weight * self.shifted_expectation(iop, &shifted, &args,
+                        nr_shots, observable, rng)?;
+                }
+
+                for name in expr.parameters()
+                {
+                    *grad.get_mut(name).unwrap() += expr.coefficient(name) * deriv;
+                }
+            }
+        }
+
+        Ok(grad)
+    }
+
+    /// Compute an expectation value with shifted arguments.
+    ///
+    /// Compute the expectation value of `observable` over `nr_shots` runs of
+    /// this circuit, with the arguments of the parametric gate at operation
+    /// index `iop` set to `shifted`. Afterwards, the arguments of the gate
+    /// are reset to `args`, whether the execution succeeded or not.
+    fn shifted_expectation<F, R>(&mut self, iop: usize, shifted: &[f64], args: &[f64],
+        nr_shots: usize, observable: &F, rng: &mut R) -> crate::error::Result<f64>
+    where F: Fn(u64) -> f64, R: rand::RngCore
+    {
+        if let CircuitOp::ParametricGate(ref mut gate, _) = self.ops[iop]
+        {
+            gate.bind_args(shifted);
+        }
+        let res = self.execute_with_rng(nr_shots, rng)
+            .and_then(|_| self.expectation(observable));
+        if let CircuitOp::ParametricGate(ref mut gate, _) = self.ops[iop]
+        {
+            gate.bind_args(args);
+        }
+
+        res
+    }
+
+    /// Check that this circuit is a unitary transformation.
+    ///
+    /// Check that this circuit only contains (possibly parametric) gates and
+    /// barriers. If it contains any other operation, a `NonUnitaryOperation`
+    /// error is returned.
+    fn check_unitary(&self) -> crate::error::Result<()>
+    {
+        for op in self.ops.iter()
+        {
+            let desc = match *op
             {
-                res[key as usize] += 1;
-            }
-            Ok(res)
+                CircuitOp::Gate(_, _)
+                | CircuitOp::ParametricGate(_, _)
+                | CircuitOp::Barrier(_)              => continue,
+                CircuitOp::ConditionalGate(_, _, _, _) => "conditional gate",
+                CircuitOp::Reset(_)
+                | CircuitOp::ResetAll                => "reset",
+                CircuitOp::Measure(_, _, _)
+                | CircuitOp::MeasureAll(_, _)        => "measure",
+                CircuitOp::Peek(_, _, _)
+                | CircuitOp::PeekAll(_, _)           => "peek"
+            };
+            return Err(crate::error::Error::NonUnitaryOperation(String::from(desc)));
+        }
+
+        Ok(())
+    }
+
+    /// Check the size of an observable.
+    fn check_observable(&self, observable: &crate::cmatrix::CMatrix)
+        -> crate::error::Result<()>
+    {
+        let size = 1 << self.nr_qbits;
+        if observable.rows() != size || observable.cols() != size
+        {
+            Err(crate::error::Error::InvalidMatrixSize(observable.rows(), observable.cols()))
         }
         else
         {
-            Err(crate::error::Error::NotExecuted)
+            Ok(())
         }
     }
 
-    /// Create a histogram of measurements.
+    /// Compute the final state vector.
     ///
-    /// Create a histogram of the measured classical bits. The `n` bits in the
-    /// classical register are collected in a string key, with the last character
-    /// in the key corresponding to the first bit (at index 0) in the classical
-    /// register and vice versa.
-    pub fn histogram_string(&self)
-        -> crate::error::Result<::std::collections::HashMap<String, usize>>
+    /// Compute the state vector resulting from applying the operations in
+    /// this circuit to the |00...0⟩ state. This circuit is assumed to be a
+    /// unitary transformation, with all its parameters bound.
+    fn final_state(&self) -> crate::error::Result<crate::cmatrix::CVector>
     {
-        if let Some(ref c_state) = self.c_state
+        let mut psi = crate::cmatrix::CVector::zeros(1 << self.nr_qbits);
+        psi[0] = crate::cmatrix::COMPLEX_ONE;
+        for op in self.ops.iter()
         {
-            let mut res = ::std::collections::HashMap::new();
-            for &key in c_state
+            match *op
             {
-                let skey = format!("{:0width$b}", key, width=self.nr_cbits);
-                let count = res.entry(skey).or_insert(0);
-                *count += 1;
+                CircuitOp::Gate(ref gate, ref bits) => {
+                    crate::gates::apply_gate_slice(psi.view_mut(), &**gate, bits, self.nr_qbits)?;
+                },
+                CircuitOp::ParametricGate(ref gate, ref bits) => {
+                    crate::gates::apply_gate_slice(psi.view_mut(), gate.gate()?, bits,
+                        self.nr_qbits)?;
+                },
+                _ => { /* Only barriers remain */ }
             }
-            Ok(res)
-        }
-        else
-        {
-            Err(crate::error::Error::NotExecuted)
         }
+
+        Ok(psi)
     }
 
-    fn is_full_register(&self, control: &[usize]) -> bool
+    /// Compute an exact expectation value.
+    ///
+    /// Compute the expectation value ⟨ψ|O|ψ⟩ of the Hermitian matrix
+    /// `observable` in the state |ψ⟩ produced by this circuit, without
+    /// sampling. The circuit may only contain gates and barriers, otherwise
+    /// a `NonUnitaryOperation` error is returned.
+    pub fn exact_expectation(&self, observable: &crate::cmatrix::CMatrix)
+        -> crate::error::Result<f64>
     {
-        let n = control.len();
-        if n != self.nr_cbits
-        {
-            return false;
-        }
+        self.check_observable(observable)?;
+        self.check_unitary()?;
+        self.check_bound()?;
+
+        let psi = self.final_state()?;
+        let opsi = observable.dot(&psi);
+        Ok(psi.iter().zip(opsi.iter()).map(|(a, b)| (a.conj() * b).re).sum())
+    }
 
-        let mut scontrol = vec![0; n];
-        scontrol.copy_from_slice(control);
-        scontrol.sort();
-        for i in 0..n
+    /// Compute an exact gradient.
+    ///
+    /// Compute the gradient of the expectation value of the Hermitian matrix
+    /// `observable` with respect to all parameters in this circuit, without
+    /// sampling. The gradient is computed using the adjoint state method:
+    /// after a forward pass computing the final state, the gates are undone
+    /// one by one in reverse order, while simultaneously propagating
+    /// `observable` applied to the final state. This requires a single
+    /// evaluation of the circuit, regardless of the number of parameters.
+    /// The same restrictions on the circuit apply as for `exact_expectation()`
+    /// and `gradient()`.
+    pub fn exact_gradient(&self, observable: &crate::cmatrix::CMatrix)
+        -> crate::error::Result<::std::collections::HashMap<String, f64>>
+    {
+        self.check_observable(observable)?;
+        self.check_unitary()?;
+        self.check_bound()?;
+
+        let mut grad: ::std::collections::HashMap<String, f64> = self.parameters()
+            .into_iter()
+            .map(|name| (name, 0.0))
+            .collect();
+
+        // The state |ψ⟩ and the adjoint state |λ⟩ = O|ψ⟩ are stored as the
+        // two columns of a single matrix, so that the inverse of each gate
+        // is applied to both in the same way as gates are applied to a
+        // quantum state.
+        let nr_bits = self.nr_qbits;
+        let psi = self.final_state()?;
+        let mut states = crate::cmatrix::CMatrix::zeros((psi.len(), 2));
+        states.column_mut(1).assign(&observable.dot(&psi));
+        states.column_mut(0).assign(&psi);
+        for op in self.ops.iter().rev()
         {
-            if scontrol[i] != i
+            match *op
             {
-                return false;
+                CircuitOp::Gate(ref gate, ref bits) => {
+                    let adjoint = crate::gates::MatrixGate::adjoint(&**gate);
+                    crate::gates::apply_gate_mat_slice(states.view_mut(), &adjoint, bits,
+                        nr_bits)?;
+                },
+                CircuitOp::ParametricGate(ref gate, ref bits) => {
+                    let adjoint = crate::gates::MatrixGate::adjoint(gate.gate()?);
+                    crate::gates::apply_gate_mat_slice(states.view_mut(), &adjoint, bits,
+                        nr_bits)?;
+
+                    // With both states taken back to before gate U, the
+                    // derivative is 2 Re ⟨λ|U†∂U|ψ⟩.
+                    let args = gate.arg_values(&self.param_values)?;
+                    for (iarg, expr) in gate.args().iter().enumerate()
+                        .filter(|(_, e)| !e.is_constant())
+                    {
+                        let deriv = crate::gates::MatrixGate::new(
+                            crate::gates::Gate::matrix(&adjoint)
+                                .dot(&crate::gradient::derivative_matrix(gate, &args, iarg)?)
+                        );
+                        let mut dpsi = states.column(0).to_owned();
+                        crate::gates::apply_gate_slice(dpsi.view_mut(), &deriv, bits, nr_bits)?;
+                        let value: f64 = 2.0 * states.column(1).iter().zip(dpsi.iter())
+                            .map(|(l, d)| (l.conj() * d).re)
+                            .sum::<f64>();
+                        for name in expr.parameters()
+                        {
+                            *grad.get_mut(name).unwrap() += expr.coefficient(name) * value;
+                        }
+                    }
+                },
+                _ => { /* Only barriers remain */ }
             }
         }
 
-        true
+        Ok(grad)
     }
 
-    fn check_open_qasm_condition_bits(&self, control: &[usize])
-        -> crate::error::ExportResult<()>
+    /// Find the classical register whose bits are exactly the bits in
+    /// `control`, in any order. OpenQasm can only condition operations on
+    /// the value of a full register, so if no such register exists, an
+    /// `IncompleteConditionRegister` error is returned.
+    fn open_qasm_condition_register(&self, control: &[usize])
+        -> crate::error::ExportResult<&crate::register::Register>
     {
-        if !self.is_full_register(control)
-        {
-            Err(crate::error::ExportError::IncompleteConditionRegister)
-        }
-        else
-        {
-            Ok(())
-        }
+        self.cregs.iter().find(|reg| reg.is_spanned_by(control))
+            .ok_or(crate::error::ExportError::IncompleteConditionRegister)
     }
 
     /// Export to OpenQasm
     ///
     /// Export this circuit to a program in OpenQasm format. On a successful
     /// conversion, the result is `Ok` with the program text. When the conversion
-    /// to OpenQasm fails, `Err` with an error message is returned.
+    /// to OpenQasm fails, `Err` with an error message is returned. Each named
+    /// quantum or classical register in the circuit is declared as a separate
+    /// register in the program.
     pub fn open_qasm(&self) -> crate::error::Result<String>
     {
         let mut res = String::from("OPENQASM 2.0;\ninclude \"qelib1.inc\";\n");
 
+        let qregs: Vec<&crate::register::Register> = self.qregs.iter()
+            .filter(|reg| reg.size() > 0)
+            .collect();
+        let cregs: Vec<&crate::register::Register> = self.cregs.iter()
+            .filter(|reg| reg.size() > 0)
+            .collect();
+
         let mut qbit_names = vec![];
-        if self.nr_qbits > 0
+        for reg in qregs.iter()
         {
-            res += &format!("qreg q[{}];\n", self.nr_qbits);
-            for i in 0..self.nr_qbits
+            res += &format!("qreg {}[{}];\n", reg.name(), reg.size());
+            for i in 0..reg.size()
             {
-                qbit_names.push(format!("q[{}]", i));
+                qbit_names.push(format!("{}[{}]", reg.name(), i));
             }
         }
         let mut cbit_names = vec![];
-        if self.nr_cbits > 0
+        for reg in cregs.iter()
         {
-            res += &format!("creg b[{}];\n", self.nr_cbits);
-            for i in 0..self.nr_cbits
+            res += &format!("creg {}[{}];\n", reg.name(), reg.size());
+            for i in 0..reg.size()
             {
-                cbit_names.push(format!("b[{}]", i));
+                cbit_names.push(format!("{}[{}]", reg.name(), i));
             }
         }
+        let qreg_names: Vec<String> = qregs.iter().map(|reg| String::from(reg.name())).collect();
+        let all_qregs: Vec<usize> = (0..qreg_names.len()).collect();
 
         for op in self.ops.iter()
         {
@@ -817,6 +1737,9 @@ impl Circuit
                 CircuitOp::Gate(ref gate, ref bits) => {
                     res += &format!("{};\n", gate.open_qasm(&qbit_names, bits)?);
                 },
+                CircuitOp::ParametricGate(ref gate, ref bits) => {
+                    res += &format!("{};\n", gate.gate()?.open_qasm(&qbit_names, bits)?);
+                },
                 CircuitOp::ConditionalGate(ref control, target, ref gate, ref bits) => {
                     if control.is_empty()
                     {
@@ -824,15 +1747,15 @@ impl Circuit
                     }
                     else
                     {
-                        // We do require that the control bits span the entire classical
-                        // register, but not necessarily in the order 0..#bits.
-                        self.check_open_qasm_condition_bits(control)?;
+                        // We do require that the control bits span an entire classical
+                        // register, but not necessarily in the order of the register.
+                        let reg = self.open_qasm_condition_register(control)?;
                         let mut starget = 0;
-                        for (tshift, sshift) in control.iter().enumerate()
+                        for (tshift, &cbit) in control.iter().enumerate()
                         {
-                            starget |= ((target >> tshift) & 0x01) << sshift;
+                            starget |= ((target >> tshift) & 0x01) << (cbit - reg.offset());
                         }
-                        let condition = format!("b == {}", starget);
+                        let condition = format!("{} == {}", reg.name(), starget);
                         let gate_qasm = gate.conditional_open_qasm(&condition, &qbit_names, bits)?;
                         res += &format!("{};\n", gate_qasm);
                     }
@@ -855,34 +1778,43 @@ impl Circuit
                     res += &format!("measure {} -> {};\n", qbit_names[qbit], cbit_names[cbit]);
                 }
                 CircuitOp::MeasureAll(ref cbits, basis) => {
-                    match basis
+                    // Qubits may have been added after the measurement
+                    self.check_nr_measured_bits(cbits, "measure all")?;
+                    for idx in all_qregs.iter()
                     {
-                        Basis::X => {
-                            let names = [String::from("q")];
-                            res += &format!("{};\n",
-                                crate::gates::H::new().open_qasm(&names, &[0])?);
-                        },
-                        Basis::Y => {
-                            let names = [String::from("q")];
-                            res += &format!("{};\n",
-                                crate::gates::Sdg::new().open_qasm(&names, &[0])?);
-                            res += &format!("{};\n",
-                                crate::gates::H::new().open_qasm(&names, &[0])?);
+                        match basis
+                        {
+                            Basis::X => {
+                                res += &format!("{};\n",
+                                    crate::gates::H::new().open_qasm(&qreg_names, &[*idx])?);
+                            },
+                            Basis::Y => {
+                                res += &format!("{};\n",
+                                    crate::gates::Sdg::new().open_qasm(&qreg_names, &[*idx])?);
+                                res += &format!("{};\n",
+                                    crate::gates::H::new().open_qasm(&qreg_names, &[*idx])?);
+                            }
+                            _ => {}
                         }
-                        _ => {}
                     }
 
-                    if cbits.len() == self.nr_cbits
-                        && cbits.iter().enumerate().all(|(i, &b)| i==b)
-                    {
-                        res += &format!("measure q -> b;\n");
-                    }
-                    else
+                    // Measure a full quantum register at once, if its qubits are
+                    // measured into a full classical register in the same order.
+                    for qreg in qregs.iter()
                     {
-                        for (qbit, &cbit) in cbits.iter().enumerate()
+                        let reg_cbits = &cbits[qreg.offset()..qreg.offset()+qreg.size()];
+                        let creg = cregs.iter().find(|creg| creg.bits() == reg_cbits);
+                        if let Some(creg) = creg
+                        {
+                            res += &format!("measure {} -> {};\n", qreg.name(), creg.name());
+                        }
+                        else
                         {
-                            res += &format!("measure {} -> {};\n", qbit_names[qbit],
-                                cbit_names[cbit]);
+                            for (qbit, &cbit) in qreg.bits().into_iter().zip(reg_cbits)
+                            {
+                                res += &format!("measure {} -> {};\n", qbit_names[qbit],
+                                    cbit_names[cbit]);
+                            }
                         }
                     }
                 },
@@ -900,13 +1832,16 @@ impl Circuit
                     res += &format!("reset {};\n", qbit_names[qbit]);
                 },
                 CircuitOp::ResetAll => {
-                    res += "reset q;\n";
+                    for name in qreg_names.iter()
+                    {
+                        res += &format!("reset {};\n", name);
+                    }
                 },
                 CircuitOp::Barrier(ref qbits) => {
                     if qbits.len() == self.nr_qbits
                         && qbits.iter().enumerate().all(|(i, &b)| i==b)
                     {
-                        res += "barrier q;\n";
+                        res += &format!("barrier {};\n", qreg_names.join(", "));
                     }
                     else
                     {
@@ -963,6 +1898,9 @@ impl Circuit
                 CircuitOp::Gate(ref gate, ref bits) => {
                     res += &format!("{}\n", gate.c_qasm(&qbit_names, bits)?);
                 },
+                CircuitOp::ParametricGate(ref gate, ref bits) => {
+                    res += &format!("{}\n", gate.gate()?.c_qasm(&qbit_names, bits)?);
+                },
                 CircuitOp::ConditionalGate(ref control, target, ref gate, ref bits) => {
                     if control.is_empty()
                     {
@@ -1029,7 +1967,7 @@ impl Circuit
                             /* do nothing */
                         }
                     }
-                    res += &format!("measure_all\n");
+                    res += "measure_all\n";
                 },
                 CircuitOp::Peek(_, _, _) => {
                     return Err(crate::error::Error::from(
@@ -1045,9 +1983,9 @@ impl Circuit
                     res += &format!("prep_z {}\n", qbit_names[qbit]);
                 },
                 CircuitOp::ResetAll => {
-                    for i in 0..self.nr_qbits
+                    for name in qbit_names.iter()
                     {
-                        res += &format!("prep_z {}\n", qbit_names[i]);
+                        res += &format!("prep_z {}\n", name);
                     }
                 },
                 CircuitOp::Barrier(_) => {
@@ -1069,6 +2007,9 @@ impl Circuit
                 CircuitOp::Gate(ref gate, ref bits) => {
                     gate.latex(bits, &mut state)?;
                 },
+                CircuitOp::ParametricGate(ref gate, ref bits) => {
+                    gate.latex(bits, &mut state)?;
+                },
                 CircuitOp::ConditionalGate(ref control, target, ref gate, ref bits) => {
                     state.start_range_op(bits, Some(control))?;
                     let controlled = state.set_controlled(true);
@@ -1138,7 +2079,11 @@ macro_rules! circuit_method_check
 {
     ( add_conditional_gate $res:expr ) => { $res? };
     ( add_gate $res:expr ) => { $res? };
+    ( add_parametric_gate $res:expr ) => { $res? };
     ( barrier $res:expr ) => { $res? };
+    ( crx_param $res:expr ) => { $res? };
+    ( cry_param $res:expr ) => { $res? };
+    ( crz_param $res:expr ) => { $res? };
     ( cx $res:expr ) => { $res? };
     ( h $res:expr ) => { $res? };
     ( measure $res:expr ) => { $res? };
@@ -1154,8 +2099,19 @@ macro_rules! circuit_method_check
     ( peek_all $res:expr ) => { $res? };
     ( peek_all_basis $res:expr ) => { $res? };
     ( reset $res:expr ) => { $res? };
+    ( rx $res:expr ) => { $res? };
+    ( rx_param $res:expr ) => { $res? };
+    ( ry $res:expr ) => { $res? };
+    ( ry_param $res:expr ) => { $res? };
+    ( rz $res:expr ) => { $res? };
+    ( rz_param $res:expr ) => { $res? };
     ( s $res:expr ) => { $res? };
     ( sdg $res:expr ) => { $res? };
+    ( u1 $res:expr ) => { $res? };
+    ( u1_param $res:expr ) => { $res? };
+    ( u2 $res:expr ) => { $res? };
+    ( u3 $res:expr ) => { $res? };
+    ( u3_param $res:expr ) => { $res? };
     ( x $res:expr ) => { $res? };
     ( y $res:expr ) => { $res? };
     ( z $res:expr ) => { $res? };
@@ -1168,13 +2124,13 @@ macro_rules! circuit
     ($nr_qbits:expr, $nr_cbits:expr, { $( $method_name:ident ( $( $arg:expr ),* ) );* ; } ) => {
         {
             let generator = || {
-                let mut circuit = $crate::circuit::Circuit::new($nr_qbits, $nr_cbits);
+                let mut circuit = $crate::circuit::Circuit::new($nr_qbits, $nr_cbits)?;
                 $(
                     circuit_method_check!(
                         $method_name
                         circuit.$method_name($($arg),*)
                     );
-                );*
+                )*
                 Ok(circuit) as $crate::error::Result<$crate::circuit::Circuit>
             };
             generator()
@@ -1183,9 +2139,11 @@ macro_rules! circuit
 }
 
 #[cfg(test)]
+#[allow(clippy::redundant_pattern_matching)]
 mod tests
 {
     use super::{Basis, Circuit, CircuitOp};
+    use crate::error::Error;
     use crate::gates::{CX, H, S, X};
 
     #[test]
@@ -1196,7 +2154,7 @@ mod tests
         let x = crate::cmatrix::COMPLEX_HSQRT2;
         let i = crate::cmatrix::COMPLEX_I;
 
-        let mut circuit = Circuit::new(2, 0);
+        let mut circuit = Circuit::new(2, 0).unwrap();
         assert_eq!(circuit.h(0), Ok(()));
         match circuit.ops.last()
         {
@@ -1365,8 +2323,111 @@ mod tests
             measure(0, 0);
             measure(1, 1);
         }).unwrap();
-        circuit.execute(nr_shots);
-        assert_eq!(circuit.cstate(), Some(&array![0b01, 0b01, 0b01, 0b01, 0b01]));
+        circuit.execute(nr_shots).unwrap();
+        assert_eq!(circuit.cstate().unwrap().values(), Ok(&array![0b01, 0b01, 0b01, 0b01, 0b01]));
+    }
+
+    #[test]
+    fn test_execute_no_shots()
+    {
+        let mut circuit = circuit!(2, 2, {
+            h(0);
+            measure(0, 0);
+            add_conditional_gate(&[0], 1, X::new(), &[1]);
+            measure_all(&[0, 1]);
+        }).unwrap();
+        let res = circuit.execute(0).unwrap();
+        assert_eq!(res.nr_shots(), 0);
+        assert!(res.histogram().unwrap().is_empty());
+        assert!(res.memory_strings().is_empty());
+    }
+
+    #[test]
+    fn test_execution_result()
+    {
+        let nr_shots = 100;
+
+        let mut circuit = circuit!(2, 2, {
+            h(0);
+            cx(0, 1);
+            measure(0, 0);
+            measure(1, 1);
+        }).unwrap();
+        circuit.add_creg("extra", 1).unwrap();
+        circuit.x(1).unwrap();
+        circuit.measure(1, ("extra", 0)).unwrap();
+
+        let res = circuit.execute_with_seed(nr_shots, 0x1234).unwrap();
+        assert_eq!(res.nr_shots(), nr_shots);
+        assert_eq!(res.nr_cbits(), 3);
+        assert_eq!(res.seed(), Some(0x1234));
+        assert_eq!(res.peak_nr_branches(), 2);
+        assert_eq!(res.histogram(), circuit.histogram());
+        assert_eq!(Ok(res.histogram_string()), circuit.histogram_string());
+
+        // Each shot holds the results of a single run
+        let memory = res.memory_strings();
+        assert!(memory.iter().all(|shot| shot == "011" || shot == "100"));
+        // Shots are in the same order as in the classical state of the circuit
+        assert_eq!(Some(res.memory()), circuit.cstate());
+
+        let hist = res.marginal_histogram(&[("b", 1), ("extra", 0)]).unwrap();
+        assert_eq!(hist.len(), 2);
+        assert_eq!(hist.get("01").unwrap() + hist.get("10").unwrap(), nr_shots);
+        assert_eq!(res.register_histogram("b"), circuit.register_histogram("b"));
+
+        // The same seed gives the same results, in the same order
+        let res2 = circuit.execute_with_seed(nr_shots, 0x1234).unwrap();
+        assert_eq!(res2.memory(), res.memory());
+
+        let mut rng = <rand_hc::Hc128Rng as rand_core::SeedableRng>::seed_from_u64(0x1234);
+        let res = circuit.execute_with_rng(nr_shots, &mut rng).unwrap();
+        assert_eq!(res.seed(), None);
+        assert_eq!(res.nr_shots(), nr_shots);
+
+        let res = circuit.execute(nr_shots).unwrap();
+        assert!(res.seed().is_some());
+    }
+
+    #[test]
+    fn test_execution_memory_order()
+    {
+        let nr_shots = 100;
+        let mut circuit = circuit!(1, 1, {
+            h(0);
+            measure(0, 0);
+        }).unwrap();
+
+        // Shots are not grouped by outcome
+        let res = circuit.execute_with_seed(nr_shots, 0x5678).unwrap();
+        let memory = res.memory().values().unwrap().to_vec();
+        assert!(memory.windows(2).any(|w| w[0] > w[1]));
+        assert!(memory.windows(2).filter(|w| w[0] != w[1]).count() > 2);
+
+        // The same seed gives the same shots, in the same order
+        let res2 = circuit.execute_with_seed(nr_shots, 0x5678).unwrap();
+        assert_eq!(res2.memory(), res.memory());
+    }
+
+    #[test]
+    fn test_reexecute_run_order()
+    {
+        let nr_shots = 100;
+        // Undo the measured value, and check that it is undone in every run
+        let mut circuit = circuit!(1, 2, {
+            add_conditional_gate(&[0], 1, X::new(), &[0]);
+            measure(0, 1);
+            h(0);
+            measure(0, 0);
+        }).unwrap();
+
+        let res = circuit.execute_with_seed(nr_shots, 0x9abc).unwrap();
+        circuit.reexecute().unwrap();
+        // The result is not affected by executing the circuit again
+        assert_eq!(res.memory().nr_shots(), nr_shots);
+        assert!(res.memory().values().unwrap().iter().all(|&v| v & 0b10 == 0));
+        assert!(circuit.cstate().unwrap().values().unwrap().iter().all(|&v| v & 0b10 == 0));
+        assert_ne!(Some(res.memory()), circuit.cstate());
     }
 
     #[test]
@@ -1380,7 +2441,7 @@ mod tests
             measure(0, 0);
             measure(1, 1);
         }).unwrap();
-        circuit.execute(nr_shots);
+        circuit.execute(nr_shots).unwrap();
         let hist = circuit.histogram_vec().unwrap();
         assert_eq!(hist, vec![0, nr_shots, 0, 0]);
 
@@ -1389,7 +2450,7 @@ mod tests
             measure_x(0, 0);
             measure_x(1, 1);
         }).unwrap();
-        circuit.execute(nr_shots);
+        circuit.execute(nr_shots).unwrap();
         let hist = circuit.histogram_vec().unwrap();
         assert!(hist.iter().all(
             |&count| crate::stats::measurement_ok(count, nr_shots, 0.25, tol)
@@ -1402,7 +2463,7 @@ mod tests
             measure_x(0, 0);
             measure_x(1, 1);
         }).unwrap();
-        circuit.execute(nr_shots);
+        circuit.execute(nr_shots).unwrap();
         let hist = circuit.histogram_vec().unwrap();
         assert_eq!(hist, vec![0, nr_shots, 0, 0]);
 
@@ -1411,7 +2472,7 @@ mod tests
             measure_y(0, 0);
             measure_y(1, 1);
         }).unwrap();
-        circuit.execute(nr_shots);
+        circuit.execute(nr_shots).unwrap();
         let hist = circuit.histogram_vec().unwrap();
         assert!(hist.iter().all(
             |&count| crate::stats::measurement_ok(count, nr_shots, 0.25, tol)
@@ -1432,7 +2493,7 @@ mod tests
             h(0);
             peek(0, 2);
         }).unwrap();
-        circuit.execute(1024);
+        circuit.execute(1024).unwrap();
         let hist = circuit.histogram_vec().unwrap();
         // Results of first and third measurement should be approximately equally
         // distributed over 0 and 1, second should be pure 0.
@@ -1452,7 +2513,7 @@ mod tests
             h(0);
             peek(0, 2);
         }).unwrap();
-        circuit.execute(1024);
+        circuit.execute(1024).unwrap();
         let hist = circuit.histogram().unwrap();
         // Results of first and third measurement should be approximately equally
         // distributed over 0 and 1, second should be pure 0.
@@ -1487,7 +2548,7 @@ mod tests
             h(0);
             peek_x(0, 2);
         }).unwrap();
-        circuit.execute(1024);
+        circuit.execute(1024).unwrap();
         let hist = circuit.histogram_vec().unwrap();
         // Results of first and third measurement should be approximately equally
         // distributed over 0 and 1, second should be pure 0.
@@ -1505,7 +2566,7 @@ mod tests
             sdg(0);
             peek_y(0, 2);
         }).unwrap();
-        circuit.execute(1024);
+        circuit.execute(1024).unwrap();
         let hist = circuit.histogram_vec().unwrap();
         // Results of first and second measurement should be approximately equally
         // distributed over 0 and 1, second should be pure 1.
@@ -1524,32 +2585,32 @@ mod tests
             add_conditional_gate(&[0, 1], 1, X::new(), &[1]);
             measure_all(&[0, 1]);
         }).unwrap();
-        circuit.execute(5);
-        assert_eq!(circuit.c_state, Some(array![0b00, 0b00, 0b00, 0b00, 0b00]));
+        circuit.execute(5).unwrap();
+        assert_eq!(circuit.cstate().unwrap().values(), Ok(&array![0b00, 0b00, 0b00, 0b00, 0b00]));
 
-        let mut circuit = Circuit::new(2, 2);
-        circuit.q_state = Some(crate::qustate::QuState::new(2, 5));
-        circuit.c_state = Some(array![0b01, 0b10, 0b10, 0b11, 0b00]);
+        let mut circuit = Circuit::new(2, 2).unwrap();
+        circuit.q_state = Some(crate::qustate::QuState::new(2, 5).unwrap());
+        circuit.c_state = Some(::std::sync::Arc::new(crate::cstate::CState::from_values(2, array![0b01, 0b10, 0b10, 0b11, 0b00])));
         circuit.add_conditional_gate(&[0, 1], 1, X::new(), &[1]).unwrap();
         circuit.measure_all(&[0, 1]).unwrap();
         circuit.reexecute().unwrap();
-        assert_eq!(circuit.c_state, Some(array![0b10, 0b00, 0b00, 0b00, 0b00]));
+        assert_eq!(circuit.cstate().unwrap().values(), Ok(&array![0b10, 0b00, 0b00, 0b00, 0b00]));
 
-        let mut circuit = Circuit::new(2, 2);
-        circuit.q_state = Some(crate::qustate::QuState::new(2, 5));
-        circuit.c_state = Some(array![0b01, 0b10, 0b10, 0b11, 0b00]);
+        let mut circuit = Circuit::new(2, 2).unwrap();
+        circuit.q_state = Some(crate::qustate::QuState::new(2, 5).unwrap());
+        circuit.c_state = Some(::std::sync::Arc::new(crate::cstate::CState::from_values(2, array![0b01, 0b10, 0b10, 0b11, 0b00])));
         circuit.add_conditional_gate(&[0, 1], 2, X::new(), &[1]).unwrap();
         circuit.measure_all(&[0, 1]).unwrap();
         circuit.reexecute().unwrap();
-        assert_eq!(circuit.c_state, Some(array![0b00, 0b10, 0b10, 0b00, 0b00]));
+        assert_eq!(circuit.cstate().unwrap().values(), Ok(&array![0b00, 0b10, 0b10, 0b00, 0b00]));
 
-        let mut circuit = Circuit::new(2, 2);
-        circuit.q_state = Some(crate::qustate::QuState::new(2, 5));
-        circuit.c_state = Some(array![0b01, 0b10, 0b10, 0b11, 0b00]);
+        let mut circuit = Circuit::new(2, 2).unwrap();
+        circuit.q_state = Some(crate::qustate::QuState::new(2, 5).unwrap());
+        circuit.c_state = Some(::std::sync::Arc::new(crate::cstate::CState::from_values(2, array![0b01, 0b10, 0b10, 0b11, 0b00])));
         circuit.add_conditional_gate(&[1], 1, X::new(), &[0]).unwrap();
         circuit.measure_all(&[0, 1]).unwrap();
         circuit.reexecute().unwrap();
-        assert_eq!(circuit.c_state, Some(array![0b00, 0b01, 0b01, 0b01, 0b00]));
+        assert_eq!(circuit.cstate().unwrap().values(), Ok(&array![0b00, 0b01, 0b01, 0b01, 0b00]));
     }
 
     #[test]
@@ -1562,7 +2623,7 @@ mod tests
             x(0);
             measure_all(&[0, 1]);
         }).unwrap();
-        circuit.execute(nr_shots);
+        circuit.execute(nr_shots).unwrap();
         let hist = circuit.histogram_vec().unwrap();
         assert_eq!(hist, vec![0, nr_shots, 0, 0]);
 
@@ -1570,7 +2631,7 @@ mod tests
             x(0);
             measure_all(&[1, 0]);
         }).unwrap();
-        circuit.execute(nr_shots);
+        circuit.execute(nr_shots).unwrap();
         let hist = circuit.histogram_vec().unwrap();
         assert_eq!(hist, vec![0, 0, nr_shots, 0]);
 
@@ -1579,11 +2640,19 @@ mod tests
             h(1);
             measure_all(&[0, 1]);
         }).unwrap();
-        circuit.execute(nr_shots);
+        circuit.execute(nr_shots).unwrap();
         let hist = circuit.histogram_vec().unwrap();
         assert!(hist.iter().all(
             |&count| crate::stats::measurement_ok(count, nr_shots, 0.25, tol)
         ));
+
+        let mut circuit = Circuit::new(2, 1).unwrap();
+        assert_eq!(circuit.measure_all(&[0]),
+            Err(Error::InvalidNrBits(1, 2, String::from("measure all"))));
+        assert_eq!(circuit.peek_all(&[0]),
+            Err(Error::InvalidNrBits(1, 2, String::from("peek all"))));
+        assert_eq!(circuit.open_qasm(), Ok(String::from(
+            "OPENQASM 2.0;\ninclude \"qelib1.inc\";\nqreg q[2];\ncreg b[1];\n")));
     }
 
     #[test]
@@ -1597,7 +2666,7 @@ mod tests
             h(1);
             measure_all_basis(&[0, 1], Basis::X);
         }).unwrap();
-        circuit.execute(nr_shots);
+        circuit.execute(nr_shots).unwrap();
         let hist = circuit.histogram_vec().unwrap();
         assert_eq!(hist, vec![nr_shots, 0, 0, 0]);
 
@@ -1607,7 +2676,7 @@ mod tests
             h(1);
             measure_all_basis(&[0, 1], Basis::X);
         }).unwrap();
-        circuit.execute(nr_shots);
+        circuit.execute(nr_shots).unwrap();
         let hist = circuit.histogram_vec().unwrap();
         assert_eq!(hist, vec![0, nr_shots, 0, 0]);
 
@@ -1619,14 +2688,14 @@ mod tests
             add_gate(S::new(), &[1]);
             measure_all_basis(&[0, 1], Basis::Y);
         }).unwrap();
-        circuit.execute(nr_shots);
+        circuit.execute(nr_shots).unwrap();
         let hist = circuit.histogram_vec().unwrap();
         assert_eq!(hist, vec![0, nr_shots, 0, 0]);
 
         let mut circuit = circuit!(2, 2, {
             measure_all_basis(&[0, 1], Basis::Y);
         }).unwrap();
-        circuit.execute(nr_shots);
+        circuit.execute(nr_shots).unwrap();
         let hist = circuit.histogram_vec().unwrap();
         assert!(hist.iter().all(
             |&count| crate::stats::measurement_ok(count, nr_shots, 0.25, tol)
@@ -1647,7 +2716,7 @@ mod tests
             h(0);
             peek_all(&[2]);
         }).unwrap();
-        circuit.execute(1024);
+        circuit.execute(1024).unwrap();
         let hist = circuit.histogram_vec().unwrap();
         // Results of first and third measurement should be approximately equally
         // distributed over 0 and 1, second should be pure 0.
@@ -1667,7 +2736,7 @@ mod tests
             h(0);
             peek_all(&[4, 5]);
         }).unwrap();
-        circuit.execute(1024);
+        circuit.execute(1024).unwrap();
         let hist = circuit.histogram().unwrap();
         // Results of first and third measurement should be approximately equally
         // distributed over 0 and 1, second should be pure 0.
@@ -1705,7 +2774,7 @@ mod tests
             h(0);
             peek_all_basis(&[2], Basis::X);
         }).unwrap();
-        circuit.execute(1024);
+        circuit.execute(1024).unwrap();
         let hist = circuit.histogram_vec().unwrap();
         // Results of first and third measurement should be approximately equally
         // distributed over 0 and 1, second should be pure 0.
@@ -1725,7 +2794,7 @@ mod tests
             s(0);
             peek_all_basis(&[4, 5], Basis::Y);
         }).unwrap();
-        circuit.execute(1024);
+        circuit.execute(1024).unwrap();
         let hist = circuit.histogram().unwrap();
         // Results of first measurement should be approximately equally
         // distributed over 0 and 1 for both qubits, second should be pure 0
@@ -1761,7 +2830,7 @@ mod tests
             measure(0, 0);
             measure(1, 1);
         }).unwrap();
-        circuit.execute(nr_shots);
+        circuit.execute(nr_shots).unwrap();
 
         let hist = circuit.histogram().unwrap();
         // With this many shots, we expect all keys to be present
@@ -1787,7 +2856,7 @@ mod tests
             measure(0, 0);
             measure(1, 1);
         }).unwrap();
-        circuit.execute(nr_shots);
+        circuit.execute(nr_shots).unwrap();
 
         let hist = circuit.histogram_vec().unwrap();
         assert_eq!(hist.iter().sum::<usize>(), nr_shots);
@@ -1808,7 +2877,7 @@ mod tests
             measure(0, 0);
             measure(1, 1);
         }).unwrap();
-        circuit.execute(nr_shots);
+        circuit.execute(nr_shots).unwrap();
 
         let hist = circuit.histogram_string().unwrap();
         // With this many shots, we expect all keys to be present
@@ -1822,6 +2891,112 @@ mod tests
         ));
     }
 
+    #[test]
+    fn test_wide_register()
+    {
+        assert!(Circuit::new(2, crate::cstate::MAX_NR_BITS).is_ok());
+        assert!(matches!(Circuit::new(2, crate::cstate::MAX_NR_BITS + 1),
+            Err(Error::TooManyCBits(_, crate::cstate::MAX_NR_BITS))));
+
+        let mut circuit = circuit!(3, 130, {
+            x(0);
+            measure(0, 0);
+            measure(0, 100);
+            measure_all(&[129, 1, 64]);
+            add_conditional_gate(&[100, 129, 64], 0b011, X::new(), &[2]);
+            measure(2, 2);
+        }).unwrap();
+        circuit.execute(10).unwrap();
+
+        let hist = circuit.histogram_string().unwrap();
+        let key = format!("1{}1{}101", "0".repeat(28), "0".repeat(97));
+        assert_eq!(hist.len(), 1);
+        assert_eq!(hist.get(&key), Some(&10));
+
+        assert_eq!(circuit.histogram(), Err(Error::TooManyCBits(130, 64)));
+        assert_eq!(circuit.histogram_vec(), Err(Error::TooManyCBits(130, 63)));
+        assert_eq!(circuit.expectation(|_| 1.0), Err(Error::TooManyCBits(130, 64)));
+
+        let control: Vec<usize> = (0..65).collect();
+        assert_eq!(circuit.add_conditional_gate(&control, 0, X::new(), &[0]),
+            Err(Error::TooManyCBits(65, 64)));
+    }
+
+    #[test]
+    fn test_registers()
+    {
+        let mut circuit = Circuit::new(2, 3).unwrap();
+        assert_eq!(circuit.qregs().len(), 1);
+        assert_eq!(circuit.qreg("q").unwrap().bits(), vec![0, 1]);
+        assert_eq!(circuit.cregs().len(), 1);
+        assert_eq!(circuit.creg("b").unwrap().bits(), vec![0, 1, 2]);
+
+        circuit.add_qreg("ancilla", 2).unwrap();
+        circuit.add_creg("syndrome", 2).unwrap();
+        assert_eq!(circuit.nr_qbits(), 4);
+        assert_eq!(circuit.nr_cbits(), 5);
+        assert_eq!(circuit.qreg("ancilla").unwrap().bits(), vec![2, 3]);
+        assert_eq!(circuit.creg("syndrome").unwrap().bits(), vec![3, 4]);
+
+        assert_eq!(circuit.add_qreg("ancilla", 1),
+            Err(Error::DuplicateRegister(String::from("ancilla"))));
+        assert_eq!(circuit.add_creg("q", 1), Err(Error::DuplicateRegister(String::from("q"))));
+        assert_eq!(circuit.add_qreg("Data", 1),
+            Err(Error::InvalidRegisterName(String::from("Data"))));
+        assert_eq!(circuit.add_creg("big", crate::cstate::MAX_NR_BITS),
+            Err(Error::TooManyCBits(crate::cstate::MAX_NR_BITS + 5, crate::cstate::MAX_NR_BITS)));
+        assert_eq!(circuit.qreg("syndrome"), Err(Error::UnknownRegister(String::from("syndrome"))));
+        assert_eq!(circuit.creg("ancilla"), Err(Error::UnknownRegister(String::from("ancilla"))));
+        assert_eq!(circuit.nr_qbits(), 4);
+        assert_eq!(circuit.nr_cbits(), 5);
+
+        assert_eq!(circuit.x(("data", 0)), Err(Error::UnknownRegister(String::from("data"))));
+        assert_eq!(circuit.x(("ancilla", 2)),
+            Err(Error::InvalidRegisterIndex(String::from("ancilla"), 2)));
+        assert_eq!(circuit.measure(("ancilla", 0), ("ancilla", 0)),
+            Err(Error::UnknownRegister(String::from("ancilla"))));
+        assert_eq!(circuit.cx(0, 4), Err(Error::InvalidQBit(4)));
+        assert_eq!(circuit.measure(0, 5), Err(Error::InvalidCBit(5)));
+        assert_eq!(circuit.register_histogram("syndrome"), Err(Error::NotExecuted));
+    }
+
+    #[test]
+    fn test_named_bits()
+    {
+        let nr_shots = 20;
+
+        let mut circuit = Circuit::new(0, 0).unwrap();
+        assert!(circuit.qregs().is_empty());
+        assert!(circuit.cregs().is_empty());
+        circuit.add_qreg("data", 3).unwrap();
+        circuit.add_qreg("ancilla", 2).unwrap();
+        circuit.add_creg("syndrome", 2).unwrap();
+        circuit.add_creg("out", 3).unwrap();
+
+        circuit.x(("data", 0)).unwrap();
+        circuit.cx(("data", 0), ("ancilla", 1)).unwrap();
+        circuit.measure(("ancilla", 1), ("syndrome", 1)).unwrap();
+        circuit.add_conditional_gate(&[("syndrome", 0), ("syndrome", 1)], 0b10, X::new(),
+            &[("data", 2)]).unwrap();
+        circuit.measure_all(&[2, 3, 4, 0, 1]).unwrap();
+        circuit.execute(nr_shots).unwrap();
+
+        let hist = circuit.register_histogram("out").unwrap();
+        assert_eq!(hist.len(), 1);
+        assert_eq!(hist.get("101"), Some(&nr_shots));
+        let hist = circuit.register_histogram("syndrome").unwrap();
+        assert_eq!(hist.len(), 1);
+        assert_eq!(hist.get("10"), Some(&nr_shots));
+        assert_eq!(circuit.register_histogram("data"),
+            Err(Error::UnknownRegister(String::from("data"))));
+
+        assert_eq!(circuit.histogram_vec().unwrap()[0b10110], nr_shots);
+
+        // Adding a register clears the results
+        circuit.add_creg("extra", 1).unwrap();
+        assert!(circuit.cstate().is_none());
+    }
+
     #[test]
     fn test_reset()
     {
@@ -1835,7 +3010,7 @@ mod tests
             measure(0, 0);
             measure(1, 1);
         }).unwrap();
-        circuit.execute(nr_shots);
+        circuit.execute(nr_shots).unwrap();
         let hist = circuit.histogram_vec().unwrap();
         assert_eq!(hist, vec![nr_shots, 0, 0, 0]);
 
@@ -1847,7 +3022,7 @@ mod tests
             measure(0, 0);
             measure(1, 1);
         }).unwrap();
-        circuit.execute(nr_shots);
+        circuit.execute(nr_shots).unwrap();
         let hist = circuit.histogram_vec().unwrap();
         assert_eq!(hist, vec![0, 0, nr_shots, 0]);
 
@@ -1859,7 +3034,7 @@ mod tests
             measure(0, 0);
             measure(1, 1);
         }).unwrap();
-        circuit.execute(nr_shots);
+        circuit.execute(nr_shots).unwrap();
         let hist = circuit.histogram_vec().unwrap();
         assert!(crate::stats::measurement_ok(hist[0], nr_shots, 0.5, tol));
         assert_eq!(hist[1], 0);
@@ -1880,7 +3055,7 @@ mod tests
             reset_all();
             measure_all(&[0, 1, 2, 3, 4]);
         }).unwrap();
-        circuit.execute(nr_shots);
+        circuit.execute(nr_shots).unwrap();
         let hist = circuit.histogram_vec().unwrap();
         assert_eq!(hist[0], nr_shots);
         assert!(hist[1..].iter().all(|&c| c == 0));
@@ -1964,7 +3139,7 @@ reset q;
             x(0);
             measure_all(&[0, 1]);
             add_conditional_gate(&[0, 1], 1, X::new(), &[0]);
-            add_conditional_gate(&[], 1, X::new(), &[1]);
+            add_conditional_gate(&[] as &[usize], 1, X::new(), &[1]);
         }).unwrap();
         assert_eq!(circuit.open_qasm(), Ok(String::from(
 r#"OPENQASM 2.0;
@@ -1983,6 +3158,63 @@ x q[1];
         assert!(matches!(circuit.open_qasm(), Err(_)));
     }
 
+    #[test]
+    fn test_open_qasm_registers()
+    {
+        let mut circuit = Circuit::new(0, 0).unwrap();
+        circuit.add_qreg("data", 3).unwrap();
+        circuit.add_qreg("ancilla", 2).unwrap();
+        circuit.add_creg("syndrome", 2).unwrap();
+        circuit.add_creg("out", 3).unwrap();
+        circuit.x(("data", 0)).unwrap();
+        circuit.cx(("data", 0), ("ancilla", 1)).unwrap();
+        circuit.barrier(&[0, 1, 2, 3, 4]).unwrap();
+        circuit.measure(("ancilla", 1), ("syndrome", 1)).unwrap();
+        circuit.add_conditional_gate(&[("syndrome", 0), ("syndrome", 1)], 0b10, X::new(),
+            &[("data", 2)]).unwrap();
+        circuit.add_conditional_gate(&[("out", 2), ("out", 0), ("out", 1)], 0b001, X::new(),
+            &[("ancilla", 0)]).unwrap();
+        circuit.measure_all(&[2, 3, 4, 0, 1]).unwrap();
+        circuit.reset_all();
+        circuit.measure_all_basis(&[0, 1, 2, 3, 4], Basis::X).unwrap();
+        assert_eq!(circuit.open_qasm(), Ok(String::from(
+r#"OPENQASM 2.0;
+include "qelib1.inc";
+qreg data[3];
+qreg ancilla[2];
+creg syndrome[2];
+creg out[3];
+x data[0];
+cx data[0], ancilla[1];
+barrier data, ancilla;
+measure ancilla[1] -> syndrome[1];
+if (syndrome == 2) x data[2];
+if (out == 4) x ancilla[0];
+measure data -> out;
+measure ancilla -> syndrome;
+reset data;
+reset ancilla;
+h data;
+h ancilla;
+measure data[0] -> syndrome[0];
+measure data[1] -> syndrome[1];
+measure data[2] -> out[0];
+measure ancilla[0] -> out[1];
+measure ancilla[1] -> out[2];
+"#)));
+
+        circuit.add_conditional_gate(&[("syndrome", 1), ("out", 0)], 1, X::new(), &[0]).unwrap();
+        assert_eq!(circuit.open_qasm(), Err(Error::ExportError(
+            crate::error::ExportError::IncompleteConditionRegister)));
+
+        // Qubits added after measuring all qubits have no classical bit
+        let mut circuit = Circuit::new(2, 2).unwrap();
+        circuit.measure_all(&[0, 1]).unwrap();
+        circuit.add_qreg("extra", 1).unwrap();
+        assert_eq!(circuit.open_qasm(),
+            Err(Error::InvalidNrBits(2, 3, String::from("measure all"))));
+    }
+
     #[test]
     fn test_c_qasm()
     {
@@ -2039,7 +3271,7 @@ measure_all
             x(0);
             measure_all(&[0, 1]);
             add_conditional_gate(&[0, 1], 1, X::new(), &[0]);
-            add_conditional_gate(&[], 1, X::new(), &[1]);
+            add_conditional_gate(&[] as &[usize], 1, X::new(), &[1]);
         }).unwrap();
         assert_eq!(circuit.c_qasm(), Ok(String::from(
 r#"version 1.0
@@ -2084,4 +3316,344 @@ r#"\Qcircuit @C=1em @R=.7em {
 }
 "#)));
     }
+
+    #[test]
+    fn test_parametric()
+    {
+        let nr_shots = 1024;
+        let tol = 1.0e-5;
+
+        let mut circuit = circuit!(2, 2, {
+            rx_param("2*theta", 0);
+            cx(0, 1);
+            u3_param("phi", "0", "-phi", 1);
+            measure_all(&[0, 1]);
+        }).unwrap();
+        assert_eq!(circuit.parameters(), vec![String::from("theta"), String::from("phi")]);
+
+        assert!(matches!(circuit.add_parametric_gate("RX", &["theta*theta"],
+            |args| crate::gates::RX::new(args[0]), &[0]), Err(Error::ParseError(_))));
+        assert_eq!(circuit.add_parametric_gate("CX", &[], |_| CX::new(), &[0]),
+            Err(Error::InvalidNrBits(1, 2, String::from("CX"))));
+        assert_eq!(circuit.add_parametric_gate("X", &[], |_| X::new(), &[2]),
+            Err(Error::InvalidQBit(2)));
+
+        let mut values = ::std::collections::HashMap::new();
+        values.insert(String::from("theta"), ::std::f64::consts::FRAC_PI_4);
+        circuit.bind(&values);
+        assert_eq!(circuit.open_qasm(), Err(Error::UnboundParameter(String::from("phi"))));
+        assert_eq!(circuit.c_qasm(), Err(Error::UnboundParameter(String::from("phi"))));
+
+        values.insert(String::from("phi"), 0.0);
+        circuit.bind(&values);
+        circuit.execute(nr_shots).unwrap();
+        let hist = circuit.histogram_vec().unwrap();
+        assert_eq!(hist[0] + hist[3], nr_shots);
+        assert!(crate::stats::measurement_ok(hist[0], nr_shots, 0.5, tol));
+
+        values.insert(String::from("theta"), 0.0);
+        values.insert(String::from("phi"), ::std::f64::consts::PI);
+        circuit.bind(&values);
+        circuit.execute(nr_shots).unwrap();
+        let hist = circuit.histogram_vec().unwrap();
+        assert_eq!(hist[2], nr_shots);
+
+        assert_eq!(circuit.open_qasm(), Ok(String::from(
+r#"OPENQASM 2.0;
+include "qelib1.inc";
+qreg q[2];
+creg b[2];
+rx(0) q[0];
+cx q[0], q[1];
+u3(3.141592653589793, 0, -3.141592653589793) q[1];
+measure q -> b;
+"#)));
+    }
+
+    #[test]
+    fn test_parametric_unbound()
+    {
+        let mut circuit = circuit!(1, 1, {
+            ry_param("theta/2 + 1", 0);
+            rz_param("-lambda", 0);
+            u1_param("0.5", 0);
+        }).unwrap();
+        assert_eq!(circuit.parameters(), vec![String::from("theta"), String::from("lambda")]);
+
+        assert_eq!(circuit.reexecute(), Err(Error::UnboundParameter(String::from("theta"))));
+
+        assert_eq!(circuit.latex(), Ok(String::from(
+r#"\Qcircuit @C=1em @R=.7em {
+    \lstick{\ket{0}} & \gate{RY(0.5*theta + 1)} & \gate{RZ(-lambda)} & \gate{U_1(0.5000)} & \qw \\
+    \lstick{0} & \cw & \cw & \cw & \cw \\
+}
+"#)));
+    }
+
+    #[test]
+    fn test_exact_gradient()
+    {
+        let h = 1.0e-6;
+
+        let mut circuit = circuit!(3, 0, {
+            rx_param("a", 0);
+            ry_param("2*b + 0.3", 1);
+            h(2);
+            rz_param("a - c", 2);
+            crx_param("b", 0, 1);
+            cry_param("c", 1, 2);
+            barrier(&[0, 1, 2]);
+            crz_param("-a", 2, 0);
+            u1_param("d", 1);
+            cx(1, 2);
+            u3_param("b", "c", "0.5*d", 0);
+        }).unwrap();
+
+        // Observable Z ⊗ X ⊗ Z + 0.5 Y ⊗ I ⊗ I
+        let z = array![[crate::cmatrix::COMPLEX_ONE, crate::cmatrix::COMPLEX_ZERO],
+            [crate::cmatrix::COMPLEX_ZERO, -crate::cmatrix::COMPLEX_ONE]];
+        let x = array![[crate::cmatrix::COMPLEX_ZERO, crate::cmatrix::COMPLEX_ONE],
+            [crate::cmatrix::COMPLEX_ONE, crate::cmatrix::COMPLEX_ZERO]];
+        let y = array![[crate::cmatrix::COMPLEX_ZERO, -crate::cmatrix::COMPLEX_I],
+            [crate::cmatrix::COMPLEX_I, crate::cmatrix::COMPLEX_ZERO]];
+        let eye = crate::cmatrix::CMatrix::eye(2);
+        let observable = crate::cmatrix::kron_mat(&crate::cmatrix::kron_mat(&z, &x), &z)
+            + crate::cmatrix::kron_mat(&crate::cmatrix::kron_mat(&y, &eye), &eye) * 0.5;
+
+        let mut values = ::std::collections::HashMap::new();
+        values.insert(String::from("a"), 0.3);
+        values.insert(String::from("b"), -1.1);
+        values.insert(String::from("c"), 2.3);
+        values.insert(String::from("d"), 0.8);
+        circuit.bind(&values);
+
+        let grad = circuit.exact_gradient(&observable).unwrap();
+        assert_eq!(grad.len(), 4);
+        for name in circuit.parameters()
+        {
+            let mut shifted = values.clone();
+            *shifted.get_mut(&name).unwrap() += h;
+            circuit.bind(&shifted);
+            let plus = circuit.exact_expectation(&observable).unwrap();
+            *shifted.get_mut(&name).unwrap() -= 2.0 * h;
+            circuit.bind(&shifted);
+            let minus = circuit.exact_expectation(&observable).unwrap();
+            let fd = (plus - minus) / (2.0 * h);
+            assert!((grad[&name] - fd).abs() < 1.0e-8, "{}: {} != {}", name, grad[&name], fd);
+        }
+
+        assert!(matches!(circuit.exact_gradient(&eye), Err(Error::InvalidMatrixSize(2, 2))));
+        circuit.add_creg("b", 1).unwrap();
+        circuit.measure(0, 0).unwrap();
+        assert_eq!(circuit.exact_gradient(&observable),
+            Err(Error::NonUnitaryOperation(String::from("measure"))));
+    }
+
+    #[test]
+    fn test_gate_fusion()
+    {
+        let mut circuit = Circuit::new(3, 3).unwrap();
+        assert_eq!(circuit.gate_fusion(), None);
+        circuit.set_gate_fusion(Some(2));
+        assert_eq!(circuit.gate_fusion(), Some(2));
+        circuit.set_gate_fusion(None);
+        assert_eq!(circuit.gate_fusion(), None);
+
+        // Gates should not be fused across measurements and barriers
+        let mut circuit = circuit!(2, 3, {
+            x(0);
+            measure(0, 0);
+            x(0);
+            measure(0, 1);
+            x(1);
+            barrier(&[1]);
+            x(1);
+            measure(1, 2);
+        }).unwrap();
+        circuit.execute(10).unwrap();
+        assert_eq!(circuit.cstate().unwrap().values(), Ok(&ndarray::Array1::from_elem(10, 0b001)));
+
+        // Fused and unfused execution should give the same results
+        let mut circuit = circuit!(4, 4, {
+            h(0);
+            rz(0.25, 0);
+            rx(0.3, 0);
+            cx(0, 2);
+            u3(0.1, 0.2, 0.3, 2);
+            h(1);
+            add_gate(crate::gates::CCX::new(), &[3, 1, 2]);
+            measure(2, 2);
+            h(3);
+            cx(3, 1);
+            ry(0.7, 2);
+            measure_all(&[0, 1, 2, 3]);
+        }).unwrap();
+        let mut c_states = vec![];
+        for &max_bits in [None, Some(1), Some(2), Some(3), Some(4)].iter()
+        {
+            let mut rng = <rand_hc::Hc128Rng as rand_core::SeedableRng>::seed_from_u64(0x1234);
+            circuit.set_gate_fusion(max_bits);
+            circuit.execute_with_rng(100, &mut rng).unwrap();
+            c_states.push(circuit.cstate().unwrap().clone());
+        }
+        for c_state in c_states[1..].iter()
+        {
+            assert_eq!(c_state, &c_states[0]);
+        }
+    }
+
+    #[test]
+    fn test_sparse_state()
+    {
+        let mut circuit = Circuit::new(3, 3).unwrap();
+        assert!(!circuit.sparse_state());
+        circuit.set_sparse_state(true);
+        assert!(circuit.sparse_state());
+
+        // A GHZ state on 40 qubits
+        let nr_bits = 40;
+        let mut circuit = Circuit::new(nr_bits, nr_bits).unwrap();
+        circuit.set_sparse_state(true);
+        circuit.h(0).unwrap();
+        for bit in 1..nr_bits
+        {
+            circuit.cx(bit - 1, bit).unwrap();
+        }
+        let cbits: Vec<usize> = (0..nr_bits).collect();
+        circuit.measure_all(&cbits).unwrap();
+        circuit.execute(100).unwrap();
+        let c_state = circuit.cstate().unwrap().values().unwrap();
+        assert!(c_state.iter().all(|&c| c == 0 || c == (1 << nr_bits) - 1));
+        assert!(c_state.iter().any(|&c| c == 0));
+        assert!(c_state.iter().any(|&c| c != 0));
+
+        // Reversible arithmetic: increment a 3-bit register
+        let mut circuit = circuit!(3, 3, {
+            x(0);
+            x(2);
+            add_gate(crate::gates::CCX::new(), &[1, 2, 0]);
+            cx(2, 1);
+            x(2);
+            measure_all(&[2, 1, 0]);
+        }).unwrap();
+        circuit.set_sparse_state(true);
+        circuit.execute(10).unwrap();
+        assert_eq!(circuit.cstate().unwrap().values(), Ok(&ndarray::Array1::from_elem(10, 0b110)));
+    }
+
+    #[test]
+    fn test_memory_budget()
+    {
+        let amp_size = ::std::mem::size_of::<crate::cmatrix::StateNumber>();
+
+        let mut circuit = Circuit::new(2, 2).unwrap();
+        assert_eq!(circuit.memory_budget(), None);
+        circuit.set_memory_budget(Some(1000));
+        assert_eq!(circuit.memory_budget(), Some(1000));
+
+        circuit.h(0).unwrap();
+        circuit.measure(0, 0).unwrap();
+        circuit.reset(0).unwrap();
+        circuit.measure_all(&[0, 1]).unwrap();
+        // At most 14 branches of 4 amplitudes, and 10 classical registers
+        assert_eq!(circuit.estimated_memory(10), 14 * 4 * amp_size + 80);
+        // At most 5 branches, and 3 classical registers
+        assert_eq!(circuit.estimated_memory(3), 5 * 4 * amp_size + 24);
+        assert_eq!(Circuit::new(100, 2).unwrap().estimated_memory(10), usize::MAX);
+
+        // States that cannot be stored at all
+        let mut circuit = Circuit::new(100, 2).unwrap();
+        assert_eq!(circuit.execute(10),
+            Err(Error::MemoryBudgetExceeded(usize::MAX, usize::MAX)));
+        assert!(circuit.cstate().is_none());
+
+        // Initial state does not fit
+        let mut circuit = Circuit::new(10, 1).unwrap();
+        circuit.h(0).unwrap();
+        circuit.measure(0, 0).unwrap();
+        circuit.set_memory_budget(Some(1000));
+        assert_eq!(circuit.execute(100),
+            Err(Error::MemoryBudgetExceeded(1024 * amp_size + 800, 1000)));
+        assert!(circuit.cstate().is_none());
+
+        // The measurement splits the state in two branches, which requires
+        // room for three branches while measuring.
+        let branch_size = 1024 * amp_size;
+        circuit.set_memory_budget(Some(3 * branch_size + 799));
+        assert_eq!(circuit.execute(100),
+            Err(Error::MemoryBudgetExceeded(3 * branch_size, 3 * branch_size - 1)));
+        circuit.set_memory_budget(Some(3 * branch_size + 800));
+        assert!(circuit.execute(100).is_ok());
+        assert_eq!(circuit.estimated_memory(100), 3 * branch_size + 800);
+    }
+
+    #[test]
+    fn test_gradient()
+    {
+        let nr_shots = 20000;
+        let tol = 0.05;
+        let mut rng = <rand_hc::Hc128Rng as rand_core::SeedableRng>::seed_from_u64(0x1234);
+
+        let mut circuit = circuit!(2, 2, {
+            ry_param("theta", 0);
+            crx_param("2*phi", 0, 1);
+            rz_param("phi", 1);
+            h(1);
+        }).unwrap();
+
+        let mut values = ::std::collections::HashMap::new();
+        values.insert(String::from("theta"), 1.2);
+        values.insert(String::from("phi"), 0.4);
+        circuit.bind(&values);
+
+        // Z ⊗ Z
+        let mut observable = crate::cmatrix::CMatrix::zeros((4, 4));
+        for (i, &v) in [1.0, -1.0, -1.0, 1.0].iter().enumerate()
+        {
+            observable[(i, i)] = crate::cmatrix::COMPLEX_ONE * v;
+        }
+        let exact = circuit.exact_gradient(&observable).unwrap();
+
+        circuit.measure_all(&[0, 1]).unwrap();
+        let zz = |key: u64| if key.count_ones() % 2 == 0 { 1.0 } else { -1.0 };
+        circuit.execute_with_rng(10, &mut rng).unwrap();
+        let c_state = circuit.cstate().cloned();
+        let grad = circuit.gradient_with_rng(nr_shots, zz, &mut rng).unwrap();
+        // The results of the previous execution should be preserved
+        assert_eq!(circuit.cstate().cloned(), c_state);
+        for name in circuit.parameters()
+        {
+            assert!((grad[&name] - exact[&name]).abs() < tol, "{}: {} != {}",
+                name, grad[&name], exact[&name]);
+        }
+
+        // The original parameters should be bound again afterwards
+        circuit.execute_with_rng(1, &mut rng).unwrap();
+        assert!(matches!(circuit.expectation(zz), Ok(_)));
+        assert_eq!(circuit.open_qasm(), Ok(String::from(
+r#"OPENQASM 2.0;
+include "qelib1.inc";
+qreg q[2];
+creg b[2];
+u3(1.2, 0, 0) q[0];
+s q[1]; cx q[0], q[1]; ry(-0.8/2) q[1]; cx q[0], q[1]; ry(0.8/2) q[1]; sdg q[1];
+rz(0.4) q[1];
+h q[1];
+measure q -> b;
+"#)));
+
+        // The arguments should also be restored when execution fails
+        let qasm = circuit.open_qasm();
+        circuit.set_memory_budget(Some(1));
+        assert!(matches!(circuit.gradient(1, zz), Err(Error::MemoryBudgetExceeded(_, 1))));
+        assert_eq!(circuit.open_qasm(), qasm);
+        circuit.set_memory_budget(None);
+
+        circuit.add_parametric_gate("H", &["theta"], |_| H::new(), &[0]).unwrap();
+        circuit.bind(&values);
+        assert_eq!(circuit.gradient(1, zz), Err(Error::NoGradient(String::from("H"))));
+
+        let circuit = Circuit::new(1, 1).unwrap();
+        assert_eq!(circuit.expectation(zz), Err(Error::NotExecuted));
+    }
 }
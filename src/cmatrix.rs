@@ -27,6 +27,87 @@ pub type CMatrix = ndarray::Array2<CNumber>;
 pub type CVecSliceMut<'a> = ndarray::ArrayViewMut1<'a, CNumber>;
 pub type CMatSliceMut<'a> = ndarray::ArrayViewMut2<'a, CNumber>;
 
+/// Type of the amplitudes in a quantum state. This is `Complex64`, unless the
+/// `single-precision` feature is enabled, in which case it is `Complex32`.
+#[cfg(not(feature = "single-precision"))]
+pub type StateNumber = num_complex::Complex64;
+/// Type of the amplitudes in a quantum state. This is `Complex64`, unless the
+/// `single-precision` feature is enabled, in which case it is `Complex32`.
+#[cfg(feature = "single-precision")]
+pub type StateNumber = num_complex::Complex32;
+pub type StateMatrix = ndarray::Array2<StateNumber>;
+
+/// Amplitudes of a quantum state.
+///
+/// Trait Amplitude is implemented by the complex number types in which the
+/// coefficients of a quantum state can be stored. Gates are always described
+/// in double precision; amplitudes of other types are converted when a gate
+/// is applied.
+pub trait Amplitude: ndarray::LinalgScalar + Send + Sync
+{
+    /// The machine epsilon of the underlying floating point type
+    const EPSILON: f64;
+
+    /// Convert a double precision number to an amplitude
+    fn from_complex(c: CNumber) -> Self;
+    /// Convert this amplitude to a double precision number
+    fn to_complex(self) -> CNumber;
+
+    /// Apply a gate.
+    ///
+    /// Apply gate `gate` operating on the bits in `bits` to the columns of
+    /// `states`, each describing a state of `nr_bits` qubits. An error is
+    /// returned if the bits do not match the gate, or the number of rows in
+    /// `states` does not match the number of bits.
+    fn apply_gate<G>(states: ndarray::ArrayViewMut2<Self>, gate: &G, bits: &[usize],
+        nr_bits: usize) -> crate::error::Result<()>
+    where G: crate::gates::Gate + ?Sized;
+}
+
+impl Amplitude for num_complex::Complex64
+{
+    const EPSILON: f64 = f64::EPSILON;
+
+    fn from_complex(c: CNumber) -> Self
+    {
+        c
+    }
+
+    fn to_complex(self) -> CNumber
+    {
+        self
+    }
+
+    fn apply_gate<G>(states: CMatSliceMut, gate: &G, bits: &[usize], nr_bits: usize)
+        -> crate::error::Result<()>
+    where G: crate::gates::Gate + ?Sized
+    {
+        crate::gates::apply_gate_mat_slice(states, gate, bits, nr_bits)
+    }
+}
+
+impl Amplitude for num_complex::Complex32
+{
+    const EPSILON: f64 = f32::EPSILON as f64;
+
+    fn from_complex(c: CNumber) -> Self
+    {
+        num_complex::Complex32::new(c.re as f32, c.im as f32)
+    }
+
+    fn to_complex(self) -> CNumber
+    {
+        CNumber::new(f64::from(self.re), f64::from(self.im))
+    }
+
+    fn apply_gate<G>(states: ndarray::ArrayViewMut2<Self>, gate: &G, bits: &[usize],
+        nr_bits: usize) -> crate::error::Result<()>
+    where G: crate::gates::Gate + ?Sized
+    {
+        crate::gates::apply_gate_amplitudes(states, gate, bits, nr_bits)
+    }
+}
+
 /// Compute the Kronecker product `v0` ⊗ `v1`.
 pub fn kron_vec(v0: &CVector, v1: &CVector) -> CVector
 {
@@ -58,13 +139,16 @@ pub fn kron_mat(a0: &CMatrix, a1: &CMatrix) -> CMatrix
 macro_rules! assert_complex_vector_eq
 {
     ($a0:expr, $a1:expr) => {
+        $crate::assert_complex_vector_eq!($a0, $a1, 1.0e-15)
+    };
+    ($a0:expr, $a1:expr, $tol:expr) => {
         {
             let (n0, n1) = ($a0.len(), $a1.len());
 
             assert!(n0 == n1, "Incompatible array dimensions, {} vs {}", n0, n1);
 
             let diff = $a0 - $a1;
-            let tol = 1.0e-15;
+            let tol = $tol;
             let mut diff_elems = vec![];
             for i in 0..n0
             {
@@ -92,6 +176,9 @@ macro_rules! assert_complex_vector_eq
 macro_rules! assert_complex_matrix_eq
 {
     ($a0:expr, $a1:expr) => {
+        $crate::assert_complex_matrix_eq!($a0, $a1, 1.0e-15)
+    };
+    ($a0:expr, $a1:expr, $tol:expr) => {
         {
             let (n0, m0, n1, m1) = ($a0.rows(), $a0.cols(), $a1.rows(), $a1.cols());
 
@@ -99,7 +186,7 @@ macro_rules! assert_complex_matrix_eq
                 n0, m0, n1, m1);
 
             let diff = $a0 - $a1;
-            let tol = 1.0e-15;
+            let tol = $tol;
             let mut diff_elems = vec![];
             for i in 0..n0
             {
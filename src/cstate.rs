@@ -0,0 +1,453 @@
+// Copyright 2019 Q1t BV
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Classical registers holding the measurement results of an experiment.
+
+/// The maximum number of bits in a classical register.
+pub const MAX_NR_BITS: usize = 1 << 16;
+
+/// The number of bits in a single word of a classical register
+const WORD_SIZE: usize = 64;
+
+/// Classical state
+///
+/// Struct CState holds the classical registers for all runs of an experiment.
+/// The register of each run is a bit vector of at most `MAX_NR_BITS` bits,
+/// stored in 64-bit words. The words are grouped by position, i.e. bit `b` in
+/// the register of run `i` is stored in bit `b % 64` of `word(b / 64)[i]`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CState
+{
+    /// The number of bits in the register of a single run
+    nr_bits: usize,
+    /// The words holding the registers for all runs
+    words: Vec<ndarray::Array1<u64>>
+}
+
+impl CState
+{
+    /// Create a new classical state.
+    ///
+    /// Create a new classical state for `nr_shots` runs of an experiment with
+    /// `nr_bits` classical bits, with all bits cleared.
+    pub fn new(nr_bits: usize, nr_shots: usize) -> Self
+    {
+        CState
+        {
+            nr_bits: nr_bits,
+            words: vec![ndarray::Array1::zeros(nr_shots); Self::nr_words_for(nr_bits)]
+        }
+    }
+
+    /// Create a new classical state from register values.
+    ///
+    /// Create a new classical state with `nr_bits` bits, where the registers
+    /// of the runs are given in `values`. The number of bits should be at most
+    /// 64.
+    #[cfg(test)]
+    pub(crate) fn from_values(nr_bits: usize, values: ndarray::Array1<u64>) -> Self
+    {
+        assert!(nr_bits <= WORD_SIZE, "Too many bits for register values");
+        CState
+        {
+            nr_bits: nr_bits,
+            words: vec![values]
+        }
+    }
+
+    /// The number of words needed to store a register of `nr_bits` bits. At
+    /// least a single word is used, so that the number of runs is known even
+    /// for an empty register.
+    fn nr_words_for(nr_bits: usize) -> usize
+    {
+        (nr_bits / WORD_SIZE + (nr_bits % WORD_SIZE != 0) as usize).max(1)
+    }
+
+    /// The estimated memory needed for a classical state.
+    ///
+    /// Return the number of bytes needed to store the classical registers of
+    /// `nr_bits` bits for `nr_shots` runs.
+    pub(crate) fn memory_size(nr_bits: usize, nr_shots: usize) -> usize
+    {
+        nr_shots.saturating_mul(Self::nr_words_for(nr_bits))
+            .saturating_mul(::std::mem::size_of::<u64>())
+    }
+
+    /// The number of bits in the register of a single run
+    pub fn nr_bits(&self) -> usize
+    {
+        self.nr_bits
+    }
+
+    /// The number of runs in the experiment
+    pub fn nr_shots(&self) -> usize
+    {
+        self.words[0].len()
+    }
+
+    /// The number of 64-bit words in the register of a single run
+    pub fn nr_words(&self) -> usize
+    {
+        self.words.len()
+    }
+
+    /// Return the words at position `idx` in the registers of all runs. Word
+    /// `idx` holds bits `64*idx` up to `64*(idx+1)` of the register.
+    pub fn word(&self, idx: usize) -> &ndarray::Array1<u64>
+    {
+        &self.words[idx]
+    }
+
+    /// Return the value of bit `bit` in the register of run `shot`.
+    pub fn bit(&self, shot: usize, bit: usize) -> bool
+    {
+        (self.words[bit / WORD_SIZE][shot] >> (bit % WORD_SIZE)) & 1 != 0
+    }
+
+    /// The register values.
+    ///
+    /// Return the registers for all runs as integer values, with the first
+    /// classical bit (at index 0) corresponding to the least significant bit
+    /// in the value. If there are more than 64 classical bits, the registers
+    /// can not be represented in a single value, and a `TooManyCBits` error is
+    /// returned.
+    pub fn values(&self) -> crate::error::Result<&ndarray::Array1<u64>>
+    {
+        if self.nr_bits > WORD_SIZE
+        {
+            Err(crate::error::Error::TooManyCBits(self.nr_bits, WORD_SIZE))
+        }
+        else
+        {
+            Ok(&self.words[0])
+        }
+    }
+
+    /// The register as a string.
+    ///
+    /// Return the register of run `shot` as a string of `0` and `1` characters,
+    /// with the last character corresponding to the first bit (at index 0) in
+    /// the register and vice versa.
+    pub fn bit_string(&self, shot: usize) -> String
+    {
+        (0..self.nr_bits).rev()
+            .map(|bit| if self.bit(shot, bit) { '1' } else { '0' })
+            .collect()
+    }
+
+    /// Select bits.
+    ///
+    /// Create a new classical state holding, for each run, only the bits with
+    /// indices in `bits`. Bit `i` in the new register is bit `bits[i]` in this
+    /// register. If any of the indices is out of range, an `InvalidCBit` error
+    /// is returned.
+    pub fn marginal(&self, bits: &[usize]) -> crate::error::Result<Self>
+    {
+        if let Some(&bit) = bits.iter().find(|&&bit| bit >= self.nr_bits)
+        {
+            return Err(crate::error::Error::InvalidCBit(bit));
+        }
+
+        let mut res = CState::new(bits.len(), self.nr_shots());
+        for (dst, &src) in bits.iter().enumerate()
+        {
+            let mask = 1 << (dst % WORD_SIZE);
+            let word = &mut res.words[dst / WORD_SIZE];
+            for shot in 0..self.nr_shots()
+            {
+                if self.bit(shot, src)
+                {
+                    word[shot] |= mask;
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    /// Reorder runs.
+    ///
+    /// Reorder the runs in this classical state, such that the register of run
+    /// `i` becomes the register of run `order[i]` before reordering.
+    pub(crate) fn permute(&mut self, order: &[usize])
+    {
+        for word in self.words.iter_mut()
+        {
+            *word = word.select(ndarray::Axis(0), order);
+        }
+    }
+
+    /// Create a histogram of the registers.
+    ///
+    /// Count how often each register value occurs over all runs. The registers
+    /// are collected in a `u64` key, with the first bit (at index 0)
+    /// corresponding to the least significant bit in the key. If there are
+    /// more than 64 bits, a `TooManyCBits` error is returned.
+    pub fn histogram(&self) -> crate::error::Result<crate::idhash::U64HashMap<usize>>
+    {
+        let mut res = crate::idhash::new_u64_hash_map();
+        for &key in self.values()?
+        {
+            let count = res.entry(key).or_insert(0);
+            *count += 1;
+        }
+        Ok(res)
+    }
+
+    /// Create a histogram of the registers.
+    ///
+    /// Count how often each register value occurs over all runs, in a vector
+    /// indexed by the register value. The bit order of the indices is the same
+    /// as in `histogram()`. The vector is of length `2`<sub>`n`</sub>, with `n`
+    /// the number of bits. If this length can not be represented, a
+    /// `TooManyCBits` error is returned.
+    pub fn histogram_vec(&self) -> crate::error::Result<Vec<usize>>
+    {
+        let max_nr_bits = 8 * ::std::mem::size_of::<usize>() - 1;
+        if self.nr_bits > max_nr_bits
+        {
+            return Err(crate::error::Error::TooManyCBits(self.nr_bits, max_nr_bits));
+        }
+
+        let mut res = vec![0; 1 << self.nr_bits];
+        for &key in self.values()?
+        {
+            res[key as usize] += 1;
+        }
+        Ok(res)
+    }
+
+    /// Create a histogram of the registers.
+    ///
+    /// Count how often each register value occurs over all runs. The registers
+    /// are collected in string keys as returned by `bit_string()`, so that
+    /// this works for registers of any width.
+    pub fn histogram_string(&self) -> ::std::collections::HashMap<String, usize>
+    {
+        let mut res = ::std::collections::HashMap::new();
+        for shot in 0..self.nr_shots()
+        {
+            let count = res.entry(self.bit_string(shot)).or_insert(0);
+            *count += 1;
+        }
+        res
+    }
+
+    /// Evaluate a condition.
+    ///
+    /// For each run, check if the classical bits with indices from `control`
+    /// form the target word `target`. The bit at the position of the first
+    /// index in `control` corresponds to the least significant bit in
+    /// `target`.
+    pub(crate) fn matches(&self, control: &[usize], target: u64) -> Vec<bool>
+    {
+        (0..self.nr_shots())
+            .map(|shot| {
+                control.iter().enumerate()
+                    .all(|(shift, &bit)| self.bit(shot, bit) == ((target >> shift) & 1 != 0))
+            })
+            .collect()
+    }
+
+    /// Store measurement results.
+    ///
+    /// Store the results of a measurement in classical bits `cbits`. Function
+    /// `store` should write the measured values in the bits at the positions it
+    /// is passed, in an array of 64-bit words holding a value for each run.
+    /// If all bits in `cbits` are in the same word, this word is passed
+    /// directly, otherwise the results are measured into a temporary array
+    /// first, and copied to the registers afterwards.
+    pub(crate) fn store_measurement<F>(&mut self, cbits: &[usize], store: F)
+        -> crate::error::Result<()>
+    where F: FnOnce(&mut ndarray::Array1<u64>, &[usize]) -> crate::error::Result<()>
+    {
+        if let Some(&cbit) = cbits.iter().find(|&&cbit| cbit >= self.nr_bits)
+        {
+            return Err(crate::error::Error::InvalidCBit(cbit));
+        }
+
+        let word_idx = cbits.first().map_or(0, |&cbit| cbit / WORD_SIZE);
+        if cbits.iter().all(|&cbit| cbit / WORD_SIZE == word_idx)
+        {
+            let local: Vec<usize> = cbits.iter().map(|&cbit| cbit % WORD_SIZE).collect();
+            store(&mut self.words[word_idx], &local)
+        }
+        else if cbits.len() > WORD_SIZE
+        {
+            Err(crate::error::Error::TooManyCBits(cbits.len(), WORD_SIZE))
+        }
+        else
+        {
+            let mut values = ndarray::Array1::zeros(self.nr_shots());
+            let local: Vec<usize> = (0..cbits.len()).collect();
+            store(&mut values, &local)?;
+            for (src, &cbit) in cbits.iter().enumerate()
+            {
+                let mask = 1 << (cbit % WORD_SIZE);
+                for (dst, &value) in self.words[cbit / WORD_SIZE].iter_mut().zip(values.iter())
+                {
+                    if (value >> src) & 1 == 0
+                    {
+                        *dst &= !mask;
+                    }
+                    else
+                    {
+                        *dst |= mask;
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::CState;
+    use crate::error::Error;
+
+    #[test]
+    fn test_new()
+    {
+        let c_state = CState::new(3, 5);
+        assert_eq!(c_state.nr_bits(), 3);
+        assert_eq!(c_state.nr_shots(), 5);
+        assert_eq!(c_state.nr_words(), 1);
+        assert_eq!(c_state.values(), Ok(&ndarray::Array1::zeros(5)));
+
+        let c_state = CState::new(0, 4);
+        assert_eq!(c_state.nr_shots(), 4);
+        assert_eq!(c_state.nr_words(), 1);
+
+        let c_state = CState::new(129, 2);
+        assert_eq!(c_state.nr_bits(), 129);
+        assert_eq!(c_state.nr_shots(), 2);
+        assert_eq!(c_state.nr_words(), 3);
+        assert_eq!(c_state.values(), Err(Error::TooManyCBits(129, 64)));
+
+        assert_eq!(CState::memory_size(64, 10), 80);
+        assert_eq!(CState::memory_size(65, 10), 160);
+    }
+
+    #[test]
+    fn test_store_measurement()
+    {
+        let mut c_state = CState::new(130, 3);
+
+        // Bits in a single word
+        c_state.store_measurement(&[66, 68], |values, bits| {
+            assert_eq!(bits, &[2, 4]);
+            values[0] |= 1 << 2;
+            values[2] |= 1 << 4;
+            Ok(())
+        }).unwrap();
+        assert_eq!(c_state.word(0), &array![0, 0, 0]);
+        assert_eq!(c_state.word(1), &array![0b00100, 0, 0b10000]);
+        assert_eq!(c_state.word(2), &array![0, 0, 0]);
+
+        // Bits spanning multiple words
+        c_state.store_measurement(&[129, 0, 66], |values, bits| {
+            assert_eq!(bits, &[0, 1, 2]);
+            values[0] = 0b011;
+            values[1] = 0b110;
+            Ok(())
+        }).unwrap();
+        assert_eq!(c_state.word(0), &array![1, 1, 0]);
+        assert_eq!(c_state.word(1), &array![0, 0b00100, 0b10000]);
+        assert_eq!(c_state.word(2), &array![0b10, 0, 0]);
+        assert!(c_state.bit(0, 129));
+        assert!(c_state.bit(1, 66));
+        assert!(!c_state.bit(2, 66));
+
+        assert_eq!(c_state.store_measurement(&[130], |_, _| Ok(())),
+            Err(Error::InvalidCBit(130)));
+    }
+
+    #[test]
+    fn test_bit_string()
+    {
+        let mut c_state = CState::new(70, 2);
+        c_state.store_measurement(&[0, 69], |values, _| {
+            values[0] = 0b01;
+            values[1] = 0b10;
+            Ok(())
+        }).unwrap();
+        assert_eq!(c_state.bit_string(0), format!("{}1", "0".repeat(69)));
+        assert_eq!(c_state.bit_string(1), format!("1{}", "0".repeat(69)));
+
+        let c_state = CState::from_values(3, array![0b101, 0b010]);
+        assert_eq!(c_state.bit_string(0), "101");
+        assert_eq!(c_state.bit_string(1), "010");
+    }
+
+    #[test]
+    fn test_matches()
+    {
+        let c_state = CState::from_values(3, array![0b101, 0b010, 0b001, 0b100]);
+        assert_eq!(c_state.matches(&[0, 2], 0b11), vec![true, false, false, false]);
+        assert_eq!(c_state.matches(&[2, 0], 0b01), vec![false, false, false, true]);
+        assert_eq!(c_state.matches(&[1], 0), vec![true, false, true, true]);
+        assert_eq!(c_state.matches(&[], 0), vec![true; 4]);
+
+        let mut c_state = CState::new(100, 2);
+        c_state.store_measurement(&[99], |values, bits| {
+            values[1] |= 1 << bits[0];
+            Ok(())
+        }).unwrap();
+        assert_eq!(c_state.matches(&[99, 0], 0b01), vec![false, true]);
+    }
+
+    #[test]
+    fn test_marginal()
+    {
+        let c_state = CState::from_values(3, array![0b101, 0b010, 0b001, 0b100]);
+        let marginal = c_state.marginal(&[2, 0]).unwrap();
+        assert_eq!(marginal.nr_bits(), 2);
+        assert_eq!(marginal.values(), Ok(&array![0b11, 0b00, 0b10, 0b01]));
+        assert_eq!(c_state.marginal(&[]).unwrap().nr_shots(), 4);
+        assert_eq!(c_state.marginal(&[3]), Err(Error::InvalidCBit(3)));
+
+        let mut c_state = CState::new(100, 2);
+        c_state.store_measurement(&[99], |values, bits| {
+            values[1] |= 1 << bits[0];
+            Ok(())
+        }).unwrap();
+        let marginal = c_state.marginal(&[0, 99]).unwrap();
+        assert_eq!(marginal.values(), Ok(&array![0b00, 0b10]));
+
+        let mut permuted = c_state.clone();
+        permuted.permute(&[1, 0]);
+        assert!(permuted.bit(0, 99));
+        assert!(!permuted.bit(1, 99));
+    }
+
+    #[test]
+    fn test_histograms()
+    {
+        let c_state = CState::from_values(2, array![0b01, 0b11, 0b01, 0b00]);
+        let hist = c_state.histogram().unwrap();
+        assert_eq!(hist.len(), 3);
+        assert_eq!(hist.get(&0b01), Some(&2));
+        assert_eq!(hist.get(&0b11), Some(&1));
+        assert_eq!(hist.get(&0b00), Some(&1));
+        assert_eq!(c_state.histogram_vec(), Ok(vec![1, 2, 0, 1]));
+        let hist = c_state.histogram_string();
+        assert_eq!(hist.len(), 3);
+        assert_eq!(hist.get("01"), Some(&2));
+
+        let c_state = CState::new(65, 3);
+        assert_eq!(c_state.histogram(), Err(Error::TooManyCBits(65, 64)));
+        assert_eq!(c_state.histogram_string().get(&"0".repeat(65)), Some(&3));
+    }
+}
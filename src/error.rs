@@ -88,6 +88,12 @@ pub enum ParseError
     TrailingText(String),
     /// Unclosed parentheses in argument expression
     UnclosedParentheses(String),
+    /// Expression is not affine in its parameters
+    NotAffine(String),
+    /// Unable to parse a Pauli string
+    InvalidPauliString(String),
+    /// Unable to parse a boolean expression
+    InvalidBooleanExpression(String),
 }
 
 impl ::std::fmt::Display for ParseError
@@ -122,6 +128,15 @@ impl ::std::fmt::Display for ParseError
             },
             ParseError::UnclosedParentheses(ref text) => {
                 write!(f, "Unclosed parentheses in expression: \"{}\"", text)
+            },
+            ParseError::NotAffine(ref text) => {
+                write!(f, "Expression \"{}\" is not affine in its parameters", text)
+            },
+            ParseError::InvalidPauliString(ref text) => {
+                write!(f, "Invalid Pauli string \"{}\", expected only I, X, Y, or Z", text)
+            },
+            ParseError::InvalidBooleanExpression(ref text) => {
+                write!(f, "Failed to parse boolean expression \"{}\"", text)
             }
         }
     }
@@ -140,8 +155,52 @@ pub enum Error
     InvalidQBit(usize),
     /// Invalid index for classical bit
     InvalidCBit(usize),
+    /// Quantum bit occurs more than once in the bits a gate operates on
+    DuplicateQBit(usize),
+    /// Size of a state vector does not match the number of quantum bits
+    InvalidStateSize(usize, usize),
+    /// Number of values passed does not match the number of runs
+    InvalidNrRuns(usize, usize),
+    /// Number of qubit coefficients is not even
+    InvalidNrCoefficients(usize),
+    /// Number of quantum bits exceeds the maximum supported
+    TooManyQBits(usize, usize),
+    /// Number of classical bits exceeds the maximum supported
+    TooManyCBits(usize, usize),
+    /// Name of a register is not a valid identifier
+    InvalidRegisterName(String),
+    /// A register with the same name already exists
+    DuplicateRegister(String),
+    /// No register with the given name exists
+    UnknownRegister(String),
+    /// Invalid index for a bit in a register
+    InvalidRegisterIndex(String, usize),
+    /// Measurement outcomes cannot be sampled from a state
+    SamplingFailed(String),
     /// Results asked for circuit that has not been run yet
     NotExecuted,
+    /// Matrix is not square, or its size is not a power of two
+    InvalidMatrixSize(usize, usize),
+    /// Matrix for a gate is not unitary
+    NotUnitary(String),
+    /// No value was bound to a parameter
+    UnboundParameter(String),
+    /// No gradient can be computed for the arguments of a gate
+    NoGradient(String),
+    /// Operation that is not a unitary transformation, where one was expected
+    NonUnitaryOperation(String),
+    /// Length of a truth table is not a power of two
+    InvalidTruthTable(usize),
+    /// Modulus is too small, or too large for the number of bits
+    InvalidModulus(u64, usize),
+    /// Number has no inverse modulo a modulus
+    NotInvertible(u64, u64),
+    /// No non-trivial factors of a number were found
+    FactoringFailed(u64),
+    /// Number of time steps in a product formula is zero
+    InvalidNrSteps(usize),
+    /// Estimated memory needed to store the state exceeds the memory budget
+    MemoryBudgetExceeded(usize, usize),
     /// Other errors that should not occur
     InternalError(String),
     /// Error reating to the export of a circuit
@@ -181,9 +240,75 @@ impl ::std::fmt::Display for Error
             Error::InvalidCBit(bit) => {
                 write!(f, "Invalid index {} for a classical bit", bit)
             },
+            Error::DuplicateQBit(bit) => {
+                write!(f, "Quantum bit {} occurs more than once", bit)
+            },
+            Error::InvalidStateSize(size, nr_bits) => {
+                write!(f, "A state of size {} does not match the number of quantum bits {}", size, nr_bits)
+            },
+            Error::InvalidNrRuns(actual, expected) => {
+                write!(f, "Expected values for {} runs, got {}", expected, actual)
+            },
+            Error::InvalidNrCoefficients(nr_coefs) => {
+                write!(f, "Expected two coefficients for each quantum bit, got {} coefficients", nr_coefs)
+            },
+            Error::TooManyQBits(nr_bits, max_nr_bits) => {
+                write!(f, "At most {} quantum bits are supported, got {}", max_nr_bits, nr_bits)
+            },
+            Error::TooManyCBits(nr_bits, max_nr_bits) => {
+                write!(f, "At most {} classical bits are supported, got {}", max_nr_bits, nr_bits)
+            },
+            Error::InvalidRegisterName(ref name) => {
+                write!(f, "Invalid register name \"{}\"", name)
+            },
+            Error::DuplicateRegister(ref name) => {
+                write!(f, "A register named \"{}\" already exists", name)
+            },
+            Error::UnknownRegister(ref name) => {
+                write!(f, "Unknown register \"{}\"", name)
+            },
+            Error::InvalidRegisterIndex(ref name, idx) => {
+                write!(f, "Invalid index {} for a bit in register \"{}\"", idx, name)
+            },
+            Error::SamplingFailed(ref err) => {
+                write!(f, "Unable to sample measurement outcomes: {}", err)
+            },
             Error::NotExecuted => {
                 write!(f, "The circuit has not been executed yet")
             },
+            Error::InvalidMatrixSize(rows, cols) => {
+                write!(f, "A {}×{} matrix cannot be the matrix of a gate, it should be square with a size that is a power of two", rows, cols)
+            },
+            Error::NotUnitary(ref desc) => {
+                write!(f, "The matrix for \"{}\" is not unitary", desc)
+            },
+            Error::UnboundParameter(ref name) => {
+                write!(f, "No value was bound to parameter \"{}\"", name)
+            },
+            Error::NoGradient(ref name) => {
+                write!(f, "Unable to compute the gradient with respect to the arguments of gate \"{}\"", name)
+            },
+            Error::NonUnitaryOperation(ref desc) => {
+                write!(f, "Operation \"{}\" is not a unitary transformation", desc)
+            },
+            Error::InvalidTruthTable(len) => {
+                write!(f, "A truth table of length {} is invalid, its length should be a power of two", len)
+            },
+            Error::InvalidModulus(modulus, nr_bits) => {
+                write!(f, "Modulus {} is invalid for numbers of {} bits", modulus, nr_bits)
+            },
+            Error::NotInvertible(value, modulus) => {
+                write!(f, "{} has no inverse modulo {}", value, modulus)
+            },
+            Error::FactoringFailed(number) => {
+                write!(f, "Failed to find non-trivial factors of {}", number)
+            },
+            Error::InvalidNrSteps(nr_steps) => {
+                write!(f, "Invalid number of time steps {}", nr_steps)
+            },
+            Error::MemoryBudgetExceeded(required, budget) => {
+                write!(f, "Storing the quantum state requires an estimated {} bytes of memory, exceeding the budget of {} bytes", required, budget)
+            },
             Error::InternalError(ref err) => {
                 write!(f, "Internal error: {}", err)
             },
@@ -0,0 +1,259 @@
+// Copyright 2019 Q1t BV
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Results of the execution of a circuit.
+
+/// Result of an execution
+///
+/// Struct ExecutionResult holds the outcome of executing a circuit: the
+/// classical register of every run (or shot), and information about the
+/// execution itself. The registers are kept in a stable shot order: each shot
+/// holds all measurement results of a single run of the circuit, and the
+/// order of the shots does not depend on the measured outcomes. The result
+/// shares its registers with the classical state of the circuit, which holds
+/// the shots in the same order. When the circuit is executed with a known
+/// seed, this order is reproducible.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExecutionResult
+{
+    /// The classical registers of all shots
+    memory: ::std::sync::Arc<crate::cstate::CState>,
+    /// The named classical registers in the circuit
+    cregs: Vec<crate::register::Register>,
+    /// The seed of the random number generator used, if known
+    seed: Option<u64>,
+    /// The time taken by the execution
+    elapsed: ::std::time::Duration,
+    /// The largest number of branches of the quantum state during execution
+    peak_nr_branches: usize
+}
+
+impl ExecutionResult
+{
+    /// Create a new execution result.
+    ///
+    /// Create a new result for an execution of a circuit with classical
+    /// registers `cregs`, resulting in the registers in `memory`. The
+    /// execution used a random number generator seeded with `seed` if known,
+    /// took time `elapsed`, and split the quantum state in at most
+    /// `peak_nr_branches` branches.
+    pub(crate) fn new(memory: ::std::sync::Arc<crate::cstate::CState>, cregs: Vec<crate::register::Register>,
+        seed: Option<u64>, elapsed: ::std::time::Duration, peak_nr_branches: usize) -> Self
+    {
+        ExecutionResult
+        {
+            memory: memory,
+            cregs: cregs,
+            seed: seed,
+            elapsed: elapsed,
+            peak_nr_branches: peak_nr_branches
+        }
+    }
+
+    /// The number of shots in the execution
+    pub fn nr_shots(&self) -> usize
+    {
+        self.memory.nr_shots()
+    }
+
+    /// The number of classical bits in the register of a single shot
+    pub fn nr_cbits(&self) -> usize
+    {
+        self.memory.nr_bits()
+    }
+
+    /// The seed of the random number generator used for sampling, or `None`
+    /// if the circuit was executed with a random number generator passed in
+    /// by the caller.
+    pub fn seed(&self) -> Option<u64>
+    {
+        self.seed
+    }
+
+    /// The wall clock time taken by the execution
+    pub fn elapsed(&self) -> ::std::time::Duration
+    {
+        self.elapsed
+    }
+
+    /// The largest number of branches the quantum state was split into,
+    /// after any operation in the circuit.
+    pub fn peak_nr_branches(&self) -> usize
+    {
+        self.peak_nr_branches
+    }
+
+    /// The classical registers of all shots, in shot order.
+    pub fn memory(&self) -> &crate::cstate::CState
+    {
+        &self.memory
+    }
+
+    /// The classical registers of all shots as strings.
+    ///
+    /// Return the register of each shot, in shot order, as a string of `0`
+    /// and `1` characters, with the last character corresponding to the first
+    /// classical bit (at index 0) and vice versa.
+    pub fn memory_strings(&self) -> Vec<String>
+    {
+        (0..self.nr_shots()).map(|shot| self.memory.bit_string(shot)).collect()
+    }
+
+    /// Create a histogram of measurements.
+    ///
+    /// Create a histogram of the measured classical bits, with keys as
+    /// described in `Circuit::histogram()`. If there are more than 64
+    /// classical bits, a `TooManyCBits` error is returned.
+    pub fn histogram(&self) -> crate::error::Result<crate::idhash::U64HashMap<usize>>
+    {
+        self.memory.histogram()
+    }
+
+    /// Create a histogram of measurements.
+    ///
+    /// Create a histogram of the measured classical bits in a vector, as
+    /// described in `Circuit::histogram_vec()`.
+    pub fn histogram_vec(&self) -> crate::error::Result<Vec<usize>>
+    {
+        self.memory.histogram_vec()
+    }
+
+    /// Create a histogram of measurements.
+    ///
+    /// Create a histogram of the measured classical bits, with string keys as
+    /// described in `Circuit::histogram_string()`.
+    pub fn histogram_string(&self) -> ::std::collections::HashMap<String, usize>
+    {
+        self.memory.histogram_string()
+    }
+
+    /// Create a histogram of measurements in a register.
+    ///
+    /// Create a histogram of the measured values of the bits in the classical
+    /// register called `name`, with string keys as described in
+    /// `Circuit::register_histogram()`. If no register with this name exists,
+    /// an `UnknownRegister` error is returned.
+    pub fn register_histogram(&self, name: &str)
+        -> crate::error::Result<::std::collections::HashMap<String, usize>>
+    {
+        let reg = self.cregs.iter().find(|reg| reg.name() == name)
+            .ok_or_else(|| crate::error::Error::UnknownRegister(String::from(name)))?;
+        Ok(self.memory.marginal(&reg.bits())?.histogram_string())
+    }
+
+    /// Select classical bits.
+    ///
+    /// Return the classical registers of all shots, in shot order, restricted
+    /// to the bits in `cbits`. Bits can be given by index or by register name
+    /// and index. Bit `i` in the new registers is the bit at `cbits[i]`, so
+    /// the first bit in `cbits` becomes the least significant bit. If a bit
+    /// does not exist, an `InvalidCBit`, `UnknownRegister`, or
+    /// `InvalidRegisterIndex` error is returned.
+    pub fn marginal_memory<C>(&self, cbits: &[C]) -> crate::error::Result<crate::cstate::CState>
+    where C: crate::register::BitIndex
+    {
+        let cbits = cbits.iter()
+            .map(|cbit| cbit.bit_index(&self.cregs))
+            .collect::<crate::error::Result<Vec<usize>>>()?;
+        self.memory.marginal(&cbits)
+    }
+
+    /// Create a marginal histogram.
+    ///
+    /// Create a histogram of the measured values of only the classical bits
+    /// in `cbits`, summing the counts over all values of the other bits. The
+    /// keys are strings, with the last character in the key corresponding to
+    /// the first bit in `cbits` and vice versa. Bits are given and checked as
+    /// in `marginal_memory()`.
+    pub fn marginal_histogram<C>(&self, cbits: &[C])
+        -> crate::error::Result<::std::collections::HashMap<String, usize>>
+    where C: crate::register::BitIndex
+    {
+        Ok(self.marginal_memory(cbits)?.histogram_string())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::ExecutionResult;
+    use crate::cstate::CState;
+    use crate::error::Error;
+    use crate::register::Register;
+
+    fn result() -> ExecutionResult
+    {
+        let memory = CState::from_values(4, array![0b1010, 0b0110, 0b1011, 0b0010]);
+        let cregs = vec![
+            Register::new("low", 0, 2).unwrap(),
+            Register::new("high", 2, 2).unwrap()
+        ];
+        ExecutionResult::new(::std::sync::Arc::new(memory), cregs, Some(3), ::std::time::Duration::from_millis(5), 2)
+    }
+
+    #[test]
+    fn test_metadata()
+    {
+        let res = result();
+        assert_eq!(res.nr_shots(), 4);
+        assert_eq!(res.nr_cbits(), 4);
+        assert_eq!(res.seed(), Some(3));
+        assert_eq!(res.elapsed(), ::std::time::Duration::from_millis(5));
+        assert_eq!(res.peak_nr_branches(), 2);
+        assert_eq!(res.memory_strings(), vec!["1010", "0110", "1011", "0010"]);
+    }
+
+    #[test]
+    fn test_histograms()
+    {
+        let res = result();
+        let hist = res.histogram().unwrap();
+        assert_eq!(hist.len(), 4);
+        assert_eq!(hist.get(&0b1010), Some(&1));
+        assert_eq!(res.histogram_vec().unwrap()[0b0010], 1);
+        assert_eq!(res.histogram_string().get("1011"), Some(&1));
+
+        let hist = res.register_histogram("high").unwrap();
+        assert_eq!(hist.len(), 3);
+        assert_eq!(hist.get("10"), Some(&2));
+        assert_eq!(hist.get("01"), Some(&1));
+        assert_eq!(hist.get("00"), Some(&1));
+        assert_eq!(res.register_histogram("mid"),
+            Err(Error::UnknownRegister(String::from("mid"))));
+    }
+
+    #[test]
+    fn test_marginal()
+    {
+        let res = result();
+        let memory = res.marginal_memory(&[1, 3]).unwrap();
+        assert_eq!(memory.values(), Ok(&array![0b11, 0b01, 0b11, 0b01]));
+        let memory = res.marginal_memory(&[("high", 1), ("low", 0)]).unwrap();
+        assert_eq!(memory.values(), Ok(&array![0b01, 0b00, 0b11, 0b00]));
+
+        let hist = res.marginal_histogram(&[0]).unwrap();
+        assert_eq!(hist.get("0"), Some(&3));
+        assert_eq!(hist.get("1"), Some(&1));
+        let hist = res.marginal_histogram(&[3, 0]).unwrap();
+        assert_eq!(hist.len(), 3);
+        assert_eq!(hist.get("11"), Some(&1));
+        assert_eq!(hist.get("00"), Some(&2));
+        assert_eq!(hist.get("01"), Some(&1));
+
+        assert_eq!(res.marginal_memory(&[4]), Err(Error::InvalidCBit(4)));
+        assert_eq!(res.marginal_histogram(&[("high", 2)]),
+            Err(Error::InvalidRegisterIndex(String::from("high"), 2)));
+        assert_eq!(res.marginal_histogram(&[] as &[usize]).unwrap().get(""), Some(&4));
+    }
+}
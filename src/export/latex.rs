@@ -555,6 +555,7 @@ pub trait Latex: crate::gates::Gate
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests
 {
     use super::{LatexExportState, Latex};
@@ -0,0 +1,308 @@
+// Copyright 2019 Q1t BV
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Fusion of consecutive gates into a single gate, before execution.
+
+use crate::export::CircuitGate;
+
+/// Operation resulting from gate fusion
+pub enum FusedOp<'a>
+{
+    /// A single gate, applied as is
+    Single(&'a dyn CircuitGate, &'a [usize]),
+    /// A single gate for a sequence of gates, acting on the given qubits
+    Fused(crate::gates::MatrixGate, Vec<usize>)
+}
+
+impl<'a> FusedOp<'a>
+{
+    /// Apply this operation to quantum state `state`.
+    pub fn apply(&self, state: &mut crate::qustate::QuState) -> crate::error::Result<()>
+    {
+        match *self
+        {
+            FusedOp::Single(gate, bits) => state.apply_gate(gate, bits),
+            FusedOp::Fused(ref gate, ref bits) => state.apply_gate(gate, bits)
+        }
+    }
+}
+
+/// Gate fusion
+///
+/// Struct GateFusion collects runs of consecutive gates that together act on
+/// at most a given number of qubits, and combines them into a single gate,
+/// so that the state needs to be updated only once for the whole run. Gates
+/// are added one by one, and operations are passed to a callback function
+/// when a run is complete. A run is ended when the next gate would make it
+/// act on too many qubits, or explicitly by calling `flush()`, which should
+/// be done before any operation that is not a gate, like a measurement, a
+/// reset, a conditional gate, or a barrier. Errors returned by the callback
+/// are passed on to the caller.
+pub struct GateFusion<'a>
+{
+    /// The maximum number of qubits a fused gate may act on
+    max_bits: usize,
+    /// The qubits the gates in the current run act on
+    bits: Vec<usize>,
+    /// The gates in the current run
+    gates: Vec<(&'a dyn CircuitGate, &'a [usize])>
+}
+
+impl<'a> GateFusion<'a>
+{
+    /// Create a new gate fusion, combining gates into fused gates acting on
+    /// at most `max_bits` qubits. If `max_bits` is zero, all gates are
+    /// passed on as they are.
+    pub fn new(max_bits: usize) -> Self
+    {
+        GateFusion { max_bits: max_bits, bits: vec![], gates: vec![] }
+    }
+
+    /// Add a gate.
+    ///
+    /// Add gate `gate`, acting on the qubits in `bits`, to the current run.
+    /// If it does not fit, the current run is completed first. Gates acting
+    /// on more than the maximum number of qubits are passed on to `apply`
+    /// directly.
+    pub fn add<F>(&mut self, gate: &'a dyn CircuitGate, bits: &'a [usize], apply: &mut F)
+        -> crate::error::Result<()>
+    where F: FnMut(FusedOp<'a>) -> crate::error::Result<()>
+    {
+        let nr_new_bits = bits.iter().filter(|bit| !self.bits.contains(bit)).count();
+        if self.bits.len() + nr_new_bits > self.max_bits
+        {
+            self.flush(apply)?;
+        }
+
+        if bits.len() > self.max_bits
+        {
+            apply(FusedOp::Single(gate, bits))
+        }
+        else
+        {
+            for &bit in bits
+            {
+                if !self.bits.contains(&bit)
+                {
+                    self.bits.push(bit);
+                }
+            }
+            self.gates.push((gate, bits));
+            Ok(())
+        }
+    }
+
+    /// Complete the current run.
+    ///
+    /// Pass the gates in the current run to `apply`. A run consisting of a
+    /// single gate is passed on unchanged, longer runs are combined into a
+    /// single gate. If all gates in the run are diagonal, or all permute
+    /// the basis states, so is the combined gate; otherwise, the gates are
+    /// combined into a dense matrix.
+    pub fn flush<F>(&mut self, apply: &mut F) -> crate::error::Result<()>
+    where F: FnMut(FusedOp<'a>) -> crate::error::Result<()>
+    {
+        let fused_bits = ::std::mem::take(&mut self.bits);
+        let gates = ::std::mem::take(&mut self.gates);
+        if let [(gate, bits)] = gates[..]
+        {
+            apply(FusedOp::Single(gate, bits))
+        }
+        else if !gates.is_empty()
+        {
+            let gate = if gates.iter().all(|&(gate, _)| gate.diagonal().is_some())
+                {
+                    let mut diagonal = crate::cmatrix::CVector::from_elem(1 << fused_bits.len(),
+                        crate::cmatrix::COMPLEX_ONE);
+                    Self::apply_run(diagonal.view_mut().insert_axis(ndarray::Axis(1)),
+                        &gates, &fused_bits)?;
+                    crate::gates::MatrixGate::from_diagonal(diagonal)
+                }
+                else if gates.iter().all(|&(gate, _)| gate.permutation().is_some())
+                {
+                    // Track where each basis state ends up, by permuting
+                    // the basis state indices
+                    let mut idxs = crate::cmatrix::CVector::from_shape_fn(1 << fused_bits.len(),
+                        |i| num_complex::Complex::new(i as f64, 0.0));
+                    Self::apply_run(idxs.view_mut().insert_axis(ndarray::Axis(1)),
+                        &gates, &fused_bits)?;
+                    let idxs = idxs.iter().map(|c| c.re as usize).collect();
+                    crate::gates::MatrixGate::from_permutation(crate::permutation::Permutation::new(idxs))
+                }
+                else
+                {
+                    let mut mat = crate::cmatrix::CMatrix::eye(1 << fused_bits.len());
+                    Self::apply_run(mat.view_mut(), &gates, &fused_bits)?;
+                    crate::gates::MatrixGate::new(mat)
+                };
+            apply(FusedOp::Fused(gate, fused_bits))
+        }
+        else
+        {
+            Ok(())
+        }
+    }
+
+    /// Apply a run of gates.
+    ///
+    /// Apply the gates in `gates` to `state`, whose rows correspond to the
+    /// basis states of the qubits in `fused_bits`.
+    fn apply_run(mut state: crate::cmatrix::CMatSliceMut,
+        gates: &[(&'a dyn CircuitGate, &'a [usize])], fused_bits: &[usize])
+        -> crate::error::Result<()>
+    {
+        let nr_bits = fused_bits.len();
+        for &(gate, bits) in gates.iter()
+        {
+            let local_bits: Vec<usize> = bits.iter()
+                .filter_map(|bit| fused_bits.iter().position(|b| b == bit))
+                .collect();
+            crate::gates::apply_gate_mat_slice(state.view_mut(), gate, &local_bits, nr_bits)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{FusedOp, GateFusion};
+    use crate::export::CircuitGate;
+    use crate::gates::{Gate, CCX, CX, CZ, H, RX, Swap, T, U1, U3, X, Z};
+
+    fn gates() -> Vec<(Box<dyn CircuitGate>, Vec<usize>)>
+    {
+        vec![
+            (Box::new(H::new()), vec![0]),
+            (Box::new(T::new()), vec![0]),
+            (Box::new(RX::new(0.3)), vec![0]),
+            (Box::new(CX::new()), vec![0, 2]),
+            (Box::new(U3::new(0.1, 0.2, 0.3)), vec![2]),
+            (Box::new(H::new()), vec![1]),
+            (Box::new(CCX::new()), vec![3, 1, 2]),
+            (Box::new(H::new()), vec![3]),
+            (Box::new(CX::new()), vec![3, 1]),
+            (Box::new(T::new()), vec![1])
+        ]
+    }
+
+    /// Fuse the gates in `gates`, and return the sizes of the fused gates
+    /// together with the final state.
+    fn fuse(gates: &[(Box<dyn CircuitGate>, Vec<usize>)], max_bits: usize)
+        -> (Vec<usize>, crate::cmatrix::CVector)
+    {
+        let nr_bits = 4;
+        let mut state = crate::cmatrix::CVector::from_shape_fn(1 << nr_bits, |i| {
+            num_complex::Complex::new((i as f64).sin(), (2.0 * i as f64).cos())
+        });
+        let mut sizes = vec![];
+        {
+            let mut apply = |op: FusedOp| {
+                match op
+                {
+                    FusedOp::Single(gate, bits) => {
+                        sizes.push(0);
+                        crate::gates::apply_gate_slice(state.view_mut(), gate, bits, nr_bits).unwrap();
+                    },
+                    FusedOp::Fused(gate, bits) => {
+                        sizes.push(gate.nr_affected_bits());
+                        crate::gates::apply_gate_slice(state.view_mut(), &gate, &bits, nr_bits).unwrap();
+                    }
+                }
+                Ok(())
+            };
+            let mut fusion = GateFusion::new(max_bits);
+            for (gate, bits) in gates.iter()
+            {
+                fusion.add(&**gate, bits, &mut apply).unwrap();
+            }
+            fusion.flush(&mut apply).unwrap();
+        }
+
+        (sizes, state)
+    }
+
+    #[test]
+    fn test_fusion()
+    {
+        let gates = gates();
+        let (sizes, expected) = fuse(&gates, 0);
+        assert_eq!(sizes, vec![0; gates.len()]);
+
+        let (sizes, state) = fuse(&gates, 1);
+        assert_eq!(sizes, vec![1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_complex_vector_eq!(&state, &expected);
+
+        let (sizes, state) = fuse(&gates, 2);
+        assert_eq!(sizes, vec![2, 0, 0, 2]);
+        assert_complex_vector_eq!(&state, &expected);
+
+        let (sizes, state) = fuse(&gates, 3);
+        assert_eq!(sizes, vec![3, 3]);
+        assert_complex_vector_eq!(&state, &expected);
+
+        let (sizes, state) = fuse(&gates, 4);
+        assert_eq!(sizes, vec![4]);
+        assert_complex_vector_eq!(&state, &expected);
+    }
+
+    #[test]
+    fn test_fusion_diagonal_permutation()
+    {
+        let diagonal: Vec<(Box<dyn CircuitGate>, Vec<usize>)> = vec![
+            (Box::new(T::new()), vec![0]),
+            (Box::new(CZ::new()), vec![0, 2]),
+            (Box::new(Z::new()), vec![2]),
+            (Box::new(U1::new(0.3)), vec![1])
+        ];
+        let permutation: Vec<(Box<dyn CircuitGate>, Vec<usize>)> = vec![
+            (Box::new(X::new()), vec![1]),
+            (Box::new(CX::new()), vec![1, 0]),
+            (Box::new(Swap::new()), vec![0, 2]),
+            (Box::new(CCX::new()), vec![0, 1, 2])
+        ];
+        let mixed: Vec<(Box<dyn CircuitGate>, Vec<usize>)> = vec![
+            (Box::new(T::new()), vec![0]),
+            (Box::new(CX::new()), vec![0, 1])
+        ];
+
+        for (gates, is_diagonal, is_permutation) in [(diagonal, true, false),
+            (permutation, false, true), (mixed, false, false)]
+        {
+            let (_, expected) = fuse(&gates, 0);
+            let (sizes, state) = fuse(&gates, 3);
+            assert_eq!(sizes.len(), 1);
+            assert_complex_vector_eq!(&state, &expected);
+
+            let mut fusion = GateFusion::new(3);
+            let mut apply = |op: FusedOp| {
+                if let FusedOp::Fused(gate, _) = op
+                {
+                    assert_eq!(gate.diagonal().is_some(), is_diagonal);
+                    assert_eq!(gate.permutation().is_some(), is_permutation);
+                }
+                else
+                {
+                    panic!("Gates were not fused");
+                }
+                Ok(())
+            };
+            for (gate, bits) in gates.iter()
+            {
+                fusion.add(&**gate, bits, &mut apply).unwrap();
+            }
+            fusion.flush(&mut apply).unwrap();
+        }
+    }
+}
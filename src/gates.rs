@@ -12,22 +12,29 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod arith;
 mod controlled;
 mod composite;
 mod hadamard;
 mod identity;
 mod kron;
+mod matrix;
+mod multicontrolled;
+mod paulirotation;
+mod qft;
 mod rx;
 mod ry;
 mod rz;
 mod s;
 mod staticloop;
+mod synthesis;
 mod swap;
 mod t;
 mod v;
 mod u1;
 mod u2;
 mod u3;
+mod unitary;
 mod x;
 mod y;
 mod z;
@@ -48,8 +55,8 @@ fn get_sort_key(idx: usize, nr_bits: usize, affected_bits: &[usize]) -> usize
 
 /// Reorder bits.
 ///
-/// When applying multi-bit gates, the rows in the state are shuffled
-/// such that:
+/// Multi-bit gates expect the rows in the state they are applied to to be
+/// ordered such that:
 /// * The first half of the rows correspond to components with the first
 ///   affected bit being 0, the second half to those with this bit being 1.
 /// * Within each of these two blocks, the first half corresponds to
@@ -67,79 +74,429 @@ pub fn bit_permutation(nr_bits: usize, affected_bits: &[usize]) -> crate::permut
     crate::permutation::Permutation::new(idxs).inverse()
 }
 
+/// Minimum number of coefficients in a part of a state, before work on it is
+/// divided over multiple threads.
+#[cfg(feature = "parallel")]
+const PARALLEL_THRESHOLD: usize = 1 << 14;
+
+/// Divide work.
+///
+/// Call `f` on parts of `view`. With the `parallel` feature, if `view` is
+/// large enough, it is split recursively into two halves along axis `axis`,
+/// at a multiple of `chunk_size`, and the halves are processed in parallel.
+/// Otherwise, `f` is called on the full `view`.
+#[cfg_attr(not(feature = "parallel"), allow(unused_variables))]
+fn split_work<A, D, F>(view: ndarray::ArrayViewMut<A, D>, axis: ndarray::Axis,
+    chunk_size: usize, f: &F)
+where A: Send, D: ndarray::Dimension, F: Fn(ndarray::ArrayViewMut<A, D>) + Sync
+{
+    #[cfg(feature = "parallel")]
+    {
+        let nr_chunks = view.len_of(axis) / chunk_size;
+        if nr_chunks > 1 && view.len() >= PARALLEL_THRESHOLD
+        {
+            let (first, second) = view.split_at(axis, (nr_chunks / 2) * chunk_size);
+            rayon::join(|| split_work(first, axis, chunk_size, f),
+                || split_work(second, axis, chunk_size, f));
+            return;
+        }
+    }
+
+    f(view);
+}
+
+/// Maximum number of coefficients gathered in the work buffer when applying
+/// a multi-qubit gate
+const GATHER_BUFFER_SIZE: usize = 1 << 12;
+
+/// Apply a multi-qubit gate.
+///
+/// Apply gate `gate` operating on the bits in `bits` to the rows of `matrix`,
+/// describing a state of `nr_bits` qubits. The state is not reordered.
+/// Instead, the coefficients in a batch of subspaces, in each of which the
+/// qubits not affected by the gate are fixed, are gathered in a small work
+/// buffer, ordered as `Gate::apply_mat_slice()` expects. After the gate is
+/// applied to the buffer, the results are scattered back into `matrix`. See
+/// `apply_kernel()` and `apply_gate_gathered()`.
+fn apply_gate_strided<T, G>(matrix: ndarray::ArrayViewMut2<T>, gate: &G, bits: &[usize],
+    nr_bits: usize)
+where T: crate::cmatrix::Amplitude, G: Gate + ?Sized
+{
+    apply_kernel(matrix, bits, nr_bits, &|m, b, n| apply_gate_gathered(m, gate, b, n));
+}
+
+/// Apply a kernel.
+///
+/// Call `kernel` on `matrix`, describing a state of `nr_bits` qubits, for a
+/// gate operating on the bits in `bits`. With the `parallel` feature, as long
+/// as the first qubit is not affected by the gate, and the state is large
+/// enough, the state is split into the halves for the first qubit being 0
+/// and 1, which are processed in parallel.
+fn apply_kernel<T, K>(matrix: ndarray::ArrayViewMut2<T>, bits: &[usize], nr_bits: usize,
+    kernel: &K)
+where T: Send, K: Fn(ndarray::ArrayViewMut2<T>, &[usize], usize) + Sync
+{
+    #[cfg(feature = "parallel")]
+    {
+        if nr_bits > bits.len() && !bits.contains(&0) && matrix.len() >= PARALLEL_THRESHOLD
+        {
+            let sub_bits: Vec<usize> = bits.iter().map(|&b| b - 1).collect();
+            let (first, second) = matrix.split_at(ndarray::Axis(0), 1 << (nr_bits - 1));
+            rayon::join(|| apply_kernel(first, &sub_bits, nr_bits - 1, kernel),
+                || apply_kernel(second, &sub_bits, nr_bits - 1, kernel));
+            return;
+        }
+    }
+
+    kernel(matrix, bits, nr_bits);
+}
+
+/// Compute subspace offsets.
+///
+/// For a gate operating on the bits in `bits` in a system of `nr_bits`
+/// qubits, return the offsets of the rows within a subspace in which the
+/// unaffected qubits are fixed, in the order of the gate. Also returned are
+/// the masks of the affected and unaffected bits in a row index.
+fn subspace_offsets(bits: &[usize], nr_bits: usize) -> (Vec<usize>, usize, usize)
+{
+    let gate_bits = bits.len();
+    let offsets: Vec<usize> = (0..1 << gate_bits).map(|x| {
+            bits.iter().enumerate()
+                .filter(|&(i, _)| (x >> (gate_bits - 1 - i)) & 1 == 1)
+                .fold(0, |off, (_, &b)| off | (1 << (nr_bits - 1 - b)))
+        }).collect();
+    let affected_mask = offsets[offsets.len() - 1];
+    let free_mask = ((1 << nr_bits) - 1) & !affected_mask;
+
+    (offsets, affected_mask, free_mask)
+}
+
+/// Apply a diagonal gate.
+///
+/// Apply a gate with diagonal `diagonal`, operating on the bits in `bits`, to
+/// the rows of `matrix`, describing a state of `nr_bits` qubits. Rows for
+/// which the diagonal element is one are not touched.
+fn apply_diagonal<T>(mut matrix: ndarray::ArrayViewMut2<T>, diagonal: &[crate::cmatrix::CNumber],
+    bits: &[usize], nr_bits: usize)
+where T: crate::cmatrix::Amplitude
+{
+    let (offsets, affected_mask, free_mask) = subspace_offsets(bits, nr_bits);
+    let factors: Vec<(usize, T)> = offsets.iter().zip(diagonal)
+        .filter(|&(_, &d)| d != crate::cmatrix::COMPLEX_ONE)
+        .map(|(&off, &d)| (off, T::from_complex(d)))
+        .collect();
+    if factors.is_empty()
+    {
+        return;
+    }
+
+    let nr_subspaces = 1 << (nr_bits - bits.len());
+    for mut col in matrix.gencolumns_mut()
+    {
+        let mut base = 0;
+        for _ in 0..nr_subspaces
+        {
+            for &(off, d) in factors.iter()
+            {
+                col[base | off] = col[base | off] * d;
+            }
+            base = ((base | affected_mask) + 1) & free_mask;
+        }
+    }
+}
+
+/// Apply a permutation gate.
+///
+/// Apply a gate that permutes the basis states according to `perm`,
+/// operating on the bits in `bits`, to the rows of `matrix`, describing a
+/// state of `nr_bits` qubits. Only rows that are moved by the permutation are
+/// touched.
+fn apply_permutation<T>(mut matrix: ndarray::ArrayViewMut2<T>, perm: &[usize],
+    bits: &[usize], nr_bits: usize)
+where T: crate::cmatrix::Amplitude
+{
+    let (offsets, affected_mask, free_mask) = subspace_offsets(bits, nr_bits);
+    let moves: Vec<(usize, usize)> = perm.iter().enumerate()
+        .filter(|&(dst, &src)| dst != src)
+        .map(|(dst, &src)| (offsets[dst], offsets[src]))
+        .collect();
+    if moves.is_empty()
+    {
+        return;
+    }
+
+    let nr_subspaces = 1 << (nr_bits - bits.len());
+    let mut work = vec![T::zero(); moves.len()];
+    for mut col in matrix.gencolumns_mut()
+    {
+        let mut base = 0;
+        for _ in 0..nr_subspaces
+        {
+            for (w, &(_, src)) in work.iter_mut().zip(moves.iter())
+            {
+                *w = col[base | src];
+            }
+            for (&w, &(dst, _)) in work.iter().zip(moves.iter())
+            {
+                col[base | dst] = w;
+            }
+            base = ((base | affected_mask) + 1) & free_mask;
+        }
+    }
+}
+
+/// Apply a multi-qubit gate.
+///
+/// Apply gate `gate` operating on the bits in `bits` to the rows of `matrix`,
+/// by gathering the coefficients for batches of subspaces in a work buffer.
+/// See `apply_gate_strided()`.
+fn apply_gate_gathered<T, G>(mut matrix: ndarray::ArrayViewMut2<T>, gate: &G, bits: &[usize],
+    nr_bits: usize)
+where T: crate::cmatrix::Amplitude, G: Gate + ?Sized
+{
+    let gate_bits = bits.len();
+    let nr_cols = matrix.cols();
+    let (offsets, affected_mask, free_mask) = subspace_offsets(bits, nr_bits);
+
+    // Number of subspaces handled at once. This must be a power of two, so
+    // that the gate sees the extra subspaces as additional trailing bits.
+    let nr_subspaces = 1 << (nr_bits - gate_bits);
+    let max_batch_size = ((GATHER_BUFFER_SIZE >> gate_bits) / nr_cols).max(1);
+    let batch_size = (1 << (usize::BITS - 1 - max_batch_size.leading_zeros())).min(nr_subspaces);
+
+    let mut work = crate::cmatrix::CMatrix::zeros((batch_size << gate_bits, nr_cols));
+    let mut bases = vec![0; batch_size];
+    let mut base = 0;
+    for _ in 0..nr_subspaces / batch_size
+    {
+        // Enumerate the indices with all affected bits zero, in order
+        for b in bases.iter_mut()
+        {
+            *b = base;
+            base = ((base | affected_mask) + 1) & free_mask;
+        }
+
+        for (x, &off) in offsets.iter().enumerate()
+        {
+            for (j, &b) in bases.iter().enumerate()
+            {
+                for col in 0..nr_cols
+                {
+                    work[[x * batch_size + j, col]] = matrix[[b | off, col]].to_complex();
+                }
+            }
+        }
+        gate.apply_mat_slice(work.view_mut());
+        for (x, &off) in offsets.iter().enumerate()
+        {
+            for (j, &b) in bases.iter().enumerate()
+            {
+                for col in 0..nr_cols
+                {
+                    matrix[[b | off, col]] = T::from_complex(work[[x * batch_size + j, col]]);
+                }
+            }
+        }
+    }
+}
+
+/// Check the bits a gate operates on.
+///
+/// Check if gate `gate` can be applied to the qubits in `bits`, in a state of
+/// `nr_bits` qubits. An `InvalidNrBits` error is returned if the number of
+/// bits does not match the gate, an `InvalidQBit` error if a bit index is out
+/// of range, and a `DuplicateQBit` error if a qubit occurs more than once.
+pub(crate) fn check_gate_bits<G>(gate: &G, bits: &[usize], nr_bits: usize)
+    -> crate::error::Result<()>
+where G: Gate + ?Sized
+{
+    gate.check_nr_bits(bits)?;
+    for (i, &bit) in bits.iter().enumerate()
+    {
+        if bit >= nr_bits
+        {
+            return Err(crate::error::Error::InvalidQBit(bit));
+        }
+        if bits[..i].contains(&bit)
+        {
+            return Err(crate::error::Error::DuplicateQBit(bit));
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that a state of `nr_bits` qubits, stored in `nr_rows` rows, has the
+/// correct size. If not, an `InvalidStateSize` error is returned.
+fn check_state_size(nr_rows: usize, nr_bits: usize) -> crate::error::Result<()>
+{
+    if 1usize.checked_shl(nr_bits as u32) == Some(nr_rows)
+    {
+        Ok(())
+    }
+    else
+    {
+        Err(crate::error::Error::InvalidStateSize(nr_rows, nr_bits))
+    }
+}
+
 /// Apply a gate
 ///
 /// Apply gate `gate` operating on the bits in `bits` to a vector `vec`. The
-/// number of elements in `vec` must be 2^`nr_bits`.
-pub fn apply_gate_slice<G>(mut vec: crate::cmatrix::CVecSliceMut, gate: &G,
-    bits: &[usize], nr_bits: usize)
+/// number of elements in `vec` must be 2^`nr_bits`. Gates that are diagonal,
+/// or that permute the basis states, are applied by scaling or moving the
+/// affected coefficients in place. When the `parallel` feature is enabled,
+/// single qubit gates are applied to blocks of the vector in parallel. An
+/// error is returned if the bits do not match the gate, or the size of `vec`
+/// does not match the number of bits.
+pub fn apply_gate_slice<G>(vec: crate::cmatrix::CVecSliceMut, gate: &G,
+    bits: &[usize], nr_bits: usize) -> crate::error::Result<()>
 where G: Gate + ?Sized
 {
-    let gate_bits = gate.nr_affected_bits();
-    assert!(gate_bits == bits.len(),
-        "The number of bits affected by the {} gate should be {}, but {} bits were provided.",
-        gate.description(), gate_bits, bits.len()
-    );
-    assert!(vec.len() == 1 << nr_bits,
-        "The number of bits in the state does not match the total number of bits.");
+    check_gate_bits(gate, bits, nr_bits)?;
+    check_state_size(vec.len(), nr_bits)?;
+    apply_gate_slice_unchecked(vec, gate, bits, nr_bits);
+    Ok(())
+}
 
-    if let &[bit] = bits
+/// Apply a gate
+///
+/// Apply gate `gate` operating on the bits in `bits` to a vector `vec`, without
+/// checking the arguments. See `apply_gate_slice()`.
+fn apply_gate_slice_unchecked<G>(vec: crate::cmatrix::CVecSliceMut, gate: &G,
+    bits: &[usize], nr_bits: usize)
+where G: Gate + ?Sized
+{
+    if let Some(diagonal) = gate.diagonal()
+    {
+        apply_kernel(vec.insert_axis(ndarray::Axis(1)), bits, nr_bits,
+            &|m, b, n| apply_diagonal(m, diagonal.as_slice().unwrap(), b, n));
+    }
+    else if let Some(perm) = gate.permutation()
+    {
+        apply_kernel(vec.insert_axis(ndarray::Axis(1)), bits, nr_bits,
+            &|m, b, n| apply_permutation(m, perm.indices(), b, n));
+    }
+    else if let &[bit] = bits
     {
         let block_size = 1 << (nr_bits - bit);
-        let nr_blocks = 1 << bit;
-        for i in 0..nr_blocks
-        {
-            gate.apply_slice(vec.slice_mut(s![i*block_size..(i+1)*block_size]));
-        }
+        split_work(vec, ndarray::Axis(0), block_size, &|mut part: crate::cmatrix::CVecSliceMut| {
+            let nr_blocks = part.len() / block_size;
+            for i in 0..nr_blocks
+            {
+                gate.apply_slice(part.slice_mut(s![i*block_size..(i+1)*block_size]));
+            }
+        });
     }
     else
     {
-        let perm = bit_permutation(nr_bits, bits);
-        let mut work = crate::cmatrix::CVector::zeros(1 << nr_bits);
-        perm.apply_inverse_vec_into(vec.view(), work.view_mut());
-        gate.apply_slice(work.view_mut());
-        perm.apply_vec_into(work.view(), vec);
+        apply_gate_strided(vec.insert_axis(ndarray::Axis(1)), gate, bits, nr_bits);
     }
 }
 
 /// Apply a gate
 ///
 /// Apply gate `gate` operating on the bits in `bits` to a matrix `matrix`. The
-/// number of rows in `matrix` must be 2^`nr_bits`.
-pub fn apply_gate_mat_slice<G>(mut matrix: crate::cmatrix::CMatSliceMut, gate: &G,
-    bits: &[usize], nr_bits: usize)
+/// number of rows in `matrix` must be 2^`nr_bits`. Gates that are diagonal,
+/// or that permute the basis states, are applied by scaling or moving the
+/// affected coefficients in place. When the `parallel` feature is enabled,
+/// the gate is applied to groups of columns in parallel, and single qubit
+/// gates additionally to blocks of rows. An error is returned if the bits do
+/// not match the gate, or the number of rows in `matrix` does not match the
+/// number of bits.
+pub fn apply_gate_mat_slice<G>(matrix: crate::cmatrix::CMatSliceMut, gate: &G,
+    bits: &[usize], nr_bits: usize) -> crate::error::Result<()>
 where G: Gate + ?Sized
 {
-    let gate_bits = gate.nr_affected_bits();
-    assert!(gate_bits == bits.len(),
-        "The number of bits affected by the {} gate should be {}, but {} bits were provided.",
-        gate.description(), gate_bits, bits.len()
-    );
-    assert!(matrix.rows() == 1 << nr_bits,
-        "The number of bits in the state does not match the total number of bits.");
+    check_gate_bits(gate, bits, nr_bits)?;
+    check_state_size(matrix.rows(), nr_bits)?;
+    apply_gate_mat_slice_unchecked(matrix, gate, bits, nr_bits);
+    Ok(())
+}
 
-    if let &[bit] = bits
+/// Apply a gate
+///
+/// Apply gate `gate` operating on the bits in `bits` to a matrix `matrix`,
+/// without checking the arguments. See `apply_gate_mat_slice()`.
+fn apply_gate_mat_slice_unchecked<G>(matrix: crate::cmatrix::CMatSliceMut, gate: &G,
+    bits: &[usize], nr_bits: usize)
+where G: Gate + ?Sized
+{
+    if let Some(diagonal) = gate.diagonal()
+    {
+        split_work(matrix, ndarray::Axis(1), 1, &|cols: crate::cmatrix::CMatSliceMut| {
+            apply_kernel(cols, bits, nr_bits,
+                &|m, b, n| apply_diagonal(m, diagonal.as_slice().unwrap(), b, n));
+        });
+    }
+    else if let Some(perm) = gate.permutation()
+    {
+        split_work(matrix, ndarray::Axis(1), 1, &|cols: crate::cmatrix::CMatSliceMut| {
+            apply_kernel(cols, bits, nr_bits,
+                &|m, b, n| apply_permutation(m, perm.indices(), b, n));
+        });
+    }
+    else if let &[bit] = bits
     {
         let block_size = 1 << (nr_bits - bit);
-        let nr_blocks = 1 << bit;
-        for i in 0..nr_blocks
-        {
-            gate.apply_mat_slice(matrix.slice_mut(s![i*block_size..(i+1)*block_size, ..]));
-        }
+        split_work(matrix, ndarray::Axis(1), 1, &|cols: crate::cmatrix::CMatSliceMut| {
+            split_work(cols, ndarray::Axis(0), block_size, &|mut part: crate::cmatrix::CMatSliceMut| {
+                let nr_blocks = part.rows() / block_size;
+                for i in 0..nr_blocks
+                {
+                    gate.apply_mat_slice(part.slice_mut(s![i*block_size..(i+1)*block_size, ..]));
+                }
+            });
+        });
     }
     else
     {
-        let perm = bit_permutation(nr_bits, bits);
-        let mut work = crate::cmatrix::CMatrix::zeros((matrix.rows(), matrix.cols()));
-        ndarray::Zip::from(matrix.gencolumns()).and(work.gencolumns_mut())
-            .apply(|s, d| perm.apply_inverse_vec_into(s, d));
-        gate.apply_mat_slice(work.view_mut());
-        ndarray::Zip::from(matrix.gencolumns_mut()).and(work.gencolumns())
-            .apply(|d, s| perm.apply_vec_into(s, d));
+        split_work(matrix, ndarray::Axis(1), 1, &|cols: crate::cmatrix::CMatSliceMut| {
+            apply_gate_strided(cols, gate, bits, nr_bits);
+        });
+    }
+}
+
+/// Apply a gate to amplitudes of a different type.
+///
+/// Apply gate `gate` operating on the bits in `bits` to the columns of
+/// `matrix`, each describing a state of `nr_bits` qubits, where the amplitudes
+/// are not stored as `Complex64`. Diagonal and permutation gates are applied
+/// in place; for other gates, the amplitudes are converted to double precision
+/// while gathering them in a work buffer, and back when scattering the
+/// results. See `apply_gate_mat_slice()`.
+pub fn apply_gate_amplitudes<T, G>(matrix: ndarray::ArrayViewMut2<T>, gate: &G,
+    bits: &[usize], nr_bits: usize) -> crate::error::Result<()>
+where T: crate::cmatrix::Amplitude, G: Gate + ?Sized
+{
+    check_gate_bits(gate, bits, nr_bits)?;
+    check_state_size(matrix.rows(), nr_bits)?;
+
+    if let Some(diagonal) = gate.diagonal()
+    {
+        split_work(matrix, ndarray::Axis(1), 1, &|cols: ndarray::ArrayViewMut2<T>| {
+            apply_kernel(cols, bits, nr_bits,
+                &|m, b, n| apply_diagonal(m, diagonal.as_slice().unwrap(), b, n));
+        });
+    }
+    else if let Some(perm) = gate.permutation()
+    {
+        split_work(matrix, ndarray::Axis(1), 1, &|cols: ndarray::ArrayViewMut2<T>| {
+            apply_kernel(cols, bits, nr_bits,
+                &|m, b, n| apply_permutation(m, perm.indices(), b, n));
+        });
     }
+    else
+    {
+        split_work(matrix, ndarray::Axis(1), 1, &|cols: ndarray::ArrayViewMut2<T>| {
+            apply_gate_strided(cols, gate, bits, nr_bits);
+        });
+    }
+
+    Ok(())
 }
 
-pub trait Gate
+pub trait Gate: Send + Sync
 {
     /// Cost of this gate.
     ///
@@ -147,7 +504,7 @@ pub trait Gate
     /// optimizing circuits, or when trying to decompose a transformation
     /// in an optimal gate sequence. The default implementation returns
     /// `std::f64::INFINITY`.
-    fn cost(&self) -> f64 { ::std::f64::INFINITY }
+    fn cost(&self) -> f64 { f64::INFINITY }
 
     /// Return a short description of the gate. This may be the name of the
     /// gate (e.g. `"H"`, `"CX"`), or the way the gate was constructed (like
@@ -161,6 +518,28 @@ pub trait Gate
     /// provides
     fn matrix(&self) -> crate::cmatrix::CMatrix;
 
+    /// Diagonal of the gate matrix.
+    ///
+    /// If the matrix describing this gate is diagonal, return the elements on
+    /// its diagonal, so that the gate can be applied by scaling coefficients
+    /// in place. The default implementation returns `None`.
+    fn diagonal(&self) -> Option<crate::cmatrix::CVector>
+    {
+        None
+    }
+
+    /// Basis permutation.
+    ///
+    /// If this gate maps every basis state onto another basis state, without
+    /// changing its phase, return the permutation `perm` for which
+    /// `perm.matrix()` equals the gate matrix, so that the gate can be
+    /// applied by moving coefficients in place. The default implementation
+    /// returns `None`.
+    fn permutation(&self) -> Option<crate::permutation::Permutation>
+    {
+        None
+    }
+
     /// Apply a gate.
     ///
     /// Apply a gate to quantum state `state`. The number of rows `r` in `state`
@@ -342,11 +721,18 @@ where G: Gate
     assert_complex_matrix_eq!(&*state, result);
 }
 
-pub use self::controlled::{C, CH, CRX, CRY, CRZ, CS, CSdg, CT, CTdg, CU1, CU2, CU3, CV, CVdg, CX, CY, CZ, CCRX, CCRY, CCRZ, CCX, CCZ};
+pub use self::controlled::{C, CH, CRX, CRY, CRZ, CS, CSdg, CT, CTdg, CU1, CU2, CU3, CV, CVdg,
+    CX, CY, CZ, CCRX, CCRY, CCRZ, CCX, CCZ};
 pub use self::composite::Composite;
+pub(crate) use self::composite::ExpressionValue;
 pub use self::hadamard::H;
 pub use self::identity::I;
 pub use self::kron::Kron;
+pub(crate) use self::matrix::MatrixGate;
+pub use self::multicontrolled::MultiControlled;
+pub use self::paulirotation::PauliRotation;
+pub(crate) use self::paulirotation::add_rotation_gadget;
+pub use self::qft::QFT;
 pub use self::rx::RX;
 pub use self::ry::RY;
 pub use self::rz::RZ;
@@ -357,7 +743,202 @@ pub use self::swap::Swap;
 pub use self::u1::U1;
 pub use self::u2::U2;
 pub use self::u3::U3;
+pub use self::unitary::Unitary;
 pub use self::v::{V, Vdg};
 pub use self::x::X;
 pub use self::y::Y;
 pub use self::z::Z;
+
+#[cfg(test)]
+mod tests
+{
+    use super::{apply_gate_amplitudes, apply_gate_mat_slice, apply_gate_slice, bit_permutation, Gate};
+    use crate::error::Error;
+
+    /// Apply `gate` by permuting the full state, such that the affected bits
+    /// come first.
+    fn apply_permuted<G: Gate + ?Sized>(gate: &G, state: &crate::cmatrix::CMatrix, bits: &[usize],
+        nr_bits: usize) -> crate::cmatrix::CMatrix
+    {
+        let perm = bit_permutation(nr_bits, bits);
+        let mut work = crate::cmatrix::CMatrix::zeros((state.rows(), state.cols()));
+        ndarray::Zip::from(state.gencolumns()).and(work.gencolumns_mut())
+            .apply(|s, d| perm.apply_inverse_vec_into(s, d));
+        gate.apply_mat_slice(work.view_mut());
+        let mut res = crate::cmatrix::CMatrix::zeros((state.rows(), state.cols()));
+        ndarray::Zip::from(res.gencolumns_mut()).and(work.gencolumns())
+            .apply(|d, s| perm.apply_vec_into(s, d));
+        res
+    }
+
+    #[test]
+    fn test_apply_gate_multi_bit()
+    {
+        let qft = crate::gates::QFT::new(3, true);
+        for &(nr_bits, nr_cols) in [(4, 1), (5, 3), (13, 3), (14, 1)].iter()
+        {
+            let state = crate::cmatrix::CMatrix::from_shape_fn((1 << nr_bits, nr_cols), |(i, j)| {
+                num_complex::Complex::new(((i + 7*j) as f64).sin(), ((5*i + j) as f64).cos())
+            });
+
+            for bits in [[0, 1], [3, 1], [nr_bits-1, 0]].iter()
+            {
+                let expected = apply_permuted(&crate::gates::CX::new(), &state, bits, nr_bits);
+                let mut result = state.clone();
+                apply_gate_mat_slice(result.view_mut(), &crate::gates::CX::new(), bits, nr_bits).unwrap();
+                assert_complex_matrix_eq!(&result, &expected);
+
+                for i in 0..nr_cols
+                {
+                    let mut column = state.column(i).to_owned();
+                    apply_gate_slice(column.view_mut(), &crate::gates::CX::new(), bits, nr_bits).unwrap();
+                    assert_complex_vector_eq!(&column, &expected.column(i).to_owned());
+                }
+            }
+
+            for bits in [[2, 0, 1], [nr_bits-1, 1, nr_bits-2]].iter()
+            {
+                let expected = apply_permuted(&qft, &state, bits, nr_bits);
+                let mut result = state.clone();
+                apply_gate_mat_slice(result.view_mut(), &qft, bits, nr_bits).unwrap();
+                assert_complex_matrix_eq!(&result, &expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_apply_gate_amplitudes()
+    {
+        use crate::cmatrix::Amplitude;
+        use crate::gates::*;
+
+        let nr_bits = 10;
+        let gates: Vec<(Box<dyn Gate>, Vec<usize>)> = vec![
+            (Box::new(H::new()), vec![3]),
+            (Box::new(RX::new(0.37)), vec![0]),
+            (Box::new(CX::new()), vec![3, 8]),
+            (Box::new(U3::new(1.1, -0.3, 2.4)), vec![9]),
+            (Box::new(CCX::new()), vec![8, 0, 5]),
+            (Box::new(T::new()), vec![5]),
+            (Box::new(QFT::new(3, false)), vec![2, 7, 4]),
+            (Box::new(CRY::new(0.8)), vec![6, 1]),
+            (Box::new(Swap::new()), vec![1, 9]),
+            (Box::new(PauliRotation::rxx(-0.6)), vec![4, 0])
+        ];
+
+        let mut state = crate::cmatrix::CMatrix::from_shape_fn((1 << nr_bits, 2), |(i, j)| {
+            num_complex::Complex::new(((i + 7*j) as f64).sin(), ((5*i + j) as f64).cos())
+        });
+        for mut col in state.gencolumns_mut()
+        {
+            let norm = col.iter().map(|c| c.norm_sqr()).sum::<f64>().sqrt();
+            col /= crate::cmatrix::CNumber::new(norm, 0.0);
+        }
+        let mut single = state.mapv(num_complex::Complex32::from_complex);
+
+        let nr_rounds = 10;
+        for _ in 0..nr_rounds
+        {
+            for (gate, bits) in gates.iter()
+            {
+                apply_gate_mat_slice(state.view_mut(), &**gate, bits, nr_bits).unwrap();
+                apply_gate_amplitudes(single.view_mut(), &**gate, bits, nr_bits).unwrap();
+            }
+        }
+
+        // The norm of the error should be well within the documented bound
+        let nr_gates = nr_rounds * gates.len();
+        for (col, single_col) in state.gencolumns().into_iter().zip(single.gencolumns())
+        {
+            let err = col.iter().zip(single_col.iter())
+                .map(|(&d, &s)| (d - s.to_complex()).norm_sqr())
+                .sum::<f64>()
+                .sqrt();
+            assert!(err < nr_gates as f64 * 1.0e-7, "error {} too large", err);
+        }
+    }
+
+    #[test]
+    fn test_diagonal_permutation()
+    {
+        use crate::gates::*;
+
+        let diagonal_gates: Vec<Box<dyn Gate>> = vec![
+            Box::new(Z::new()), Box::new(S::new()), Box::new(Sdg::new()),
+            Box::new(T::new()), Box::new(Tdg::new()), Box::new(U1::new(0.7)),
+            Box::new(RZ::new(-1.3)), Box::new(CZ::new()), Box::new(CU1::new(0.4)),
+            Box::new(CRZ::new(2.1)), Box::new(CCZ::new()), Box::new(C::new(CCZ::new())),
+            Box::new(PauliRotation::rzz(0.9)),
+            Box::new(MultiControlled::new_negated(Box::new(U1::new(0.3)), &[true, false, true])
+                .with_ancillas())
+        ];
+        let permutation_gates: Vec<Box<dyn Gate>> = vec![
+            Box::new(X::new()), Box::new(CX::new()), Box::new(CCX::new()),
+            Box::new(Swap::new()), Box::new(C::new(Swap::new())), Box::new(C::new(CCX::new())),
+            Box::new(MultiControlled::new_negated(Box::new(X::new()), &[false, true, true])
+                .with_ancillas())
+        ];
+        let other_gates: Vec<Box<dyn Gate>> = vec![
+            Box::new(H::new()), Box::new(Y::new()), Box::new(U3::new(0.1, 0.2, 0.3)),
+            Box::new(CH::new()), Box::new(PauliRotation::rxx(0.9))
+        ];
+
+        for gate in diagonal_gates.iter()
+        {
+            let diagonal = gate.diagonal().unwrap();
+            let mut mat = crate::cmatrix::CMatrix::zeros((diagonal.len(), diagonal.len()));
+            mat.diag_mut().assign(&diagonal);
+            assert_complex_matrix_eq!(&mat, &gate.matrix());
+            assert!(gate.permutation().is_none());
+        }
+        for gate in permutation_gates.iter()
+        {
+            let perm = gate.permutation().unwrap();
+            assert_complex_matrix_eq!(&perm.matrix::<crate::cmatrix::CNumber>(), &gate.matrix());
+            assert!(gate.diagonal().is_none());
+        }
+        for gate in other_gates.iter()
+        {
+            assert!(gate.diagonal().is_none() && gate.permutation().is_none());
+        }
+
+        let nr_bits = 7;
+        let state = crate::cmatrix::CMatrix::from_shape_fn((1 << nr_bits, 3), |(i, j)| {
+            num_complex::Complex::new(((i + 7*j) as f64).sin(), ((5*i + j) as f64).cos())
+        });
+        for gate in diagonal_gates.iter().chain(permutation_gates.iter())
+        {
+            let bits: Vec<usize> = [5, 0, 3, 6, 1, 4].iter().cloned()
+                .take(gate.nr_affected_bits())
+                .collect();
+            let expected = apply_permuted(&**gate, &state, &bits, nr_bits);
+            let mut result = state.clone();
+            apply_gate_mat_slice(result.view_mut(), &**gate, &bits, nr_bits).unwrap();
+            assert_complex_matrix_eq!(&result, &expected);
+
+            let mut column = state.column(1).to_owned();
+            apply_gate_slice(column.view_mut(), &**gate, &bits, nr_bits).unwrap();
+            assert_complex_vector_eq!(&column, &expected.column(1).to_owned());
+        }
+    }
+
+    #[test]
+    fn test_apply_gate_errors()
+    {
+        let mut state = crate::cmatrix::CMatrix::zeros((8, 2));
+        let mut column = crate::cmatrix::CVector::zeros(8);
+
+        assert!(matches!(
+            apply_gate_mat_slice(state.view_mut(), &crate::gates::CX::new(), &[0, 1, 2], 3),
+            Err(Error::InvalidNrBits(3, 2, _))
+        ));
+        assert_eq!(apply_gate_slice(column.view_mut(), &crate::gates::H::new(), &[3], 3),
+            Err(Error::InvalidQBit(3)));
+        assert_eq!(apply_gate_slice(column.view_mut(), &crate::gates::CX::new(), &[2, 2], 3),
+            Err(Error::DuplicateQBit(2)));
+        assert_eq!(apply_gate_mat_slice(state.view_mut(), &crate::gates::H::new(), &[0], 4),
+            Err(Error::InvalidStateSize(8, 4)));
+        assert_eq!(apply_gate_amplitudes(state.view_mut(), &crate::gates::X::new(), &[1], 2),
+            Err(Error::InvalidStateSize(8, 2)));
+    }
+}
@@ -0,0 +1,791 @@
+// Copyright 2019 Q1t BV
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Reversible arithmetic.
+//!
+//! The functions in this module create composite gates performing arithmetic
+//! on registers of qubits. A register of `n` qubits holds an `n`-bit unsigned
+//! integer, where the first qubit of the register holds the most significant
+//! bit. The registers a gate operates on are laid out consecutively in the
+//! order given in the documentation of each function, so that the index of a
+//! basis state in the matrix of the gate is formed by concatenating the
+//! values of the registers.
+//!
+//! Ancilla bits should be in the `|0⟩` state when the gate is applied, and
+//! are returned to the `|0⟩` state afterwards. For inputs violating the
+//! preconditions of a gate, the results are unspecified.
+
+/// The bits of a register.
+///
+/// Return the bits of the register of `nr_bits` qubits starting at gate bit
+/// `offset`, least significant bit first.
+fn register(offset: usize, nr_bits: usize) -> Vec<usize>
+{
+    (offset..offset+nr_bits).rev().collect()
+}
+
+/// Add a phase gate.
+///
+/// Add a phase shift over angle `angle` on bit `target`, controlled by the
+/// bits in `controls`, to `composite`.
+fn add_phase(composite: &mut crate::gates::Composite, controls: &[usize], target: usize,
+    angle: f64)
+{
+    match controls.len()
+    {
+        0 => {
+            composite.add_gate(crate::gates::U1::new(angle), &[target]);
+        },
+        1 => {
+            composite.add_gate(crate::gates::CU1::new(angle), &[controls[0], target]);
+        },
+        n => {
+            let gate = crate::gates::MultiControlled::new(
+                Box::new(crate::gates::U1::new(angle)), n);
+            let mut bits = controls.to_vec();
+            bits.push(target);
+            composite.add_gate(gate, &bits);
+        }
+    }
+}
+
+/// The angle of the phase shift on bit `bit` of a Fourier transformed register
+/// of `nr_bits` qubits, that adds `value` to the register.
+fn fourier_angle(value: u64, bit: usize, nr_bits: usize) -> f64
+{
+    let size = 1u128 << nr_bits;
+    let shifted = ((value as u128) << bit) % size;
+    2.0 * ::std::f64::consts::PI * shifted as f64 / size as f64
+}
+
+/// Add a constant in Fourier space.
+///
+/// Add gates to `composite` that add `value` modulo `2`<sup>`n`</sup> to the
+/// Fourier transformed `n`-bit register `reg` (least significant bit first),
+/// when all bits in `controls` are set.
+fn add_fourier_constant(composite: &mut crate::gates::Composite, controls: &[usize],
+    reg: &[usize], value: u64)
+{
+    for (j, &bit) in reg.iter().enumerate()
+    {
+        let angle = fourier_angle(value, j, reg.len());
+        if angle != 0.0
+        {
+            add_phase(composite, controls, bit, angle);
+        }
+    }
+}
+
+/// Add a register in Fourier space.
+///
+/// Add gates to `composite` that add (or, if `subtract` is `true`, subtract)
+/// the value of register `a` to the Fourier transformed register `reg`
+/// modulo `2`<sup>`n`</sup>, when all bits in `controls` are set. Both
+/// registers are given least significant bit first.
+fn add_fourier_register(composite: &mut crate::gates::Composite, controls: &[usize],
+    a: &[usize], reg: &[usize], subtract: bool)
+{
+    let sign = if subtract { -1.0 } else { 1.0 };
+    let mut ctrl = controls.to_vec();
+    ctrl.push(0);
+    for (i, &abit) in a.iter().enumerate()
+    {
+        *ctrl.last_mut().unwrap() = abit;
+        for (j, &bit) in reg.iter().enumerate().take(reg.len().saturating_sub(i))
+        {
+            add_phase(composite, &ctrl, bit, sign * fourier_angle(1, i + j, reg.len()));
+        }
+    }
+}
+
+/// Apply a quantum Fourier transform, or its inverse, on register `reg`
+/// (least significant bit first).
+fn add_qft(composite: &mut crate::gates::Composite, reg: &[usize], inverse: bool)
+{
+    let bits: Vec<usize> = reg.iter().rev().cloned().collect();
+    if inverse
+    {
+        composite.add_gate(crate::gates::QFT::new_inverse(bits.len(), true), &bits);
+    }
+    else
+    {
+        composite.add_gate(crate::gates::QFT::new(bits.len(), true), &bits);
+    }
+}
+
+/// Modular addition in Fourier space.
+///
+/// Add gates to `composite` that add either the constant `value` (when
+/// `a` is `None`), or the value of register `a`, modulo `modulus` to the
+/// Fourier transformed register `reg`, when all bits in `controls` are set.
+/// The register `reg` is one bit wider than needed to hold values below
+/// `modulus`, and ancilla bit `flag` is used to record overflow. Both the
+/// original value in `reg` and the value added should be less than `modulus`.
+fn add_fourier_modular(composite: &mut crate::gates::Composite, controls: &[usize],
+    a: Option<&[usize]>, value: u64, reg: &[usize], flag: usize, modulus: u64)
+{
+    let msb = reg[reg.len() - 1];
+    let size = 1u64 << reg.len();
+    let add = |composite: &mut crate::gates::Composite, subtract: bool| {
+        match a
+        {
+            Some(a) => add_fourier_register(composite, controls, a, reg, subtract),
+            None    => add_fourier_constant(composite, controls, reg,
+                if subtract { size - value } else { value })
+        }
+    };
+
+    // Compute the sum minus the modulus, and record in the flag if it is
+    // negative.
+    add(composite, false);
+    add_fourier_constant(composite, &[], reg, size - modulus);
+    add_qft(composite, reg, true);
+    composite.add_gate(crate::gates::CX::new(), &[msb, flag]);
+    add_qft(composite, reg, false);
+    // If it is negative, add the modulus back in.
+    add_fourier_constant(composite, &[flag], reg, modulus);
+    // Reset the flag, which is set only when the result minus the value added
+    // is non-negative.
+    add(composite, true);
+    add_qft(composite, reg, true);
+    composite.add_gate(crate::gates::X::new(), &[msb]);
+    composite.add_gate(crate::gates::CX::new(), &[msb, flag]);
+    composite.add_gate(crate::gates::X::new(), &[msb]);
+    add_qft(composite, reg, false);
+    add(composite, false);
+}
+
+/// Check a modulus.
+///
+/// Check that `modulus` is at least 2, and that the values below it can be
+/// stored in `nr_bits` bits. If not, return an `InvalidModulus` error.
+fn check_modulus(nr_bits: usize, modulus: u64) -> crate::error::Result<()>
+{
+    if modulus < 2 || nr_bits >= 63 || modulus > (1 << nr_bits)
+    {
+        Err(crate::error::Error::InvalidModulus(modulus, nr_bits))
+    }
+    else
+    {
+        Ok(())
+    }
+}
+
+/// Add gates for the majority of three bits, in the ripple-carry adder.
+fn add_maj(composite: &mut crate::gates::Composite, c: usize, b: usize, a: usize)
+{
+    composite.add_gate(crate::gates::CX::new(), &[a, b]);
+    composite.add_gate(crate::gates::CX::new(), &[a, c]);
+    composite.add_gate(crate::gates::CCX::new(), &[c, b, a]);
+}
+
+/// Add gates for the inverse of the majority computation.
+fn add_maj_inverse(composite: &mut crate::gates::Composite, c: usize, b: usize, a: usize)
+{
+    composite.add_gate(crate::gates::CCX::new(), &[c, b, a]);
+    composite.add_gate(crate::gates::CX::new(), &[a, c]);
+    composite.add_gate(crate::gates::CX::new(), &[a, b]);
+}
+
+/// Add gates that uncompute the majority, and compute the sum bit.
+fn add_uma(composite: &mut crate::gates::Composite, c: usize, b: usize, a: usize)
+{
+    composite.add_gate(crate::gates::CCX::new(), &[c, b, a]);
+    composite.add_gate(crate::gates::CX::new(), &[a, c]);
+    composite.add_gate(crate::gates::CX::new(), &[c, b]);
+}
+
+/// Ripple-carry adder.
+///
+/// Create a Cuccaro ripple-carry adder for `nr_bits`-bit numbers. The gate
+/// operates on registers `a` (`nr_bits` bits), `b` (`nr_bits` bits), a
+/// carry-in bit `c`, and a carry-out bit `z`, and computes
+/// `b ← (a + b + c) mod 2`<sup>`n`</sup>, and flips `z` when the sum
+/// overflows. Registers `a` and `c` are unchanged. The adder uses only `CX`
+/// and `CCX` gates, and no ancillas besides the carry-in bit.
+pub fn ripple_carry_adder(nr_bits: usize) -> crate::gates::Composite
+{
+    let a = register(0, nr_bits);
+    let b = register(nr_bits, nr_bits);
+    let c = 2 * nr_bits;
+    let z = 2 * nr_bits + 1;
+
+    let mut composite = crate::gates::Composite::new("ADD", 2*nr_bits + 2);
+    if nr_bits == 0
+    {
+        return composite;
+    }
+
+    add_maj(&mut composite, c, b[0], a[0]);
+    for i in 1..nr_bits
+    {
+        add_maj(&mut composite, a[i-1], b[i], a[i]);
+    }
+    composite.add_gate(crate::gates::CX::new(), &[a[nr_bits-1], z]);
+    for i in (1..nr_bits).rev()
+    {
+        add_uma(&mut composite, a[i-1], b[i], a[i]);
+    }
+    add_uma(&mut composite, c, b[0], a[0]);
+
+    composite
+}
+
+/// Comparator.
+///
+/// Create a gate comparing two `nr_bits`-bit numbers. The gate operates on
+/// registers `a` (`nr_bits` bits), `b` (`nr_bits` bits), an ancilla bit, and
+/// a target bit `t`, and flips `t` when `a < b`. All other bits are unchanged.
+/// The comparison is performed by computing the carry of `(¬a) + b` with the
+/// majority chain of the ripple-carry adder.
+pub fn comparator(nr_bits: usize) -> crate::gates::Composite
+{
+    let a = register(0, nr_bits);
+    let b = register(nr_bits, nr_bits);
+    let c = 2 * nr_bits;
+    let t = 2 * nr_bits + 1;
+
+    let mut composite = crate::gates::Composite::new("LT", 2*nr_bits + 2);
+    if nr_bits == 0
+    {
+        return composite;
+    }
+
+    for &bit in a.iter()
+    {
+        composite.add_gate(crate::gates::X::new(), &[bit]);
+    }
+    add_maj(&mut composite, c, b[0], a[0]);
+    for i in 1..nr_bits
+    {
+        add_maj(&mut composite, a[i-1], b[i], a[i]);
+    }
+    composite.add_gate(crate::gates::CX::new(), &[a[nr_bits-1], t]);
+    for i in (1..nr_bits).rev()
+    {
+        add_maj_inverse(&mut composite, a[i-1], b[i], a[i]);
+    }
+    add_maj_inverse(&mut composite, c, b[0], a[0]);
+    for &bit in a.iter()
+    {
+        composite.add_gate(crate::gates::X::new(), &[bit]);
+    }
+
+    composite
+}
+
+/// QFT adder.
+///
+/// Create a Draper adder for `nr_bits`-bit numbers, operating on registers
+/// `a` (`nr_bits` bits) and `b` (`nr_bits` bits), computing
+/// `b ← (a + b) mod 2`<sup>`n`</sup>. The addition is performed with
+/// controlled phase gates on the Fourier transform of `b`, and needs no
+/// ancillas.
+pub fn qft_adder(nr_bits: usize) -> crate::gates::Composite
+{
+    let a = register(0, nr_bits);
+    let b = register(nr_bits, nr_bits);
+
+    let mut composite = crate::gates::Composite::new("QADD", 2*nr_bits);
+    add_qft(&mut composite, &b, false);
+    add_fourier_register(&mut composite, &[], &a, &b, false);
+    add_qft(&mut composite, &b, true);
+    composite
+}
+
+/// Constant adder.
+///
+/// Create a gate on a single register `b` of `nr_bits` bits, computing
+/// `b ← (b + value) mod 2`<sup>`n`</sup>. The addition is performed with
+/// phase gates on the Fourier transform of `b`.
+pub fn constant_adder(nr_bits: usize, value: u64) -> crate::gates::Composite
+{
+    let b = register(0, nr_bits);
+
+    let mut composite = crate::gates::Composite::new(&format!("ADD({})", value), nr_bits);
+    add_qft(&mut composite, &b, false);
+    add_fourier_constant(&mut composite, &[], &b, value);
+    add_qft(&mut composite, &b, true);
+    composite
+}
+
+/// Modular adder.
+///
+/// Create a gate adding two numbers modulo `modulus`, where the numbers are
+/// stored in `nr_bits` bits. The gate operates on registers `a` (`nr_bits`
+/// bits), `b` (`nr_bits+1` bits), and an ancilla bit, and computes
+/// `b ← (a + b) mod modulus`, for `a, b < modulus`. The extra bit in `b` is
+/// used to detect overflow, it should be zero on input, and is zero on
+/// output. Returns an `InvalidModulus` error if `modulus` is less than 2, or
+/// too large to fit in `nr_bits` bits.
+pub fn modular_adder(nr_bits: usize, modulus: u64)
+    -> crate::error::Result<crate::gates::Composite>
+{
+    check_modulus(nr_bits, modulus)?;
+
+    let a = register(0, nr_bits);
+    let b = register(nr_bits, nr_bits+1);
+    let flag = 2*nr_bits + 1;
+
+    let mut composite = crate::gates::Composite::new(&format!("ADD mod {}", modulus),
+        2*nr_bits + 2);
+    add_qft(&mut composite, &b, false);
+    add_fourier_modular(&mut composite, &[], Some(&a), 0, &b, flag, modulus);
+    add_qft(&mut composite, &b, true);
+    Ok(composite)
+}
+
+/// Modular constant adder.
+///
+/// Create a gate adding constant `value` modulo `modulus` to a number stored
+/// in `nr_bits` bits. The gate operates on register `b` (`nr_bits+1` bits),
+/// and an ancilla bit, and computes `b ← (b + value) mod modulus`, for
+/// `b < modulus`. The extra bit in `b` is used to detect overflow, it should
+/// be zero on input, and is zero on output. Returns an `InvalidModulus`
+/// error if `modulus` is less than 2, or too large to fit in `nr_bits` bits.
+pub fn modular_constant_adder(nr_bits: usize, value: u64, modulus: u64)
+    -> crate::error::Result<crate::gates::Composite>
+{
+    check_modulus(nr_bits, modulus)?;
+
+    let b = register(0, nr_bits+1);
+    let flag = nr_bits + 1;
+
+    let value = value % modulus;
+    let mut composite = crate::gates::Composite::new(
+        &format!("ADD({}) mod {}", value, modulus), nr_bits + 2);
+    add_qft(&mut composite, &b, false);
+    add_fourier_modular(&mut composite, &[], None, value, &b, flag, modulus);
+    add_qft(&mut composite, &b, true);
+    Ok(composite)
+}
+
+/// Modular multiply-accumulate.
+///
+/// Add gates to `composite` that add `value·x` modulo `modulus` to register
+/// `reg`, with `x` the value of register `x`, when all bits in `controls`
+/// are set. Both registers are given least significant bit first, and `reg`
+/// is one bit wider than `x`.
+fn add_modular_product(composite: &mut crate::gates::Composite, controls: &[usize],
+    x: &[usize], value: u64, reg: &[usize], flag: usize, modulus: u64)
+{
+    add_qft(composite, reg, false);
+    let mut ctrl = controls.to_vec();
+    ctrl.push(0);
+    let mut term = value % modulus;
+    for &bit in x.iter()
+    {
+        if term != 0
+        {
+            *ctrl.last_mut().unwrap() = bit;
+            add_fourier_modular(composite, &ctrl, None, term, reg, flag, modulus);
+        }
+        term = ((term as u128 * 2) % modulus as u128) as u64;
+    }
+    add_qft(composite, reg, true);
+}
+
+/// Modular multiply-accumulate.
+///
+/// Create a gate adding the product of a constant `value` and a number `x`
+/// to a number `b`, modulo `modulus`, where the numbers are stored in
+/// `nr_bits` bits. The gate operates on registers `x` (`nr_bits` bits), `b`
+/// (`nr_bits+1` bits), and an ancilla bit, and computes
+/// `b ← (b + value·x) mod modulus`, for `b < modulus`. Register `x` is
+/// unchanged. The extra bit in `b` is used to detect overflow, it should be
+/// zero on input, and is zero on output. Returns an `InvalidModulus` error if
+/// `modulus` is less than 2, or too large to fit in `nr_bits` bits.
+pub fn modular_multiply_add(nr_bits: usize, value: u64, modulus: u64)
+    -> crate::error::Result<crate::gates::Composite>
+{
+    check_modulus(nr_bits, modulus)?;
+
+    let x = register(0, nr_bits);
+    let b = register(nr_bits, nr_bits+1);
+    let flag = 2*nr_bits + 1;
+
+    let value = value % modulus;
+    let mut composite = crate::gates::Composite::new(
+        &format!("MAC({}) mod {}", value, modulus), 2*nr_bits + 2);
+    add_modular_product(&mut composite, &[], &x, value, &b, flag, modulus);
+    Ok(composite)
+}
+
+/// Modular multiplication in place.
+///
+/// Add gates to `composite` that multiply register `x` in place by `value`
+/// modulo `modulus`, when all bits in `controls` are set, using register
+/// `reg` and bit `flag` as ancillas.
+fn add_modular_multiplication(composite: &mut crate::gates::Composite, controls: &[usize],
+    x: &[usize], value: u64, reg: &[usize], flag: usize, modulus: u64)
+    -> crate::error::Result<()>
+{
+    let value = value % modulus;
+    let inverse = modular_inverse(value, modulus)
+        .ok_or(crate::error::Error::NotInvertible(value, modulus))?;
+
+    add_modular_product(composite, controls, x, value, reg, flag, modulus);
+    for (&xbit, &bbit) in x.iter().zip(reg.iter())
+    {
+        if controls.is_empty()
+        {
+            composite.add_gate(crate::gates::Swap::new(), &[xbit, bbit]);
+        }
+        else
+        {
+            let mut bits = controls.to_vec();
+            bits.push(xbit);
+            bits.push(bbit);
+            composite.add_gate(crate::gates::CX::new(), &[bbit, xbit]);
+            composite.add_gate(crate::gates::MultiControlled::new(
+                Box::new(crate::gates::X::new()), controls.len() + 1), &bits);
+            composite.add_gate(crate::gates::CX::new(), &[bbit, xbit]);
+        }
+    }
+    add_modular_product(composite, controls, x, modulus - inverse, reg, flag, modulus);
+    Ok(())
+}
+
+/// Modular multiplier.
+///
+/// Create a gate multiplying a number `x`, stored in `nr_bits` bits, in
+/// place by constant `value`, modulo `modulus`. The gate operates on register
+/// `x` (`nr_bits` bits), followed by `nr_bits+2` ancilla bits, and computes
+/// `x ← (value·x) mod modulus`, for `x < modulus`. Since the operation must
+/// be reversible, `value` must be coprime to `modulus`. The product is first
+/// accumulated in the ancillas, after which the registers are swapped, and
+/// the original value of `x` is cleared by subtracting the product with the
+/// modular inverse of `value`. Returns an `InvalidModulus` error if `modulus`
+/// is less than 2, or too large to fit in `nr_bits` bits, or a `NotInvertible`
+/// error if `value` has no inverse modulo `modulus`.
+pub fn modular_multiplier(nr_bits: usize, value: u64, modulus: u64)
+    -> crate::error::Result<crate::gates::Composite>
+{
+    check_modulus(nr_bits, modulus)?;
+
+    let x = register(0, nr_bits);
+    let b = register(nr_bits, nr_bits+1);
+    let flag = 2*nr_bits + 1;
+
+    let mut composite = crate::gates::Composite::new(
+        &format!("MUL({}) mod {}", value % modulus, modulus), 2*nr_bits + 2);
+    add_modular_multiplication(&mut composite, &[], &x, value, &b, flag, modulus)?;
+    Ok(composite)
+}
+
+/// Controlled modular multiplier.
+///
+/// Create a gate multiplying a number `x` in place by constant `value`,
+/// modulo `modulus`, when a control bit is set. The gate operates on a
+/// control bit, followed by the bits of the modular multiplier created by
+/// `modular_multiplier()`. Returns an `InvalidModulus` error if `modulus`
+/// is less than 2, or too large to fit in `nr_bits` bits, or a `NotInvertible`
+/// error if `value` has no inverse modulo `modulus`.
+pub fn controlled_modular_multiplier(nr_bits: usize, value: u64, modulus: u64)
+    -> crate::error::Result<crate::gates::Composite>
+{
+    check_modulus(nr_bits, modulus)?;
+
+    let x = register(1, nr_bits);
+    let b = register(nr_bits+1, nr_bits+1);
+    let flag = 2*nr_bits + 2;
+
+    let mut composite = crate::gates::Composite::new(
+        &format!("CMUL({}) mod {}", value % modulus, modulus), 2*nr_bits + 3);
+    add_modular_multiplication(&mut composite, &[0], &x, value, &b, flag, modulus)?;
+    Ok(composite)
+}
+
+/// Modular inverse.
+///
+/// Compute the inverse of `value` modulo `modulus`, using the extended
+/// Euclidean algorithm. Returns `None` if `value` and `modulus` are not
+/// coprime.
+pub fn modular_inverse(value: u64, modulus: u64) -> Option<u64>
+{
+    let (mut r0, mut r1) = (modulus as i128, (value % modulus) as i128);
+    let (mut t0, mut t1) = (0i128, 1i128);
+    while r1 != 0
+    {
+        let q = r0 / r1;
+        let (r, t) = (r0 - q*r1, t0 - q*t1);
+        r0 = r1;
+        r1 = r;
+        t0 = t1;
+        t1 = t;
+    }
+
+    if r0 == 1
+    {
+        Some(t0.rem_euclid(modulus as i128) as u64)
+    }
+    else
+    {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{comparator, constant_adder, controlled_modular_multiplier, modular_adder,
+        modular_constant_adder, modular_inverse, modular_multiplier, modular_multiply_add,
+        qft_adder, ripple_carry_adder};
+    use crate::gates::Gate;
+
+    /// Check that the matrix of `gate` maps basis state `input` to basis
+    /// state `output`
+    fn check_mapping(mat: &crate::cmatrix::CMatrix, input: usize, output: usize)
+    {
+        for (row, &value) in mat.column(input).iter().enumerate()
+        {
+            let expected = if row == output { 1.0 } else { 0.0 };
+            assert!((value - expected).norm() < 1.0e-10,
+                "Mapping {} to {}: element at row {} is {}", input, output, row, value);
+        }
+    }
+
+    #[test]
+    fn test_ripple_carry_adder()
+    {
+        for n in 1..4
+        {
+            let gate = ripple_carry_adder(n);
+            assert_eq!(gate.nr_affected_bits(), 2*n + 2);
+            let mat = gate.matrix();
+            for a in 0..1 << n
+            {
+                for b in 0..1 << n
+                {
+                    for c in 0..2
+                    {
+                        for z in 0..2
+                        {
+                            let sum = a + b + c;
+                            let input = (((a << n) | b) << 2) | (c << 1) | z;
+                            let output = (((a << n) | (sum % (1 << n))) << 2)
+                                | (c << 1) | (z ^ (sum >> n));
+                            check_mapping(&mat, input, output);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_comparator()
+    {
+        for n in 1..4
+        {
+            let mat = comparator(n).matrix();
+            for a in 0..1 << n
+            {
+                for b in 0..1 << n
+                {
+                    for t in 0..2
+                    {
+                        let input = (((a << n) | b) << 2) | t;
+                        let output = (((a << n) | b) << 2) | (t ^ (a < b) as usize);
+                        check_mapping(&mat, input, output);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_qft_adder()
+    {
+        for n in 1..4
+        {
+            let mat = qft_adder(n).matrix();
+            for a in 0..1 << n
+            {
+                for b in 0..1 << n
+                {
+                    check_mapping(&mat, (a << n) | b, (a << n) | ((a + b) % (1 << n)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_constant_adder()
+    {
+        let n = 3;
+        for value in 0..10
+        {
+            let gate = constant_adder(n, value);
+            assert_eq!(gate.description(), format!("ADD({})", value));
+            let mat = gate.matrix();
+            for b in 0..1 << n
+            {
+                check_mapping(&mat, b, (b + value as usize) % (1 << n));
+            }
+        }
+    }
+
+    #[test]
+    fn test_modular_adder()
+    {
+        let n = 2;
+        for &modulus in [2, 3, 4].iter()
+        {
+            let mat = modular_adder(n, modulus).unwrap().matrix();
+            let modulus = modulus as usize;
+            for a in 0..modulus
+            {
+                for b in 0..modulus
+                {
+                    let input = ((a << (n+1)) | b) << 1;
+                    let output = ((a << (n+1)) | ((a + b) % modulus)) << 1;
+                    check_mapping(&mat, input, output);
+                }
+            }
+        }
+
+        assert!(matches!(modular_adder(2, 5), Err(crate::error::Error::InvalidModulus(5, 2))));
+        assert!(matches!(modular_adder(2, 1), Err(crate::error::Error::InvalidModulus(1, 2))));
+    }
+
+    #[test]
+    fn test_modular_constant_adder()
+    {
+        let n = 3;
+        for &modulus in [5, 7, 8].iter()
+        {
+            for value in 0..10
+            {
+                let mat = modular_constant_adder(n, value, modulus).unwrap().matrix();
+                for b in 0..modulus as usize
+                {
+                    check_mapping(&mat, b << 1, ((b + value as usize) % modulus as usize) << 1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_modular_multiply_add()
+    {
+        let n = 2;
+        let modulus = 3;
+        for value in 0..4
+        {
+            let mat = modular_multiply_add(n, value, modulus).unwrap().matrix();
+            for x in 0..1 << n
+            {
+                for b in 0..modulus as usize
+                {
+                    let input = ((x << (n+1)) | b) << 1;
+                    let output = ((x << (n+1)) | ((b + value as usize * x) % modulus as usize)) << 1;
+                    check_mapping(&mat, input, output);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_modular_multiplier()
+    {
+        let n = 2;
+        for &(value, modulus) in [(2, 3), (1, 3), (3, 4)].iter()
+        {
+            let gate = modular_multiplier(n, value, modulus).unwrap();
+            assert_eq!(gate.nr_affected_bits(), 2*n + 2);
+            let mat = gate.matrix();
+            for x in 0..modulus as usize
+            {
+                let output = (x * value as usize) % modulus as usize;
+                check_mapping(&mat, x << (n+2), output << (n+2));
+            }
+        }
+
+        // Computing the full matrix for larger registers takes long, only
+        // transform the valid inputs.
+        let n = 3;
+        for &(value, modulus) in [(3, 5), (4, 7), (3, 8)].iter()
+        {
+            let gate = modular_multiplier(n, value, modulus).unwrap();
+            let mut state = crate::cmatrix::CMatrix::zeros((1 << (2*n + 2), modulus as usize));
+            for x in 0..modulus as usize
+            {
+                state[[x << (n+2), x]] = crate::cmatrix::COMPLEX_ONE;
+            }
+            gate.apply_mat_slice(state.view_mut());
+            for x in 0..modulus as usize
+            {
+                let output = (x * value as usize) % modulus as usize;
+                for (row, &value) in state.column(x).iter().enumerate()
+                {
+                    let expected = if row == output << (n+2) { 1.0 } else { 0.0 };
+                    assert!((value - expected).norm() < 1.0e-10);
+                }
+            }
+        }
+
+        assert!(matches!(modular_multiplier(3, 2, 6),
+            Err(crate::error::Error::NotInvertible(2, 6))));
+        assert!(matches!(modular_multiplier(3, 2, 9),
+            Err(crate::error::Error::InvalidModulus(9, 3))));
+    }
+
+    #[test]
+    fn test_controlled_modular_multiplier()
+    {
+        let n = 2;
+        let modulus = 3;
+        let gate = controlled_modular_multiplier(n, 2, modulus).unwrap();
+        assert_eq!(gate.nr_affected_bits(), 2*n + 3);
+        let mat = gate.matrix();
+        for c in 0..2
+        {
+            for x in 0..modulus as usize
+            {
+                let output = if c == 1 { (2 * x) % modulus as usize } else { x };
+                check_mapping(&mat, ((c << n) | x) << (n+2), ((c << n) | output) << (n+2));
+            }
+        }
+
+        assert!(matches!(controlled_modular_multiplier(3, 3, 6),
+            Err(crate::error::Error::NotInvertible(3, 6))));
+    }
+
+    #[test]
+    fn test_modular_inverse()
+    {
+        assert_eq!(modular_inverse(3, 7), Some(5));
+        assert_eq!(modular_inverse(10, 7), Some(5));
+        assert_eq!(modular_inverse(1, 2), Some(1));
+        assert_eq!(modular_inverse(4, 6), None);
+        assert_eq!(modular_inverse(0, 5), None);
+    }
+
+    #[test]
+    fn test_export()
+    {
+        let gate = ripple_carry_adder(1);
+        let bit_names: Vec<String> = (0..4).map(|i| format!("q[{}]", i)).collect();
+        let bits: Vec<usize> = (0..4).collect();
+        assert_eq!(crate::export::OpenQasm::open_qasm(&gate, &bit_names, &bits),
+            Ok(String::from("cx q[0], q[1]; cx q[0], q[2]; ccx q[2], q[1], q[0]; cx q[0], q[3]; ccx q[2], q[1], q[0]; cx q[0], q[2]; cx q[2], q[1]")));
+
+        let gate = modular_constant_adder(2, 1, 3).unwrap();
+        let bit_names: Vec<String> = (0..4).map(|i| format!("q[{}]", i)).collect();
+        let bits: Vec<usize> = (0..4).collect();
+        assert!(crate::export::OpenQasm::open_qasm(&gate, &bit_names, &bits).is_ok());
+        assert!(crate::export::CQasm::c_qasm(&gate, &bit_names, &bits).is_ok());
+    }
+}
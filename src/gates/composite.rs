@@ -41,11 +41,83 @@ impl SubGateDesc
     }
 }
 
+/// Value of an arithmetic expression.
+///
+/// The expression parser in `Composite` computes the value of an expression
+/// through this trait, so that the same parser can be used both for real
+/// numbers, and for expressions in symbolic parameters. Operations whose
+/// result cannot be represented in the value type return `None`.
+pub(crate) trait ExpressionValue: Sized
+{
+    /// Create a value for the real number `value`.
+    fn from_real(value: f64) -> Self;
+    /// Create a value for the variable called `name`, or return `None` if
+    /// variables are not supported.
+    fn from_name(name: &str) -> Option<Self>;
+    /// Negate this value.
+    fn negate(self) -> Self;
+    /// Combine this value with `other`, using operator `op`, which is one of
+    /// `+`, `-`, `*`, `/`, or `^`.
+    fn apply_operator(self, op: &str, other: Self) -> Option<Self>;
+    /// Apply function `fun` to this value. The function is one of `sin`,
+    /// `cos`, `tan`, `exp`, `ln`, or `sqrt`.
+    fn apply_function(self, fun: &str) -> Option<Self>;
+}
+
+impl ExpressionValue for f64
+{
+    fn from_real(value: f64) -> Self
+    {
+        value
+    }
+
+    fn from_name(_name: &str) -> Option<Self>
+    {
+        None
+    }
+
+    fn negate(self) -> Self
+    {
+        -self
+    }
+
+    fn apply_operator(self, op: &str, other: Self) -> Option<Self>
+    {
+        match op
+        {
+            "+" => Some(self + other),
+            "-" => Some(self - other),
+            "*" => Some(self * other),
+            "/" => Some(self / other),
+            "^" => Some(self.powf(other)),
+            // LCOV_EXCL_START
+            _   => { unreachable!() }
+            // LCOV_EXCL_STOP
+        }
+    }
+
+    fn apply_function(self, fun: &str) -> Option<Self>
+    {
+        match fun
+        {
+            "sin"  => Some(self.sin()),
+            "cos"  => Some(self.cos()),
+            "tan"  => Some(self.tan()),
+            "exp"  => Some(self.exp()),
+            "ln"   => Some(self.ln()),
+            "sqrt" => Some(self.sqrt()),
+            // LCOV_EXCL_START
+            _      => { unreachable!() }
+            // LCOV_EXCL_STOP
+        }
+    }
+}
+
 /// Operation in a composite gate.
 struct SubGate
 {
     /// The gate
-    gate: Box<CircuitGate>,
+    gate: Box<dyn CircuitGate>,
     /// The bits on which the gate acts
     bits: Vec<usize>
 }
@@ -169,16 +241,19 @@ impl Composite
     /// Parse a possibly parenthesized expression
     ///
     /// Parse an argument to a gate. This function expects either an expression
-    /// in parentheses, or a literal real number. On success, the parsed
-    /// number is returned, together with the remainder of the string to be
-    /// parsed. On failure, a ParseError is returned.
-    fn parse_parenthesized_expression(expr: &str) -> crate::error::ParseResult<(f64, &str)>
+    /// in parentheses, a variable name if the value type supports variables,
+    /// or a literal real number. On success, the parsed value is returned,
+    /// together with the remainder of the string to be parsed. On failure, a
+    /// ParseError is returned.
+    fn parse_parenthesized_expression<T>(expr: &str) -> crate::error::ParseResult<(T, &str)>
+    where T: ExpressionValue
     {
         let fun_open = regex::Regex::new(r"^\s*\(").unwrap();
         let fun_close = regex::Regex::new(r"^\s*\)").unwrap();
+        let name = regex::Regex::new(r"(?i)^\s*([a-z_][a-z0-9_]*)\b").unwrap();
         if let Some(m) = fun_open.find(expr)
         {
-            let (result, rest) = Self::parse_sum_expression(&expr[m.end()..])?;
+            let (result, rest) = Self::parse_sum_value(&expr[m.end()..])?;
             if let Some(m) = fun_close.find(rest)
             {
                 Ok((result, &rest[m.end()..]))
@@ -190,7 +265,15 @@ impl Composite
         }
         else
         {
-            Self::parse_real_literal(expr)
+            if let Some(captures) = name.captures(expr)
+            {
+                if let Some(value) = T::from_name(&captures[1])
+                {
+                    return Ok((value, &expr[captures.get(0).unwrap().end()..]));
+                }
+            }
+            let (value, rest) = Self::parse_real_literal(expr)?;
+            Ok((T::from_real(value), rest))
         }
     }
 
@@ -200,30 +283,21 @@ impl Composite
     /// in this function, otherwise control is passed on to
     /// `parse_parenthesized_expression()`. The functions that are recognised
     /// are `sin`, `cos`, `tan`, `exp`, `ln`, and `sqrt`. On success, the parsed
-    /// number is returned, together with the remainder of the string to be
+    /// value is returned, together with the remainder of the string to be
     /// parsed. On failure, a ParseError is returned.
-    fn parse_function_expression(expr: &str) -> crate::error::ParseResult<(f64, &str)>
+    fn parse_function_expression<T>(expr: &str) -> crate::error::ParseResult<(T, &str)>
+    where T: ExpressionValue
     {
         let fun_open = regex::Regex::new(r"^\s*(sin|cos|tan|exp|ln|sqrt)\s*\(").unwrap();
         let fun_close = regex::Regex::new(r"^\s*\)").unwrap();
         if let Some(captures) = fun_open.captures(expr)
         {
             let m = captures.get(0).unwrap();
-            let (arg, new_rest) = Self::parse_sum_expression(&expr[m.end()..])?;
+            let (arg, new_rest) = Self::parse_sum_value::<T>(&expr[m.end()..])?;
             if let Some(m) = fun_close.find(new_rest)
             {
-                let result = match &captures[1]
-                {
-                    "sin"  => arg.sin(),
-                    "cos"  => arg.cos(),
-                    "tan"  => arg.tan(),
-                    "exp"  => arg.exp(),
-                    "ln"   => arg.ln(),
-                    "sqrt" => arg.sqrt(),
-                    // LCOV_EXCL_START
-                    _      => { unreachable!() }
-                    // LCOV_EXCL_STOP
-                };
+                let result = arg.apply_function(&captures[1])
+                    .ok_or_else(|| crate::error::ParseError::NotAffine(String::from(expr)))?;
                 Ok((result, &new_rest[m.end()..]))
             }
             else
@@ -240,17 +314,20 @@ impl Composite
     /// Parse a power-raising expression
     ///
     /// Parse an argument to a gate, in the form of a number possibly raised
-    /// to another number.  On success, the parsed number is returned, together
+    /// to another number.  On success, the parsed value is returned, together
     /// with the remainder of the string to be parsed. On failure, a ParseError
     /// is returned.
-    fn parse_power_expression(expr: &str) -> crate::error::ParseResult<(f64, &str)>
+    fn parse_power_expression<T>(expr: &str) -> crate::error::ParseResult<(T, &str)>
+    where T: ExpressionValue
     {
         let op = regex::Regex::new(r"^\s*\^").unwrap();
-        let (left, rest) = Self::parse_function_expression(expr)?;
+        let (left, rest) = Self::parse_function_expression::<T>(expr)?;
         if let Some(m) = op.find(rest)
         {
             let (right, new_rest) = Self::parse_power_expression(&rest[m.end()..])?;
-            Ok((left.powf(right), new_rest))
+            let result = left.apply_operator("^", right)
+                .ok_or_else(|| crate::error::ParseError::NotAffine(String::from(expr)))?;
+            Ok((result, new_rest))
         }
         else
         {
@@ -261,10 +338,11 @@ impl Composite
     /// Parse a negated expression
     ///
     /// Parse an argument to a gate, in the form of a number that is zero or
-    /// more times negated.  On success, the parsed number is returned, together
+    /// more times negated.  On success, the parsed value is returned, together
     /// with the remainder of the string to be parsed. On failure, a ParseError
     /// is returned.
-    fn parse_negative_expression(expr: &str) -> crate::error::ParseResult<(f64, &str)>
+    fn parse_negative_expression<T>(expr: &str) -> crate::error::ParseResult<(T, &str)>
+    where T: ExpressionValue
     {
         let op = regex::Regex::new(r"^\s*\-").unwrap();
         let mut rest = expr;
@@ -275,10 +353,10 @@ impl Composite
             flip_sign = !flip_sign;
         }
 
-        let (mut result, new_rest) = Self::parse_power_expression(rest)?;
+        let (mut result, new_rest) = Self::parse_power_expression::<T>(rest)?;
         if flip_sign
         {
-            result = -result;
+            result = result.negate();
         }
 
         Ok((result, new_rest))
@@ -287,25 +365,20 @@ impl Composite
     /// Parse a product expression
     ///
     /// Parse an argument to a gate, in the form of a product or quotient of one
-    /// or more expressions. On success, the parsed number is returned, together
+    /// or more expressions. On success, the parsed value is returned, together
     /// with the remainder of the string to be parsed. On failure, a ParseError
     /// is returned.
-    fn parse_product_expression(expr: &str) -> crate::error::ParseResult<(f64, &str)>
+    fn parse_product_expression<T>(expr: &str) -> crate::error::ParseResult<(T, &str)>
+    where T: ExpressionValue
     {
         let op = regex::Regex::new(r"^\s*([*/])").unwrap();
-        let (mut left, mut rest) = Self::parse_negative_expression(expr)?;
+        let (mut left, mut rest) = Self::parse_negative_expression::<T>(expr)?;
         while let Some(captures) = op.captures(rest)
         {
             let m = captures.get(0).unwrap();
             let (right, new_rest) = Self::parse_negative_expression(&rest[m.end()..])?;
-            if &captures[1] == "*"
-            {
-                left *= right;
-            }
-            else
-            {
-                left /= right;
-            }
+            left = left.apply_operator(&captures[1], right)
+                .ok_or_else(|| crate::error::ParseError::NotAffine(String::from(expr)))?;
             rest = new_rest;
         }
 
@@ -319,21 +392,29 @@ impl Composite
     /// with the remainder of the string to be parsed. On failure, a ParseError
     /// is returned.
     pub fn parse_sum_expression(expr: &str) -> crate::error::ParseResult<(f64, &str)>
+    {
+        Self::parse_sum_value(expr)
+    }
+
+    /// Parse a sum expression
+    ///
+    /// Parse a sum or difference of one or more expressions, computing its
+    /// value in value type `T`. On success, the parsed value is returned,
+    /// together with the remainder of the string to be parsed. If an
+    /// operation cannot be represented in `T`, ParseError::NotAffine is
+    /// returned. On other failures, a ParseError is returned as in
+    /// `parse_sum_expression()`.
+    pub(crate) fn parse_sum_value<T>(expr: &str) -> crate::error::ParseResult<(T, &str)>
+    where T: ExpressionValue
     {
         let op = regex::Regex::new(r"^\s*([-+])").unwrap();
-        let (mut left, mut rest) = Self::parse_product_expression(expr)?;
+        let (mut left, mut rest) = Self::parse_product_expression::<T>(expr)?;
         while let Some(captures) = op.captures(rest)
         {
             let m = captures.get(0).unwrap();
             let (right, new_rest) = Self::parse_product_expression(&rest[m.end()..])?;
-            if &captures[1] == "+"
-            {
-                left += right;
-            }
-            else
-            {
-                left -= right;
-            }
+            left = left.apply_operator(&captures[1], right)
+                .ok_or_else(|| crate::error::ParseError::NotAffine(String::from(expr)))?;
             rest = new_rest;
         }
 
@@ -469,6 +550,7 @@ impl Composite
     ///   one or more bit numbers on which the sub gate operates, separated by
     ///   white space."Failed to parse argument \"{}\"", text
     /// * Currently, only real numbers are allowed for parameters.
+    ///
     /// Examples:
     /// ```text
     /// H 1; CX 0 1; H 1
@@ -657,8 +739,8 @@ impl Composite
     ///
     /// Append a `n`-ary subgate `gate`, operating on the `n` qubits in `bits`,
     /// to this composite gate.
-    pub fn add_gate<G: 'static>(&mut self, gate: G, bits: &[usize])
-    where G: CircuitGate
+    pub fn add_gate<G>(&mut self, gate: G, bits: &[usize])
+    where G: 'static + CircuitGate
     {
         self.ops.push(SubGate::new(gate, bits));
     }
@@ -690,17 +772,21 @@ impl crate::gates::Gate for Composite
 
     fn apply_slice(&self, mut state: crate::cmatrix::CVecSliceMut)
     {
+        // The state may contain additional bits, not affected by this gate,
+        // following the bits of the gate.
+        let nr_bits = self.nr_bits + (state.len() >> self.nr_bits).trailing_zeros() as usize;
         for op in self.ops.iter()
         {
-            apply_gate_slice(state.view_mut(), &*op.gate, &op.bits, self.nr_bits);
+            apply_gate_slice_unchecked(state.view_mut(), &*op.gate, &op.bits, nr_bits);
         }
     }
 
     fn apply_mat_slice(&self, mut state: crate::cmatrix::CMatSliceMut)
     {
+        let nr_bits = self.nr_bits + (state.rows() >> self.nr_bits).trailing_zeros() as usize;
         for op in self.ops.iter()
         {
-            apply_gate_mat_slice(state.view_mut(), &*op.gate, &op.bits, self.nr_bits);
+            apply_gate_mat_slice_unchecked(state.view_mut(), &*op.gate, &op.bits, nr_bits);
         }
     }
 }
@@ -711,7 +797,7 @@ impl crate::export::OpenQasm for Composite
         -> crate::error::Result<String>
     {
         let mut res = String::new();
-        if self.ops.len() > 0
+        if !self.ops.is_empty()
         {
             let gate_bits: Vec<usize> = self.ops[0].bits.iter().map(|&b| bits[b]).collect();
             res = self.ops[0].gate.open_qasm(bit_names, &gate_bits)?;
@@ -729,7 +815,7 @@ impl crate::export::OpenQasm for Composite
         bits: &[usize]) -> crate::error::Result<String>
     {
         let mut res = String::new();
-        if self.ops.len() > 0
+        if !self.ops.is_empty()
         {
             let gate_bits: Vec<usize> = self.ops[0].bits.iter().map(|&b| bits[b]).collect();
             res = self.ops[0].gate.conditional_open_qasm(condition, bit_names,
@@ -753,7 +839,7 @@ impl crate::export::CQasm for Composite
         -> crate::error::Result<String>
     {
         let mut res = String::new();
-        if self.ops.len() > 0
+        if !self.ops.is_empty()
         {
             let gate_bits: Vec<usize> = self.ops[0].bits.iter()
                 .map(|&b| bits[b]).collect();
@@ -772,7 +858,7 @@ impl crate::export::CQasm for Composite
         bits: &[usize]) -> crate::error::Result<String>
     {
         let mut res = String::new();
-        if self.ops.len() > 0
+        if !self.ops.is_empty()
         {
             let gate_bits: Vec<usize> = self.ops[0].bits.iter()
                 .map(|&b| bits[b]).collect();
@@ -814,6 +900,7 @@ impl crate::export::Latex for Composite
 }
 
 #[cfg(test)]
+#[allow(clippy::approx_constant, clippy::excessive_precision)]
 mod tests
 {
     use super::Composite;
@@ -898,6 +985,26 @@ mod tests
         ]);
     }
 
+    #[test]
+    fn test_apply_extra_bits()
+    {
+        let mut gate = Composite::new("Inc2", 2);
+        gate.add_gate(CX::new(), &[1, 0]);
+        gate.add_gate(X::new(), &[1]);
+
+        // Apply on a state with an additional, unaffected, trailing bit
+        let mut state = crate::cmatrix::CMatrix::from_shape_fn((8, 2), |(i, j)| {
+            Complex::new((i + j) as f64, (i * j) as f64)
+        });
+        let result = crate::cmatrix::kron_mat(&gate.matrix(), &crate::cmatrix::CMatrix::eye(2))
+            .dot(&state);
+        let mut vec_state = state.column(1).to_owned();
+        gate.apply_mat_slice(state.view_mut());
+        assert_complex_matrix_eq!(&state, &result);
+        gate.apply_slice(vec_state.view_mut());
+        assert_complex_vector_eq!(&vec_state, &result.column(1));
+    }
+
     #[test]
     fn test_from_string()
     {
@@ -922,7 +1029,7 @@ mod tests
                 ]);
             },
             // LCOV_EXCL_START
-            Err(err) => { panic!(err); }
+            Err(err) => { panic!("{}", err); }
             // LCOV_EXCL_STOP
         }
 
@@ -934,7 +1041,7 @@ mod tests
                 assert_complex_matrix_eq!(gate.matrix(), array![[z, -i], [i, z]]);
             },
             // LCOV_EXCL_START
-            Err(err) => { panic!(err); }
+            Err(err) => { panic!("{}", err); }
             // LCOV_EXCL_STOP
         }
     }
@@ -1218,7 +1325,7 @@ mod tests
                 ]);
             },
             // LCOV_EXCL_START
-            Err(err) => { panic!(err); }
+            Err(err) => { panic!("{}", err); }
             // LCOV_EXCL_STOP
         }
 
@@ -1233,7 +1340,7 @@ mod tests
                 ]);
             },
             // LCOV_EXCL_START
-            Err(err) => { panic!(err); }
+            Err(err) => { panic!("{}", err); }
             // LCOV_EXCL_STOP
         }
 
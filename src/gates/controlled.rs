@@ -67,6 +67,25 @@ where G: crate::gates::Gate
         res
     }
 
+    fn diagonal(&self) -> Option<crate::cmatrix::CVector>
+    {
+        self.gate.diagonal().map(|gd| {
+            let gsize = gd.len();
+            let mut res = crate::cmatrix::CVector::ones(2*gsize);
+            res.slice_mut(s![gsize..]).assign(&gd);
+            res
+        })
+    }
+
+    fn permutation(&self) -> Option<crate::permutation::Permutation>
+    {
+        self.gate.permutation().map(|gp| {
+            let gsize = gp.size();
+            let idxs = (0..gsize).chain(gp.indices().iter().map(|&i| gsize + i)).collect();
+            crate::permutation::Permutation::new(idxs)
+        })
+    }
+
     fn apply_slice(&self, mut state: crate::cmatrix::CVecSliceMut)
     {
         let n = state.len() / 2;
@@ -168,6 +187,21 @@ macro_rules! declare_controlled_impl
     };
 }
 
+#[macro_export]
+macro_rules! declare_controlled_default
+{
+    ($name:ident) => {
+        impl Default for $name
+        {
+            fn default() -> Self
+            {
+                Self::new()
+            }
+        }
+    };
+    ($name:ident $(, $arg:ident)+) => {};
+}
+
 #[macro_export]
 macro_rules! declare_controlled_cost
 {
@@ -248,7 +282,7 @@ macro_rules! declare_controlled_qasm
                     {
                         let iend = istart + len + 1;
                         let replacement;
-                        match crate::gates::Composite::parse_sum_expression(&res[istart+1..iend-1])
+                        match $crate::gates::Composite::parse_sum_expression(&res[istart+1..iend-1])
                         {
                             Ok((val, "")) => { replacement = Some(val.to_string()); },
                             _             => { replacement = None; }
@@ -293,6 +327,11 @@ macro_rules! declare_controlled_impl_gate
             fn description(&self) -> &str { self.cgate.description() }
             fn nr_affected_bits(&self) -> usize { self.cgate.nr_affected_bits() }
             fn matrix(&self) -> $crate::cmatrix::CMatrix { self.cgate.matrix() }
+            fn diagonal(&self) -> Option<$crate::cmatrix::CVector> { self.cgate.diagonal() }
+            fn permutation(&self) -> Option<$crate::permutation::Permutation>
+            {
+                self.cgate.permutation()
+            }
             fn apply_slice(&self, state: $crate::cmatrix::CVecSliceMut)
             {
                 self.cgate.apply_slice(state);
@@ -311,6 +350,7 @@ macro_rules! declare_controlled
     ($(#[$attr:meta])* $name:ident, $gate_type:ty) => {
         declare_controlled_type!($(#[$attr])* $name, $gate_type);
         declare_controlled_impl!($name, $gate_type);
+        declare_controlled_default!($name);
         declare_controlled_impl_gate!($name, $gate_type);
         declare_controlled_qasm!(OpenQasm, $name, open_qasm);
         declare_controlled_qasm!(CQasm, $name, c_qasm);
@@ -319,6 +359,7 @@ macro_rules! declare_controlled
     ($(#[$attr:meta])* $name:ident, $gate_type:ty, cost=$cost:expr $(, arg=$arg:ident)* $(, open_qasm=$open_qasm:expr)* $(, c_qasm=$c_qasm:expr)*) => {
         declare_controlled_type!($(#[$attr])* $name, $gate_type $(, $arg)*);
         declare_controlled_impl!($name, $gate_type, cost=$cost $(, $arg)*);
+        declare_controlled_default!($name $(, $arg)*);
         declare_controlled_impl_gate!($name, $gate_type, cost=Self::cost());
         declare_controlled_qasm!(OpenQasm, $name, open_qasm $(, qasm=$open_qasm)* $(, arg=$arg)*);
         declare_controlled_qasm!(CQasm, $name, c_qasm $(, qasm=$c_qasm)* $(, arg=$arg)*);
@@ -490,6 +531,7 @@ declare_controlled!(
     c_qasm="h {2}\ntoffoli {0}, {1}, {2}\nh {2}");
 
 #[cfg(test)]
+#[allow(clippy::approx_constant)]
 mod tests
 {
     use crate::gates::{gate_test, Gate, H, X};
@@ -68,6 +68,14 @@ impl H
 
 }
 
+impl Default for H
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
 impl crate::gates::Gate for H
 {
     fn cost(&self) -> f64
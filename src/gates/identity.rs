@@ -30,6 +30,14 @@ impl I
     }
 }
 
+impl Default for I
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
 impl crate::gates::Gate for I
 {
     fn cost(&self) -> f64
@@ -0,0 +1,171 @@
+// Copyright 2019 Q1t BV
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// Representation of the matrix of a `MatrixGate`
+enum Representation
+{
+    /// A dense matrix
+    Dense(crate::cmatrix::CMatrix),
+    /// A diagonal matrix, stored as its diagonal
+    Diagonal(crate::cmatrix::CVector),
+    /// A permutation matrix
+    Permutation(crate::permutation::Permutation)
+}
+
+/// Gate described by an arbitrary matrix.
+///
+/// Helper gate that can be used to apply an arbitrary, not necessarily
+/// unitary, matrix to (part of) a state vector, like the adjoint of a gate
+/// or the derivative of a gate. Diagonal and permutation matrices can be
+/// stored as such, so that the gate is applied with the specialized kernels
+/// for these gates.
+pub struct MatrixGate
+{
+    /// The number of bits the matrix operates on
+    nr_bits: usize,
+    /// The matrix to apply
+    repr: Representation
+}
+
+impl MatrixGate
+{
+    /// Create a new gate applying matrix `mat`.
+    pub fn new(mat: crate::cmatrix::CMatrix) -> Self
+    {
+        MatrixGate
+        {
+            nr_bits: mat.rows().trailing_zeros() as usize,
+            repr: Representation::Dense(mat)
+        }
+    }
+
+    /// Create a new gate applying the diagonal matrix with diagonal `diagonal`.
+    pub fn from_diagonal(diagonal: crate::cmatrix::CVector) -> Self
+    {
+        MatrixGate
+        {
+            nr_bits: diagonal.len().trailing_zeros() as usize,
+            repr: Representation::Diagonal(diagonal)
+        }
+    }
+
+    /// Create a new gate applying the permutation `perm` to the basis states.
+    pub fn from_permutation(perm: crate::permutation::Permutation) -> Self
+    {
+        MatrixGate
+        {
+            nr_bits: perm.size().trailing_zeros() as usize,
+            repr: Representation::Permutation(perm)
+        }
+    }
+
+    /// Create a new gate applying the Hermitian conjugate of the matrix of
+    /// gate `gate`.
+    pub fn adjoint<G>(gate: &G) -> Self
+    where G: crate::gates::Gate + ?Sized
+    {
+        Self::new(gate.matrix().t().mapv(|c| c.conj()))
+    }
+}
+
+impl crate::gates::Gate for MatrixGate
+{
+    fn description(&self) -> &str
+    {
+        "M"
+    }
+
+    fn nr_affected_bits(&self) -> usize
+    {
+        self.nr_bits
+    }
+
+    fn matrix(&self) -> crate::cmatrix::CMatrix
+    {
+        match self.repr
+        {
+            Representation::Dense(ref mat)         => mat.clone(),
+            Representation::Diagonal(ref diagonal) => {
+                let mut mat = crate::cmatrix::CMatrix::zeros((diagonal.len(), diagonal.len()));
+                mat.diag_mut().assign(diagonal);
+                mat
+            },
+            Representation::Permutation(ref perm)  => perm.matrix()
+        }
+    }
+
+    fn diagonal(&self) -> Option<crate::cmatrix::CVector>
+    {
+        match self.repr
+        {
+            Representation::Diagonal(ref diagonal) => Some(diagonal.clone()),
+            _                                      => None
+        }
+    }
+
+    fn permutation(&self) -> Option<crate::permutation::Permutation>
+    {
+        match self.repr
+        {
+            Representation::Permutation(ref perm) => Some(perm.clone()),
+            _                                     => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::MatrixGate;
+    use crate::gates::{Gate, RX};
+
+    #[test]
+    fn test_matrix_gate()
+    {
+        let gate = RX::new(0.7);
+        let adjoint = MatrixGate::adjoint(&gate);
+        assert_eq!(adjoint.nr_affected_bits(), 1);
+        assert_complex_matrix_eq!(adjoint.matrix().dot(&gate.matrix()),
+            crate::cmatrix::CMatrix::eye(2));
+    }
+
+    #[test]
+    fn test_diagonal_permutation()
+    {
+        let z = crate::cmatrix::COMPLEX_ZERO;
+        let o = crate::cmatrix::COMPLEX_ONE;
+        let i = crate::cmatrix::COMPLEX_I;
+
+        let gate = MatrixGate::from_diagonal(array![o, i, -o, -i]);
+        assert_eq!(gate.nr_affected_bits(), 2);
+        assert_eq!(gate.diagonal(), Some(array![o, i, -o, -i]));
+        assert!(gate.permutation().is_none());
+        assert_complex_matrix_eq!(gate.matrix(), array![
+            [o, z,  z,  z],
+            [z, i,  z,  z],
+            [z, z, -o,  z],
+            [z, z,  z, -i]
+        ]);
+
+        let perm = crate::permutation::Permutation::new(vec![1, 0]);
+        let gate = MatrixGate::from_permutation(perm.clone());
+        assert_eq!(gate.nr_affected_bits(), 1);
+        assert_eq!(gate.permutation(), Some(perm));
+        assert!(gate.diagonal().is_none());
+        assert_complex_matrix_eq!(gate.matrix(), array![[z, o], [o, z]]);
+
+        let gate = MatrixGate::new(array![[z, o], [o, z]]);
+        assert!(gate.diagonal().is_none() && gate.permutation().is_none());
+    }
+}
@@ -0,0 +1,522 @@
+// Copyright 2019 Q1t BV
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::gates::Gate;
+
+/// Gate with multiple control bits.
+///
+/// Where `C<G>` adds a single control bit to a gate type known at compile
+/// time, a `MultiControlled` gate wraps any gate with an arbitrary number
+/// of control bits, determined at run time. Each control bit can optionally
+/// be negated, in which case the gate is applied when that bit is `|0⟩`
+/// instead of `|1⟩`. The bits the gate operates on are the control bits,
+/// followed by the bits of the controlled gate.
+///
+/// When exported to OpenQasm or c-Qasm, the gate is decomposed into `X`
+/// gates for the negated controls, a ladder of Toffoli gates combining the
+/// controls, and a decomposition of the matrix of the controlled gate. By
+/// default, the decomposition uses no additional qubits. Alternatively, the
+/// gate can be created with ancilla bits using `with_ancillas()`, which
+/// results in a much shorter export. The ancilla bits follow the bits of the
+/// controlled gate, they must be in the `|0⟩` state when the gate is applied
+/// and are returned to the `|0⟩` state afterwards.
+pub struct MultiControlled
+{
+    /// The gate being controlled
+    gate: Box<dyn crate::export::CircuitGate>,
+    /// For each control bit, whether it is negated
+    negated: Vec<bool>,
+    /// The number of ancilla bits used in the export
+    nr_ancillas: usize,
+    /// The description of this gate
+    desc: String
+}
+
+impl MultiControlled
+{
+    /// Create a new multiply controlled gate.
+    ///
+    /// Create a new gate applying `gate` when all of the first `nr_controls`
+    /// bits it operates on are `|1⟩`.
+    pub fn new(gate: Box<dyn crate::export::CircuitGate>, nr_controls: usize) -> Self
+    {
+        Self::new_negated(gate, &vec![false; nr_controls])
+    }
+
+    /// Create a new multiply controlled gate with negated controls.
+    ///
+    /// Create a new gate with `negated.len()` control bits, applying `gate`
+    /// when each control bit `i` is `|0⟩` if `negated[i]` is `true`, and
+    /// `|1⟩` otherwise.
+    pub fn new_negated(gate: Box<dyn crate::export::CircuitGate>, negated: &[bool]) -> Self
+    {
+        let desc = "C".repeat(negated.len()) + gate.description();
+        MultiControlled
+        {
+            gate: gate,
+            negated: negated.to_vec(),
+            nr_ancillas: 0,
+            desc: desc
+        }
+    }
+
+    /// Use ancilla bits in the export.
+    ///
+    /// Add ancilla bits to this gate, which are used when exporting it to
+    /// OpenQasm or c-Qasm. For `n` control bits, `n-2` ancillas are added
+    /// (none when there are fewer than three controls), operating on the last
+    /// bits of the gate. The ancilla bits should be initialized to `|0⟩`.
+    pub fn with_ancillas(mut self) -> Self
+    {
+        self.nr_ancillas = self.negated.len().saturating_sub(2);
+        self
+    }
+
+    /// The number of control bits of this gate.
+    pub fn nr_controls(&self) -> usize
+    {
+        self.negated.len()
+    }
+
+    /// The number of ancilla bits of this gate.
+    pub fn nr_ancillas(&self) -> usize
+    {
+        self.nr_ancillas
+    }
+
+    /// The index of the block in the state that is affected by the gate.
+    fn active_block(&self) -> usize
+    {
+        self.negated.iter().fold(0, |idx, &neg| (idx << 1) | if neg { 0 } else { 1 })
+    }
+
+    /// Decompose into primitive gates.
+    ///
+    /// Decompose this gate into a composite gate made up of primitive gates,
+    /// that can be exported.
+    fn decompose(&self) -> crate::gates::Composite
+    {
+        let nr_controls = self.nr_controls();
+        let nr_gate_bits = self.gate.nr_affected_bits();
+        let mut composite = crate::gates::Composite::new(&self.desc,
+            self.nr_affected_bits());
+
+        let controls: Vec<usize> = (0..nr_controls).collect();
+        let gate_bits: Vec<usize> = (nr_controls..nr_controls+nr_gate_bits).collect();
+        let ancillas: Vec<usize> = (nr_controls+nr_gate_bits..self.nr_affected_bits())
+            .collect();
+
+        for (bit, _) in self.negated.iter().enumerate().filter(|(_, &neg)| neg)
+        {
+            composite.add_gate(crate::gates::X::new(), &[bit]);
+        }
+
+        if ancillas.is_empty()
+        {
+            crate::gates::synthesis::add_controlled_unitary(&mut composite,
+                &self.gate.matrix(), &controls, &gate_bits);
+        }
+        else
+        {
+            // Compute the conjunction of all but the last control in the
+            // last ancilla.
+            let mut ladder = vec![[controls[0], controls[1], ancillas[0]]];
+            for i in 1..ancillas.len()
+            {
+                ladder.push([controls[i+1], ancillas[i-1], ancillas[i]]);
+            }
+
+            for bits in ladder.iter()
+            {
+                composite.add_gate(crate::gates::CCX::new(), bits);
+            }
+            crate::gates::synthesis::add_controlled_unitary(&mut composite,
+                &self.gate.matrix(), &[controls[nr_controls-1], ancillas[ancillas.len()-1]],
+                &gate_bits);
+            for bits in ladder.iter().rev()
+            {
+                composite.add_gate(crate::gates::CCX::new(), bits);
+            }
+        }
+
+        for (bit, _) in self.negated.iter().enumerate().filter(|(_, &neg)| neg)
+        {
+            composite.add_gate(crate::gates::X::new(), &[bit]);
+        }
+
+        composite
+    }
+}
+
+impl crate::gates::Gate for MultiControlled
+{
+    fn cost(&self) -> f64
+    {
+        self.decompose().cost()
+    }
+
+    fn description(&self) -> &str
+    {
+        &self.desc
+    }
+
+    fn nr_affected_bits(&self) -> usize
+    {
+        self.nr_controls() + self.gate.nr_affected_bits() + self.nr_ancillas
+    }
+
+    fn matrix(&self) -> crate::cmatrix::CMatrix
+    {
+        let gm = self.gate.matrix();
+        let gsize = gm.rows();
+        let offset = self.active_block() * gsize;
+
+        let mut res = crate::cmatrix::CMatrix::eye(gsize << self.nr_controls());
+        res.slice_mut(s![offset..offset+gsize, offset..offset+gsize]).assign(&gm);
+
+        if self.nr_ancillas > 0
+        {
+            crate::cmatrix::kron_mat(&res, &crate::cmatrix::CMatrix::eye(1 << self.nr_ancillas))
+        }
+        else
+        {
+            res
+        }
+    }
+
+    fn diagonal(&self) -> Option<crate::cmatrix::CVector>
+    {
+        self.gate.diagonal().map(|gd| {
+            let gsize = gd.len();
+            let offset = self.active_block() * gsize;
+
+            let mut res = crate::cmatrix::CVector::ones(gsize << self.nr_controls());
+            res.slice_mut(s![offset..offset+gsize]).assign(&gd);
+
+            let nr_ancilla_states = 1 << self.nr_ancillas;
+            crate::cmatrix::CVector::from_shape_fn(res.len() * nr_ancilla_states,
+                |i| res[i / nr_ancilla_states])
+        })
+    }
+
+    fn permutation(&self) -> Option<crate::permutation::Permutation>
+    {
+        self.gate.permutation().map(|gp| {
+            let gsize = gp.size();
+            let offset = self.active_block() * gsize;
+
+            let mut idxs: Vec<usize> = (0..gsize << self.nr_controls()).collect();
+            for (dst, &src) in idxs[offset..offset+gsize].iter_mut().zip(gp.indices())
+            {
+                *dst = offset + src;
+            }
+
+            let nr_ancilla_states = 1 << self.nr_ancillas;
+            let idxs = (0..idxs.len() * nr_ancilla_states)
+                .map(|i| idxs[i / nr_ancilla_states] * nr_ancilla_states + i % nr_ancilla_states)
+                .collect();
+            crate::permutation::Permutation::new(idxs)
+        })
+    }
+
+    fn apply_slice(&self, mut state: crate::cmatrix::CVecSliceMut)
+    {
+        let n = state.len() >> self.nr_controls();
+        let offset = self.active_block() * n;
+        self.gate.apply_slice(state.slice_mut(s![offset..offset+n]));
+    }
+
+    fn apply_mat_slice(&self, mut state: crate::cmatrix::CMatSliceMut)
+    {
+        let n = state.rows() >> self.nr_controls();
+        let offset = self.active_block() * n;
+        self.gate.apply_mat_slice(state.slice_mut(s![offset..offset+n, ..]));
+    }
+}
+
+impl crate::export::OpenQasm for MultiControlled
+{
+    fn open_qasm(&self, bit_names: &[String], bits: &[usize])
+        -> crate::error::Result<String>
+    {
+        self.check_nr_bits(bits)?;
+        self.decompose().open_qasm(bit_names, bits)
+    }
+
+    fn conditional_open_qasm(&self, condition: &str, bit_names: &[String],
+        bits: &[usize]) -> crate::error::Result<String>
+    {
+        self.check_nr_bits(bits)?;
+        self.decompose().conditional_open_qasm(condition, bit_names, bits)
+    }
+}
+
+impl crate::export::CQasm for MultiControlled
+{
+    fn c_qasm(&self, bit_names: &[String], bits: &[usize])
+        -> crate::error::Result<String>
+    {
+        self.check_nr_bits(bits)?;
+        self.decompose().c_qasm(bit_names, bits)
+    }
+
+    fn conditional_c_qasm(&self, condition: &str, bit_names: &[String],
+        bits: &[usize]) -> crate::error::Result<String>
+    {
+        self.check_nr_bits(bits)?;
+        self.decompose().conditional_c_qasm(condition, bit_names, bits)
+    }
+}
+
+impl crate::export::Latex for MultiControlled
+{
+    fn latex(&self, bits: &[usize], state: &mut crate::export::LatexExportState)
+        -> crate::error::Result<()>
+    {
+        self.check_nr_bits(bits)?;
+
+        let nr_controls = self.nr_controls();
+        let gate_bits = &bits[nr_controls..nr_controls+self.gate.nr_affected_bits()];
+        let min = *gate_bits.iter().min().unwrap();
+        let max = *gate_bits.iter().max().unwrap();
+
+        // We can only draw the controls if they are all outside of the range
+        // of bits of the controlled gate. If not, draw a block gate.
+        if bits[..nr_controls].iter().any(|&b| b > min && b < max)
+        {
+            return state.add_block_gate(bits, self.description());
+        }
+
+        state.start_range_op(bits, None)?;
+
+        for (&control, &neg) in bits.iter().zip(self.negated.iter())
+        {
+            let target = if control < min { min } else { max };
+            let ctrl = if neg { "ctrlo" } else { "ctrl" };
+            state.set_field(control,
+                format!(r"\{}{{{}}}", ctrl, target as isize - control as isize))?;
+        }
+
+        let controlled = state.set_controlled(true);
+        self.gate.latex(gate_bits, state)?;
+        state.set_controlled(controlled);
+
+        state.end_range_op();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::MultiControlled;
+    use crate::gates::{gate_test, CCX, CX, Gate, H, X};
+    use crate::export::{Latex, LatexExportState, OpenQasm, CQasm};
+
+    #[test]
+    fn test_description()
+    {
+        let gate = MultiControlled::new(Box::new(X::new()), 3);
+        assert_eq!(gate.description(), "CCCX");
+        let gate = MultiControlled::new_negated(Box::new(H::new()), &[true, false]);
+        assert_eq!(gate.description(), "CCH");
+    }
+
+    #[test]
+    fn test_nr_affected_bits()
+    {
+        let gate = MultiControlled::new(Box::new(X::new()), 3);
+        assert_eq!(gate.nr_controls(), 3);
+        assert_eq!(gate.nr_ancillas(), 0);
+        assert_eq!(gate.nr_affected_bits(), 4);
+
+        let gate = MultiControlled::new(Box::new(CX::new()), 4).with_ancillas();
+        assert_eq!(gate.nr_controls(), 4);
+        assert_eq!(gate.nr_ancillas(), 2);
+        assert_eq!(gate.nr_affected_bits(), 8);
+
+        let gate = MultiControlled::new(Box::new(X::new()), 2).with_ancillas();
+        assert_eq!(gate.nr_ancillas(), 0);
+        assert_eq!(gate.nr_affected_bits(), 3);
+    }
+
+    #[test]
+    fn test_matrix()
+    {
+        let z = crate::cmatrix::COMPLEX_ZERO;
+        let o = crate::cmatrix::COMPLEX_ONE;
+        let x = crate::cmatrix::COMPLEX_HSQRT2;
+
+        let gate = MultiControlled::new(Box::new(X::new()), 2);
+        assert_complex_matrix_eq!(gate.matrix(), CCX::new().matrix());
+
+        let gate = MultiControlled::new_negated(Box::new(H::new()), &[true]);
+        assert_complex_matrix_eq!(gate.matrix(), array![
+            [x,  x, z, z],
+            [x, -x, z, z],
+            [z,  z, o, z],
+            [z,  z, z, o]
+        ]);
+
+        let gate = MultiControlled::new_negated(Box::new(X::new()), &[false, true]);
+        let mut res = crate::cmatrix::CMatrix::eye(8);
+        res[[4, 4]] = z;
+        res[[4, 5]] = o;
+        res[[5, 4]] = o;
+        res[[5, 5]] = z;
+        assert_complex_matrix_eq!(gate.matrix(), &res);
+
+        let gate = MultiControlled::new(Box::new(X::new()), 3).with_ancillas();
+        assert_complex_matrix_eq!(gate.matrix(),
+            crate::cmatrix::kron_mat(&MultiControlled::new(Box::new(X::new()), 3).matrix(),
+                &crate::cmatrix::CMatrix::eye(2)));
+    }
+
+    #[test]
+    fn test_apply()
+    {
+        let z = crate::cmatrix::COMPLEX_ZERO;
+        let o = crate::cmatrix::COMPLEX_ONE;
+        let x = crate::cmatrix::COMPLEX_HSQRT2;
+        let h = 0.5 * o;
+
+        let gate = MultiControlled::new_negated(Box::new(H::new()), &[true]);
+        let mut state = array![
+            [o, z, h, z],
+            [z, z, h, o],
+            [z, o, h, z],
+            [z, z, h, z]
+        ];
+        let result = array![
+            [x, z, x, x],
+            [x, z, z, -x],
+            [z, o, h, z],
+            [z, z, h, z]
+        ];
+        gate_test(gate, &mut state, &result);
+
+        let gate = MultiControlled::new(Box::new(CX::new()), 2);
+        let mut state = crate::cmatrix::CMatrix::eye(16);
+        let result = gate.matrix();
+        gate_test(gate, &mut state, &result);
+    }
+
+    /// Check that the decomposition of `gate` implements the same
+    /// transformation as the gate itself, for all states with the ancilla
+    /// bits set to zero.
+    fn check_decomposition(gate: &MultiControlled)
+    {
+        let nr_ancillas = gate.nr_ancillas();
+        let decomposed = gate.decompose().matrix();
+        let full = gate.matrix();
+        for col in (0..full.cols()).step_by(1 << nr_ancillas)
+        {
+            for row in 0..full.rows()
+            {
+                assert!((decomposed[[row, col]] - full[[row, col]]).norm() < 1.0e-10,
+                    "Element {}, {} differs", row, col);
+            }
+        }
+    }
+
+    #[test]
+    fn test_decompose()
+    {
+        check_decomposition(&MultiControlled::new(Box::new(X::new()), 1));
+        check_decomposition(&MultiControlled::new(Box::new(X::new()), 3));
+        check_decomposition(&MultiControlled::new(Box::new(X::new()), 4).with_ancillas());
+        check_decomposition(&MultiControlled::new_negated(Box::new(H::new()), &[true, false, true]));
+        check_decomposition(&MultiControlled::new_negated(Box::new(H::new()), &[false, true, true]).with_ancillas());
+        check_decomposition(&MultiControlled::new_negated(Box::new(CX::new()), &[false, true]));
+        check_decomposition(&MultiControlled::new(Box::new(CX::new()), 3).with_ancillas());
+    }
+
+    #[test]
+    fn test_open_qasm()
+    {
+        let bit_names = [String::from("qb0"), String::from("qb1"), String::from("qb2"),
+            String::from("qb3"), String::from("qb4")];
+
+        let gate = MultiControlled::new(Box::new(X::new()), 2);
+        let qasm = gate.open_qasm(&bit_names, &[0, 1, 2]);
+        assert_eq!(qasm, Ok(String::from("ccx qb0, qb1, qb2")));
+
+        let gate = MultiControlled::new_negated(Box::new(X::new()), &[true, false]);
+        let qasm = gate.open_qasm(&bit_names, &[2, 0, 1]);
+        assert_eq!(qasm, Ok(String::from("x qb2; ccx qb2, qb0, qb1; x qb2")));
+
+        let gate = MultiControlled::new(Box::new(X::new()), 3).with_ancillas();
+        let qasm = gate.open_qasm(&bit_names, &[0, 1, 2, 3, 4]);
+        assert_eq!(qasm, Ok(String::from("ccx qb0, qb1, qb4; ccx qb2, qb4, qb3; ccx qb0, qb1, qb4")));
+
+        let qasm = gate.conditional_open_qasm("b == 1", &bit_names, &[0, 1, 2, 3, 4]);
+        assert_eq!(qasm, Ok(String::from("if (b == 1) ccx qb0, qb1, qb4; if (b == 1) ccx qb2, qb4, qb3; if (b == 1) ccx qb0, qb1, qb4")));
+    }
+
+    #[test]
+    fn test_c_qasm()
+    {
+        let bit_names = [String::from("qb0"), String::from("qb1"), String::from("qb2"),
+            String::from("qb3"), String::from("qb4")];
+
+        let gate = MultiControlled::new_negated(Box::new(X::new()), &[true, false]);
+        let qasm = gate.c_qasm(&bit_names, &[2, 0, 1]);
+        assert_eq!(qasm, Ok(String::from("x qb2\ntoffoli qb2, qb0, qb1\nx qb2")));
+
+        let gate = MultiControlled::new(Box::new(X::new()), 3).with_ancillas();
+        let qasm = gate.c_qasm(&bit_names, &[0, 1, 2, 3, 4]);
+        assert_eq!(qasm, Ok(String::from("toffoli qb0, qb1, qb4\ntoffoli qb2, qb4, qb3\ntoffoli qb0, qb1, qb4")));
+    }
+
+    #[test]
+    fn test_latex()
+    {
+        let gate = MultiControlled::new_negated(Box::new(X::new()), &[true, false]);
+        let mut state = LatexExportState::new(3, 0);
+        assert_eq!(gate.latex(&[0, 2, 1], &mut state), Ok(()));
+        assert_eq!(state.code(),
+r#"\Qcircuit @C=1em @R=.7em {
+    \lstick{\ket{0}} & \ctrlo{1} & \qw \\
+    \lstick{\ket{0}} & \targ & \qw \\
+    \lstick{\ket{0}} & \ctrl{-1} & \qw \\
+}
+"#);
+
+        let gate = MultiControlled::new(Box::new(CX::new()), 1);
+        let mut state = LatexExportState::new(3, 0);
+        assert_eq!(gate.latex(&[1, 0, 2], &mut state), Ok(()));
+        assert_eq!(state.code(),
+r#"\Qcircuit @C=1em @R=.7em {
+    \lstick{\ket{0}} & \multigate{2}{CCX} & \qw \\
+    \lstick{\ket{0}} & \ghost{CCX} & \qw \\
+    \lstick{\ket{0}} & \ghost{CCX} & \qw \\
+}
+"#);
+    }
+
+    #[test]
+    fn test_circuit()
+    {
+        let mut circuit = crate::circuit::Circuit::new(4, 4).unwrap();
+        circuit.x(0).unwrap();
+        circuit.x(2).unwrap();
+        circuit.add_gate(MultiControlled::new_negated(Box::new(X::new()), &[false, true, false]),
+            &[0, 1, 2, 3]).unwrap();
+        circuit.measure_all(&[0, 1, 2, 3]).unwrap();
+        circuit.execute(10).unwrap();
+        let hist = circuit.histogram_vec().unwrap();
+        assert_eq!(hist[0b1101], 10);
+    }
+}
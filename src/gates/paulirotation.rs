@@ -0,0 +1,408 @@
+// Copyright 2019 Q1t BV
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::gates::Gate;
+
+/// Add the rotation `exp(-iθP/2)` over angle `theta` for Pauli string `string`
+/// to composite gate `composite`, as a sequence of basis changes, a `CX`
+/// ladder computing the parity, and a `R`<sub>`Z`</sub> rotation.
+pub(crate) fn add_rotation_gadget(composite: &mut crate::gates::Composite,
+    string: &crate::pauli::PauliString, theta: f64)
+{
+    let support = string.support();
+    let last = match support.last()
+        {
+            Some(&last) => last,
+            None => { return; }
+        };
+
+    let add_basis_change = |composite: &mut crate::gates::Composite, sign: f64| {
+        for &bit in support.iter()
+        {
+            match string.ops()[bit]
+            {
+                crate::pauli::Pauli::X => {
+                    composite.add_gate(crate::gates::H::new(), &[bit]);
+                },
+                crate::pauli::Pauli::Y => {
+                    composite.add_gate(crate::gates::RX::new(sign * ::std::f64::consts::FRAC_PI_2), &[bit]);
+                },
+                _ => { }
+            }
+        }
+    };
+
+    add_basis_change(composite, 1.0);
+    for pair in support.windows(2)
+    {
+        composite.add_gate(crate::gates::CX::new(), pair);
+    }
+    composite.add_gate(crate::gates::RZ::new(theta), &[last]);
+    for pair in support.windows(2).rev()
+    {
+        composite.add_gate(crate::gates::CX::new(), pair);
+    }
+    add_basis_change(composite, -1.0);
+}
+
+/// Pauli rotation
+///
+/// The `PauliRotation` gate performs the rotation `exp(-iθP/2)` for a Pauli
+/// string `P` over `n` qubits, where the first operator in the string acts
+/// on the first qubit of the gate. The `R`<sub>`XX`</sub>,
+/// `R`<sub>`YY`</sub>, and `R`<sub>`ZZ`</sub> Ising coupling gates are
+/// special cases of this gate, as are the single qubit rotations.
+///
+/// The gate is applied to a state directly: when the string consists of
+/// only `I` and `Z` operators, the rotation multiplies each basis state with
+/// a phase depending on the parity of the bits in the support of the string,
+/// otherwise it mixes pairs of basis states that differ in the bits where
+/// the string has an `X` or `Y` operator. When exported, the gate is expanded
+/// into basis changes, a ladder of `CX` gates, and a `R`<sub>`Z`</sub> gate.
+pub struct PauliRotation
+{
+    /// The Pauli string generating the rotation
+    string: crate::pauli::PauliString,
+    /// The rotation angle
+    theta: f64,
+    /// Description of this gate
+    desc: String
+}
+
+impl PauliRotation
+{
+    /// Create a new Pauli rotation.
+    ///
+    /// Create a new gate performing the rotation `exp(-iθP/2)` over angle
+    /// `theta`, for Pauli string `P` given by `string`.
+    pub fn new(string: crate::pauli::PauliString, theta: f64) -> Self
+    {
+        let desc = format!("R{}({:.4})", string, theta);
+        PauliRotation { string: string, theta: theta, desc: desc }
+    }
+
+    /// Create a new `R`<sub>`XX`</sub> gate `exp(-iθX⊗X/2)`.
+    pub fn rxx(theta: f64) -> Self
+    {
+        Self::new(crate::pauli::PauliString::new(vec![crate::pauli::Pauli::X; 2]), theta)
+    }
+
+    /// Create a new `R`<sub>`YY`</sub> gate `exp(-iθY⊗Y/2)`.
+    pub fn ryy(theta: f64) -> Self
+    {
+        Self::new(crate::pauli::PauliString::new(vec![crate::pauli::Pauli::Y; 2]), theta)
+    }
+
+    /// Create a new `R`<sub>`ZZ`</sub> gate `exp(-iθZ⊗Z/2)`.
+    pub fn rzz(theta: f64) -> Self
+    {
+        Self::new(crate::pauli::PauliString::new(vec![crate::pauli::Pauli::Z; 2]), theta)
+    }
+
+    /// The Pauli string generating this rotation
+    pub fn string(&self) -> &crate::pauli::PauliString
+    {
+        &self.string
+    }
+
+    /// The rotation angle
+    pub fn theta(&self) -> f64
+    {
+        self.theta
+    }
+
+    /// Decompose the rotation.
+    ///
+    /// Return a composite gate performing the same rotation, using `H` and
+    /// `R`<sub>`X`</sub>`(±π/2)` gates to rotate the qubits in the support
+    /// of the string to the `Z` basis, a ladder of `CX` gates computing the
+    /// parity of these qubits into the last of them, and a
+    /// `R`<sub>`Z`</sub>`(θ)` gate on that qubit. For the identity string,
+    /// the rotation is only a global phase, and the composite gate is empty.
+    pub fn decompose(&self) -> crate::gates::Composite
+    {
+        let mut composite = crate::gates::Composite::new(&self.desc, self.string.nr_bits());
+        add_rotation_gadget(&mut composite, &self.string, self.theta);
+        composite
+    }
+
+    /// Apply the rotation to the rows of `state`.
+    fn transform_mat(&self, mut state: crate::cmatrix::CMatSliceMut)
+    {
+        let n = self.string.nr_bits();
+        let block_size = state.rows() >> n;
+
+        // Qubit 0 of the gate is the most significant bit of the index. The
+        // string maps |x⟩ to i^(nr_y) (-1)^|x & sign_mask| |x ^ flip_mask⟩.
+        let mut flip_mask = 0;
+        let mut sign_mask = 0;
+        let mut nr_y = 0;
+        for (bit, &op) in self.string.ops().iter().enumerate()
+        {
+            let mask = 1 << (n - bit - 1);
+            match op
+            {
+                crate::pauli::Pauli::I => { },
+                crate::pauli::Pauli::X => { flip_mask |= mask; },
+                crate::pauli::Pauli::Y => { flip_mask |= mask; sign_mask |= mask; nr_y += 1; },
+                crate::pauli::Pauli::Z => { sign_mask |= mask; }
+            }
+        }
+        let parity = |x: usize| (x & sign_mask).count_ones() % 2;
+
+        if flip_mask == 0
+        {
+            let phase = num_complex::Complex::from_polar(&1.0, &(-0.5 * self.theta));
+            for x in 0..(1 << n)
+            {
+                let factor = if parity(x) == 0 { phase } else { phase.conj() };
+                let mut rows = state.slice_mut(s![x*block_size..(x+1)*block_size, ..]);
+                rows *= factor;
+            }
+        }
+        else
+        {
+            let (sin, cos) = (0.5 * self.theta).sin_cos();
+            // -i sin(θ/2) i^(nr_y)
+            let off_diag = num_complex::Complex::new(0.0, -sin)
+                * crate::cmatrix::COMPLEX_I.powi(nr_y);
+            let high_bit = 1 << (usize::BITS - 1 - flip_mask.leading_zeros());
+            for x in (0..(1 << n)).filter(|&x| x & high_bit == 0)
+            {
+                let y = x ^ flip_mask;
+                let fx = if parity(y) == 0 { off_diag } else { -off_diag };
+                let fy = if parity(x) == 0 { off_diag } else { -off_diag };
+
+                let rows_x = state.slice(s![x*block_size..(x+1)*block_size, ..]).to_owned();
+                let rows_y = state.slice(s![y*block_size..(y+1)*block_size, ..]).to_owned();
+                state.slice_mut(s![x*block_size..(x+1)*block_size, ..])
+                    .assign(&(&rows_x * cos + &rows_y * fx));
+                state.slice_mut(s![y*block_size..(y+1)*block_size, ..])
+                    .assign(&(&rows_y * cos + &rows_x * fy));
+            }
+        }
+    }
+}
+
+impl crate::gates::Gate for PauliRotation
+{
+    fn cost(&self) -> f64
+    {
+        self.decompose().cost()
+    }
+
+    fn description(&self) -> &str
+    {
+        &self.desc
+    }
+
+    fn nr_affected_bits(&self) -> usize
+    {
+        self.string.nr_bits()
+    }
+
+    fn matrix(&self) -> crate::cmatrix::CMatrix
+    {
+        let mut res = crate::cmatrix::CMatrix::eye(1 << self.string.nr_bits());
+        self.transform_mat(res.view_mut());
+        res
+    }
+
+    fn diagonal(&self) -> Option<crate::cmatrix::CVector>
+    {
+        let is_diagonal = self.string.ops().iter().all(|&op| {
+                op == crate::pauli::Pauli::I || op == crate::pauli::Pauli::Z
+            });
+        if is_diagonal
+        {
+            Some(self.matrix().diag().to_owned())
+        }
+        else
+        {
+            None
+        }
+    }
+
+    fn apply_slice(&self, state: crate::cmatrix::CVecSliceMut)
+    {
+        self.transform_mat(state.insert_axis(ndarray::Axis(1)));
+    }
+
+    fn apply_mat_slice(&self, state: crate::cmatrix::CMatSliceMut)
+    {
+        self.transform_mat(state);
+    }
+}
+
+impl crate::export::OpenQasm for PauliRotation
+{
+    fn open_qasm(&self, bit_names: &[String], bits: &[usize])
+        -> crate::error::Result<String>
+    {
+        self.check_nr_bits(bits)?;
+        self.decompose().open_qasm(bit_names, bits)
+    }
+
+    fn conditional_open_qasm(&self, condition: &str, bit_names: &[String],
+        bits: &[usize]) -> crate::error::Result<String>
+    {
+        self.check_nr_bits(bits)?;
+        self.decompose().conditional_open_qasm(condition, bit_names, bits)
+    }
+}
+
+impl crate::export::CQasm for PauliRotation
+{
+    fn c_qasm(&self, bit_names: &[String], bits: &[usize])
+        -> crate::error::Result<String>
+    {
+        self.check_nr_bits(bits)?;
+        self.decompose().c_qasm(bit_names, bits)
+    }
+
+    fn conditional_c_qasm(&self, condition: &str, bit_names: &[String],
+        bits: &[usize]) -> crate::error::Result<String>
+    {
+        self.check_nr_bits(bits)?;
+        self.decompose().conditional_c_qasm(condition, bit_names, bits)
+    }
+}
+
+impl crate::export::Latex for PauliRotation
+{
+    fn latex(&self, bits: &[usize], state: &mut crate::export::LatexExportState)
+        -> crate::error::Result<()>
+    {
+        self.check_nr_bits(bits)?;
+        let contents = format!("R_{{{}}}({:.4})", self.string, self.theta);
+        state.add_block_gate(bits, &contents)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::PauliRotation;
+    use crate::gates::{gate_test, Gate};
+    use crate::export::{Latex, LatexExportState, OpenQasm, CQasm};
+    use crate::pauli::{PauliString, PauliSum};
+
+    /// The matrix of exp(-iθP/2), computed from the matrix of P
+    fn rotation_matrix(desc: &str, theta: f64) -> crate::cmatrix::CMatrix
+    {
+        let p = PauliString::parse(desc).unwrap().matrix();
+        let n = p.rows();
+        crate::cmatrix::CMatrix::eye(n) * num_complex::Complex::new((0.5 * theta).cos(), 0.0)
+            + p * num_complex::Complex::new(0.0, -(0.5 * theta).sin())
+    }
+
+    #[test]
+    fn test_description()
+    {
+        let gate = PauliRotation::new(PauliString::parse("XIZ").unwrap(), 0.5);
+        assert_eq!(gate.description(), "RXIZ(0.5000)");
+        assert_eq!(gate.nr_affected_bits(), 3);
+        assert_eq!(gate.theta(), 0.5);
+        assert_eq!(gate.string().to_string(), "XIZ");
+        assert_eq!(PauliRotation::rzz(::std::f64::consts::PI).description(), "RZZ(3.1416)");
+    }
+
+    #[test]
+    fn test_cost()
+    {
+        let cost = 2.0 * crate::gates::CX::cost() + crate::gates::U1::cost();
+        assert_eq!(PauliRotation::rzz(0.3).cost(), cost);
+        assert_eq!(PauliRotation::rxx(0.3).cost(), cost + 4.0 * crate::gates::H::cost());
+    }
+
+    #[test]
+    fn test_matrix()
+    {
+        let theta = 0.83;
+        for &desc in ["X", "Y", "Z", "I", "XX", "YY", "ZZ", "XYZ", "IYI", "ZIZ", "YXIZ"].iter()
+        {
+            let gate = PauliRotation::new(PauliString::parse(desc).unwrap(), theta);
+            let expected = rotation_matrix(desc, theta);
+            assert_complex_matrix_eq!(&gate.matrix(), &expected);
+            if desc != "I"
+            {
+                assert_complex_matrix_eq!(&gate.decompose().matrix(), &expected);
+            }
+        }
+        let gate = PauliRotation::new(PauliString::parse("I").unwrap(), theta);
+        assert_complex_matrix_eq!(&gate.decompose().matrix(), &crate::cmatrix::CMatrix::eye(2));
+
+        assert_complex_matrix_eq!(&PauliRotation::rxx(theta).matrix(), &rotation_matrix("XX", theta));
+        assert_complex_matrix_eq!(&PauliRotation::ryy(theta).matrix(), &rotation_matrix("YY", theta));
+        assert_complex_matrix_eq!(&PauliRotation::rzz(theta).matrix(), &rotation_matrix("ZZ", theta));
+        assert_complex_matrix_eq!(&PauliRotation::new(PauliString::parse("Y").unwrap(), theta).matrix(),
+            &crate::gates::RY::new(theta).matrix());
+
+        // Agrees with the exact time evolution
+        let h = PauliSum::from_terms(3, &[(0.5 * theta, "YZX")]).unwrap();
+        let evolution = crate::algorithms::trotter::exact_evolution(&h, 1.0);
+        let diff = PauliRotation::new(PauliString::parse("YZX").unwrap(), theta).matrix() - evolution;
+        assert!(diff.iter().all(|x| x.norm() < 1.0e-12));
+    }
+
+    #[test]
+    fn test_apply()
+    {
+        let n = 3;
+        let mut state = crate::cmatrix::CMatrix::from_shape_fn((1 << (n+1), 3), |(i, j)| {
+            num_complex::Complex::new(((i + 2*j) as f64).sin(), ((3*i + j) as f64).cos())
+        });
+
+        for &desc in ["ZZI", "XYZ", "IIY", "YIX"].iter()
+        {
+            let gate = PauliRotation::new(PauliString::parse(desc).unwrap(), 1.27);
+            let result = crate::cmatrix::kron_mat(&gate.matrix(), &crate::cmatrix::CMatrix::eye(2))
+                .dot(&state);
+            gate_test(gate, &mut state, &result);
+            state = result;
+        }
+    }
+
+    #[test]
+    fn test_open_qasm()
+    {
+        let bit_names = [String::from("qb0"), String::from("qb1"), String::from("qb2")];
+        let qasm = PauliRotation::rzz(0.5).open_qasm(&bit_names, &[0, 2]);
+        assert_eq!(qasm, Ok(String::from("cx qb0, qb2; rz(0.5) qb2; cx qb0, qb2")));
+        let qasm = PauliRotation::new(PauliString::parse("XIY").unwrap(), 0.5)
+            .open_qasm(&bit_names, &[0, 1, 2]);
+        assert_eq!(qasm, Ok(String::from("h qb0; rx(1.5707963267948966) qb2; cx qb0, qb2; rz(0.5) qb2; cx qb0, qb2; h qb0; rx(-1.5707963267948966) qb2")));
+    }
+
+    #[test]
+    fn test_c_qasm()
+    {
+        let bit_names = [String::from("qb0"), String::from("qb1")];
+        let qasm = PauliRotation::rxx(0.5).c_qasm(&bit_names, &[1, 0]);
+        assert_eq!(qasm, Ok(String::from("h qb1\nh qb0\ncnot qb1, qb0\nrz qb0, 0.5\ncnot qb1, qb0\nh qb1\nh qb0")));
+    }
+
+    #[test]
+    fn test_latex()
+    {
+        let mut state = LatexExportState::new(2, 0);
+        assert_eq!(PauliRotation::rzz(::std::f64::consts::FRAC_PI_2).latex(&[0, 1], &mut state), Ok(()));
+        assert_eq!(state.code(),
+r#"\Qcircuit @C=1em @R=.7em {
+    \lstick{\ket{0}} & \multigate{1}{R_{ZZ}(1.5708)} & \qw \\
+    \lstick{\ket{0}} & \ghost{R_{ZZ}(1.5708)} & \qw \\
+}
+"#);
+    }
+}
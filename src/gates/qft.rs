@@ -0,0 +1,526 @@
+// Copyright 2019 Q1t BV
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::gates::Gate;
+
+/// Quantum Fourier transform
+///
+/// The `QFT` gate performs a quantum Fourier transform on `n` qubits, mapping
+/// basis state |`j`⟩ to
+/// `2`<sup>`-n/2`</sup>`∑`<sub>`k`</sub>` exp(2πijk/2`<sup>`n`</sup>`)`|`k`⟩,
+/// where the first qubit is the most significant bit. Without the final
+/// swaps, the order of the qubits in the result is reversed. In the
+/// approximate quantum Fourier transform, the controlled rotations over
+/// angles `2π/2`<sup>`k`</sup> for large `k` are dropped.
+///
+/// The gate is applied to a state directly, using an FFT-like algorithm,
+/// instead of gate by gate. When exported, the gate is expanded into
+/// Hadamard gates, controlled phase gates, and swaps.
+pub struct QFT
+{
+    /// The number of bits on which this transform operates
+    nr_bits: usize,
+    /// Whether to reverse the order of the qubits at the end
+    with_swaps: bool,
+    /// Whether this is the inverse transform
+    inverse: bool,
+    /// The maximum order `k` of the `2π/2`<sup>`k`</sup> rotations to keep
+    max_order: Option<usize>,
+    /// Description of this gate
+    desc: String
+}
+
+impl QFT
+{
+    /// Create a new quantum Fourier transform on `nr_bits` qubits, reversing
+    /// the order of the qubits at the end if `with_swaps` is `true`.
+    pub fn new(nr_bits: usize, with_swaps: bool) -> Self
+    {
+        QFT
+        {
+            nr_bits: nr_bits,
+            with_swaps: with_swaps,
+            inverse: false,
+            max_order: None,
+            desc: String::from("QFT")
+        }
+    }
+
+    /// Create a new inverse quantum Fourier transform on `nr_bits` qubits,
+    /// reversing the order of the qubits at the start if `with_swaps` is `true`.
+    pub fn new_inverse(nr_bits: usize, with_swaps: bool) -> Self
+    {
+        QFT
+        {
+            nr_bits: nr_bits,
+            with_swaps: with_swaps,
+            inverse: true,
+            max_order: None,
+            desc: String::from("QFT†")
+        }
+    }
+
+    /// Approximate the transform.
+    ///
+    /// Turn this gate into an approximate quantum Fourier transform, in which
+    /// only controlled rotations over angles `2π/2`<sup>`k`</sup> for
+    /// `k ≤ max_order` are performed. For `max_order ≥ n`, the transform is
+    /// exact.
+    pub fn with_approximation(mut self, max_order: usize) -> Self
+    {
+        self.max_order = Some(max_order);
+        self
+    }
+
+    /// Return the inverse of this transform.
+    pub fn inverse(&self) -> Self
+    {
+        let mut res = if self.inverse
+            {
+                Self::new(self.nr_bits, self.with_swaps)
+            }
+            else
+            {
+                Self::new_inverse(self.nr_bits, self.with_swaps)
+            };
+        res.max_order = self.max_order;
+        res
+    }
+
+    /// Return whether the rotation over `2π/2`<sup>`order`</sup> is performed.
+    fn keep_rotation(&self, order: usize) -> bool
+    {
+        self.max_order.is_none_or(|max_order| order <= max_order)
+    }
+
+    /// Apply a Hadamard gate on bit `bit` to the rows of `state`, where each
+    /// basis state of the gate corresponds to `block_size` consecutive rows.
+    fn apply_hadamard(&self, state: &mut crate::cmatrix::CMatSliceMut, bit: usize,
+        block_size: usize)
+    {
+        let stride = block_size << (self.nr_bits - bit - 1);
+        let h = crate::cmatrix::COMPLEX_HSQRT2;
+        for i in (0..state.rows()).filter(|i| (i / stride) % 2 == 0)
+        {
+            for j in 0..state.cols()
+            {
+                let (a, b) = (state[[i, j]], state[[i+stride, j]]);
+                state[[i, j]] = h * (a + b);
+                state[[i+stride, j]] = h * (a - b);
+            }
+        }
+    }
+
+    /// Apply all controlled rotations with target bit `bit` to the rows of
+    /// `state`, where each basis state of the gate corresponds to
+    /// `block_size` consecutive rows. For the inverse transform, the
+    /// rotations are inverted.
+    fn apply_rotations(&self, state: &mut crate::cmatrix::CMatSliceMut, bit: usize,
+        block_size: usize)
+    {
+        let n = self.nr_bits;
+        let sign = if self.inverse { -1.0 } else { 1.0 };
+
+        let phases: Vec<crate::cmatrix::CNumber> = (0..(1 << n)).map(|idx: usize| {
+                let mut angle = 0.0;
+                if (idx >> (n - bit - 1)) & 1 == 1
+                {
+                    for control in bit+1..n
+                    {
+                        let order = control - bit + 1;
+                        if (idx >> (n - control - 1)) & 1 == 1 && self.keep_rotation(order)
+                        {
+                            angle += 2.0 * ::std::f64::consts::PI / (1u64 << order) as f64;
+                        }
+                    }
+                }
+                num_complex::Complex::from_polar(&1.0, &(sign * angle))
+            })
+            .collect();
+
+        for (i, mut row) in state.genrows_mut().into_iter().enumerate()
+        {
+            let phase = phases[i / block_size];
+            if phase != crate::cmatrix::COMPLEX_ONE
+            {
+                row *= phase;
+            }
+        }
+    }
+
+    /// Reverse the order of the bits in the rows of `state`, where each
+    /// basis state of the gate corresponds to `block_size` consecutive rows.
+    fn apply_swaps(&self, state: &mut crate::cmatrix::CMatSliceMut, block_size: usize)
+    {
+        let n = self.nr_bits;
+        for idx in 0..(1 << n)
+        {
+            let rev = (0..n).fold(0, |r, b| (r << 1) | ((idx >> b) & 1));
+            if rev > idx
+            {
+                for i in 0..block_size
+                {
+                    for j in 0..state.cols()
+                    {
+                        state.swap([idx*block_size + i, j], [rev*block_size + i, j]);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Apply this transform to the rows of `state`.
+    fn transform_mat(&self, mut state: crate::cmatrix::CMatSliceMut)
+    {
+        let n = self.nr_bits;
+        assert!(state.rows() % (1 << n) == 0,
+            "The number of rows in the state is {}, which is not valid for a {}-bit gate.",
+            state.rows(), n);
+
+        let block_size = state.rows() >> n;
+        if self.inverse
+        {
+            if self.with_swaps
+            {
+                self.apply_swaps(&mut state, block_size);
+            }
+            for bit in (0..n).rev()
+            {
+                self.apply_rotations(&mut state, bit, block_size);
+                self.apply_hadamard(&mut state, bit, block_size);
+            }
+        }
+        else
+        {
+            for bit in 0..n
+            {
+                self.apply_hadamard(&mut state, bit, block_size);
+                self.apply_rotations(&mut state, bit, block_size);
+            }
+            if self.with_swaps
+            {
+                self.apply_swaps(&mut state, block_size);
+            }
+        }
+    }
+
+    /// Decompose this transform.
+    ///
+    /// Expand this transform into a composite gate consisting of Hadamard
+    /// gates, controlled phase gates, and swaps, that can be exported.
+    pub fn decompose(&self) -> crate::gates::Composite
+    {
+        let n = self.nr_bits;
+        let angle = |order: usize| 2.0 * ::std::f64::consts::PI / (1u64 << order) as f64;
+
+        let mut composite = crate::gates::Composite::new(&self.desc, n);
+        if self.inverse
+        {
+            if self.with_swaps
+            {
+                for bit in 0..n/2
+                {
+                    composite.add_gate(crate::gates::Swap::new(), &[bit, n-bit-1]);
+                }
+            }
+            for bit in (0..n).rev()
+            {
+                for control in (bit+1..n).rev()
+                {
+                    let order = control - bit + 1;
+                    if self.keep_rotation(order)
+                    {
+                        composite.add_gate(crate::gates::CU1::new(-angle(order)), &[control, bit]);
+                    }
+                }
+                composite.add_gate(crate::gates::H::new(), &[bit]);
+            }
+        }
+        else
+        {
+            for bit in 0..n
+            {
+                composite.add_gate(crate::gates::H::new(), &[bit]);
+                for control in bit+1..n
+                {
+                    let order = control - bit + 1;
+                    if self.keep_rotation(order)
+                    {
+                        composite.add_gate(crate::gates::CU1::new(angle(order)), &[control, bit]);
+                    }
+                }
+            }
+            if self.with_swaps
+            {
+                for bit in 0..n/2
+                {
+                    composite.add_gate(crate::gates::Swap::new(), &[bit, n-bit-1]);
+                }
+            }
+        }
+        composite
+    }
+}
+
+impl crate::gates::Gate for QFT
+{
+    fn cost(&self) -> f64
+    {
+        self.decompose().cost()
+    }
+
+    fn description(&self) -> &str
+    {
+        &self.desc
+    }
+
+    fn nr_affected_bits(&self) -> usize
+    {
+        self.nr_bits
+    }
+
+    fn matrix(&self) -> crate::cmatrix::CMatrix
+    {
+        let mut res = crate::cmatrix::CMatrix::eye(1 << self.nr_bits);
+        self.transform_mat(res.view_mut());
+        res
+    }
+
+    fn apply_slice(&self, state: crate::cmatrix::CVecSliceMut)
+    {
+        self.transform_mat(state.insert_axis(ndarray::Axis(1)));
+    }
+
+    fn apply_mat_slice(&self, state: crate::cmatrix::CMatSliceMut)
+    {
+        self.transform_mat(state);
+    }
+}
+
+impl crate::export::OpenQasm for QFT
+{
+    fn open_qasm(&self, bit_names: &[String], bits: &[usize])
+        -> crate::error::Result<String>
+    {
+        self.check_nr_bits(bits)?;
+        self.decompose().open_qasm(bit_names, bits)
+    }
+
+    fn conditional_open_qasm(&self, condition: &str, bit_names: &[String],
+        bits: &[usize]) -> crate::error::Result<String>
+    {
+        self.check_nr_bits(bits)?;
+        self.decompose().conditional_open_qasm(condition, bit_names, bits)
+    }
+}
+
+impl crate::export::CQasm for QFT
+{
+    fn c_qasm(&self, bit_names: &[String], bits: &[usize])
+        -> crate::error::Result<String>
+    {
+        self.check_nr_bits(bits)?;
+        self.decompose().c_qasm(bit_names, bits)
+    }
+
+    fn conditional_c_qasm(&self, condition: &str, bit_names: &[String],
+        bits: &[usize]) -> crate::error::Result<String>
+    {
+        self.check_nr_bits(bits)?;
+        self.decompose().conditional_c_qasm(condition, bit_names, bits)
+    }
+}
+
+impl crate::export::Latex for QFT
+{
+    fn latex(&self, bits: &[usize], state: &mut crate::export::LatexExportState)
+        -> crate::error::Result<()>
+    {
+        self.check_nr_bits(bits)?;
+        if self.inverse
+        {
+            state.add_block_gate(bits, r"\mathrm{QFT}^\dagger")
+        }
+        else
+        {
+            state.add_block_gate(bits, r"\mathrm{QFT}")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::QFT;
+    use crate::gates::{gate_test, Gate};
+    use crate::export::{Latex, LatexExportState, OpenQasm, CQasm};
+
+    /// The matrix of the discrete Fourier transform on `n` qubits
+    fn dft_matrix(n: usize) -> crate::cmatrix::CMatrix
+    {
+        let size = 1 << n;
+        let norm = 1.0 / (size as f64).sqrt();
+        crate::cmatrix::CMatrix::from_shape_fn((size, size), |(j, k)| {
+            let angle = 2.0 * ::std::f64::consts::PI * ((j * k) % size) as f64 / size as f64;
+            num_complex::Complex::from_polar(&norm, &angle)
+        })
+    }
+
+    /// Reverse the order of the bits in the row indices of `mat`
+    fn reverse_rows(mat: &crate::cmatrix::CMatrix, n: usize) -> crate::cmatrix::CMatrix
+    {
+        let mut res = mat.clone();
+        for idx in 0..(1 << n)
+        {
+            let rev = (0..n).fold(0, |r, b| (r << 1) | ((idx >> b) & 1));
+            res.row_mut(rev).assign(&mat.row(idx));
+        }
+        res
+    }
+
+    fn adjoint(mat: &crate::cmatrix::CMatrix) -> crate::cmatrix::CMatrix
+    {
+        mat.t().mapv(|c| c.conj())
+    }
+
+    #[test]
+    fn test_description()
+    {
+        assert_eq!(QFT::new(3, true).description(), "QFT");
+        assert_eq!(QFT::new_inverse(3, true).description(), "QFT†");
+        assert_eq!(QFT::new(3, true).inverse().description(), "QFT†");
+        assert_eq!(QFT::new(4, false).nr_affected_bits(), 4);
+    }
+
+    #[test]
+    fn test_cost()
+    {
+        // 3 H, 3 CU1, 1 Swap
+        let cost = 3.0 * crate::gates::H::cost() + 3.0 * crate::gates::CU1::new(0.0).cost()
+            + 3.0 * crate::gates::CX::cost();
+        assert_eq!(QFT::new(3, true).cost(), cost);
+        assert_eq!(QFT::new(3, false).cost(), cost - 3.0 * crate::gates::CX::cost());
+    }
+
+    #[test]
+    fn test_matrix()
+    {
+        for n in 1..5
+        {
+            let dft = dft_matrix(n);
+            assert_complex_matrix_eq!(QFT::new(n, true).matrix(), &dft);
+            assert_complex_matrix_eq!(QFT::new(n, false).matrix(), reverse_rows(&dft, n));
+            assert_complex_matrix_eq!(QFT::new_inverse(n, true).matrix(), adjoint(&dft));
+            assert_complex_matrix_eq!(QFT::new_inverse(n, false).matrix(),
+                adjoint(&reverse_rows(&dft, n)));
+            assert_complex_matrix_eq!(QFT::new(n, true).decompose().matrix(), &dft);
+            assert_complex_matrix_eq!(QFT::new_inverse(n, false).decompose().matrix(),
+                adjoint(&reverse_rows(&dft, n)));
+        }
+    }
+
+    #[test]
+    fn test_approximation()
+    {
+        let n = 5;
+        let exact = QFT::new(n, true).with_approximation(n).matrix();
+        assert_complex_matrix_eq!(&exact, &dft_matrix(n));
+
+        for order in 1..n
+        {
+            let gate = QFT::new(n, true).with_approximation(order);
+            let mat = gate.matrix();
+            assert_complex_matrix_eq!(&mat, &gate.decompose().matrix());
+            assert_complex_matrix_eq!(&QFT::new_inverse(n, true).with_approximation(order).matrix(),
+                &adjoint(&mat));
+            assert_complex_matrix_eq!(&gate.inverse().matrix(), &adjoint(&mat));
+            // Dropping rotations makes the transform inexact, but the error
+            // decreases with the order.
+            let error = (&mat - &exact).iter().map(|c| c.norm()).fold(0.0, f64::max);
+            assert!(error > 1.0e-3 && error < 2.0 * ::std::f64::consts::PI / (1 << order) as f64);
+        }
+    }
+
+    #[test]
+    fn test_apply()
+    {
+        let n = 3;
+        let mut state = crate::cmatrix::CMatrix::from_shape_fn((1 << (n+1), 3), |(i, j)| {
+            num_complex::Complex::new(((i + 2*j) as f64).sin(), ((3*i + j) as f64).cos())
+        });
+
+        let gates = [QFT::new(n, true), QFT::new(n, false), QFT::new_inverse(n, true),
+            QFT::new(n, true).with_approximation(2)];
+        for gate in IntoIterator::into_iter(gates)
+        {
+            let result = crate::cmatrix::kron_mat(&gate.matrix(), &crate::cmatrix::CMatrix::eye(2))
+                .dot(&state);
+            let mut mat_state = state.clone();
+            gate.apply_mat_slice(mat_state.view_mut());
+            assert_complex_matrix_eq!(&mat_state, &result);
+            gate_test(gate, &mut state, &result);
+            state = result;
+        }
+    }
+
+    #[test]
+    fn test_open_qasm()
+    {
+        let bit_names = [String::from("qb0"), String::from("qb1"), String::from("qb2")];
+        let qasm = QFT::new(3, true).open_qasm(&bit_names, &[0, 1, 2]);
+        assert_eq!(qasm, Ok(String::from("h qb0; cu1(1.5707963267948966) qb1, qb0; cu1(0.7853981633974483) qb2, qb0; h qb1; cu1(1.5707963267948966) qb2, qb1; h qb2; cx qb0, qb2; cx qb2, qb0; cx qb0, qb2")));
+        let qasm = QFT::new_inverse(2, false).open_qasm(&bit_names, &[2, 0]);
+        assert_eq!(qasm, Ok(String::from("h qb0; cu1(-1.5707963267948966) qb0, qb2; h qb2")));
+        let qasm = QFT::new(3, false).with_approximation(2).open_qasm(&bit_names, &[0, 1, 2]);
+        assert_eq!(qasm, Ok(String::from("h qb0; cu1(1.5707963267948966) qb1, qb0; h qb1; cu1(1.5707963267948966) qb2, qb1; h qb2")));
+    }
+
+    #[test]
+    fn test_c_qasm()
+    {
+        let bit_names = [String::from("qb0"), String::from("qb1")];
+        let qasm = QFT::new(2, true).c_qasm(&bit_names, &[0, 1]);
+        assert_eq!(qasm, Ok(String::from("h qb0\ncr qb1, qb0, 1.5707963267948966\nh qb1\nswap qb0, qb1")));
+    }
+
+    #[test]
+    fn test_latex()
+    {
+        let mut state = LatexExportState::new(3, 0);
+        assert_eq!(QFT::new(3, true).latex(&[0, 1, 2], &mut state), Ok(()));
+        assert_eq!(QFT::new_inverse(2, true).latex(&[1, 2], &mut state), Ok(()));
+        assert_eq!(state.code(),
+r#"\Qcircuit @C=1em @R=.7em {
+    \lstick{\ket{0}} & \multigate{2}{\mathrm{QFT}} & \qw & \qw \\
+    \lstick{\ket{0}} & \ghost{\mathrm{QFT}} & \multigate{1}{\mathrm{QFT}^\dagger} & \qw \\
+    \lstick{\ket{0}} & \ghost{\mathrm{QFT}} & \ghost{\mathrm{QFT}^\dagger} & \qw \\
+}
+"#);
+    }
+
+    #[test]
+    fn test_circuit()
+    {
+        let nr_shots = 1024;
+        let mut circuit = crate::circuit::Circuit::new(3, 3).unwrap();
+        // Prepare the Fourier transform of |5⟩, and transform back
+        circuit.x(0).unwrap();
+        circuit.x(2).unwrap();
+        circuit.add_gate(QFT::new(3, true), &[0, 1, 2]).unwrap();
+        circuit.add_gate(QFT::new_inverse(3, true), &[0, 1, 2]).unwrap();
+        circuit.measure_all(&[2, 1, 0]).unwrap();
+        circuit.execute(nr_shots).unwrap();
+        assert_eq!(circuit.histogram_vec().unwrap()[5], nr_shots);
+    }
+}
@@ -65,6 +65,12 @@ impl crate::gates::Gate for RZ
         array![[p.conj(), z], [z, p]]
     }
 
+    fn diagonal(&self) -> Option<crate::cmatrix::CVector>
+    {
+        let p = num_complex::Complex::from_polar(&1.0, &(0.5 * self.lambda));
+        Some(array![p.conj(), p])
+    }
+
     fn apply_slice(&self, mut state: crate::cmatrix::CVecSliceMut)
     {
         assert!(state.len() % 2 == 0, "Number of rows is not even.");
@@ -38,6 +38,14 @@ impl S
     }
 }
 
+impl Default for S
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
 impl crate::gates::Gate for S
 {
     fn cost(&self) -> f64
@@ -63,6 +71,12 @@ impl crate::gates::Gate for S
         array![[o, z], [z, i]]
     }
 
+    fn diagonal(&self) -> Option<crate::cmatrix::CVector>
+    {
+        let o = crate::cmatrix::COMPLEX_ONE;
+        Some(array![o, crate::cmatrix::COMPLEX_I])
+    }
+
     fn apply_slice(&self, mut state: crate::cmatrix::CVecSliceMut)
     {
         assert!(state.len() % 2 == 0, "Number of rows is not even.");
@@ -134,6 +148,14 @@ impl Sdg
     }
 }
 
+impl Default for Sdg
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
 impl crate::gates::Gate for Sdg
 {
     fn cost(&self) -> f64
@@ -159,6 +181,12 @@ impl crate::gates::Gate for Sdg
         array![[o, z], [z, -i]]
     }
 
+    fn diagonal(&self) -> Option<crate::cmatrix::CVector>
+    {
+        let o = crate::cmatrix::COMPLEX_ONE;
+        Some(array![o, -crate::cmatrix::COMPLEX_I])
+    }
+
     fn apply_slice(&self, mut state: crate::cmatrix::CVecSliceMut)
     {
         assert!(state.len() % 2 == 0, "Number of rows is not even.");
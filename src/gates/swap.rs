@@ -56,6 +56,14 @@ impl Swap
     }
 }
 
+impl Default for Swap
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
 impl crate::gates::Gate for Swap
 {
     fn cost(&self) -> f64
@@ -85,6 +93,11 @@ impl crate::gates::Gate for Swap
         ]
     }
 
+    fn permutation(&self) -> Option<crate::permutation::Permutation>
+    {
+        Some(crate::permutation::Permutation::new(vec![0, 2, 1, 3]))
+    }
+
     fn apply_slice(&self, state: crate::cmatrix::CVecSliceMut)
     {
         Self::transform(state);
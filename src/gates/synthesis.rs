@@ -0,0 +1,383 @@
+// Copyright 2019 Q1t BV
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+/// Matrix elements with a norm smaller than this are considered zero when
+/// decomposing a matrix into primitive gates.
+const ZERO_TOLERANCE: f64 = 1.0e-12;
+
+/// Hermitian conjugate of matrix `mat`.
+pub fn conjugate_transpose(mat: &crate::cmatrix::CMatrix) -> crate::cmatrix::CMatrix
+{
+    mat.t().mapv(|c| c.conj())
+}
+
+/// Check if matrix `mat` is (approximately) the Pauli `X` matrix.
+fn is_pauli_x(mat: &crate::cmatrix::CMatrix) -> bool
+{
+    let o = crate::cmatrix::COMPLEX_ONE;
+    (mat[[0, 0]].norm() < ZERO_TOLERANCE) && (mat[[1, 1]].norm() < ZERO_TOLERANCE)
+        && (mat[[0, 1]] - o).norm() < ZERO_TOLERANCE
+        && (mat[[1, 0]] - o).norm() < ZERO_TOLERANCE
+}
+
+/// Decompose a matrix into two-level unitaries.
+///
+/// Decompose the unitary matrix `mat` into a sequence of two-level unitary
+/// matrices, i.e. unitary transformations that only affect two basis states.
+/// Every element in the result is a tuple `(i0, i1, u)`, where `i0` and `i1`
+/// are the indices of the basis states being transformed, and `u` is the
+/// `2×2` unitary matrix acting on these states. The sequence is returned in
+/// the order of application, so that the first element is the first
+/// transformation to apply.
+fn two_level_decomposition(mat: &crate::cmatrix::CMatrix)
+    -> Vec<(usize, usize, crate::cmatrix::CMatrix)>
+{
+    let z = crate::cmatrix::COMPLEX_ZERO;
+    let o = crate::cmatrix::COMPLEX_ONE;
+
+    let n = mat.rows();
+    let mut work = mat.clone();
+    let mut ops = vec![];
+    for col in 0..n
+    {
+        // Eliminate the elements below the diagonal in this column, by
+        // mixing their rows with the diagonal row
+        for row in col+1..n
+        {
+            let b = work[[row, col]];
+            if b.norm() < ZERO_TOLERANCE
+            {
+                continue;
+            }
+
+            let a = work[[col, col]];
+            let norm = (a.norm_sqr() + b.norm_sqr()).sqrt();
+            let g = array![[a.conj(), b.conj()], [-b, a]] / norm;
+            let r0 = work.row(col).to_owned();
+            let r1 = work.row(row).to_owned();
+            work.row_mut(col).assign(&(&r0 * g[[0, 0]] + &r1 * g[[0, 1]]));
+            work.row_mut(row).assign(&(&r0 * g[[1, 0]] + &r1 * g[[1, 1]]));
+            ops.push((col, row, g));
+        }
+
+        // Since the matrix is unitary, the diagonal element is now a pure
+        // phase. Remove it, unless it is already 1.
+        let phase = work[[col, col]];
+        if (phase - o).norm() >= ZERO_TOLERANCE
+        {
+            if col+1 < n
+            {
+                ops.push((col, col+1, array![[phase.conj(), z], [z, o]]));
+            }
+            else
+            {
+                ops.push((col-1, col, array![[o, z], [z, phase.conj()]]));
+            }
+            work.row_mut(col).mapv_inplace(|c| c * phase.conj());
+        }
+    }
+
+    // We now have G_k⋯G_1 U = I, so U = G_1^†⋯G_k^†, i.e. apply G_k^† first.
+    // Merge subsequent transformations acting on the same pair of states.
+    let mut result: Vec<(usize, usize, crate::cmatrix::CMatrix)> = vec![];
+    for (i0, i1, g) in ops.into_iter().rev()
+    {
+        let g_dg = conjugate_transpose(&g);
+        match result.last_mut()
+        {
+            Some((j0, j1, ref mut prev)) if *j0 == i0 && *j1 == i1 => {
+                *prev = g_dg.dot(prev);
+            },
+            _ => {
+                result.push((i0, i1, g_dg));
+            }
+        }
+    }
+    result
+}
+
+/// Decompose a single qubit unitary.
+///
+/// Find angles `α`, `β`, `γ`, and `δ`, such that unitary `mat` can be
+/// written as `exp(iα) R`<sub>`Z`</sub>`(β) R`<sub>`Y`</sub>`(γ) R`<sub>`Z`</sub>`(δ)`.
+fn zyz_decomposition(mat: &crate::cmatrix::CMatrix) -> (f64, f64, f64, f64)
+{
+    let det = mat[[0, 0]] * mat[[1, 1]] - mat[[0, 1]] * mat[[1, 0]];
+    let alpha = 0.5 * det.arg();
+    let su = mat * num_complex::Complex::from_polar(&1.0, &-alpha);
+
+    let (c, s) = (su[[0, 0]].norm(), su[[1, 0]].norm());
+    let gamma = 2.0 * s.atan2(c);
+    let sum = if c < ZERO_TOLERANCE { 0.0 } else { 2.0 * su[[1, 1]].arg() };
+    let diff = if s < ZERO_TOLERANCE { 0.0 } else { 2.0 * su[[1, 0]].arg() };
+
+    (alpha, 0.5 * (sum + diff), gamma, 0.5 * (sum - diff))
+}
+
+/// Square root of a single qubit unitary.
+///
+/// Compute a unitary matrix `V` such that `V`<sup>`2`</sup> is equal to the
+/// `2×2` unitary matrix `mat`.
+fn sqrt_unitary(mat: &crate::cmatrix::CMatrix) -> crate::cmatrix::CMatrix
+{
+    let trace = mat[[0, 0]] + mat[[1, 1]];
+    let det = mat[[0, 0]] * mat[[1, 1]] - mat[[0, 1]] * mat[[1, 0]];
+    let disc = (trace * trace - 4.0 * det).sqrt();
+    let lambda0 = 0.5 * (trace + disc);
+    let lambda1 = 0.5 * (trace - disc);
+
+    let eye = crate::cmatrix::CMatrix::eye(2);
+    if (lambda0 - lambda1).norm() < ZERO_TOLERANCE
+    {
+        // A normal matrix with degenerate eigenvalues is a multiple of the
+        // identity
+        return eye * lambda0.sqrt();
+    }
+
+    // Since the matrix is normal, the eigenvectors are orthogonal, and we
+    // can write U = λ₀P + λ₁(I-P), with P the projector on the first
+    // eigenvector.
+    let v0 = array![mat[[0, 1]], lambda0 - mat[[0, 0]]];
+    let v1 = array![lambda0 - mat[[1, 1]], mat[[1, 0]]];
+    let v = if v0.iter().map(|c| c.norm_sqr()).sum::<f64>() > v1.iter().map(|c| c.norm_sqr()).sum::<f64>()
+        {
+            v0
+        }
+        else
+        {
+            v1
+        };
+    let norm_sq: f64 = v.iter().map(|c| c.norm_sqr()).sum();
+    let proj = array![
+        [v[0] * v[0].conj(), v[0] * v[1].conj()],
+        [v[1] * v[0].conj(), v[1] * v[1].conj()]
+    ] / norm_sq;
+
+    let (s0, s1) = (lambda0.sqrt(), lambda1.sqrt());
+    eye * s1 + proj * (s0 - s1)
+}
+
+/// Add a multiply controlled single qubit gate.
+///
+/// Add the single qubit transformation described by the `2×2` unitary matrix
+/// `mat`, acting on qubit `target` and controlled by the qubits in `controls`,
+/// to composite gate `composite`. The gate is only applied when all control
+/// bits are `1`. When there are no control bits, the gate is added up to a
+/// global phase.
+fn add_controlled_gate(composite: &mut crate::gates::Composite,
+    mat: &crate::cmatrix::CMatrix, controls: &[usize], target: usize)
+{
+    match controls
+    {
+        [] => {
+            let (_, beta, gamma, delta) = zyz_decomposition(mat);
+            add_rotation(composite, crate::gates::RZ::new(delta), delta, target);
+            add_rotation(composite, crate::gates::RY::new(gamma), gamma, target);
+            add_rotation(composite, crate::gates::RZ::new(beta), beta, target);
+        },
+        &[control] if is_pauli_x(mat) => {
+            composite.add_gate(crate::gates::CX::new(), &[control, target]);
+        },
+        &[control] => {
+            // Write U = exp(iα) A X B X C, with ABC = I.
+            let (alpha, beta, gamma, delta) = zyz_decomposition(mat);
+            let c_angle = 0.5 * (delta - beta);
+            let b_angle = -0.5 * (delta + beta);
+            add_rotation(composite, crate::gates::RZ::new(c_angle), c_angle, target);
+            composite.add_gate(crate::gates::CX::new(), &[control, target]);
+            add_rotation(composite, crate::gates::RZ::new(b_angle), b_angle, target);
+            add_rotation(composite, crate::gates::RY::new(-0.5*gamma), gamma, target);
+            composite.add_gate(crate::gates::CX::new(), &[control, target]);
+            add_rotation(composite, crate::gates::RY::new(0.5*gamma), gamma, target);
+            add_rotation(composite, crate::gates::RZ::new(beta), beta, target);
+            add_rotation(composite, crate::gates::U1::new(alpha), alpha, control);
+        },
+        &[control0, control1] if is_pauli_x(mat) => {
+            composite.add_gate(crate::gates::CCX::new(), &[control0, control1, target]);
+        },
+        _ => {
+            // Lemma 7.9 from Barenco et al., Phys. Rev. A 52, 3457 (1995)
+            let (&last, rest) = controls.split_last().unwrap();
+            let sqrt = sqrt_unitary(mat);
+            let sqrt_dg = conjugate_transpose(&sqrt);
+            let x = pauli_x();
+            add_controlled_gate(composite, &sqrt, &[last], target);
+            add_controlled_gate(composite, &x, rest, last);
+            add_controlled_gate(composite, &sqrt_dg, &[last], target);
+            add_controlled_gate(composite, &x, rest, last);
+            add_controlled_gate(composite, &sqrt, rest, target);
+        }
+    }
+}
+
+/// Add a rotation gate `gate` over angle `angle` on qubit `bit` to composite
+/// gate `composite`, unless the angle is zero.
+fn add_rotation<G>(composite: &mut crate::gates::Composite, gate: G, angle: f64,
+    bit: usize)
+where G: 'static + crate::export::CircuitGate
+{
+    if angle.abs() >= ZERO_TOLERANCE
+    {
+        composite.add_gate(gate, &[bit]);
+    }
+}
+
+/// The Pauli `X` matrix.
+fn pauli_x() -> crate::cmatrix::CMatrix
+{
+    array![
+        [crate::cmatrix::COMPLEX_ZERO, crate::cmatrix::COMPLEX_ONE],
+        [crate::cmatrix::COMPLEX_ONE, crate::cmatrix::COMPLEX_ZERO]
+    ]
+}
+
+/// Add a multiply controlled gate with arbitrary control values.
+///
+/// Add the single qubit transformation `mat` operating on qubit
+/// `bits[target]` to composite gate `composite`, controlled by the qubits in
+/// `controls` and by all other qubits in `bits`. The gate is applied only
+/// when all bits in `controls` are `1`, and the values of the other bits in
+/// `bits` match the corresponding bits in basis state index `value`.
+fn add_polarized_gate(composite: &mut crate::gates::Composite,
+    mat: &crate::cmatrix::CMatrix, controls: &[usize], bits: &[usize],
+    value: usize, target: usize)
+{
+    let nr_bits = bits.len();
+    let negated: Vec<usize> = (0..nr_bits)
+        .filter(|&i| i != target && (value >> (nr_bits - 1 - i)) & 1 == 0)
+        .map(|i| bits[i])
+        .collect();
+    let mut all_controls = controls.to_vec();
+    all_controls.extend((0..nr_bits).filter(|&i| i != target).map(|i| bits[i]));
+
+    for &bit in negated.iter()
+    {
+        composite.add_gate(crate::gates::X::new(), &[bit]);
+    }
+    add_controlled_gate(composite, mat, &all_controls, bits[target]);
+    for &bit in negated.iter()
+    {
+        composite.add_gate(crate::gates::X::new(), &[bit]);
+    }
+}
+
+/// Add a two-level unitary.
+///
+/// Add the transformation `mat`, acting on basis states `i0` and `i1` of the
+/// qubits in `bits`, and controlled by the qubits in `controls`, to
+/// composite gate `composite`. Using a Gray code connecting `i0` and `i1`,
+/// the first basis state is moved next to the second, so that the two differ
+/// in a single bit only. The transformation is then applied as a multiply
+/// controlled single qubit gate, after which the basis states are moved back.
+fn add_two_level_gate(composite: &mut crate::gates::Composite,
+    mat: &crate::cmatrix::CMatrix, controls: &[usize], bits: &[usize],
+    i0: usize, i1: usize)
+{
+    let nr_bits = bits.len();
+    let x = pauli_x();
+
+    let diff = i0 ^ i1;
+    let flip_bits: Vec<usize> = (0..nr_bits).filter(|&b| (diff >> (nr_bits - 1 - b)) & 1 == 1)
+        .collect();
+    let (&last_bit, path_bits) = flip_bits.split_last().unwrap();
+
+    let mut swaps = vec![];
+    let mut idx = i0;
+    for &bit in path_bits
+    {
+        swaps.push((idx, bit));
+        idx ^= 1 << (nr_bits - 1 - bit);
+    }
+
+    // The swaps only exchange the amplitudes of basis states, and cancel
+    // each other when the controls are not set, so they need not be
+    // controlled by the qubits in `controls`.
+    for &(value, bit) in swaps.iter()
+    {
+        add_polarized_gate(composite, &x, &[], bits, value, bit);
+    }
+    if (idx >> (nr_bits - 1 - last_bit)) & 1 == 0
+    {
+        add_polarized_gate(composite, mat, controls, bits, idx, last_bit);
+    }
+    else
+    {
+        let flipped = array![[mat[[1, 1]], mat[[1, 0]]], [mat[[0, 1]], mat[[0, 0]]]];
+        add_polarized_gate(composite, &flipped, controls, bits, idx, last_bit);
+    }
+    for &(value, bit) in swaps.iter().rev()
+    {
+        add_polarized_gate(composite, &x, &[], bits, value, bit);
+    }
+}
+
+/// Add a multiply controlled unitary.
+///
+/// Add the transformation described by the unitary matrix `mat`, operating
+/// on the qubits in `bits` and controlled by the qubits in `controls`, to
+/// composite gate `composite`. The transformation is only applied when all
+/// control bits are `1`. The decomposition is exact, except when there are
+/// no control bits and `mat` operates on a single qubit, in which case the
+/// global phase is lost.
+pub fn add_controlled_unitary(composite: &mut crate::gates::Composite,
+    mat: &crate::cmatrix::CMatrix, controls: &[usize], bits: &[usize])
+{
+    if bits.len() == 1
+    {
+        add_controlled_gate(composite, mat, controls, bits[0]);
+    }
+    else
+    {
+        for (i0, i1, op) in two_level_decomposition(mat)
+        {
+            add_two_level_gate(composite, &op, controls, bits, i0, i1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{sqrt_unitary, zyz_decomposition};
+    use crate::gates::{Composite, Gate, H, RX, T, U3};
+
+    #[test]
+    fn test_zyz_decomposition()
+    {
+        for gate in [H::new().matrix(), T::new().matrix(), RX::new(0.7).matrix(),
+            U3::new(1.3, -0.4, 2.2).matrix(), U3::new(::std::f64::consts::PI, 0.3, 0.9).matrix()].iter()
+        {
+            let (alpha, beta, gamma, delta) = zyz_decomposition(gate);
+            let mut composite = Composite::new("ZYZ", 1);
+            composite.add_gate(crate::gates::RZ::new(delta), &[0]);
+            composite.add_gate(crate::gates::RY::new(gamma), &[0]);
+            composite.add_gate(crate::gates::RZ::new(beta), &[0]);
+            let res = composite.matrix() * num_complex::Complex::from_polar(&1.0, &alpha);
+            assert!((res - gate).iter().all(|c| c.norm() < 1.0e-12));
+        }
+    }
+
+    #[test]
+    fn test_sqrt_unitary()
+    {
+        for gate in [H::new().matrix(), T::new().matrix(), RX::new(0.7).matrix(),
+            crate::gates::X::new().matrix(), crate::cmatrix::CMatrix::eye(2),
+            U3::new(1.3, -0.4, 2.2).matrix()].iter()
+        {
+            let sqrt = sqrt_unitary(gate);
+            assert!((sqrt.dot(&sqrt) - gate).iter().all(|c| c.norm() < 1.0e-12));
+        }
+    }
+}
@@ -31,6 +31,14 @@ impl T
     }
 }
 
+impl Default for T
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
 impl crate::gates::Gate for T
 {
     fn cost(&self) -> f64
@@ -57,6 +65,14 @@ impl crate::gates::Gate for T
         array![[o, z], [z, x+x*i]]
     }
 
+    fn diagonal(&self) -> Option<crate::cmatrix::CVector>
+    {
+        let o = crate::cmatrix::COMPLEX_ONE;
+        let x = crate::cmatrix::COMPLEX_HSQRT2;
+        let i = crate::cmatrix::COMPLEX_I;
+        Some(array![o, x+x*i])
+    }
+
     fn apply_slice(&self, mut state: crate::cmatrix::CVecSliceMut)
     {
         assert!(state.len() % 2 == 0, "Number of rows is not even.");
@@ -121,6 +137,14 @@ impl Tdg
     }
 }
 
+impl Default for Tdg
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
 impl crate::gates::Gate for Tdg
 {
     fn cost(&self) -> f64
@@ -147,6 +171,14 @@ impl crate::gates::Gate for Tdg
         array![[o, z], [z, x-x*i]]
     }
 
+    fn diagonal(&self) -> Option<crate::cmatrix::CVector>
+    {
+        let o = crate::cmatrix::COMPLEX_ONE;
+        let x = crate::cmatrix::COMPLEX_HSQRT2;
+        let i = crate::cmatrix::COMPLEX_I;
+        Some(array![o, x-x*i])
+    }
+
     fn apply_slice(&self, mut state: crate::cmatrix::CVecSliceMut)
     {
         assert!(state.len() % 2 == 0, "Number of rows is not even.");
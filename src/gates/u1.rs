@@ -25,7 +25,6 @@ use crate::gates::Gate;
 /// │ 0    exp(iλ) │
 /// └              ┘
 /// ```
-
 pub struct U1
 {
     lambda: f64,
@@ -71,6 +70,12 @@ impl crate::gates::Gate for U1
         array![[o, z], [z, p]]
     }
 
+    fn diagonal(&self) -> Option<crate::cmatrix::CVector>
+    {
+        let o = crate::cmatrix::COMPLEX_ONE;
+        Some(array![o, num_complex::Complex::from_polar(&1.0, &self.lambda)])
+    }
+
     fn apply_slice(&self, mut state: crate::cmatrix::CVecSliceMut)
     {
         assert!(state.len() % 2 == 0, "Number of rows is not even.");
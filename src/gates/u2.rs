@@ -104,6 +104,7 @@ impl crate::export::Latex for U2
 }
 
 #[cfg(test)]
+#[allow(clippy::approx_constant)]
 mod tests
 {
     use crate::gates::{gate_test, Gate, U2};
@@ -114,6 +114,7 @@ impl crate::export::Latex for U3
 }
 
 #[cfg(test)]
+#[allow(clippy::approx_constant, clippy::excessive_precision)]
 mod tests
 {
     use crate::gates::{gate_test, Gate, U3};
@@ -0,0 +1,423 @@
+// Copyright 2019 Q1t BV
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::gates::Gate;
+
+/// Default tolerance on the deviation of `U`<sup>`†`</sup>`U` from the
+/// identity, when checking if a matrix is unitary.
+const UNITARY_TOLERANCE: f64 = 1.0e-10;
+
+/// Gate with a user supplied matrix.
+///
+/// The `Unitary` gate applies an arbitrary unitary transformation, given by
+/// a `2`<sup>`n`</sup>`×2`<sup>`n`</sup> matrix, to `n` qubits. When the gate
+/// is exported to OpenQasm or c-Qasm, the matrix is first decomposed into
+/// two-level unitaries, each of which is implemented as a sequence of
+/// multiply controlled `X` gates and a multiply controlled single qubit gate.
+/// These are in turn expressed in terms of `CX`, `CCX`, `R`<sub>`Y`</sub>,
+/// `R`<sub>`Z`</sub> and `U`<sub>`1`</sub> gates. Note that the number of
+/// gates in the export grows exponentially with the number of qubits, so the
+/// export is really only useful for gates operating on a few qubits. The
+/// export is exact, up to a global phase.
+pub struct Unitary
+{
+    /// The name of the gate
+    name: String,
+    /// The number of qubits this gate operates on
+    nr_bits: usize,
+    /// The matrix describing the transformation
+    mat: crate::cmatrix::CMatrix,
+    /// The cost of the decomposition of this gate into primitive gates
+    cost: f64
+}
+
+impl Unitary
+{
+    /// Create a new unitary gate.
+    ///
+    /// Create a new gate with name `name`, applying the transformation
+    /// described by `matrix`. The matrix must be square, its size must be a
+    /// power of two, and it must be unitary, otherwise an error is returned.
+    pub fn new(name: &str, matrix: crate::cmatrix::CMatrix) -> crate::error::Result<Self>
+    {
+        Self::new_with_tolerance(name, matrix, UNITARY_TOLERANCE)
+    }
+
+    /// Create a new unitary gate.
+    ///
+    /// Create a new gate with name `name`, applying the transformation
+    /// described by `matrix`. The matrix must be square, and its size must be a
+    /// power of two. The matrix is considered unitary when no element of
+    /// `U`<sup>`†`</sup>`U - I` has a norm larger than `tolerance`. If any of
+    /// these conditions does not hold, an error is returned.
+    pub fn new_with_tolerance(name: &str, matrix: crate::cmatrix::CMatrix,
+        tolerance: f64) -> crate::error::Result<Self>
+    {
+        let (rows, cols) = (matrix.rows(), matrix.cols());
+        if rows != cols || rows < 2 || !rows.is_power_of_two()
+        {
+            return Err(crate::error::Error::InvalidMatrixSize(rows, cols));
+        }
+
+        let mut prod = crate::gates::synthesis::conjugate_transpose(&matrix).dot(&matrix);
+        prod -= &crate::cmatrix::CMatrix::eye(rows);
+        if prod.iter().any(|c| c.norm().is_nan() || c.norm() > tolerance)
+        {
+            return Err(crate::error::Error::NotUnitary(String::from(name)));
+        }
+
+        let mut gate = Unitary
+        {
+            name: String::from(name),
+            nr_bits: rows.trailing_zeros() as usize,
+            mat: matrix,
+            cost: 0.0
+        };
+        // The decomposition is expensive, so compute its cost only once
+        gate.cost = gate.decompose().cost();
+        Ok(gate)
+    }
+
+    /// Decompose into primitive gates.
+    ///
+    /// Decompose the transformation of this gate into a composite gate made
+    /// up of primitive gates, that can be exported. The composite gate
+    /// implements the same transformation up to a global phase.
+    fn decompose(&self) -> crate::gates::Composite
+    {
+        let mut composite = crate::gates::Composite::new(&self.name, self.nr_bits);
+        let bits: Vec<usize> = (0..self.nr_bits).collect();
+        crate::gates::synthesis::add_controlled_unitary(&mut composite, &self.mat,
+            &[], &bits);
+        composite
+    }
+}
+
+impl crate::gates::Gate for Unitary
+{
+    fn cost(&self) -> f64
+    {
+        self.cost
+    }
+
+    fn description(&self) -> &str
+    {
+        &self.name
+    }
+
+    fn nr_affected_bits(&self) -> usize
+    {
+        self.nr_bits
+    }
+
+    fn matrix(&self) -> crate::cmatrix::CMatrix
+    {
+        self.mat.clone()
+    }
+
+    fn apply_slice(&self, mut state: crate::cmatrix::CVecSliceMut)
+    {
+        assert!(state.len() % (1 << self.nr_bits) == 0,
+            "The number of rows in the state is {}, which is not valid for a {}-bit gate.",
+            state.len(), self.nr_bits);
+
+        let n = state.len() >> self.nr_bits;
+        let mut res = crate::cmatrix::CVector::zeros(state.len());
+        for i in 0..(1 << self.nr_bits)
+        {
+            let mut slice = res.slice_mut(s![i*n..(i+1)*n]);
+            for j in 0..(1 << self.nr_bits)
+            {
+                let m = self.mat[[i, j]];
+                if m != crate::cmatrix::COMPLEX_ZERO
+                {
+                    slice.scaled_add(m, &state.slice(s![j*n..(j+1)*n]));
+                }
+            }
+        }
+
+        state.assign(&res);
+    }
+
+    fn apply_mat_slice(&self, mut state: crate::cmatrix::CMatSliceMut)
+    {
+        assert!(state.rows() % (1 << self.nr_bits) == 0,
+            "The number of rows in the state is {}, which is not valid for a {}-bit gate.",
+            state.rows(), self.nr_bits);
+
+        let n = state.rows() >> self.nr_bits;
+        let mut res = crate::cmatrix::CMatrix::zeros((state.rows(), state.cols()));
+        for i in 0..(1 << self.nr_bits)
+        {
+            let mut slice = res.slice_mut(s![i*n..(i+1)*n, ..]);
+            for j in 0..(1 << self.nr_bits)
+            {
+                let m = self.mat[[i, j]];
+                if m != crate::cmatrix::COMPLEX_ZERO
+                {
+                    slice.scaled_add(m, &state.slice(s![j*n..(j+1)*n, ..]));
+                }
+            }
+        }
+
+        state.assign(&res);
+    }
+}
+
+impl crate::export::OpenQasm for Unitary
+{
+    fn open_qasm(&self, bit_names: &[String], bits: &[usize])
+        -> crate::error::Result<String>
+    {
+        self.check_nr_bits(bits)?;
+        self.decompose().open_qasm(bit_names, bits)
+    }
+
+    fn conditional_open_qasm(&self, condition: &str, bit_names: &[String],
+        bits: &[usize]) -> crate::error::Result<String>
+    {
+        self.check_nr_bits(bits)?;
+        self.decompose().conditional_open_qasm(condition, bit_names, bits)
+    }
+}
+
+impl crate::export::CQasm for Unitary
+{
+    fn c_qasm(&self, bit_names: &[String], bits: &[usize])
+        -> crate::error::Result<String>
+    {
+        self.check_nr_bits(bits)?;
+        self.decompose().c_qasm(bit_names, bits)
+    }
+
+    fn conditional_c_qasm(&self, condition: &str, bit_names: &[String],
+        bits: &[usize]) -> crate::error::Result<String>
+    {
+        self.check_nr_bits(bits)?;
+        self.decompose().conditional_c_qasm(condition, bit_names, bits)
+    }
+}
+
+impl crate::export::Latex for Unitary {}
+
+#[cfg(test)]
+mod tests
+{
+    use super::Unitary;
+    use crate::gates::{gate_test, CCX, Composite, CX, Gate, H, RX, T, U3};
+    use crate::export::{Latex, LatexExportState, OpenQasm, CQasm};
+    use crate::error::Error;
+
+    /// Check that `m0` and `m1` are equal, up to a global phase
+    fn assert_equal_up_to_phase(m0: &crate::cmatrix::CMatrix, m1: &crate::cmatrix::CMatrix)
+    {
+        let (idx, _) = m1.indexed_iter()
+            .max_by(|(_, a), (_, b)| a.norm().partial_cmp(&b.norm()).unwrap())
+            .unwrap();
+        let phase = m0[idx] / m1[idx];
+        let diff = m0 - &(m1 * phase);
+        assert!(diff.iter().all(|c| c.norm() < 1.0e-10),
+            "Matrices differ:\n{}\n{}", m0, m1);
+    }
+
+    #[test]
+    fn test_new()
+    {
+        let z = crate::cmatrix::COMPLEX_ZERO;
+        let o = crate::cmatrix::COMPLEX_ONE;
+        let x = crate::cmatrix::COMPLEX_HSQRT2;
+
+        let gate = Unitary::new("H", array![[x, x], [x, -x]]);
+        assert!(gate.is_ok());
+        let gate = gate.unwrap();
+        assert_eq!(gate.description(), "H");
+        assert_eq!(gate.nr_affected_bits(), 1);
+
+        let gate = Unitary::new("CX", CX::new().matrix()).unwrap();
+        assert_eq!(gate.nr_affected_bits(), 2);
+
+        let gate = Unitary::new("CCX", CCX::new().matrix()).unwrap();
+        assert_eq!(gate.nr_affected_bits(), 3);
+        assert_eq!(gate.cost(), gate.decompose().cost());
+
+        assert!(matches!(Unitary::new("A", array![[o, z, z], [z, o, z]]),
+            Err(Error::InvalidMatrixSize(2, 3))));
+        assert!(matches!(Unitary::new("B", crate::cmatrix::CMatrix::eye(3)),
+            Err(Error::InvalidMatrixSize(3, 3))));
+        assert!(matches!(Unitary::new("C", array![[o]]),
+            Err(Error::InvalidMatrixSize(1, 1))));
+        assert_eq!(Unitary::new("D", array![[o, o], [z, o]]).err(),
+            Some(Error::NotUnitary(String::from("D"))));
+        assert_eq!(Unitary::new("E", array![[o, z], [z, o * 1.0e-3 + o]]).err(),
+            Some(Error::NotUnitary(String::from("E"))));
+        assert!(Unitary::new_with_tolerance("F", array![[o, z], [z, o * 1.0e-3 + o]], 1.0e-2).is_ok());
+    }
+
+    #[test]
+    fn test_matrix()
+    {
+        let mut composite = Composite::new("G", 2);
+        composite.add_gate(H::new(), &[0]);
+        composite.add_gate(CX::new(), &[0, 1]);
+        composite.add_gate(T::new(), &[1]);
+        let gate = Unitary::new("G", composite.matrix()).unwrap();
+        assert_complex_matrix_eq!(gate.matrix(), composite.matrix());
+    }
+
+    #[test]
+    fn test_apply()
+    {
+        let z = crate::cmatrix::COMPLEX_ZERO;
+        let o = crate::cmatrix::COMPLEX_ONE;
+        let x = crate::cmatrix::COMPLEX_HSQRT2;
+        let h = 0.5 * o;
+
+        let gate = Unitary::new("H", array![[x, x], [x, -x]]).unwrap();
+        let mut state = array![
+            [o, z, x,  x],
+            [z, o, x, -x]
+        ];
+        let result = array![
+            [x,  x, o, z],
+            [x, -x, z, o]
+        ];
+        gate_test(gate, &mut state, &result);
+
+        let gate = Unitary::new("CX", CX::new().matrix()).unwrap();
+        let mut state = array![
+            [o, z, z, z, h],
+            [z, o, z, z, h],
+            [z, z, o, z, h],
+            [z, z, z, o, h]
+        ];
+        let result = array![
+            [o, z, z, z, h],
+            [z, o, z, z, h],
+            [z, z, z, o, h],
+            [z, z, o, z, h]
+        ];
+        gate_test(gate, &mut state, &result);
+
+        let gate = Unitary::new("H", array![[x, x], [x, -x]]).unwrap();
+        let mut state = array![[o, z], [z, z], [z, o], [z, z]];
+        gate.apply_mat(&mut state);
+        assert_complex_matrix_eq!(&state, &array![[x, x], [z, z], [x, -x], [z, z]]);
+    }
+
+    #[test]
+    fn test_decompose()
+    {
+        let gate = Unitary::new("H", H::new().matrix()).unwrap();
+        assert_equal_up_to_phase(&gate.decompose().matrix(), &gate.matrix());
+
+        let gate = Unitary::new("CX", CX::new().matrix()).unwrap();
+        assert_equal_up_to_phase(&gate.decompose().matrix(), &gate.matrix());
+
+        let gate = Unitary::new("CCX", CCX::new().matrix()).unwrap();
+        assert_equal_up_to_phase(&gate.decompose().matrix(), &gate.matrix());
+
+        let mut composite = Composite::new("G", 3);
+        composite.add_gate(H::new(), &[0]);
+        composite.add_gate(U3::new(0.3, 1.2, -0.8), &[1]);
+        composite.add_gate(CX::new(), &[0, 2]);
+        composite.add_gate(RX::new(2.1), &[2]);
+        composite.add_gate(CX::new(), &[2, 1]);
+        composite.add_gate(T::new(), &[1]);
+        composite.add_gate(H::new(), &[2]);
+        let gate = Unitary::new("G", composite.matrix()).unwrap();
+        assert_equal_up_to_phase(&gate.decompose().matrix(), &gate.matrix());
+
+        let mut composite = Composite::new("G", 4);
+        composite.add_gate(H::new(), &[3]);
+        composite.add_gate(CCX::new(), &[3, 1, 0]);
+        composite.add_gate(U3::new(2.3, 0.2, -1.8), &[2]);
+        composite.add_gate(CX::new(), &[2, 3]);
+        composite.add_gate(RX::new(0.1), &[1]);
+        let gate = Unitary::new("G", composite.matrix()).unwrap();
+        assert_equal_up_to_phase(&gate.decompose().matrix(), &gate.matrix());
+    }
+
+    #[test]
+    fn test_open_qasm()
+    {
+        let bit_names = [String::from("qb0"), String::from("qb1")];
+
+        let gate = Unitary::new("H", H::new().matrix()).unwrap();
+        let qasm = gate.open_qasm(&bit_names, &[1]);
+        assert_eq!(qasm, Ok(String::from("rz(-3.141592653589793) qb1; u3(1.5707963267948966, 0, 0) qb1")));
+
+        let gate = Unitary::new("CX", CX::new().matrix()).unwrap();
+        let qasm = gate.open_qasm(&bit_names, &[0, 1]);
+        assert_eq!(qasm, Ok(String::from("cx qb0, qb1")));
+        let qasm = gate.open_qasm(&bit_names, &[1, 0]);
+        assert_eq!(qasm, Ok(String::from("cx qb1, qb0")));
+
+        let qasm = gate.conditional_open_qasm("b == 1", &bit_names, &[0, 1]);
+        assert_eq!(qasm, Ok(String::from("if (b == 1) cx qb0, qb1")));
+
+        let qasm = gate.open_qasm(&bit_names, &[0]);
+        assert!(matches!(qasm, Err(Error::InvalidNrBits(1, 2, _))));
+    }
+
+    #[test]
+    fn test_c_qasm()
+    {
+        let bit_names = [String::from("qb0"), String::from("qb1")];
+
+        let gate = Unitary::new("H", H::new().matrix()).unwrap();
+        let qasm = gate.c_qasm(&bit_names, &[1]);
+        assert_eq!(qasm, Ok(String::from("rz qb1, -3.141592653589793\nry qb1, 1.5707963267948966")));
+
+        let gate = Unitary::new("CX", CX::new().matrix()).unwrap();
+        let qasm = gate.c_qasm(&bit_names, &[1, 0]);
+        assert_eq!(qasm, Ok(String::from("cnot qb1, qb0")));
+
+        let qasm = gate.conditional_c_qasm("b[0]", &bit_names, &[0, 1]);
+        assert_eq!(qasm, Ok(String::from("c-cnot b[0], qb0, qb1")));
+    }
+
+    #[test]
+    fn test_latex()
+    {
+        let gate = Unitary::new("G", CX::new().matrix()).unwrap();
+        let mut state = LatexExportState::new(2, 0);
+        assert_eq!(gate.latex(&[0, 1], &mut state), Ok(()));
+        assert_eq!(state.code(),
+r#"\Qcircuit @C=1em @R=.7em {
+    \lstick{\ket{0}} & \multigate{1}{G} & \qw \\
+    \lstick{\ket{0}} & \ghost{G} & \qw \\
+}
+"#);
+    }
+
+    #[test]
+    fn test_circuit()
+    {
+        let nr_shots = 1024;
+        let tol = 1.0e-5;
+
+        let mut bell = Composite::new("Bell", 2);
+        bell.add_gate(H::new(), &[0]);
+        bell.add_gate(CX::new(), &[0, 1]);
+        let gate = Unitary::new("Bell", bell.matrix()).unwrap();
+
+        let mut circuit = crate::circuit::Circuit::new(3, 3).unwrap();
+        circuit.add_gate(gate, &[2, 0]).unwrap();
+        circuit.measure_all(&[0, 1, 2]).unwrap();
+        circuit.execute(nr_shots).unwrap();
+        let hist = circuit.histogram_vec().unwrap();
+        assert_eq!(hist[0] + hist[5], nr_shots);
+        assert!(crate::stats::measurement_ok(hist[0], nr_shots, 0.5, tol));
+    }
+}
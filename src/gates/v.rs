@@ -36,6 +36,14 @@ impl V
     }
 }
 
+impl Default for V
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
 impl crate::gates::Gate for V
 {
     fn cost(&self) -> f64
@@ -105,6 +113,14 @@ impl Vdg
     }
 }
 
+impl Default for Vdg
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
 impl crate::gates::Gate for Vdg
 {
     fn cost(&self) -> f64
@@ -57,6 +57,14 @@ impl X
     }
 }
 
+impl Default for X
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
 impl crate::gates::Gate for X
 {
     fn cost(&self) -> f64
@@ -81,6 +89,11 @@ impl crate::gates::Gate for X
         array![[z, o], [o, z]]
     }
 
+    fn permutation(&self) -> Option<crate::permutation::Permutation>
+    {
+        Some(crate::permutation::Permutation::new(vec![1, 0]))
+    }
+
     fn apply_slice(&self, state: crate::cmatrix::CVecSliceMut)
     {
         Self::transform(state);
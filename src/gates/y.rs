@@ -31,6 +31,14 @@ impl Y
     }
 }
 
+impl Default for Y
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
 impl crate::gates::Gate for Y
 {
     fn cost(&self) -> f64
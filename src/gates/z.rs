@@ -31,6 +31,14 @@ impl Z
     }
 }
 
+impl Default for Z
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
 impl crate::gates::Gate for Z
 {
     fn cost(&self) -> f64
@@ -55,6 +63,12 @@ impl crate::gates::Gate for Z
         array![[o, z], [z, -o]]
     }
 
+    fn diagonal(&self) -> Option<crate::cmatrix::CVector>
+    {
+        let o = crate::cmatrix::COMPLEX_ONE;
+        Some(array![o, -o])
+    }
+
     fn apply_slice(&self, mut state: crate::cmatrix::CVecSliceMut)
     {
         assert!(state.len() % 2 == 0, "Number of rows is not even.");
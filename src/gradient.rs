@@ -0,0 +1,151 @@
+// Copyright 2019 Q1t BV
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Support for the computation of gradients with respect to gate arguments.
+
+use std::f64::consts::{FRAC_PI_2, SQRT_2};
+
+/// Parameter shift rule for gates of the form `exp(-iθG/2)`, where the
+/// generator `G` has eigenvalues `±1`.
+const TWO_TERM_RULE: [(f64, f64); 2] = [(FRAC_PI_2, 0.5), (-FRAC_PI_2, -0.5)];
+
+/// Parameter shift rule for controlled rotations, whose generator has
+/// eigenvalues `0` and `±1`.
+const FOUR_TERM_RULE: [(f64, f64); 4] = [
+    (FRAC_PI_2, (SQRT_2 + 1.0) / (4.0 * SQRT_2)),
+    (-FRAC_PI_2, -(SQRT_2 + 1.0) / (4.0 * SQRT_2)),
+    (3.0 * FRAC_PI_2, -(SQRT_2 - 1.0) / (4.0 * SQRT_2)),
+    (-3.0 * FRAC_PI_2, (SQRT_2 - 1.0) / (4.0 * SQRT_2))
+];
+
+/// Type of a parametric gate.
+///
+/// Return the type of the gates built by parametric gate `gate`, i.e. the
+/// description of a built gate with its arguments stripped. The type is
+/// taken from the gate itself rather than from the name the parametric gate
+/// was given, so that a shift rule is only used for the gates it applies to.
+fn gate_type(gate: &crate::parameter::ParametricGate) -> String
+{
+    let built = gate.build(&vec![0.0; gate.args().len()]);
+    let desc = built.description();
+    String::from(desc.split('(').next().unwrap_or(desc))
+}
+
+/// Parameter shift rule for a gate.
+///
+/// Return the parameter shift rule for the arguments of parametric gate
+/// `gate`, as a list of `(shift, weight)` pairs. The derivative of an
+/// expectation value `E` with respect to an argument `θ` is then
+/// `∑ weight·E(θ + shift)`. Returns `Error::NoGradient` if no shift rule is
+/// known for the type of gate built.
+pub fn shift_rule(gate: &crate::parameter::ParametricGate)
+    -> crate::error::Result<&'static [(f64, f64)]>
+{
+    let gate_type = gate_type(gate);
+    match (gate_type.as_str(), gate.args().len())
+    {
+        ("RX", 1) | ("RY", 1) | ("RZ", 1) | ("U1", 1) | ("U3", 3) => Ok(&TWO_TERM_RULE),
+        ("CRX", 1) | ("CRY", 1) | ("CRZ", 1)                     => Ok(&FOUR_TERM_RULE),
+        _ => Err(crate::error::Error::NoGradient(gate_type))
+    }
+}
+
+/// Frequency of a gate matrix.
+///
+/// Return the frequency `ω` of the elements of the matrix of gates of type
+/// `gate_type`, as a function of argument `idx`. Every matrix element is of
+/// the form `a + b·exp(iωθ) + c·exp(-iωθ)` in this argument. For the rotation
+/// gates, the frequency is `1/2`, for the phase arguments of `U1` and `U3` it
+/// is `1`.
+fn matrix_frequency(gate_type: &str, idx: usize) -> f64
+{
+    match (gate_type, idx)
+    {
+        ("U1", _) | ("U3", 1) | ("U3", 2) => 1.0,
+        _                                 => 0.5
+    }
+}
+
+/// Gate derivative matrix.
+///
+/// Compute the derivative of the matrix of gate `gate`, with respect to
+/// argument `idx`, when its arguments are `args`. Since the elements of the
+/// matrix of all gates with a known shift rule have a single frequency `ω`
+/// in each argument, the derivative can be computed exactly from the matrices
+/// at arguments shifted by `±π/(2ω)`.
+pub fn derivative_matrix(gate: &crate::parameter::ParametricGate, args: &[f64],
+    idx: usize) -> crate::error::Result<crate::cmatrix::CMatrix>
+{
+    shift_rule(gate)?;
+
+    let omega = matrix_frequency(&gate_type(gate), idx);
+    let shift = FRAC_PI_2 / omega;
+    let mut shifted = args.to_vec();
+    shifted[idx] = args[idx] + shift;
+    let plus = gate.build(&shifted).matrix();
+    shifted[idx] = args[idx] - shift;
+    let minus = gate.build(&shifted).matrix();
+
+    Ok((plus - minus) * (0.5 * omega))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{derivative_matrix, shift_rule};
+    use crate::error::Error;
+    use crate::gates::{Gate, CRY, RX};
+    use crate::parameter::{Expression, ParametricGate};
+
+    #[test]
+    fn test_shift_rule()
+    {
+        let gate = ParametricGate::new("RX", vec![Expression::parameter("theta")],
+            |args| RX::new(args[0]));
+        assert_eq!(shift_rule(&gate).unwrap().len(), 2);
+        let gate = ParametricGate::new("rotation", vec![Expression::parameter("lambda")],
+            |args| crate::gates::CRZ::new(args[0]));
+        assert_eq!(shift_rule(&gate).unwrap().len(), 4);
+        let gate = ParametricGate::new("RX", vec![Expression::parameter("theta")],
+            |_| crate::gates::H::new());
+        assert_eq!(shift_rule(&gate), Err(Error::NoGradient(String::from("H"))));
+    }
+
+    #[test]
+    fn test_derivative_matrix()
+    {
+        let h = 1.0e-6;
+        let gate = ParametricGate::new("CRY", vec![Expression::parameter("theta")],
+            |args| CRY::new(args[0]));
+        let deriv = derivative_matrix(&gate, &[0.3], 0).unwrap();
+        let fd = (CRY::new(0.3 + h).matrix() - CRY::new(0.3 - h).matrix())
+            * (0.5 / h);
+        assert!((deriv - fd).iter().all(|c| c.norm() < 1.0e-8));
+
+        let gate = ParametricGate::new("U3", vec![Expression::parameter("a"),
+            Expression::parameter("b"), Expression::parameter("c")],
+            |args| crate::gates::U3::new(args[0], args[1], args[2]));
+        for idx in 0..3
+        {
+            let mut args = [0.4, -1.2, 2.1];
+            let deriv = derivative_matrix(&gate, &args, idx).unwrap();
+            args[idx] += h;
+            let plus = gate.build(&args).matrix();
+            args[idx] -= 2.0 * h;
+            let minus = gate.build(&args).matrix();
+            let fd = (plus - minus) * (0.5 / h);
+            assert!((deriv - fd).iter().all(|c| c.norm() < 1.0e-8));
+        }
+    }
+}
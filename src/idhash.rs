@@ -70,6 +70,7 @@ impl ::std::hash::Hasher for IdentityHasher
 }
 
 /// Structure to build an [IdentityHasher](struct.IdentityHasher.html).
+#[derive(Clone)]
 pub struct BuildIdentityHasher {}
 
 impl BuildIdentityHasher
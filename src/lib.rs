@@ -46,7 +46,7 @@
 //! state:
 //! ```
 //! use q1tsim::circuit::Circuit;
-//! use q1tsim::gates::{CS, CT, Swap};
+//! use q1tsim::gates::QFT;
 //!
 //! fn main()
 //! {
@@ -56,25 +56,19 @@
 //!     // Create a quantum circuit with 3 quantum bits and 3 classical (measurement)
 //!     // bits. The circuit starts by default with all quantum bits in the |0⟩
 //!     // state, so in this case |000⟩.
-//!     let mut circuit = Circuit::new(3, 3);
-//!
-//!     // Set up a 3-qubit quantum Fourier transform
-//!     // There is no predefined method on Circuit that implements a controlled
-//!     // `S` or `T` gate, so we use the `add_gate()` method for those.
-//!     circuit.h(2);
-//!     circuit.add_gate(CS::new(), &[1, 2]);
-//!     circuit.add_gate(CT::new(), &[0, 2]);
-//!     circuit.h(1);
-//!     circuit.add_gate(CS::new(), &[0, 1]);
-//!     circuit.h(0);
-//!     circuit.add_gate(Swap::new(), &[0, 2]);
+//!     let mut circuit = Circuit::new(3, 3).unwrap();
+//!
+//!     // Add a 3-qubit quantum Fourier transform, including the final swap
+//!     // of the qubits. There is no predefined method on Circuit for this
+//!     // transform, so we use the `add_gate()` method.
+//!     circuit.add_gate(QFT::new(3, true), &[0, 1, 2]);
 //!
 //!     // Measure all quantum bits in the Pauli `Z` basis
 //!     circuit.measure_all(&[0, 1, 2]);
 //!
 //!     // Actually calculate the resulting quantum state, and perform the
 //!     // measurements, averaging over `nr_runs` runs.
-//!     circuit.execute(nr_runs);
+//!     circuit.execute(nr_runs).unwrap();
 //!
 //!     // And print the results.
 //!     let hist = circuit.histogram_string().unwrap();
@@ -99,7 +93,7 @@
 //! // classical bits
 //! let nr_qbits = 2;
 //! let nr_cbits = 2;
-//! let mut circuit = Circuit::new(nr_qbits, nr_cbits);
+//! let mut circuit = Circuit::new(nr_qbits, nr_cbits).unwrap();
 //!
 //! // Add operations on the circuit. In this case, a Hadamard transform on the
 //! // first bit, followwed by a CNOT gate with the first bit as control and
@@ -112,14 +106,19 @@
 //! circuit.measure_all(&[0, 1]);
 //!
 //! // Now execute the circuit, averaging measurements over `nr_runs` runs
-//! // of the circuit.
+//! // of the circuit. The measurement results of the individual runs are
+//! // returned in an `ExecutionResult`.
 //! let nr_runs = 1024;
-//! circuit.execute(nr_runs);
+//! let result = circuit.execute(nr_runs).unwrap();
 //!
 //! // And finally collect the results. The `histogram_vec()` method returns a
 //! // vector with at each index `i` the number if times the measurement returned
 //! // `i` in the classical register.
 //! let hist = circuit.histogram_vec();
+//!
+//! // The classical register of each run is also available, e.g. for the
+//! // first run:
+//! let first_run = &result.memory_strings()[0];
 //! ```
 //! Since version 0.3, many of the methods on `Circuit` will return a `Result`,
 //! possibly containing an error code (e.g. if invalid bit numbers are used).
@@ -139,7 +138,7 @@
 //! }).expect("Failed to build circuit");
 //!
 //! let nr_runs = 1024;
-//! circuit.execute(nr_runs);
+//! circuit.execute(nr_runs).unwrap();
 //! let hist = circuit.histogram_vec();
 //! # }
 //!```
@@ -230,22 +229,39 @@
 //! implementation for the export traits must be defined for your custom type,
 //! either through deriving or by providing your own implementation.
 //!
+//! If all you have is the matrix of your gate, consider using the
+//! [Unitary](gates/struct.Unitary.html) gate instead. It can be constructed
+//! from an arbitrary unitary matrix, and is exported to OpenQasm and c-Qasm
+//! by decomposing the matrix into primitive gates.
+//!
 //! [^no_qasm]: No reasonable way at least. Technically, we could take the matrix
 //! for the gate, decompose it into primitive gates, and export the corresponding
-//! code.
+//! code. This is what the `Unitary` gate does, but it is not feasible for
+//! arbitrary gate types in general.
+
+#![allow(clippy::redundant_field_names, clippy::manual_is_multiple_of)]
 
 #[macro_use] extern crate ndarray;
 #[cfg(test)] #[macro_use] extern crate matches;
 
 #[macro_use] pub mod cmatrix;
 #[macro_use] pub mod gates;
-pub mod circuit;
+#[macro_use] pub mod circuit;
+pub mod algorithms;
+pub mod cstate;
 pub mod error;
+pub mod execution;
 pub mod export;
+pub mod parameter;
+pub mod pauli;
 pub mod permutation;
 pub mod qustate;
+pub mod register;
 
+mod fusion;
+mod gradient;
 mod idhash;
+mod sparse;
 mod support;
 #[cfg(test)] mod stats;
 
@@ -0,0 +1,498 @@
+// Copyright 2019 Q1t BV
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Symbolic parameters for gates.
+//!
+//! This module defines affine expressions in named parameters, which can be
+//! used as arguments to gates in a circuit, so that the circuit can be built
+//! once and executed for different values of the parameters.
+
+/// Affine expression in named parameters.
+///
+/// An `Expression` is a constant plus a linear combination of named
+/// parameters, e.g. `2*theta + pi/4`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expression
+{
+    /// The constant term in the expression
+    constant: f64,
+    /// The parameter names and their coefficients
+    terms: Vec<(String, f64)>
+}
+
+impl Expression
+{
+    /// Create a new constant expression with value `value`.
+    pub fn constant(value: f64) -> Self
+    {
+        Expression { constant: value, terms: vec![] }
+    }
+
+    /// Create a new expression consisting of the single parameter `name`.
+    pub fn parameter(name: &str) -> Self
+    {
+        Expression { constant: 0.0, terms: vec![(String::from(name), 1.0)] }
+    }
+
+    /// Parse an expression.
+    ///
+    /// Parse the expression in `expr`, which may contain any of the
+    /// constructs recognised by `Composite::parse_sum_expression()`, as well
+    /// as parameter names. The expression must be affine in the parameters,
+    /// i.e. of the form `c`<sub>`0`</sub>` + c`<sub>`1`</sub>`p`<sub>`1`</sub>` + ...`,
+    /// otherwise `ParseError::NotAffine` is returned. Affinity is checked
+    /// while parsing: parameters may only be added, subtracted, negated, or
+    /// multiplied or divided by a constant.
+    pub fn parse(expr: &str) -> crate::error::ParseResult<Self>
+    {
+        match crate::gates::Composite::parse_sum_value::<Expression>(expr)
+        {
+            Ok((expression, rest)) if rest.trim().is_empty() => Ok(expression),
+            Ok((_, rest)) => Err(crate::error::ParseError::TrailingText(String::from(rest))),
+            Err(crate::error::ParseError::NotAffine(_)) => {
+                Err(crate::error::ParseError::NotAffine(String::from(expr)))
+            },
+            Err(err) => Err(err)
+        }
+    }
+
+    /// Apply `f` to the constant term and all coefficients of this
+    /// expression, dropping terms whose coefficient becomes zero.
+    fn map_coefficients<F>(mut self, f: F) -> Self
+    where F: Fn(f64) -> f64
+    {
+        self.constant = f(self.constant);
+        for term in self.terms.iter_mut()
+        {
+            term.1 = f(term.1);
+        }
+        self.terms.retain(|term| term.1 != 0.0);
+        self
+    }
+
+    /// Add `sign` times expression `other` to this expression, where `sign`
+    /// is either `1.0` or `-1.0`.
+    fn add_scaled(mut self, other: Expression, sign: f64) -> Self
+    {
+        self.constant += sign * other.constant;
+        for (name, coef) in other.terms
+        {
+            match self.terms.iter_mut().find(|term| term.0 == name)
+            {
+                Some(term) => { term.1 += sign * coef; },
+                None       => { self.terms.push((name, sign * coef)); }
+            }
+        }
+        self.terms.retain(|term| term.1 != 0.0);
+        self
+    }
+
+    /// The constant term of this expression.
+    pub fn constant_term(&self) -> f64
+    {
+        self.constant
+    }
+
+    /// The coefficient of parameter `name` in this expression.
+    pub fn coefficient(&self, name: &str) -> f64
+    {
+        self.terms.iter().find(|t| t.0 == name).map_or(0.0, |t| t.1)
+    }
+
+    /// Iterate over the names of the parameters in this expression.
+    pub fn parameters(&self) -> impl Iterator<Item=&str>
+    {
+        self.terms.iter().map(|t| t.0.as_str())
+    }
+
+    /// Check if this expression depends on any parameter.
+    pub fn is_constant(&self) -> bool
+    {
+        self.terms.is_empty()
+    }
+
+    /// Evaluate this expression.
+    ///
+    /// Compute the value of this expression, using the parameter values in
+    /// `values`. If a parameter occurring in the expression has no value
+    /// in `values`, `Error::UnboundParameter` is returned.
+    pub fn evaluate(&self, values: &::std::collections::HashMap<String, f64>)
+        -> crate::error::Result<f64>
+    {
+        let mut res = self.constant;
+        for (name, coef) in self.terms.iter()
+        {
+            match values.get(name)
+            {
+                Some(value) => { res += coef * value; },
+                None => {
+                    return Err(crate::error::Error::UnboundParameter(name.clone()));
+                }
+            }
+        }
+        Ok(res)
+    }
+}
+
+impl crate::gates::ExpressionValue for Expression
+{
+    fn from_real(value: f64) -> Self
+    {
+        Expression::constant(value)
+    }
+
+    fn from_name(name: &str) -> Option<Self>
+    {
+        if name == "pi"
+        {
+            None
+        }
+        else
+        {
+            Some(Expression::parameter(name))
+        }
+    }
+
+    fn negate(self) -> Self
+    {
+        self.map_coefficients(|c| -c)
+    }
+
+    fn apply_operator(self, op: &str, other: Self) -> Option<Self>
+    {
+        match op
+        {
+            "+" => Some(self.add_scaled(other, 1.0)),
+            "-" => Some(self.add_scaled(other, -1.0)),
+            "*" if other.is_constant() => Some(self.map_coefficients(|c| c * other.constant)),
+            "*" if self.is_constant() => Some(other.map_coefficients(|c| self.constant * c)),
+            "/" if other.is_constant() => Some(self.map_coefficients(|c| c / other.constant)),
+            "^" if self.is_constant() && other.is_constant() => {
+                Some(Expression::constant(self.constant.powf(other.constant)))
+            },
+            _ => None
+        }
+    }
+
+    fn apply_function(self, fun: &str) -> Option<Self>
+    {
+        if self.is_constant()
+        {
+            self.constant.apply_function(fun).map(Expression::constant)
+        }
+        else
+        {
+            None
+        }
+    }
+}
+
+impl From<f64> for Expression
+{
+    fn from(value: f64) -> Self
+    {
+        Expression::constant(value)
+    }
+}
+
+impl ::std::fmt::Display for Expression
+{
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result
+    {
+        let mut res = String::new();
+        for (name, coef) in self.terms.iter()
+        {
+            let term = if *coef == 1.0
+                {
+                    name.clone()
+                }
+                else if *coef == -1.0
+                {
+                    format!("-{}", name)
+                }
+                else
+                {
+                    format!("{}*{}", coef, name)
+                };
+
+            if res.is_empty()
+            {
+                res = term;
+            }
+            else if let Some(stripped) = term.strip_prefix('-')
+            {
+                res += &format!(" - {}", stripped);
+            }
+            else
+            {
+                res += &format!(" + {}", term);
+            }
+        }
+
+        if res.is_empty()
+        {
+            res = self.constant.to_string();
+        }
+        else if self.constant < 0.0
+        {
+            res += &format!(" - {}", -self.constant);
+        }
+        else if self.constant > 0.0
+        {
+            res += &format!(" + {}", self.constant);
+        }
+
+        write!(f, "{}", res)
+    }
+}
+
+/// Type of a function creating a gate from its arguments
+type GateBuilder = dyn Fn(&[f64]) -> Box<dyn crate::export::CircuitGate>;
+
+/// Gate with symbolic arguments.
+///
+/// A `ParametricGate` holds the expressions for the arguments of a gate,
+/// and a function to create the actual gate once the values of all the
+/// parameters in these expressions are known.
+pub struct ParametricGate
+{
+    /// The name of the gate
+    name: String,
+    /// The number of qubits the gate operates on
+    nr_bits: usize,
+    /// The expressions for the arguments of the gate
+    args: Vec<Expression>,
+    /// Function to create the gate for a set of argument values
+    build: Box<GateBuilder>,
+    /// The gate for the currently bound argument values, if any
+    gate: Option<Box<dyn crate::export::CircuitGate>>
+}
+
+impl ParametricGate
+{
+    /// Create a new parametric gate.
+    ///
+    /// Create a new gate with name `name`, whose arguments are given by the
+    /// expressions in `args`. Once values for the parameters are bound,
+    /// the gate is created by calling `build` with the values of the
+    /// arguments.
+    pub fn new<G, F>(name: &str, args: Vec<Expression>, build: F) -> Self
+    where G: 'static + crate::export::CircuitGate, F: 'static + Fn(&[f64]) -> G
+    {
+        let nr_bits = build(&vec![0.0; args.len()]).nr_affected_bits();
+        let mut res = ParametricGate
+        {
+            name: String::from(name),
+            nr_bits: nr_bits,
+            args: args,
+            build: Box::new(move |values| Box::new(build(values))),
+            gate: None
+        };
+        // Gates with only constant arguments are bound immediately
+        res.bind(&::std::collections::HashMap::new());
+        res
+    }
+
+    /// The name of this gate.
+    pub fn name(&self) -> &str
+    {
+        &self.name
+    }
+
+    /// The number of qubits this gate operates on.
+    pub fn nr_affected_bits(&self) -> usize
+    {
+        self.nr_bits
+    }
+
+    /// The expressions for the arguments of this gate.
+    pub fn args(&self) -> &[Expression]
+    {
+        &self.args
+    }
+
+    /// Bind parameter values.
+    ///
+    /// Create the gate for the parameter values in `values`. If not all
+    /// parameters in the arguments are present in `values`, the gate
+    /// is left unbound.
+    pub fn bind(&mut self, values: &::std::collections::HashMap<String, f64>)
+    {
+        self.gate = self.arg_values(values).ok().map(|args| (self.build)(&args));
+    }
+
+    /// Evaluate the arguments of this gate.
+    ///
+    /// Compute the values of the arguments of this gate, using the parameter
+    /// values in `values`.
+    pub fn arg_values(&self, values: &::std::collections::HashMap<String, f64>)
+        -> crate::error::Result<Vec<f64>>
+    {
+        self.args.iter().map(|arg| arg.evaluate(values)).collect()
+    }
+
+    /// Bind argument values.
+    ///
+    /// Create the gate for the argument values in `args`, overriding the
+    /// values computed from the parameters. The parameter values are used
+    /// again on the next call to `bind()`.
+    pub fn bind_args(&mut self, args: &[f64])
+    {
+        self.gate = Some((self.build)(args));
+    }
+
+    /// Create the gate for the argument values in `args`, regardless of
+    /// the currently bound parameter values.
+    pub fn build(&self, args: &[f64]) -> Box<dyn crate::export::CircuitGate>
+    {
+        (self.build)(args)
+    }
+
+    /// The gate for the currently bound parameter values.
+    ///
+    /// Return the gate created for the currently bound parameter values.
+    /// If not all parameters have been bound, `Error::UnboundParameter` is
+    /// returned.
+    pub fn gate(&self) -> crate::error::Result<&dyn crate::export::CircuitGate>
+    {
+        match self.gate
+        {
+            Some(ref gate) => Ok(&**gate),
+            None => {
+                let name = self.args.iter().flat_map(|arg| arg.parameters())
+                    .next().unwrap_or("");
+                Err(crate::error::Error::UnboundParameter(String::from(name)))
+            }
+        }
+    }
+
+    /// Export to LaTeX.
+    ///
+    /// Add this gate, operating on qubits `bits`, to the LaTeX export state
+    /// `state`. If the gate is bound, it is drawn as the bound gate,
+    /// otherwise a block with the symbolic arguments is drawn.
+    pub fn latex(&self, bits: &[usize], state: &mut crate::export::LatexExportState)
+        -> crate::error::Result<()>
+    {
+        match self.gate
+        {
+            Some(ref gate) => gate.latex(bits, state),
+            None => {
+                let args: Vec<String> = self.args.iter().map(|arg| arg.to_string()).collect();
+                state.add_block_gate(bits, &format!("{}({})", self.name, args.join(", ")))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{Expression, ParametricGate};
+    use crate::error::{Error, ParseError};
+    use crate::gates::Gate;
+
+    #[test]
+    fn test_parse()
+    {
+        let expr = Expression::parse("2*theta + pi/4").unwrap();
+        assert_eq!(expr.constant_term(), ::std::f64::consts::FRAC_PI_4);
+        assert_eq!(expr.coefficient("theta"), 2.0);
+        assert_eq!(expr.coefficient("phi"), 0.0);
+        assert_eq!(expr.parameters().collect::<Vec<&str>>(), vec!["theta"]);
+
+        let expr = Expression::parse("-(phi - 2*theta)/2 + sin(pi/2)").unwrap();
+        assert!((expr.constant_term() - 1.0).abs() < 1.0e-15);
+        assert_eq!(expr.coefficient("phi"), -0.5);
+        assert_eq!(expr.coefficient("theta"), 1.0);
+
+        let expr = Expression::parse("1.5e1").unwrap();
+        assert!(expr.is_constant());
+        assert_eq!(expr.constant_term(), 15.0);
+
+        let expr = Expression::parse("x - x").unwrap();
+        assert!(expr.is_constant());
+
+        let expr = Expression::parse("a*(b - b + 2)/4 - 2^2*pin").unwrap();
+        assert_eq!(expr.constant_term(), 0.0);
+        assert_eq!(expr.coefficient("a"), 0.5);
+        assert_eq!(expr.coefficient("pin"), -4.0);
+        assert_eq!(expr.parameters().collect::<Vec<&str>>(), vec!["a", "pin"]);
+
+        assert_eq!(Expression::parse("theta*theta"),
+            Err(ParseError::NotAffine(String::from("theta*theta"))));
+        assert_eq!(Expression::parse("a*b"),
+            Err(ParseError::NotAffine(String::from("a*b"))));
+        assert_eq!(Expression::parse("a*b - a*c"),
+            Err(ParseError::NotAffine(String::from("a*b - a*c"))));
+        assert_eq!(Expression::parse("a*(b - c)"),
+            Err(ParseError::NotAffine(String::from("a*(b - c)"))));
+        assert_eq!(Expression::parse("a*b*(c - d)"),
+            Err(ParseError::NotAffine(String::from("a*b*(c - d)"))));
+        assert_eq!(Expression::parse("sin(theta)"),
+            Err(ParseError::NotAffine(String::from("sin(theta)"))));
+        assert_eq!(Expression::parse("2/theta"),
+            Err(ParseError::NotAffine(String::from("2/theta"))));
+        assert_eq!(Expression::parse("2^theta + 1"),
+            Err(ParseError::NotAffine(String::from("2^theta + 1"))));
+        assert!(matches!(Expression::parse("2*theta +"), Err(ParseError::InvalidArgument(_))));
+        assert!(matches!(Expression::parse("2 theta"), Err(ParseError::TrailingText(_))));
+    }
+
+    #[test]
+    fn test_evaluate()
+    {
+        let expr = Expression::parse("2*theta - phi + 1").unwrap();
+        let mut values = ::std::collections::HashMap::new();
+        values.insert(String::from("theta"), 0.25);
+        assert_eq!(expr.evaluate(&values), Err(Error::UnboundParameter(String::from("phi"))));
+        values.insert(String::from("phi"), 3.0);
+        assert_eq!(expr.evaluate(&values), Ok(-1.5));
+
+        let expr = Expression::from(2.5);
+        assert_eq!(expr.evaluate(&::std::collections::HashMap::new()), Ok(2.5));
+    }
+
+    #[test]
+    fn test_display()
+    {
+        assert_eq!(Expression::parse("2*theta + 1").unwrap().to_string(), "2*theta + 1");
+        assert_eq!(Expression::parse("-theta + phi/2 - 0.5").unwrap().to_string(),
+            "-theta + 0.5*phi - 0.5");
+        assert_eq!(Expression::parse("theta - 3*phi").unwrap().to_string(), "theta - 3*phi");
+        assert_eq!(Expression::constant(0.0).to_string(), "0");
+        assert_eq!(Expression::parameter("x").to_string(), "x");
+    }
+
+    #[test]
+    fn test_parametric_gate()
+    {
+        let mut gate = ParametricGate::new("RX",
+            vec![Expression::parse("2*theta").unwrap()],
+            |args| crate::gates::RX::new(args[0]));
+        assert_eq!(gate.name(), "RX");
+        assert_eq!(gate.nr_affected_bits(), 1);
+        assert!(matches!(gate.gate(), Err(Error::UnboundParameter(_))));
+
+        let mut values = ::std::collections::HashMap::new();
+        values.insert(String::from("theta"), 0.5);
+        gate.bind(&values);
+        assert_complex_matrix_eq!(gate.gate().unwrap().matrix(),
+            crate::gates::RX::new(1.0).matrix());
+
+        let gate = ParametricGate::new("RZ", vec![Expression::constant(0.5)],
+            |args| crate::gates::RZ::new(args[0]));
+        assert!(gate.gate().is_ok());
+    }
+}
@@ -0,0 +1,421 @@
+// Copyright 2019 Q1t BV
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Pauli strings, and observables described by weighted sums of Pauli strings.
+
+/// Single qubit Pauli operator
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pauli
+{
+    /// The identity operator
+    I,
+    /// Pauli `X` operator
+    X,
+    /// Pauli `Y` operator
+    Y,
+    /// Pauli `Z` operator
+    Z
+}
+
+impl Pauli
+{
+    /// Create a Pauli operator from its name.
+    ///
+    /// Return the Pauli operator with name `c` (one of `I`, `X`, `Y`, or `Z`),
+    /// or `None` if `c` is not the name of a Pauli operator.
+    pub fn from_char(c: char) -> Option<Self>
+    {
+        match c
+        {
+            'I' => Some(Pauli::I),
+            'X' => Some(Pauli::X),
+            'Y' => Some(Pauli::Y),
+            'Z' => Some(Pauli::Z),
+            _   => None
+        }
+    }
+
+    /// The name of this Pauli operator.
+    pub fn to_char(self) -> char
+    {
+        match self
+        {
+            Pauli::I => 'I',
+            Pauli::X => 'X',
+            Pauli::Y => 'Y',
+            Pauli::Z => 'Z'
+        }
+    }
+
+    /// The 2×2 matrix for this operator.
+    pub fn matrix(self) -> crate::cmatrix::CMatrix
+    {
+        let z = crate::cmatrix::COMPLEX_ZERO;
+        let o = crate::cmatrix::COMPLEX_ONE;
+        let i = crate::cmatrix::COMPLEX_I;
+        match self
+        {
+            Pauli::I => array![[o, z], [z,  o]],
+            Pauli::X => array![[z, o], [o,  z]],
+            Pauli::Y => array![[z, -i], [i, z]],
+            Pauli::Z => array![[o, z], [z, -o]]
+        }
+    }
+}
+
+/// Pauli string
+///
+/// Struct PauliString represents a tensor product of single qubit Pauli
+/// operators, one for each qubit in the system.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PauliString
+{
+    /// The Pauli operator for each qubit
+    ops: Vec<Pauli>
+}
+
+impl PauliString
+{
+    /// Create a new Pauli string.
+    ///
+    /// Create a new Pauli string, operating with `ops[i]` on qubit `i`.
+    pub fn new(ops: Vec<Pauli>) -> Self
+    {
+        PauliString { ops: ops }
+    }
+
+    /// Parse a Pauli string.
+    ///
+    /// Create a new Pauli string from text `text`, consisting of the letters
+    /// `I`, `X`, `Y`, and `Z`. The first letter is the operator on qubit 0,
+    /// the second the operator on qubit 1, and so on. White space is ignored.
+    pub fn parse(text: &str) -> crate::error::ParseResult<Self>
+    {
+        let ops = text.chars()
+            .filter(|c| !c.is_whitespace())
+            .map(Pauli::from_char)
+            .collect::<Option<Vec<Pauli>>>()
+            .ok_or_else(|| crate::error::ParseError::InvalidPauliString(String::from(text)))?;
+        Ok(Self::new(ops))
+    }
+
+    /// The number of qubits this Pauli string operates on
+    pub fn nr_bits(&self) -> usize
+    {
+        self.ops.len()
+    }
+
+    /// The Pauli operators in this string, for each qubit.
+    pub fn ops(&self) -> &[Pauli]
+    {
+        &self.ops
+    }
+
+    /// Return `true` if this string only consists of identity operators.
+    pub fn is_identity(&self) -> bool
+    {
+        self.ops.iter().all(|&op| op == Pauli::I)
+    }
+
+    /// The qubits on which this Pauli string acts non-trivially.
+    pub fn support(&self) -> Vec<usize>
+    {
+        self.ops.iter().enumerate()
+            .filter(|(_, &op)| op != Pauli::I)
+            .map(|(bit, _)| bit)
+            .collect()
+    }
+
+    /// Check if this string commutes with `other` on every qubit separately.
+    ///
+    /// Return `true` if, on every qubit, the operator in this string is equal
+    /// to the operator in `other`, or either one of them is the identity.
+    /// Pauli strings that commute qubit-wise can be measured simultaneously
+    /// in a single product basis.
+    pub fn qubitwise_commutes(&self, other: &PauliString) -> bool
+    {
+        self.ops.iter().zip(other.ops.iter())
+            .all(|(&p, &q)| p == q || p == Pauli::I || q == Pauli::I)
+    }
+
+    /// The full matrix of this Pauli string.
+    ///
+    /// Return the `2`<sup>`n`</sup>`×2`<sup>`n`</sup> matrix of this Pauli
+    /// string, where `n` is the number of qubits.
+    pub fn matrix(&self) -> crate::cmatrix::CMatrix
+    {
+        self.ops.iter().fold(crate::cmatrix::CMatrix::eye(1),
+            |res, op| crate::cmatrix::kron_mat(&res, &op.matrix()))
+    }
+}
+
+impl ::std::fmt::Display for PauliString
+{
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result
+    {
+        let text: String = self.ops.iter().map(|op| op.to_char()).collect();
+        write!(f, "{}", text)
+    }
+}
+
+/// Weighted sum of Pauli strings
+///
+/// Struct PauliSum represents an observable, like a Hamiltonian, written as
+/// a weighted sum ∑<sub>i</sub>c<sub>i</sub>P<sub>i</sub> of Pauli strings
+/// P<sub>i</sub> with real coefficients c<sub>i</sub>.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PauliSum
+{
+    /// The number of qubits the operator acts on
+    nr_bits: usize,
+    /// The terms in the sum
+    terms: Vec<(f64, PauliString)>
+}
+
+impl PauliSum
+{
+    /// Create a new, empty, sum of Pauli strings on `nr_bits` qubits.
+    pub fn new(nr_bits: usize) -> Self
+    {
+        PauliSum { nr_bits: nr_bits, terms: vec![] }
+    }
+
+    /// Create a new sum of Pauli strings.
+    ///
+    /// Create a new sum of Pauli strings operating on `nr_bits` qubits, from
+    /// a list of terms, each given by its coefficient and the description
+    /// of the Pauli string, as accepted by `PauliString::parse()`.
+    pub fn from_terms(nr_bits: usize, terms: &[(f64, &str)]) -> crate::error::Result<Self>
+    {
+        let mut res = Self::new(nr_bits);
+        for &(coef, text) in terms
+        {
+            res.add_term(coef, PauliString::parse(text)?)?;
+        }
+        Ok(res)
+    }
+
+    /// Add a term.
+    ///
+    /// Add the Pauli string `string` with coefficient `coef` to this sum.
+    /// If the number of qubits of `string` does not match that of this sum,
+    /// an `InvalidNrBits` error is returned.
+    pub fn add_term(&mut self, coef: f64, string: PauliString) -> crate::error::Result<()>
+    {
+        if string.nr_bits() != self.nr_bits
+        {
+            Err(crate::error::Error::InvalidNrBits(string.nr_bits(), self.nr_bits,
+                string.to_string()))
+        }
+        else
+        {
+            self.terms.push((coef, string));
+            Ok(())
+        }
+    }
+
+    /// The number of qubits this operator acts on
+    pub fn nr_bits(&self) -> usize
+    {
+        self.nr_bits
+    }
+
+    /// The terms in this sum, as `(coefficient, Pauli string)` pairs.
+    pub fn terms(&self) -> &[(f64, PauliString)]
+    {
+        &self.terms
+    }
+
+    /// The full matrix of this operator.
+    ///
+    /// Return the `2`<sup>`n`</sup>`×2`<sup>`n`</sup> matrix of this sum of
+    /// Pauli strings, where `n` is the number of qubits. Use this only for
+    /// small numbers of qubits.
+    pub fn matrix(&self) -> crate::cmatrix::CMatrix
+    {
+        let size = 1 << self.nr_bits;
+        let mut res = crate::cmatrix::CMatrix::zeros((size, size));
+        for (coef, string) in self.terms.iter()
+        {
+            res.scaled_add(crate::cmatrix::COMPLEX_ONE * *coef, &string.matrix());
+        }
+        res
+    }
+
+    /// Group terms for measurement.
+    ///
+    /// Divide the terms in this sum into groups of qubit-wise commuting Pauli
+    /// strings, which can be measured simultaneously. Each group is returned
+    /// as the measurement basis for each qubit (where `I` means the qubit
+    /// need not be measured), and the indices of the terms in the group.
+    /// Identity terms are not included in any group. The groups are formed
+    /// greedily in the order of the terms.
+    pub fn measurement_groups(&self) -> Vec<(PauliString, Vec<usize>)>
+    {
+        let mut groups: Vec<(PauliString, Vec<usize>)> = vec![];
+        for (iterm, (_, string)) in self.terms.iter().enumerate()
+        {
+            if string.is_identity()
+            {
+                continue;
+            }
+
+            if let Some((basis, idxs)) = groups.iter_mut()
+                .find(|(basis, _)| basis.qubitwise_commutes(string))
+            {
+                for (b, &op) in basis.ops.iter_mut().zip(string.ops.iter())
+                {
+                    if op != Pauli::I
+                    {
+                        *b = op;
+                    }
+                }
+                idxs.push(iterm);
+            }
+            else
+            {
+                groups.push((string.clone(), vec![iterm]));
+            }
+        }
+
+        groups
+    }
+
+    /// Compute the value for a measurement outcome.
+    ///
+    /// Compute the value of the terms with indices `idxs` in this sum, for
+    /// measurement outcome `key`. Bit `i` in `key` is the result of measuring
+    /// qubit `i` in the basis for that qubit in the Pauli string for the
+    /// term, such that a bit value 0 corresponds to eigenvalue `+1`, and
+    /// a value of 1 to eigenvalue `-1`.
+    pub fn outcome_value(&self, idxs: &[usize], key: u64) -> f64
+    {
+        idxs.iter()
+            .map(|&i| {
+                let (coef, ref string) = self.terms[i];
+                let mask = string.support().iter().fold(0, |m, &bit| m | (1 << bit));
+                if (key & mask).count_ones() % 2 == 0 { coef } else { -coef }
+            })
+            .sum()
+    }
+
+    /// Compute the expectation value from measurements.
+    ///
+    /// Compute the average value of the terms with indices `idxs` in this
+    /// sum, over the classical registers in `c_state`, using
+    /// `outcome_value()`.
+    pub fn measured_value(&self, idxs: &[usize], c_state: &ndarray::Array1<u64>) -> f64
+    {
+        if c_state.is_empty()
+        {
+            0.0
+        }
+        else
+        {
+            let sum: f64 = c_state.iter().map(|&key| self.outcome_value(idxs, key)).sum();
+            sum / c_state.len() as f64
+        }
+    }
+
+    /// The sum of the coefficients of the identity terms in this sum.
+    pub fn constant(&self) -> f64
+    {
+        self.terms.iter()
+            .filter(|(_, string)| string.is_identity())
+            .map(|&(coef, _)| coef)
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{Pauli, PauliString, PauliSum};
+    use crate::error::{Error, ParseError};
+
+    #[test]
+    fn test_parse()
+    {
+        let string = PauliString::parse("XI ZY").unwrap();
+        assert_eq!(string.ops(), &[Pauli::X, Pauli::I, Pauli::Z, Pauli::Y]);
+        assert_eq!(string.nr_bits(), 4);
+        assert_eq!(string.support(), vec![0, 2, 3]);
+        assert_eq!(string.to_string(), "XIZY");
+        assert!(!string.is_identity());
+        assert!(PauliString::parse("II").unwrap().is_identity());
+
+        assert_eq!(PauliString::parse("XA"),
+            Err(ParseError::InvalidPauliString(String::from("XA"))));
+    }
+
+    #[test]
+    fn test_matrix()
+    {
+        let z = crate::cmatrix::COMPLEX_ZERO;
+        let o = crate::cmatrix::COMPLEX_ONE;
+        let i = crate::cmatrix::COMPLEX_I;
+
+        assert_complex_matrix_eq!(PauliString::parse("ZY").unwrap().matrix(), array![
+            [z,  -i, z, z],
+            [i,  z,  z, z],
+            [z,  z,  z, i],
+            [z,  z, -i, z]
+        ]);
+
+        let sum = PauliSum::from_terms(1, &[(0.5, "X"), (-1.0, "Z"), (0.25, "I")]).unwrap();
+        assert_complex_matrix_eq!(sum.matrix(), array![
+            [-0.75*o, 0.5*o],
+            [  0.5*o, 1.25*o]
+        ]);
+        assert_eq!(sum.constant(), 0.25);
+    }
+
+    #[test]
+    fn test_add_term()
+    {
+        let mut sum = PauliSum::new(2);
+        assert_eq!(sum.add_term(1.0, PauliString::parse("XZ").unwrap()), Ok(()));
+        assert_eq!(sum.add_term(1.0, PauliString::parse("XZY").unwrap()),
+            Err(Error::InvalidNrBits(3, 2, String::from("XZY"))));
+        assert_eq!(PauliSum::from_terms(2, &[(1.0, "XQ")]),
+            Err(Error::ParseError(ParseError::InvalidPauliString(String::from("XQ")))));
+        assert_eq!(sum.terms().len(), 1);
+    }
+
+    #[test]
+    fn test_measurement_groups()
+    {
+        let sum = PauliSum::from_terms(3, &[
+            (1.0, "ZZI"), (0.5, "XII"), (0.5, "IIZ"), (-1.0, "III"), (0.3, "IXX"), (0.2, "XIX")
+        ]).unwrap();
+        let groups = sum.measurement_groups();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].0.to_string(), "ZZZ");
+        assert_eq!(groups[0].1, vec![0, 2]);
+        assert_eq!(groups[1].0.to_string(), "XXX");
+        assert_eq!(groups[1].1, vec![1, 4, 5]);
+    }
+
+    #[test]
+    fn test_measured_value()
+    {
+        let sum = PauliSum::from_terms(2, &[(1.0, "ZZ"), (0.5, "ZI")]).unwrap();
+        let c_state = array![0b00, 0b01, 0b11, 0b10];
+        // ZZ: +1, -1, +1, -1; ZI (qubit 0 = bit 0): +1, -1, -1, +1
+        assert_eq!(sum.measured_value(&[0], &c_state), 0.0);
+        assert_eq!(sum.measured_value(&[1], &c_state), 0.0);
+        let c_state = array![0b00, 0b00, 0b11, 0b10];
+        assert_eq!(sum.measured_value(&[0, 1], &c_state), (2.0 + 0.5 * 2.0) / 4.0);
+    }
+}
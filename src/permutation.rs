@@ -20,6 +20,7 @@ extern crate num_traits;
 ///
 /// Struct Permutation is used to represents permutations. It can be used to
 /// shuffle the elements in a vector, or rows and columns in a matrix.
+#[derive(Clone, Debug, PartialEq)]
 pub struct Permutation
 {
     /// The permuted indexes.
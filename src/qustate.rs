@@ -12,8 +12,115 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::cmatrix::Amplitude;
 use rand::distributions::Distribution;
 
+/// Probabilities within this distance from 0 or 1 are rounded off. When the
+/// amplitudes are stored in single precision, a larger tolerance of a few
+/// times the machine epsilon is used.
+const PROBABILITY_TOLERANCE: f64 = 1.0e-12;
+
+/// Branches whose coefficients differ by less than this, after correcting for
+/// a global phase, are considered equal, and are merged. When the amplitudes
+/// are stored in single precision, a larger tolerance of a hundred times the
+/// machine epsilon is used.
+const BRANCH_TOLERANCE: f64 = 1.0e-10;
+
+/// Return `tolerance`, or a hundred times the machine epsilon of the amplitudes
+/// in a state, whichever is larger.
+fn amplitude_tolerance(tolerance: f64) -> f64
+{
+    tolerance.max(100.0 * crate::cmatrix::StateNumber::EPSILON)
+}
+
+/// Return the tolerance for rounding off probabilities. Rounding errors in the
+/// amplitudes of a normalized state change the probabilities of the outcomes
+/// by at most a few times the machine epsilon, so unlike the tolerances on the
+/// amplitudes, this tolerance is not larger than that.
+fn probability_tolerance() -> f64
+{
+    PROBABILITY_TOLERANCE.max(4.0 * crate::cmatrix::StateNumber::EPSILON)
+}
+
+/// A sparse state is converted to a dense state, when the number of non-zero
+/// amplitudes in any of its branches exceeds the dimension of the state divided
+/// by this number.
+const SPARSE_FILL_LIMIT: usize = 16;
+
+/// Amplitudes in a sparse state with a norm smaller than this are removed.
+/// When the amplitudes are stored in single precision, a larger tolerance of a
+/// hundred times the machine epsilon is used.
+const SPARSE_TOLERANCE: f64 = 1.0e-12;
+
+/// Return the number of bytes needed to store a single branch of a dense state
+/// of `nr_bits` qubits, or `None` if it does not fit in the address space.
+pub fn dense_state_size(nr_bits: usize) -> Option<usize>
+{
+    1usize.checked_shl(nr_bits as u32)?
+        .checked_mul(::std::mem::size_of::<crate::cmatrix::StateNumber>())
+}
+
+/// Return the estimated number of bytes needed to store a single amplitude in
+/// a sparse state. Since the hash map holding the amplitudes grows by doubling
+/// its capacity, up to half of the allocated space may be unused.
+fn sparse_entry_size() -> usize
+{
+    2 * (::std::mem::size_of::<(u64, crate::cmatrix::StateNumber)>() + 1)
+}
+
+/// Maximum number of rows summed in a single task when computing measurement
+/// probabilities in parallel
+#[cfg(feature = "parallel")]
+const PARALLEL_CHUNK_ROWS: usize = 1 << 12;
+
+/// Sample a binomial distribution.
+///
+/// Sample the number of successes in `n` trials with success probability `p`.
+/// Probabilities very close to 0 or 1 are usually the result of rounding
+/// errors, and are rounded off: the sampler in `rand` becomes extremely slow
+/// for tiny but non-zero probabilities. Rounding also takes care of sums of
+/// squares that add up to slightly more than 1, on which the sampler would
+/// panic. If `p` is not a number, a `SamplingFailed` error is returned.
+fn sample_binomial<R: rand::Rng>(n: usize, p: f64, rng: &mut R) -> crate::error::Result<usize>
+{
+    let tolerance = probability_tolerance();
+    if p.is_nan()
+    {
+        Err(crate::error::Error::SamplingFailed(String::from("probability is not a number")))
+    }
+    else if p < tolerance
+    {
+        Ok(0)
+    }
+    else if p > 1.0 - tolerance
+    {
+        Ok(n)
+    }
+    else
+    {
+        Ok(rng.sample(rand::distributions::Binomial::new(n as u64, p)) as usize)
+    }
+}
+
+/// Create a sampling distribution.
+///
+/// Create a distribution for sampling indices with probabilities proportional
+/// to `weights`. The `WeightedIndex` distribution in `rand` panics on weights
+/// that are not finite, so these are checked first. A `SamplingFailed` error
+/// is returned for invalid weights, or when all weights are zero.
+fn weighted_index<I>(weights: I) -> crate::error::Result<rand::distributions::WeightedIndex<f64>>
+where I: Iterator<Item=f64>
+{
+    let weights: Vec<f64> = weights.collect();
+    if !weights.iter().sum::<f64>().is_finite()
+    {
+        return Err(crate::error::Error::SamplingFailed(
+            String::from("probabilities are not finite")));
+    }
+    rand::distributions::WeightedIndex::new(weights)
+        .map_err(|err| crate::error::Error::SamplingFailed(err.to_string()))
+}
+
 /// Quantum state.
 ///
 /// Struct Qustate represents the quantum experiment. It consists of a series of
@@ -31,25 +138,73 @@ pub struct QuState
     /// Run counts for each quantum state
     counts: Vec<usize>,
     /// The quantum states themselves
-    states: crate::cmatrix::CMatrix,
+    states: crate::cmatrix::StateMatrix,
+    /// The quantum states, when stored as sparse vectors. If set, `states`
+    /// is empty.
+    sparse: Option<Vec<crate::sparse::SparseVector>>,
+    /// Whether to store the states as sparse vectors when possible
+    prefer_sparse: bool,
+    /// The maximum number of bytes used for storing the states, if any
+    memory_budget: Option<usize>
 }
 
 impl QuState
 {
     /// Create a new qustate of `nr_bits` qubits, all initialized to |0⟩, which
-    /// will be measured `nr_shots` times.
-    pub fn new(nr_bits: usize, nr_shots: usize) -> Self
+    /// will be measured `nr_shots` times. If a dense state of `nr_bits` qubits
+    /// does not fit in the address space, a `TooManyQBits` error is returned.
+    pub fn new(nr_bits: usize, nr_shots: usize) -> crate::error::Result<Self>
     {
-        let mut states = crate::cmatrix::CMatrix::zeros((1 << nr_bits, 1));
-        states[(0, 0)] = crate::cmatrix::COMPLEX_ONE;
+        if dense_state_size(nr_bits).is_none()
+        {
+            let max_nr_bits = (0..nr_bits).rev()
+                .find(|&n| dense_state_size(n).is_some())
+                .unwrap_or(0);
+            return Err(crate::error::Error::TooManyQBits(nr_bits, max_nr_bits));
+        }
 
-        QuState
+        let mut states = crate::cmatrix::StateMatrix::zeros((1 << nr_bits, 1));
+        states[(0, 0)] = crate::cmatrix::StateNumber::from_complex(crate::cmatrix::COMPLEX_ONE);
+
+        Ok(QuState
         {
             nr_bits: nr_bits,
             nr_shots: nr_shots,
             counts: vec![nr_shots],
-            states: states
+            states: states,
+            sparse: None,
+            prefer_sparse: false,
+            memory_budget: None
+        })
+    }
+
+    /// Create a new sparse qustate.
+    ///
+    /// Create a new qustate of `nr_bits` qubits, all initialized to |0⟩, which
+    /// will be measured `nr_shots` times. The state is stored as a sparse
+    /// vector, holding only the non-zero amplitudes, for as long as there are
+    /// few of them. When the state fills up, it is automatically converted to
+    /// a dense state. Since the memory used by a sparse state does not depend
+    /// on the number of qubits, this allows for simulating states of up to 64
+    /// qubits, provided they remain sparse. For more qubits, a `TooManyQBits`
+    /// error is returned.
+    pub fn new_sparse(nr_bits: usize, nr_shots: usize) -> crate::error::Result<Self>
+    {
+        if nr_bits > 64
+        {
+            return Err(crate::error::Error::TooManyQBits(nr_bits, 64));
         }
+
+        Ok(QuState
+        {
+            nr_bits: nr_bits,
+            nr_shots: nr_shots,
+            counts: vec![nr_shots],
+            states: crate::cmatrix::StateMatrix::zeros((0, 0)),
+            sparse: Some(vec![crate::sparse::basis_state(0)]),
+            prefer_sparse: true,
+            memory_budget: None
+        })
     }
 
     /// Create a new qustate from qubit coefficients.
@@ -57,11 +212,15 @@ impl QuState
     /// Create a new qustate as a direct product of qubits, where the
     /// coefficients of the |0⟩ and |1⟩ states in the qubits are given in
     /// `bit_coefs`. This array must be of size `2*n`, where `n` is the number
-    /// of qubits in the system. The state will be evaluated in `nr_shots`
-    /// separate runs.
-    pub fn from_qubit_coefs(bit_coefs: &[num_complex::Complex64], nr_shots: usize) -> Self
+    /// of qubits in the system, otherwise an `InvalidNrCoefficients` error is
+    /// returned. The state will be evaluated in `nr_shots` separate runs.
+    pub fn from_qubit_coefs(bit_coefs: &[num_complex::Complex64], nr_shots: usize)
+        -> crate::error::Result<Self>
     {
-        assert!(bit_coefs.len() % 2 == 0, "Length of coefficient array is not even");
+        if bit_coefs.len() % 2 != 0
+        {
+            return Err(crate::error::Error::InvalidNrCoefficients(bit_coefs.len()));
+        }
 
         let nr_bits = bit_coefs.len() / 2;
 
@@ -73,13 +232,16 @@ impl QuState
             states = crate::cmatrix::kron_mat(&states, &bit);
         }
 
-        QuState
+        Ok(QuState
         {
             nr_bits: nr_bits,
             nr_shots: nr_shots,
             counts: vec![nr_shots],
-            states: states
-        }
+            states: states.mapv(crate::cmatrix::StateNumber::from_complex),
+            sparse: None,
+            prefer_sparse: false,
+            memory_budget: None
+        })
     }
 
     /// Return the number of qubits in this state
@@ -88,43 +250,216 @@ impl QuState
         self.nr_bits
     }
 
+    /// Return the number of branches in this state.
+    ///
+    /// Measurements split the state into separate branches, one for each
+    /// (combination of) measurement outcome(s) that occurs. This function
+    /// returns the number of distinct quantum states currently kept.
+    pub fn nr_branches(&self) -> usize
+    {
+        match self.sparse
+        {
+            Some(ref branches) => branches.len(),
+            None => self.states.cols()
+        }
+    }
+
+    /// Return whether the states are currently stored as sparse vectors.
+    pub fn is_sparse(&self) -> bool
+    {
+        self.sparse.is_some()
+    }
+
+    /// Return the memory budget for this state in bytes, or `None` if the
+    /// memory use is not limited.
+    pub fn memory_budget(&self) -> Option<usize>
+    {
+        self.memory_budget
+    }
+
+    /// Set the memory budget.
+    ///
+    /// Limit the memory used for storing the quantum state to `budget` bytes,
+    /// or remove the limit if `budget` is `None`. Operations that would need
+    /// more memory, e.g. because a measurement splits the state into too many
+    /// branches, fail with a `MemoryBudgetExceeded` error before allocating
+    /// it, leaving the state unchanged.
+    pub fn set_memory_budget(&mut self, budget: Option<usize>)
+    {
+        self.memory_budget = budget;
+    }
+
+    /// Return the (estimated) number of bytes currently used for storing the
+    /// quantum state.
+    pub fn memory_usage(&self) -> usize
+    {
+        match self.sparse
+        {
+            Some(ref branches) => {
+                branches.iter().map(|vec| vec.len()).sum::<usize>()
+                    .saturating_mul(sparse_entry_size())
+            },
+            None => self.states.len() * ::std::mem::size_of::<crate::cmatrix::StateNumber>()
+        }
+    }
+
+    /// Return the number of bytes needed to store a single branch of this
+    /// state as a dense vector.
+    fn branch_size(&self) -> usize
+    {
+        dense_state_size(self.nr_bits).unwrap_or(usize::MAX)
+    }
+
+    /// Check if `extra` more bytes can be allocated, on top of the memory
+    /// currently in use, without exceeding the memory budget.
+    fn check_memory(&self, extra: usize) -> crate::error::Result<()>
+    {
+        let required = self.memory_usage().saturating_add(extra);
+        match self.memory_budget
+        {
+            Some(budget) if required > budget => {
+                Err(crate::error::Error::MemoryBudgetExceeded(required, budget))
+            },
+            _ => Ok(())
+        }
+    }
+
+    /// Return the maximum number of non-zero amplitudes in a branch of a
+    /// sparse state, before it is converted to a dense state.
+    fn sparse_fill_limit(&self) -> usize
+    {
+        1usize.checked_shl(self.nr_bits as u32).map_or(usize::MAX, |dim| dim / SPARSE_FILL_LIMIT)
+    }
+
+    /// Convert to a dense state if the sparse state has filled up.
+    ///
+    /// Convert a sparse state to a dense state if the number of non-zero
+    /// amplitudes in any branch exceeds the `1/SPARSE_FILL_LIMIT` part of
+    /// the size of the dense state.
+    fn densify_if_filled(&mut self) -> crate::error::Result<()>
+    {
+        let limit = self.sparse_fill_limit();
+        let filled = match self.sparse
+        {
+            Some(ref branches) => branches.iter().any(|vec| vec.len() > limit),
+            None => false
+        };
+        if filled
+        {
+            self.check_memory(self.nr_branches().saturating_mul(self.branch_size()))?;
+
+            let branches = self.sparse.take().unwrap();
+            self.states = crate::cmatrix::StateMatrix::zeros((1 << self.nr_bits, branches.len()));
+            for (vec, col) in branches.iter().zip(self.states.gencolumns_mut())
+            {
+                crate::sparse::densify_into(vec, col);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Return the number of bytes needed for `nr_branches` new branches
+    /// holding a basis state each.
+    fn basis_states_size(&self, nr_branches: usize) -> usize
+    {
+        if self.prefer_sparse
+        {
+            nr_branches.saturating_mul(sparse_entry_size())
+        }
+        else
+        {
+            nr_branches.saturating_mul(self.branch_size())
+        }
+    }
+
+    /// Set the states to the basis states with the indices in `idxs`, one
+    /// for each branch.
+    fn set_basis_states(&mut self, idxs: &[u64])
+    {
+        if self.prefer_sparse
+        {
+            self.states = crate::cmatrix::StateMatrix::zeros((0, 0));
+            self.sparse = Some(idxs.iter().map(|&idx| crate::sparse::basis_state(idx)).collect());
+        }
+        else
+        {
+            let one = crate::cmatrix::StateNumber::from_complex(crate::cmatrix::COMPLEX_ONE);
+            self.states = crate::cmatrix::StateMatrix::zeros((1 << self.nr_bits, idxs.len()));
+            for (col_idx, &idx) in idxs.iter().enumerate()
+            {
+                self.states[(idx as usize, col_idx)] = one;
+            }
+        }
+    }
+
     /// Apply a n-ary quantum gate `gate` on the qubits from `bits` in this state.
-    pub fn apply_gate<G>(&mut self, gate: &G, bits: &[usize])
+    ///
+    /// An error is returned if the bits do not match the gate, or if the
+    /// memory budget would be exceeded.
+    pub fn apply_gate<G>(&mut self, gate: &G, bits: &[usize]) -> crate::error::Result<()>
     where G: crate::gates::Gate + ?Sized
     {
-        let gate_bits = gate.nr_affected_bits();
-        assert!(gate_bits == bits.len(),
-            "The number of bits affected by the {} gate should be {}, but {} bits were provided.",
-            gate.description(), gate_bits, bits.len()
-        );
+        crate::gates::check_gate_bits(gate, bits, self.nr_bits)?;
+
+        if let Some(ref branches) = self.sparse
+        {
+            // Every amplitude may give rise to an amplitude for every basis
+            // state in the subspace of the affected bits
+            let limit = self.sparse_fill_limit();
+            let nr_entries = branches.iter()
+                .map(|vec| vec.len().checked_shl(bits.len() as u32).unwrap_or(usize::MAX).min(limit))
+                .fold(0usize, |n, len| n.saturating_add(len));
+            self.check_memory(nr_entries.saturating_mul(sparse_entry_size()))?;
+        }
 
-        crate::gates::apply_gate_mat_slice(self.states.view_mut(), gate, bits, self.nr_bits);
+        match self.sparse
+        {
+            Some(ref mut branches) => {
+                let tolerance = amplitude_tolerance(SPARSE_TOLERANCE);
+                for vec in branches.iter_mut()
+                {
+                    crate::sparse::apply_gate(vec, gate, bits, self.nr_bits, tolerance)?;
+                }
+            },
+            None => {
+                crate::cmatrix::StateNumber::apply_gate(self.states.view_mut(), gate, bits,
+                    self.nr_bits)?;
+            }
+        }
+        self.densify_if_filled()
     }
 
-    pub fn apply_unary_gate_all<G>(&mut self, gate: &G)
+    pub fn apply_unary_gate_all<G>(&mut self, gate: &G) -> crate::error::Result<()>
     where G: crate::gates::Gate
     {
         // XXX FIXME: this can be done smarter
         for bit in 0..self.nr_bits
         {
-            self.apply_gate(gate, &[bit]);
+            self.apply_gate(gate, &[bit])?;
         }
+        Ok(())
     }
 
     /// Apply a conditional n-ary quantum gate `gate`, controlled by classical
     /// bit `control`, on the qubits from `bits` in this state.
+    ///
+    /// An error is returned if the number of control values does not match
+    /// the number of runs, if the bits do not match the gate, or if the memory
+    /// budget would be exceeded.
     pub fn apply_conditional_gate<G>(&mut self, control: &[bool], gate: &G,
-        bits: &[usize])
+        bits: &[usize]) -> crate::error::Result<()>
     where G: crate::gates::Gate + ?Sized
     {
-        assert!(control.len() == self.nr_shots
-            "The number of control bits does not match the number of runs");
-
-        let gate_bits = gate.nr_affected_bits();
-        assert!(gate_bits == bits.len(),
-            "The number of bits affected by the {} gate should be {}, but {} bits were provided.",
-            gate.description(), gate_bits, bits.len()
-        );
+        if control.len() != self.nr_shots
+        {
+            return Err(crate::error::Error::InvalidNrRuns(control.len(), self.nr_shots));
+        }
+        crate::gates::check_gate_bits(gate, bits, self.nr_bits)?;
+        if self.nr_shots == 0
+        {
+            return Ok(());
+        }
 
         let mut ranges = vec![];
         let mut off = 0;
@@ -132,9 +467,9 @@ impl QuState
         {
             let mut begin = off;
             let mut prev = control[off];
-            for ibit in off+1..off+count
+            for (ibit, &ctrl) in control.iter().enumerate().take(off+count).skip(off+1)
             {
-                if control[ibit] != prev
+                if ctrl != prev
                 {
                     ranges.push((icol, ibit-begin, prev));
                     begin = ibit;
@@ -149,34 +484,186 @@ impl QuState
             off += count;
         }
 
-        let mut new_states = crate::cmatrix::CMatrix::zeros((1 << self.nr_bits, ranges.len()));
-        for (new_icol, &(icol, _, apply)) in ranges.iter().enumerate()
+        let extra = match self.sparse
+        {
+            Some(ref branches) => {
+                ranges.iter().map(|&(icol, _, apply)| {
+                        let len = branches[icol].len();
+                        if apply { len.checked_shl(bits.len() as u32).unwrap_or(usize::MAX) } else { len }
+                    })
+                    .fold(0usize, |n, len| n.saturating_add(len))
+                    .saturating_mul(sparse_entry_size())
+            },
+            None => ranges.len().saturating_mul(self.branch_size())
+        };
+        self.check_memory(extra)?;
+
+        self.counts = ranges.iter().map(|t| t.1).collect();
+
+        if let Some(ref mut branches) = self.sparse
+        {
+            let tolerance = amplitude_tolerance(SPARSE_TOLERANCE);
+            let mut new_branches = vec![];
+            for &(icol, _, apply) in ranges.iter()
+            {
+                let mut vec = branches[icol].clone();
+                if apply
+                {
+                    crate::sparse::apply_gate(&mut vec, gate, bits, self.nr_bits, tolerance)?;
+                }
+                new_branches.push(vec);
+            }
+            *branches = new_branches;
+
+            self.densify_if_filled()?;
+            self.merge_branches();
+            return Ok(());
+        }
+
+        let mut new_states = crate::cmatrix::StateMatrix::zeros((1 << self.nr_bits, ranges.len()));
+        for (new_icol, &(icol, _, _)) in ranges.iter().enumerate()
         {
             new_states.column_mut(new_icol).assign(&self.states.column(icol));
-            if apply
+        }
+
+        let columns = new_states.gencolumns_mut().into_iter().zip(ranges.iter())
+            .filter(|(_, &(_, _, apply))| apply)
+            .map(|(column, _)| column);
+        #[cfg(not(feature = "parallel"))]
+        {
+            for column in columns
             {
-                crate::gates::apply_gate_slice(new_states.column_mut(new_icol),
-                    gate, bits, self.nr_bits);
+                crate::cmatrix::StateNumber::apply_gate(column.insert_axis(ndarray::Axis(1)), gate,
+                    bits, self.nr_bits)?;
             }
         }
+        #[cfg(feature = "parallel")]
+        {
+            use rayon::prelude::*;
+
+            let nr_bits = self.nr_bits;
+            columns.collect::<Vec<_>>().into_par_iter()
+                .try_for_each(|column| {
+                    crate::cmatrix::StateNumber::apply_gate(column.insert_axis(ndarray::Axis(1)),
+                        gate, bits, nr_bits)
+                })?;
+        }
 
         self.states = new_states;
-        self.counts = ranges.iter().map(|t| t.1).collect();
+        self.merge_branches();
+        Ok(())
+    }
+
+    /// Compute the probabilities of measuring 0.
+    ///
+    /// Compute, for each quantum state in this experiment, the probability
+    /// of measuring 0 for a qubit, where the components for which the qubit
+    /// is 0 are in `nr_blocks` blocks of `block_size` rows, each followed
+    /// by a block of the same size for the qubit being 1.
+    #[cfg(not(feature = "parallel"))]
+    fn zero_probabilities(&self, block_size: usize, nr_blocks: usize) -> ndarray::Array1<f64>
+    {
+        let mut w0s = ndarray::Array1::zeros(self.states.cols());
+        let mut off = 0;
+        for _ in 0..nr_blocks
+        {
+            w0s += &self.states.slice(s![off..off+block_size, ..])
+                .mapv(|c| c.to_complex().norm_sqr())
+                .sum_axis(ndarray::Axis(0));
+            off += 2 * block_size;
+        }
+        w0s
+    }
+
+    /// Compute the probabilities of measuring 0.
+    ///
+    /// Compute, for each quantum state in this experiment, the probability
+    /// of measuring 0 for a qubit, where the components for which the qubit
+    /// is 0 are in `nr_blocks` blocks of `block_size` rows, each followed
+    /// by a block of the same size for the qubit being 1. The blocks are
+    /// divided into chunks, which are summed in parallel.
+    #[cfg(feature = "parallel")]
+    fn zero_probabilities(&self, block_size: usize, nr_blocks: usize) -> ndarray::Array1<f64>
+    {
+        use rayon::prelude::*;
+
+        let chunk_size = block_size.min(PARALLEL_CHUNK_ROWS);
+        let nr_chunks = nr_blocks * block_size / chunk_size;
+        let states = &self.states;
+        (0..nr_chunks).into_par_iter()
+            .map(|i| {
+                let pos = i * chunk_size;
+                let off = 2 * (pos - pos % block_size) + pos % block_size;
+                states.slice(s![off..off+chunk_size, ..])
+                    .mapv(|c| c.to_complex().norm_sqr())
+                    .sum_axis(ndarray::Axis(0))
+            })
+            .reduce(|| ndarray::Array1::zeros(states.cols()), |w0s, w| w0s + w)
+    }
+
+    /// Compute the probabilities of measuring 0.
+    ///
+    /// Compute, for each quantum state in this experiment, the probability
+    /// of measuring 0 for qubit `qbit`.
+    fn qubit_zero_probabilities(&self, qbit: usize) -> ndarray::Array1<f64>
+    {
+        match self.sparse
+        {
+            Some(ref branches) => {
+                branches.iter()
+                    .map(|vec| crate::sparse::zero_probability(vec, qbit, self.nr_bits))
+                    .collect()
+            },
+            None => {
+                let block_size = 1 << (self.nr_bits - qbit - 1);
+                let nr_blocks = 1 << qbit;
+                self.zero_probabilities(block_size, nr_blocks)
+            }
+        }
     }
 
-    fn collapse(mut coefs: crate::cmatrix::CVecSliceMut, block_size: usize, nr_blocks: usize,
+    fn collapse(mut coefs: ndarray::ArrayViewMut1<crate::cmatrix::StateNumber>, block_size: usize,
+        nr_blocks: usize,
         offset: usize, norm_sq: f64)
     {
         // Set coefficients for other bit to zero
         let mut off = offset;
         for _ in 0..nr_blocks
         {
-            coefs.slice_mut(s![off..off+block_size]).fill(crate::cmatrix::COMPLEX_ZERO);
+            coefs.slice_mut(s![off..off+block_size]).fill(
+                crate::cmatrix::StateNumber::from_complex(crate::cmatrix::COMPLEX_ZERO));
             off += 2 * block_size;
         }
 
         // Renormalize
-        coefs *= num_complex::Complex::new(1.0 / norm_sq.sqrt(), 0.0);
+        coefs *= crate::cmatrix::StateNumber::from_complex(
+            num_complex::Complex::new(1.0 / norm_sq.sqrt(), 0.0));
+    }
+
+    /// Check the arguments of a measurement.
+    ///
+    /// Check that quantum bit `qbit` exists in this state, that classical bit
+    /// `cbit` fits in a result value, and that `res` can hold the results for
+    /// all runs.
+    fn check_measurement(&self, qbit: usize, cbit: usize, res: &ndarray::Array1<u64>)
+        -> crate::error::Result<()>
+    {
+        if qbit >= self.nr_bits
+        {
+            Err(crate::error::Error::InvalidQBit(qbit))
+        }
+        else if cbit >= 64
+        {
+            Err(crate::error::Error::InvalidCBit(cbit))
+        }
+        else if res.len() < self.nr_shots
+        {
+            Err(crate::error::Error::InvalidNrRuns(res.len(), self.nr_shots))
+        }
+        else
+        {
+            Ok(())
+        }
     }
 
     /// Measure a qubit.
@@ -185,11 +672,12 @@ impl QuState
     /// in the `z`-basis. The random number generator `rng` is used for sampling.
     /// The result is returned as an array containing the measurement result for
     /// each run.
-    pub fn measure<R: rand::Rng>(&mut self, qbit: usize, rng: &mut R) -> ndarray::Array1<u64>
+    pub fn measure<R: rand::Rng>(&mut self, qbit: usize, rng: &mut R)
+        -> crate::error::Result<ndarray::Array1<u64>>
     {
         let mut res = ndarray::Array1::zeros(self.nr_shots);
-        self.measure_into(qbit, 0, &mut res, rng);
-        res
+        self.measure_into(qbit, 0, &mut res, rng)?;
+        Ok(res)
     }
 
     /// Measure a qubit.
@@ -199,47 +687,48 @@ impl QuState
     /// results for the total number of runs in the state. Measurement is done
     /// in the `z`-basis. The random number generator `rng` is used for sampling.
     pub fn measure_into<R: rand::Rng>(&mut self, qbit: usize, cbit: usize,
-        res: &mut ndarray::Array1<u64>, rng: &mut R)
+        res: &mut ndarray::Array1<u64>, rng: &mut R) -> crate::error::Result<()>
     {
-        assert!(qbit < self.nr_bits, "Invalid bit index");
-        assert!(res.len() >= self.nr_shots, "Not enough space to store the results");
-
-        let block_size = 1 << (self.nr_bits - qbit - 1);
-        let nr_blocks = 1 << qbit;
+        self.check_measurement(qbit, cbit, res)?;
 
         // Compute chances of measuring 0
-        let mut w0s = ndarray::Array1::zeros(self.states.cols());
-        let mut off = 0;
-        for _ in 0..nr_blocks
-        {
-            w0s += &self.states.slice(s![off..off+block_size, ..])
-                .mapv(|c| c.norm_sqr())
-                .sum_axis(ndarray::Axis(0));
-            off += 2 * block_size;
-        }
+        let w0s = self.qubit_zero_probabilities(qbit);
 
         // Compute how many times we measure 0
         let mut n0s = vec![];
         let mut new_nr_states = 0;
-        for (&w0, &c) in w0s.iter().zip(self.counts.iter())
+        let mut nr_new_entries = 0usize;
+        for (idx, (&w0, &c)) in w0s.iter().zip(self.counts.iter()).enumerate()
         {
-            // Sometimes, the sum may add up to slightly more than 1, due to
-            // numerical inaccuracies. This causes the Binomial distribution to
-            // panic, so cap w0.
-            let distribution = rand::distributions::Binomial::new(c as u64, w0.min(1.0));
-            let n0 = rng.sample(distribution) as usize;
+            let n0 = sample_binomial(c, w0, rng)?;
             n0s.push(n0);
-            new_nr_states += if n0 == 0 || n0 == c { 1 } else { 2 };
+            let nr_split = if n0 == 0 || n0 == c { 1 } else { 2 };
+            new_nr_states += nr_split;
+            if let Some(ref branches) = self.sparse
+            {
+                nr_new_entries = nr_new_entries.saturating_add(nr_split * branches[idx].len());
+            }
         }
 
+        let extra = if self.is_sparse()
+            {
+                nr_new_entries.saturating_mul(sparse_entry_size())
+            }
+            else
+            {
+                new_nr_states.saturating_mul(self.branch_size())
+            };
+        self.check_memory(extra)?;
+
         let one_mask = 1 << cbit;
         let zero_mask = !one_mask;
 
-        let mut new_states = crate::cmatrix::CMatrix::zeros((1 << self.nr_bits, new_nr_states));
-        let mut new_counts = vec![];
-        let mut new_idx = 0;
+        // The new branches, described by the index of the branch they originate
+        // from, the measured value, the probability of measuring that value,
+        // and the number of runs.
+        let mut new_branches = Vec::with_capacity(new_nr_states);
         let mut res_start = 0;
-        for idx in 0..self.states.cols()
+        for idx in 0..self.nr_branches()
         {
             let w0 = w0s[[idx]];
             let n0 = n0s[idx];
@@ -254,34 +743,48 @@ impl QuState
             );
             res_start += count;
 
-            // Collapse the wave function
-            new_states.column_mut(new_idx).assign(&self.states.column(idx));
-            if n0 == count
+            if n0 > 0
             {
-                Self::collapse(new_states.column_mut(new_idx), block_size, nr_blocks, block_size, w0);
-                new_counts.push(count);
+                new_branches.push((idx, false, w0, n0));
             }
-            else if n0 == 0
+            if n0 < count
             {
-                Self::collapse(new_states.column_mut(new_idx), block_size, nr_blocks, 0, 1.0 - w0);
-                new_counts.push(count);
+                new_branches.push((idx, true, 1.0 - w0, count - n0));
             }
-            else
-            {
-                Self::collapse(new_states.column_mut(new_idx), block_size, nr_blocks, block_size, w0);
-                new_counts.push(n0);
-                new_idx += 1;
+        }
 
-                new_states.column_mut(new_idx).assign(&self.states.column(idx));
-                Self::collapse(new_states.column_mut(new_idx), block_size, nr_blocks, 0, 1.0 - w0);
-                new_counts.push(count - n0);
+        // Collapse the wave function
+        match self.sparse
+        {
+            Some(ref mut branches) => {
+                let mut new_sparse = Vec::with_capacity(new_branches.len());
+                for &(idx, value, norm_sq, _) in new_branches.iter()
+                {
+                    let mut vec = branches[idx].clone();
+                    crate::sparse::collapse(&mut vec, qbit, self.nr_bits, value, norm_sq);
+                    new_sparse.push(vec);
+                }
+                *branches = new_sparse;
+            },
+            None => {
+                let block_size = 1 << (self.nr_bits - qbit - 1);
+                let nr_blocks = 1 << qbit;
+                let mut new_states = crate::cmatrix::StateMatrix::zeros(
+                    (1 << self.nr_bits, new_branches.len()));
+                for (new_idx, &(idx, value, norm_sq, _)) in new_branches.iter().enumerate()
+                {
+                    new_states.column_mut(new_idx).assign(&self.states.column(idx));
+                    let offset = if value { 0 } else { block_size };
+                    Self::collapse(new_states.column_mut(new_idx), block_size, nr_blocks, offset,
+                        norm_sq);
+                }
+                self.states = new_states;
             }
-
-            new_idx += 1;
         }
 
-        self.counts = new_counts;
-        self.states = new_states;
+        self.counts = new_branches.iter().map(|t| t.3).collect();
+        self.merge_branches();
+        Ok(())
     }
 
     /// Measure a qubit.
@@ -294,24 +797,12 @@ impl QuState
     /// NOTE: this is not a physical process, and impossible to reproduce on
     /// a real quantum computer.
     pub fn peek_into<R: rand::Rng>(&mut self, qbit: usize, cbit: usize,
-        res: &mut ndarray::Array1<u64>, rng: &mut R)
+        res: &mut ndarray::Array1<u64>, rng: &mut R) -> crate::error::Result<()>
     {
-        assert!(qbit < self.nr_bits, "Invalid bit index");
-        assert!(res.len() >= self.nr_shots, "Not enough space to store the results");
-
-        let block_size = 1 << (self.nr_bits - qbit - 1);
-        let nr_blocks = 1 << qbit;
+        self.check_measurement(qbit, cbit, res)?;
 
         // Compute chances of measuring 0
-        let mut w0s = ndarray::Array1::zeros(self.states.cols());
-        let mut off = 0;
-        for _ in 0..nr_blocks
-        {
-            w0s += &self.states.slice(s![off..off+block_size, ..])
-                .mapv(|c| c.norm_sqr())
-                .sum_axis(ndarray::Axis(0));
-            off += 2 * block_size;
-        }
+        let w0s = self.qubit_zero_probabilities(qbit);
 
         let one_mask = 1 << cbit;
         let zero_mask = !one_mask;
@@ -320,8 +811,7 @@ impl QuState
         for (&w0, &c) in w0s.iter().zip(self.counts.iter())
         {
             // Compute how many times we measure 0
-            let distribution = rand::distributions::Binomial::new(c as u64, w0.min(1.0));
-            let n0 = rng.sample(distribution) as usize;
+            let n0 = sample_binomial(c, w0, rng)?;
 
             // Store the result.
             res.slice_mut(s![res_start..res_start+n0]).map_inplace(
@@ -332,41 +822,76 @@ impl QuState
             );
             res_start += c;
         }
+
+        Ok(())
     }
 
     /// Measure all qubits
     ///
     /// Measure all qubits in this state, and return the results. The random
     /// number generator `rng` is used for sampling.
-    pub fn measure_all<R: rand::Rng>(&mut self, rng: &mut R) -> ndarray::Array1<u64>
+    pub fn measure_all<R: rand::Rng>(&mut self, rng: &mut R)
+        -> crate::error::Result<ndarray::Array1<u64>>
     {
         let mut res = ndarray::Array1::zeros(self.nr_shots);
         let cbits: Vec<usize> = (0..self.nr_bits).collect();
-        self.measure_all_into(&cbits, &mut res, rng);
-        res
+        self.measure_all_into(&cbits, &mut res, rng)?;
+        Ok(res)
     }
 
     fn measure_all_into_helper<R: rand::Rng>(&mut self, cbits: &[usize],
-        res: &mut ndarray::Array1<u64>, collapse: bool, rng: &mut R)
+        res: &mut ndarray::Array1<u64>, collapse: bool, rng: &mut R) -> crate::error::Result<()>
     {
-        assert!(res.len() >= self.nr_shots, "Not enough space to store the results");
-        assert!(cbits.len() == self.nr_bits,
-            "Number of measurement bits does not match number of quantum bits");
+        if cbits.len() != self.nr_bits
+        {
+            return Err(crate::error::Error::InvalidNrBits(cbits.len(), self.nr_bits,
+                String::from("measure all")));
+        }
+        if let Some(&cbit) = cbits.iter().find(|&&cbit| cbit >= 64)
+        {
+            return Err(crate::error::Error::InvalidCBit(cbit));
+        }
+        if res.len() < self.nr_shots
+        {
+            return Err(crate::error::Error::InvalidNrRuns(res.len(), self.nr_shots));
+        }
 
         let mut state_counts = vec![];
-        for col_idx in 0..self.states.cols()
+        for col_idx in 0..self.nr_branches()
         {
-            let mut count_map = crate::idhash::new_usize_hash_map();
-            let distr = rand::distributions::WeightedIndex::new(
-                self.states.column(col_idx).iter().map(|c| c.norm_sqr())
-            ).unwrap();
-            for idx in distr.sample_iter(rng).take(self.counts[col_idx])
+            match self.sparse
             {
-                let entry = count_map.entry(idx).or_insert(0);
-                *entry += 1;
+                Some(ref branches) => {
+                    let mut count_map = crate::idhash::new_u64_hash_map();
+                    let coefs: Vec<(u64, f64)> = branches[col_idx].iter()
+                        .map(|(&idx, c)| (idx, c.to_complex().norm_sqr()))
+                        .collect();
+                    let distr = weighted_index(coefs.iter().map(|t| t.1))?;
+                    for i in distr.sample_iter(rng).take(self.counts[col_idx])
+                    {
+                        let entry = count_map.entry(coefs[i].0).or_insert(0);
+                        *entry += 1;
+                    }
+                    state_counts.extend(count_map);
+                },
+                None => {
+                    let mut count_map = crate::idhash::new_usize_hash_map();
+                    let distr = weighted_index(
+                        self.states.column(col_idx).iter().map(|c| c.to_complex().norm_sqr())
+                    )?;
+                    for idx in distr.sample_iter(rng).take(self.counts[col_idx])
+                    {
+                        let entry = count_map.entry(idx).or_insert(0);
+                        *entry += 1;
+                    }
+                    state_counts.extend(count_map.into_iter().map(|(idx, n)| (idx as u64, n)));
+                }
             }
+        }
 
-            state_counts.extend(count_map.into_iter());
+        if collapse
+        {
+            self.check_memory(self.basis_states_size(state_counts.len()))?;
         }
 
         let mask = !cbits.iter().fold(0u64, |m, b| m | (1u64 << b));
@@ -375,7 +900,7 @@ impl QuState
         {
             // For each unique measurement, store n copies of it in the result,
             // where n is the number of times it was measured.
-            let rev_idx = crate::support::reverse_bits(idx as u64, self.nr_bits);
+            let rev_idx = crate::support::reverse_bits(idx, self.nr_bits);
             let perm_idx = crate::support::shuffle_bits(rev_idx, cbits);
             res.slice_mut(s![res_off..res_off+count]).map_inplace(
                 |bits| *bits = (*bits & mask) | perm_idx
@@ -386,14 +911,13 @@ impl QuState
 
         if collapse
         {
-            self.states = crate::cmatrix::CMatrix::zeros((1 << self.nr_bits, state_counts.len()));
-            for (col_idx, &(idx, _)) in state_counts.iter().enumerate()
-            {
-                self.states[(idx, col_idx)] = crate::cmatrix::COMPLEX_ONE;
-            }
+            let idxs: Vec<u64> = state_counts.iter().map(|t| t.0).collect();
+            self.set_basis_states(&idxs);
             self.counts = state_counts.iter().map(|t| t.1).collect();
-
+            self.merge_branches();
         }
+
+        Ok(())
     }
     /// Measure all qubits
     ///
@@ -403,9 +927,9 @@ impl QuState
     /// position indicated by the first element of `cbits`, and so on. The random
     /// number generator `rng` is used for sampling.
     pub fn measure_all_into<R: rand::Rng>(&mut self, cbits: &[usize],
-        res: &mut ndarray::Array1<u64>, rng: &mut R)
+        res: &mut ndarray::Array1<u64>, rng: &mut R) -> crate::error::Result<()>
     {
-        self.measure_all_into_helper(cbits, res, true, rng);
+        self.measure_all_into_helper(cbits, res, true, rng)
     }
 
     /// Measure all qubits
@@ -419,9 +943,96 @@ impl QuState
     /// NOTE: this is not a physical process, and impossible to reproduce on
     /// a real quantum computer.
     pub fn peek_all_into<R: rand::Rng>(&mut self, cbits: &[usize],
-        res: &mut ndarray::Array1<u64>, rng: &mut R)
+        res: &mut ndarray::Array1<u64>, rng: &mut R) -> crate::error::Result<()>
+    {
+        self.measure_all_into_helper(cbits, res, false, rng)
+    }
+
+    /// Check if two branches are equal.
+    ///
+    /// Check if the quantum states `a` and `b` are equal up to a global phase,
+    /// within tolerance `BRANCH_TOLERANCE`.
+    fn branches_equal(a: ndarray::ArrayView1<crate::cmatrix::StateNumber>,
+        b: ndarray::ArrayView1<crate::cmatrix::StateNumber>) -> bool
     {
-        self.measure_all_into_helper(cbits, res, false, rng);
+        let tolerance = amplitude_tolerance(BRANCH_TOLERANCE);
+
+        // Determine the phase difference from the largest coefficient
+        let (imax, _) = a.iter().enumerate().fold((0, 0.0), |(imax, max), (i, c)| {
+                let norm_sq = c.to_complex().norm_sqr();
+                if norm_sq > max { (i, norm_sq) } else { (imax, max) }
+            });
+        let phase = b[imax].to_complex() * a[imax].to_complex().conj();
+        if phase.norm() < tolerance
+        {
+            return false;
+        }
+        let phase = phase / phase.norm();
+
+        a.iter().zip(b.iter())
+            .all(|(&ca, &cb)| (ca.to_complex() * phase - cb.to_complex()).norm() < tolerance)
+    }
+
+    /// Merge identical branches.
+    ///
+    /// Measurements split the state into separate branches, one for each
+    /// outcome that occurs. After e.g. a reset of the measured qubit, these
+    /// branches may become equal again. This function merges consecutive
+    /// branches whose quantum states are equal up to a global phase, within
+    /// a small tolerance, by adding up their run counts. Since only
+    /// consecutive branches are merged, the order of the runs is unaffected.
+    /// This is done automatically after operations that split the state.
+    pub fn merge_branches(&mut self)
+    {
+        if self.counts.is_empty()
+        {
+            // No runs, hence no branches to merge
+            return;
+        }
+
+        let nr_cols = self.nr_branches();
+        let tolerance = amplitude_tolerance(BRANCH_TOLERANCE);
+        let mut keep = vec![0];
+        let mut new_counts = vec![self.counts[0]];
+        for icol in 1..nr_cols
+        {
+            let last = keep[keep.len() - 1];
+            let equal = match self.sparse
+            {
+                Some(ref branches) => {
+                    crate::sparse::equal_up_to_phase(&branches[last], &branches[icol], tolerance)
+                },
+                None => Self::branches_equal(self.states.column(last), self.states.column(icol))
+            };
+            if equal
+            {
+                let nr_counts = new_counts.len();
+                new_counts[nr_counts - 1] += self.counts[icol];
+            }
+            else
+            {
+                keep.push(icol);
+                new_counts.push(self.counts[icol]);
+            }
+        }
+
+        if keep.len() < nr_cols
+        {
+            match self.sparse
+            {
+                Some(ref mut branches) => {
+                    let old_branches = ::std::mem::take(branches);
+                    *branches = old_branches.into_iter().enumerate()
+                        .filter(|(icol, _)| keep.binary_search(icol).is_ok())
+                        .map(|(_, vec)| vec)
+                        .collect();
+                },
+                None => {
+                    self.states = self.states.select(ndarray::Axis(1), &keep);
+                }
+            }
+            self.counts = new_counts;
+        }
     }
 
     /// Reset a qubit
@@ -429,11 +1040,11 @@ impl QuState
     /// Reset the qubit with index `bit` to zero. This is done by measuring the
     /// bit, and rotating it back to zero if the result is 1. The random
     /// number generator `rng` is used for sampling in the measurement.
-    pub fn reset<R: rand::Rng>(&mut self, bit: usize, rng: &mut R)
+    pub fn reset<R: rand::Rng>(&mut self, bit: usize, rng: &mut R) -> crate::error::Result<()>
     {
-        let measurement = self.measure(bit, rng);
+        let measurement = self.measure(bit, rng)?;
         let control: Vec<bool> = measurement.iter().map(|&b| b != 0).collect();
-        self.apply_conditional_gate(&control, &crate::gates::X::new(), &[bit]);
+        self.apply_conditional_gate(&control, &crate::gates::X::new(), &[bit])
     }
 
     /// Reset all qubits
@@ -442,35 +1053,47 @@ impl QuState
     /// for all runs.
     pub fn reset_all(&mut self)
     {
-        self.states = crate::cmatrix::CMatrix::zeros((1 << self.nr_bits, 1));
-        self.states[[0, 0]] = crate::cmatrix::COMPLEX_ONE;
+        self.set_basis_states(&[0]);
         self.counts = vec![self.nr_shots];
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::needless_range_loop)]
 mod tests
 {
     use super::QuState;
+    use crate::cmatrix::Amplitude;
+    use crate::error::Error;
     use crate::gates::{CCX, CX, H, Kron, X, Y};
 
+    /// Tolerance on the amplitudes in a state, relative to the machine
+    /// epsilon of the type in which they are stored
+    const TOLERANCE: f64 = 4.0 * <crate::cmatrix::StateNumber as Amplitude>::EPSILON;
+
+    /// The amplitudes of the branches in state `s`, in double precision.
+    fn amplitudes(s: &QuState) -> crate::cmatrix::CMatrix
+    {
+        s.states.mapv(|c| c.to_complex())
+    }
+
     #[test]
     fn test_new()
     {
         let z = crate::cmatrix::COMPLEX_ZERO;
         let o = crate::cmatrix::COMPLEX_ONE;
 
-        let s = QuState::new(1, 1024);
+        let s = QuState::new(1, 1024).unwrap();
         assert_eq!(s.nr_bits, 1);
         assert_eq!(s.nr_shots, 1024);
         assert_eq!(s.counts, vec![1024]);
-        assert_complex_matrix_eq!(&s.states, &array![[o], [z]]);
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[o], [z]], TOLERANCE);
 
-        let s = QuState::new(3, 1500);
+        let s = QuState::new(3, 1500).unwrap();
         assert_eq!(s.nr_bits, 3);
         assert_eq!(s.nr_shots, 1500);
         assert_eq!(s.counts, vec![1500]);
-        assert_complex_matrix_eq!(&s.states, &array![[o], [z], [z], [z], [z], [z], [z], [z]]);
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[o], [z], [z], [z], [z], [z], [z], [z]], TOLERANCE);
     }
 
     #[test]
@@ -481,24 +1104,24 @@ mod tests
         let i = crate::cmatrix::COMPLEX_I;
 
         // |0⟩⊗|1⟩
-        let s = QuState::from_qubit_coefs(&[o, z, z, o], 1);
+        let s = QuState::from_qubit_coefs(&[o, z, z, o], 1).unwrap();
         assert_eq!(s.nr_bits, 2);
         assert_eq!(s.nr_shots, 1);
         assert_eq!(s.counts, vec![1]);
-        assert_complex_matrix_eq!(&s.states, &array![[z], [o], [z], [z]]);
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[z], [o], [z], [z]], TOLERANCE);
         // |1⟩⊗|0⟩
-        let s = QuState::from_qubit_coefs(&[z, o, o, z], 13);
+        let s = QuState::from_qubit_coefs(&[z, o, o, z], 13).unwrap();
         assert_eq!(s.nr_bits, 2);
         assert_eq!(s.nr_shots, 13);
         assert_eq!(s.counts, vec![13]);
-        assert_complex_matrix_eq!(&s.states, &array![[z], [z], [o], [z]]);
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[z], [z], [o], [z]], TOLERANCE);
         // (H|0⟩)⊗(Y|1⟩), unnormalized
-        let s = QuState::from_qubit_coefs(&[o, o, -i, z], 9);
+        let s = QuState::from_qubit_coefs(&[o, o, -i, z], 9).unwrap();
         let x = ::std::f64::consts::FRAC_1_SQRT_2 * i;
         assert_eq!(s.nr_bits, 2);
         assert_eq!(s.nr_shots, 9);
         assert_eq!(s.counts, vec![9]);
-        assert_complex_matrix_eq!(&s.states, &array![[-x], [z], [-x], [z]]);
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[-x], [z], [-x], [z]], TOLERANCE);
     }
 
     #[test]
@@ -508,36 +1131,36 @@ mod tests
         let o = crate::cmatrix::COMPLEX_ONE;
         let x = crate::cmatrix::COMPLEX_HSQRT2;
 
-        let mut s = QuState::new(2, 5);
-        s.apply_conditional_gate(&[false, false, true, true, false], &X::new(), &[1]);
+        let mut s = QuState::new(2, 5).unwrap();
+        s.apply_conditional_gate(&[false, false, true, true, false], &X::new(), &[1]).unwrap();
         assert_eq!(s.counts, vec![2, 2, 1]);
-        assert_complex_matrix_eq!(&s.states,
-            &array![[o, z, o], [z, o, z], [z, z, z], [z, z, z]]);
+        assert_complex_matrix_eq!(&amplitudes(&s),
+            &array![[o, z, o], [z, o, z], [z, z, z], [z, z, z]], TOLERANCE);
 
-        let mut s = QuState::new(2, 5);
-        s.apply_conditional_gate(&[false, false, true, true, true], &X::new(), &[0]);
+        let mut s = QuState::new(2, 5).unwrap();
+        s.apply_conditional_gate(&[false, false, true, true, true], &X::new(), &[0]).unwrap();
         assert_eq!(s.counts, vec![2, 3]);
-        assert_complex_matrix_eq!(&s.states, &array![[o, z], [z, z], [z, o], [z, z]]);
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[o, z], [z, z], [z, o], [z, z]], TOLERANCE);
 
-        let mut s = QuState::new(2, 5);
-        s.apply_conditional_gate(&[true, false, true, true, false], &H::new(), &[1]);
+        let mut s = QuState::new(2, 5).unwrap();
+        s.apply_conditional_gate(&[true, false, true, true, false], &H::new(), &[1]).unwrap();
         assert_eq!(s.counts, vec![1, 1, 2, 1]);
-        assert_complex_matrix_eq!(&s.states,
-            &array![[x, o, x, o], [x, z, x, z], [z, z, z, z], [z, z, z, z]]);
+        assert_complex_matrix_eq!(&amplitudes(&s),
+            &array![[x, o, x, o], [x, z, x, z], [z, z, z, z], [z, z, z, z]], TOLERANCE);
 
-        let mut s = QuState::from_qubit_coefs(&[o, z, x, x], 5);
-        s.apply_conditional_gate(&[true, false, true, true, false], &CX::new(), &[1, 0]);
+        let mut s = QuState::from_qubit_coefs(&[o, z, x, x], 5).unwrap();
+        s.apply_conditional_gate(&[true, false, true, true, false], &CX::new(), &[1, 0]).unwrap();
         assert_eq!(s.counts, vec![1, 1, 2, 1]);
-        assert_complex_matrix_eq!(&s.states,
-            &array![[x, x, x, x], [z, x, z, x], [z, z, z, z], [x, z, x, z]]);
+        assert_complex_matrix_eq!(&amplitudes(&s),
+            &array![[x, x, x, x], [z, x, z, x], [z, z, z, z], [x, z, x, z]], TOLERANCE);
 
-        let mut s = QuState::new(2, 5);
-        s.apply_conditional_gate(&[true, true, true, false, false], &H::new(), &[0]);
+        let mut s = QuState::new(2, 5).unwrap();
+        s.apply_conditional_gate(&[true, true, true, false, false], &H::new(), &[0]).unwrap();
         assert_eq!(s.counts, vec![3, 2]);
-        assert_complex_matrix_eq!(&s.states, &array![[x, o], [z, z], [x, z], [z, z]]);
-        s.apply_conditional_gate(&[false, false, true, true, true], &H::new(), &[0]);
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[x, o], [z, z], [x, z], [z, z]], TOLERANCE);
+        s.apply_conditional_gate(&[false, false, true, true, true], &H::new(), &[0]).unwrap();
         assert_eq!(s.counts, vec![2, 1, 2]);
-        assert_complex_matrix_eq!(&s.states, &array![[x, o, x], [z, z, z], [x, z, x], [z, z, z]]);
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[x, o, x], [z, z, z], [x, z, x], [z, z, z]], TOLERANCE);
     }
 
     #[test]
@@ -550,23 +1173,23 @@ mod tests
         let mut rng = rand::thread_rng();
 
         // |0⟩
-        let mut s = QuState::new(1, 3);
-        let m = s.measure(0, &mut rng);
+        let mut s = QuState::new(1, 3).unwrap();
+        let m = s.measure(0, &mut rng).unwrap();
         assert_eq!(m, array![0, 0, 0]);
-        assert_complex_matrix_eq!(&s.states, &array![[o], [z]]);
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[o], [z]], TOLERANCE);
 
         // |0⟩⊗|0⟩
-        let mut s = QuState::from_qubit_coefs(&[o, z, o, z], 3);
-        let m = s.measure(1, &mut rng);
+        let mut s = QuState::from_qubit_coefs(&[o, z, o, z], 3).unwrap();
+        let m = s.measure(1, &mut rng).unwrap();
         assert_eq!(m, ndarray::Array1::zeros(3));
-        assert_complex_matrix_eq!(&s.states, &array![[o], [z], [z], [z]]);
-        let m = s.measure(0, &mut rng);
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[o], [z], [z], [z]], TOLERANCE);
+        let m = s.measure(0, &mut rng).unwrap();
         assert_eq!(m, ndarray::Array1::zeros(3));
-        assert_complex_matrix_eq!(&s.states, &array![[o], [z], [z], [z]]);
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[o], [z], [z], [z]], TOLERANCE);
 
         // (H|0⟩)⊗(H|0⟩), unnormalized
-        let mut s = QuState::from_qubit_coefs(&[o, o, o, o], 1024);
-        let m0 = s.measure(0, &mut rng);
+        let mut s = QuState::from_qubit_coefs(&[o, o, o, o], 1024).unwrap();
+        let m0 = s.measure(0, &mut rng).unwrap();
         let mut prev_b = m0[0];
         let mut sc_idx = 0;
         for &b in m0.iter()
@@ -578,10 +1201,10 @@ mod tests
             }
             match b
             {
-                0 => assert_complex_vector_eq!(&s.states.column(sc_idx),
-                    &array![x, x, z, z]),
-                1 => assert_complex_vector_eq!(&s.states.column(sc_idx),
-                    &array![z, z, x, x]),
+                0 => assert_complex_vector_eq!(&amplitudes(&s).column(sc_idx),
+                    &array![x, x, z, z], TOLERANCE),
+                1 => assert_complex_vector_eq!(&amplitudes(&s).column(sc_idx),
+                    &array![z, z, x, x], TOLERANCE),
                 // LCOV_EXCL_START
                 _ => panic!("Invalid value {} for bit", b)
                 // LCOV_EXCL_STOP
@@ -589,14 +1212,15 @@ mod tests
         }
 
         // After collapse, a new measurement should yield the same result
-        let m0b = s.measure(0, &mut rng);
+        let m0b = s.measure(0, &mut rng).unwrap();
         assert_eq!(m0b, m0);
 
         // Measure second bit
-        let m1 = s.measure(1, &mut rng);
+        let m1 = s.measure(1, &mut rng).unwrap();
         let mut prev_b0 = m0[0];
         let mut prev_b1 = m1[0];
         let mut sc_idx = 0;
+        let amps = amplitudes(&s);
         for j in 0..s.nr_shots
         {
             let b0 = m0[j];
@@ -607,13 +1231,13 @@ mod tests
                 prev_b0 = b0;
                 prev_b1 = b1;
             }
-            let coefs = &s.states.column(sc_idx);
+            let coefs = &amps.column(sc_idx);
             match (b0, b1)
             {
-                (0, 0) => assert_complex_vector_eq!(coefs, &array![o, z, z, z]),
-                (0, 1) => assert_complex_vector_eq!(coefs, &array![z, o, z, z]),
-                (1, 0) => assert_complex_vector_eq!(coefs, &array![z, z, o, z]),
-                (1, 1) => assert_complex_vector_eq!(coefs, &array![z, z, z, o]),
+                (0, 0) => assert_complex_vector_eq!(coefs, &array![o, z, z, z], TOLERANCE),
+                (0, 1) => assert_complex_vector_eq!(coefs, &array![z, o, z, z], TOLERANCE),
+                (1, 0) => assert_complex_vector_eq!(coefs, &array![z, z, o, z], TOLERANCE),
+                (1, 1) => assert_complex_vector_eq!(coefs, &array![z, z, z, o], TOLERANCE),
                 // LCOV_EXCL_START
                 _      => panic!("Invalid value {:?} for bits", (b0, b1))
                 // LCOV_EXCL_STOP
@@ -635,38 +1259,38 @@ mod tests
         let mut rng = rand::thread_rng();
 
         // |0⟩
-        let mut s = QuState::new(1, nr_shots);
-        s.peek_into(0, 0, &mut measurements, &mut rng);
+        let mut s = QuState::new(1, nr_shots).unwrap();
+        s.peek_into(0, 0, &mut measurements, &mut rng).unwrap();
         assert!(measurements.iter().all(|&bits| bits == 0));
-        assert_complex_matrix_eq!(&s.states, &array![[o], [z]]);
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[o], [z]], TOLERANCE);
 
         // H|0⟩
-        let mut s = QuState::from_qubit_coefs(&[o, o], nr_shots);
-        s.peek_into(0, 0, &mut measurements, &mut rng);
+        let mut s = QuState::from_qubit_coefs(&[o, o], nr_shots).unwrap();
+        s.peek_into(0, 0, &mut measurements, &mut rng).unwrap();
         assert!(crate::stats::measurement_ok(measurements.sum() as usize, nr_shots,
             0.5, 1.0e-5));
-        assert_complex_matrix_eq!(&s.states, &array![[x], [x]]);
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[x], [x]], TOLERANCE);
 
         // H|0⟩⊗ H|0⟩
-        let mut s = QuState::from_qubit_coefs(&[o, o, o, o], nr_shots);
-        s.peek_into(0, 0, &mut measurements, &mut rng);
+        let mut s = QuState::from_qubit_coefs(&[o, o, o, o], nr_shots).unwrap();
+        s.peek_into(0, 0, &mut measurements, &mut rng).unwrap();
         assert!(crate::stats::measurement_ok(measurements.sum() as usize, nr_shots,
             0.5, 1.0e-5));
         measurements.fill(0);
-        s.peek_into(1, 0, &mut measurements, &mut rng);
+        s.peek_into(1, 0, &mut measurements, &mut rng).unwrap();
         assert!(crate::stats::measurement_ok(measurements.sum() as usize, nr_shots,
             0.5, 1.0e-5));
-        assert_complex_matrix_eq!(&s.states, &array![[h], [h], [h], [h]]);
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[h], [h], [h], [h]], TOLERANCE);
 
         // H|0⟩⊗ |1⟩
-        let mut s = QuState::from_qubit_coefs(&[x, x, z, o], nr_shots);
-        s.peek_into(0, 0, &mut measurements, &mut rng);
+        let mut s = QuState::from_qubit_coefs(&[x, x, z, o], nr_shots).unwrap();
+        s.peek_into(0, 0, &mut measurements, &mut rng).unwrap();
         assert!(crate::stats::measurement_ok(measurements.sum() as usize, nr_shots,
             0.5, 1.0e-5));
         measurements.fill(0);
-        s.peek_into(1, 0, &mut measurements, &mut rng);
+        s.peek_into(1, 0, &mut measurements, &mut rng).unwrap();
         assert_eq!(measurements.sum() as usize, nr_shots);
-        assert_complex_matrix_eq!(&s.states, &array![[z], [x], [z], [x]]);
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[z], [x], [z], [x]], TOLERANCE);
     }
 
     #[test]
@@ -676,17 +1300,17 @@ mod tests
         let x = crate::cmatrix::COMPLEX_HSQRT2;
         let i = crate::cmatrix::COMPLEX_I;
 
-        let mut s = QuState::new(3, 1);
-        s.apply_gate(&H::new(), &[0]);
-        assert_complex_matrix_eq!(&s.states, &array![[x], [z], [z], [z], [x], [z], [z], [z]]);
+        let mut s = QuState::new(3, 1).unwrap();
+        s.apply_gate(&H::new(), &[0]).unwrap();
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[x], [z], [z], [z], [x], [z], [z], [z]], TOLERANCE);
 
-        let mut s = QuState::new(3, 1);
-        s.apply_gate(&H::new(), &[1]);
-        assert_complex_matrix_eq!(&s.states, &array![[x], [z], [x], [z], [z], [z], [z], [z]]);
+        let mut s = QuState::new(3, 1).unwrap();
+        s.apply_gate(&H::new(), &[1]).unwrap();
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[x], [z], [x], [z], [z], [z], [z], [z]], TOLERANCE);
 
-        let mut s = QuState::new(3, 1);
-        s.apply_gate(&Y::new(), &[2]);
-        assert_complex_matrix_eq!(&s.states, &array![[z], [i], [z], [z], [z], [z], [z], [z]]);
+        let mut s = QuState::new(3, 1).unwrap();
+        s.apply_gate(&Y::new(), &[2]).unwrap();
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[z], [i], [z], [z], [z], [z], [z], [z]], TOLERANCE);
     }
 
     #[test]
@@ -696,22 +1320,22 @@ mod tests
         let o = crate::cmatrix::COMPLEX_ONE;
         let h = 0.5 * o;
 
-        let mut s = QuState::new(3, 1);
-        s.apply_gate(&CX::new(), &[0, 1]);
-        assert_complex_matrix_eq!(&s.states, &array![[o], [z], [z], [z], [z], [z], [z], [z]]);
+        let mut s = QuState::new(3, 1).unwrap();
+        s.apply_gate(&CX::new(), &[0, 1]).unwrap();
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[o], [z], [z], [z], [z], [z], [z], [z]], TOLERANCE);
 
-        let mut s = QuState::from_qubit_coefs(&[z, o, o, z, o, z], 1);
-        s.apply_gate(&CX::new(), &[0, 1]);
-        assert_complex_matrix_eq!(&s.states, &array![[z], [z], [z], [z], [z], [z], [o], [z]]);
+        let mut s = QuState::from_qubit_coefs(&[z, o, o, z, o, z], 1).unwrap();
+        s.apply_gate(&CX::new(), &[0, 1]).unwrap();
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[z], [z], [z], [z], [z], [z], [o], [z]], TOLERANCE);
 
-        let mut s = QuState::from_qubit_coefs(&[z, o, o, z, o, z], 1);
-        s.apply_gate(&CX::new(), &[0, 2]);
-        assert_complex_matrix_eq!(&s.states, &array![[z], [z], [z], [z], [z], [o], [z], [z]]);
+        let mut s = QuState::from_qubit_coefs(&[z, o, o, z, o, z], 1).unwrap();
+        s.apply_gate(&CX::new(), &[0, 2]).unwrap();
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[z], [z], [z], [z], [z], [o], [z], [z]], TOLERANCE);
 
-        let mut s = QuState::from_qubit_coefs(&[z, o, o, z, o, z], 1);
+        let mut s = QuState::from_qubit_coefs(&[z, o, o, z, o, z], 1).unwrap();
         let hh = Kron::new(H::new(), H::new());
-        s.apply_gate(&hh, &[1, 2]);
-        assert_complex_matrix_eq!(&s.states, &array![[z], [z], [z], [z], [h], [h], [h], [h]]);
+        s.apply_gate(&hh, &[1, 2]).unwrap();
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[z], [z], [z], [z], [h], [h], [h], [h]], TOLERANCE);
     }
 
     #[test]
@@ -722,20 +1346,63 @@ mod tests
         let x = crate::cmatrix::COMPLEX_HSQRT2;
         let hx = 0.5 * x;
 
-        let mut s = QuState::new(3, 1);
-        s.apply_gate(&CCX::new(), &[0, 1, 2]);
-        assert_complex_matrix_eq!(&s.states, &array![[o], [z], [z], [z], [z], [z], [z], [z]]);
+        let mut s = QuState::new(3, 1).unwrap();
+        s.apply_gate(&CCX::new(), &[0, 1, 2]).unwrap();
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[o], [z], [z], [z], [z], [z], [z], [z]], TOLERANCE);
 
-        let mut s = QuState::from_qubit_coefs(&[z, o, z, o, o, z], 1);
-        s.apply_gate(&CCX::new(), &[0, 2, 1]);
-        assert_complex_matrix_eq!(&s.states, &array![[z], [z], [z], [z], [z], [z], [o], [z]]);
-        s.apply_gate(&CCX::new(), &[0, 1, 2]);
-        assert_complex_matrix_eq!(&s.states, &array![[z], [z], [z], [z], [z], [z], [z], [o]]);
+        let mut s = QuState::from_qubit_coefs(&[z, o, z, o, o, z], 1).unwrap();
+        s.apply_gate(&CCX::new(), &[0, 2, 1]).unwrap();
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[z], [z], [z], [z], [z], [z], [o], [z]], TOLERANCE);
+        s.apply_gate(&CCX::new(), &[0, 1, 2]).unwrap();
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[z], [z], [z], [z], [z], [z], [z], [o]], TOLERANCE);
 
-        let mut s = QuState::from_qubit_coefs(&[x, -x, x, -x, x, -x], 1);
-        s.apply_gate(&CCX::new(), &[0, 2, 1]);
-        assert_complex_matrix_eq!(&s.states,
-            &array![[hx], [-hx], [-hx], [hx], [-hx], [-hx], [hx], [hx]]);
+        let mut s = QuState::from_qubit_coefs(&[x, -x, x, -x, x, -x], 1).unwrap();
+        s.apply_gate(&CCX::new(), &[0, 2, 1]).unwrap();
+        assert_complex_matrix_eq!(&amplitudes(&s),
+            &array![[hx], [-hx], [-hx], [hx], [-hx], [-hx], [hx], [hx]], TOLERANCE);
+    }
+
+    #[test]
+    fn test_large_state()
+    {
+        // Large enough for the work to be divided when the parallel feature
+        // is enabled
+        let nr_bits = 16;
+        let size = 1 << nr_bits;
+        let mut rng = rand::thread_rng();
+
+        let mut s = QuState::new(nr_bits, 8).unwrap();
+        s.apply_unary_gate_all(&H::new()).unwrap();
+        let amp = 1.0 / (size as f64).sqrt();
+        let tol = super::amplitude_tolerance(1.0e-12);
+        assert!(amplitudes(&s).iter().all(|c| (c.re - amp).abs() < tol && c.im == 0.0));
+
+        s.apply_conditional_gate(&[true, true, true, true, false, false, false, false],
+            &crate::gates::Z::new(), &[0]).unwrap();
+        assert_eq!(s.counts, vec![4, 4]);
+        s.apply_unary_gate_all(&H::new()).unwrap();
+        s.apply_gate(&CX::new(), &[0, nr_bits-1]).unwrap();
+        s.apply_gate(&CCX::new(), &[0, nr_bits-1, 7]).unwrap();
+
+        let one = (1 << (nr_bits-1)) + 1 + (1 << (nr_bits-8));
+        let amps = amplitudes(&s);
+        for (idx, c) in amps.column(0).iter().enumerate()
+        {
+            let expected = if idx == one { 1.0 } else { 0.0 };
+            assert!((c.re - expected).abs() < tol && c.im.abs() < tol);
+        }
+        for (idx, c) in amps.column(1).iter().enumerate()
+        {
+            let expected = if idx == 0 { 1.0 } else { 0.0 };
+            assert!((c.re - expected).abs() < tol && c.im.abs() < tol);
+        }
+
+        let mut res = ndarray::Array1::zeros(8);
+        s.peek_into(nr_bits-1, 1, &mut res, &mut rng).unwrap();
+        assert_eq!(res, array![2, 2, 2, 2, 0, 0, 0, 0]);
+        s.measure_into(0, 0, &mut res, &mut rng).unwrap();
+        assert_eq!(res, array![3, 3, 3, 3, 0, 0, 0, 0]);
+        assert_eq!(s.counts, vec![4, 4]);
     }
 
     #[test]
@@ -749,19 +1416,19 @@ mod tests
 
         let mut rng = rand::thread_rng();
 
-        let mut s = QuState::from_qubit_coefs(&[z, o, z, o, z, o], nr_shots);
-        let result = s.measure_all(&mut rng);
+        let mut s = QuState::from_qubit_coefs(&[z, o, z, o, z, o], nr_shots).unwrap();
+        let result = s.measure_all(&mut rng).unwrap();
         assert_eq!(result.shape(), [nr_shots]);
         assert!(result.iter().all(|&b| b == 0b111));
 
-        let mut s = QuState::from_qubit_coefs(&[z, o, z, o, o, z], nr_shots);
-        let result = s.measure_all(&mut rng);
+        let mut s = QuState::from_qubit_coefs(&[z, o, z, o, o, z], nr_shots).unwrap();
+        let result = s.measure_all(&mut rng).unwrap();
         assert_eq!(result.shape(), [nr_shots]);
         assert!(result.iter().all(|&b| b == 0b011));
 
-        let mut s = QuState::new(nr_bits, nr_shots);
-        s.apply_gate(&H::new(), &[2]);
-        let result = s.measure_all(&mut rng);
+        let mut s = QuState::new(nr_bits, nr_shots).unwrap();
+        s.apply_gate(&H::new(), &[2]).unwrap();
+        let result = s.measure_all(&mut rng).unwrap();
         assert_eq!(result.shape(), [nr_shots]);
         assert!(result.iter().all(|&b| (b & 0b011) == 0));
     }
@@ -779,14 +1446,14 @@ mod tests
 
         let mut rng = rand::thread_rng();
 
-        let mut s = QuState::from_qubit_coefs(&[z, o, z, o, z, o], nr_shots);
-        s.apply_gate(&H::new(), &[0]);
-        s.apply_gate(&H::new(), &[2]);
+        let mut s = QuState::from_qubit_coefs(&[z, o, z, o, z, o], nr_shots).unwrap();
+        s.apply_gate(&H::new(), &[0]).unwrap();
+        s.apply_gate(&H::new(), &[2]).unwrap();
         let mut result = ndarray::Array1::zeros(nr_shots);
-        s.peek_all_into(&[0, 1, 2], &mut result, &mut rng);
+        s.peek_all_into(&[0, 1, 2], &mut result, &mut rng).unwrap();
         // Ensure quantum state is preserved
         assert_eq!(s.counts, vec![nr_shots]);
-        assert_complex_matrix_eq!(&s.states, &array![[z], [z], [h], [-h], [z], [z], [-h], [h]]);
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[z], [z], [h], [-h], [z], [z], [-h], [h]], TOLERANCE);
         // Ensure measurement is correct
         assert_eq!(result.shape(), [nr_shots]);
         let mut n = vec![0; nr_bits];
@@ -812,34 +1479,276 @@ mod tests
 
         let mut rng = rand::thread_rng();
 
-        let mut s = QuState::from_qubit_coefs(&[o, z], nr_runs);
-        s.reset(0, &mut rng);
-        assert_complex_matrix_eq!(&s.states, &array![[o], [z]]);
+        let mut s = QuState::from_qubit_coefs(&[o, z], nr_runs).unwrap();
+        s.reset(0, &mut rng).unwrap();
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[o], [z]], TOLERANCE);
 
-        let mut s = QuState::from_qubit_coefs(&[z, o], nr_runs);
-        s.reset(0, &mut rng);
-        assert_complex_matrix_eq!(&s.states, &array![[o], [z]]);
+        let mut s = QuState::from_qubit_coefs(&[z, o], nr_runs).unwrap();
+        s.reset(0, &mut rng).unwrap();
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[o], [z]], TOLERANCE);
 
-        let mut s = QuState::from_qubit_coefs(&[z, o, z, o], nr_runs);
-        s.reset(0, &mut rng);
-        assert_complex_matrix_eq!(&s.states, &array![[z], [o], [z], [z]]);
+        let mut s = QuState::from_qubit_coefs(&[z, o, z, o], nr_runs).unwrap();
+        s.reset(0, &mut rng).unwrap();
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[z], [o], [z], [z]], TOLERANCE);
 
-        let mut s = QuState::from_qubit_coefs(&[z, o, z, o], nr_runs);
-        s.reset(1, &mut rng);
-        assert_complex_matrix_eq!(&s.states, &array![[z], [z], [o], [z]]);
+        let mut s = QuState::from_qubit_coefs(&[z, o, z, o], nr_runs).unwrap();
+        s.reset(1, &mut rng).unwrap();
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[z], [z], [o], [z]], TOLERANCE);
 
-        let mut s = QuState::from_qubit_coefs(&[x, -x, o, z], nr_runs);
-        s.reset(0, &mut rng);
+        let mut s = QuState::from_qubit_coefs(&[x, -x, o, z], nr_runs).unwrap();
+        s.reset(0, &mut rng).unwrap();
         match s.counts.len()
         {
-            1 => { assert_complex_matrix_eq!(&s.states, &array![[o], [z], [z], [z]]); },
-            2 => { assert_complex_matrix_eq!(&s.states, &array![[o, -o], [z, z], [z, z], [z, z]]); },
-            _ => { assert!(false, "unexpected number of states ({})", s.counts.len()); }
+            1 => { assert_complex_matrix_eq!(&amplitudes(&s), &array![[o], [z], [z], [z]], TOLERANCE); },
+            2 => { assert_complex_matrix_eq!(&amplitudes(&s), &array![[o, -o], [z, z], [z, z], [z, z]], TOLERANCE); },
+            _ => { panic!("unexpected number of states ({})", s.counts.len()); }
+        }
+
+        let mut s = QuState::from_qubit_coefs(&[x, -x, o, z], nr_runs).unwrap();
+        s.reset(1, &mut rng).unwrap();
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[x], [z], [-x], [z]], TOLERANCE);
+    }
+
+    #[test]
+    fn test_merge_branches()
+    {
+        let z = crate::cmatrix::COMPLEX_ZERO;
+        let o = crate::cmatrix::COMPLEX_ONE;
+        let i = crate::cmatrix::COMPLEX_I;
+        let x = crate::cmatrix::COMPLEX_HSQRT2;
+
+        let mut rng = rand::thread_rng();
+
+        // Branches differing only in global phase are merged
+        let mut s = QuState::new(1, 5).unwrap();
+        s.apply_conditional_gate(&[false, true, true, false, true], &crate::gates::RZ::new(0.7), &[0]).unwrap();
+        assert_eq!(s.counts, vec![5]);
+        assert_eq!(s.nr_branches(), 1);
+
+        // Different branches are not
+        let mut s = QuState::new(1, 5).unwrap();
+        let theta = 10.0 * super::amplitude_tolerance(super::BRANCH_TOLERANCE);
+        s.apply_conditional_gate(&[false, true, true, false, true], &crate::gates::RX::new(theta), &[0]).unwrap();
+        assert_eq!(s.counts, vec![1, 2, 1, 1]);
+
+        // Only consecutive branches are merged, to keep the order of the runs
+        let mut s = QuState::from_qubit_coefs(&[x, x, o, z], 6).unwrap();
+        s.apply_conditional_gate(&[true, true, false, false, true, true], &X::new(), &[1]).unwrap();
+        s.apply_conditional_gate(&[true, true, false, false, true, true], &X::new(), &[1]).unwrap();
+        assert_eq!(s.counts, vec![6]);
+        s.apply_conditional_gate(&[true, true, false, false, true, true], &Y::new(), &[1]).unwrap();
+        assert_eq!(s.counts, vec![2, 2, 2]);
+        assert_complex_matrix_eq!(&amplitudes(&s),
+            &array![[z, x, z], [x*i, z, x*i], [z, x, z], [x*i, z, x*i]], TOLERANCE);
+
+        // Repeatedly measuring and resetting a qubit should not increase the
+        // number of branches
+        let mut s = QuState::new(2, 1024).unwrap();
+        for _ in 0..20
+        {
+            s.apply_gate(&H::new(), &[0]).unwrap();
+            s.apply_gate(&CX::new(), &[0, 1]).unwrap();
+            s.measure(1, &mut rng).unwrap();
+            s.reset(1, &mut rng).unwrap();
+            s.reset(0, &mut rng).unwrap();
+            assert_eq!(s.nr_branches(), 1);
+        }
+        assert_eq!(s.counts, vec![1024]);
+        assert_complex_matrix_eq!(&amplitudes(&s), &array![[o], [z], [z], [z]], TOLERANCE);
+    }
+
+    fn sparse_to_dense(s: &QuState) -> crate::cmatrix::CMatrix
+    {
+        let branches = s.sparse.as_ref().unwrap();
+        let mut states = crate::cmatrix::StateMatrix::zeros((1 << s.nr_bits, branches.len()));
+        for (vec, col) in branches.iter().zip(states.gencolumns_mut())
+        {
+            crate::sparse::densify_into(vec, col);
+        }
+        states.mapv(|c| c.to_complex())
+    }
+
+    #[test]
+    fn test_sparse()
+    {
+        let s = QuState::new_sparse(64, 10).unwrap();
+        assert!(s.is_sparse());
+        assert_eq!(s.nr_branches(), 1);
+        assert_eq!(s.counts, vec![10]);
+
+        // Sparse and dense states should evolve the same
+        let mut rng_sparse = <rand_hc::Hc128Rng as rand_core::SeedableRng>::seed_from_u64(0x5eed);
+        let mut rng_dense = <rand_hc::Hc128Rng as rand_core::SeedableRng>::seed_from_u64(0x5eed);
+        let mut sparse = QuState::new_sparse(10, 100).unwrap();
+        let mut dense = QuState::new(10, 100).unwrap();
+        for s in [&mut sparse, &mut dense].iter_mut()
+        {
+            s.apply_gate(&H::new(), &[1]).unwrap();
+            s.apply_gate(&CX::new(), &[1, 3]).unwrap();
+            s.apply_gate(&X::new(), &[4]).unwrap();
+            s.apply_gate(&crate::gates::RY::new(0.8), &[0]).unwrap();
+            s.apply_gate(&CCX::new(), &[0, 3, 2]).unwrap();
+        }
+        assert!(sparse.is_sparse());
+        assert_complex_matrix_eq!(&sparse_to_dense(&sparse), &amplitudes(&dense), TOLERANCE);
+
+        let m_sparse = sparse.measure(3, &mut rng_sparse).unwrap();
+        let m_dense = dense.measure(3, &mut rng_dense).unwrap();
+        assert_eq!(m_sparse, m_dense);
+        assert_eq!(sparse.counts, dense.counts);
+        assert_complex_matrix_eq!(&sparse_to_dense(&sparse), &amplitudes(&dense), TOLERANCE);
+
+        let control: Vec<bool> = m_sparse.iter().map(|&b| b != 0).collect();
+        sparse.apply_conditional_gate(&control, &Y::new(), &[2]).unwrap();
+        dense.apply_conditional_gate(&control, &Y::new(), &[2]).unwrap();
+        assert_eq!(sparse.counts, dense.counts);
+        assert_complex_matrix_eq!(&sparse_to_dense(&sparse), &amplitudes(&dense), TOLERANCE);
+
+        sparse.reset(3, &mut rng_sparse).unwrap();
+        dense.reset(3, &mut rng_dense).unwrap();
+        sparse.reset(2, &mut rng_sparse).unwrap();
+        dense.reset(2, &mut rng_dense).unwrap();
+        assert!(sparse.is_sparse());
+        assert_eq!(sparse.counts, dense.counts);
+        assert_complex_matrix_eq!(&sparse_to_dense(&sparse), &amplitudes(&dense), TOLERANCE);
+
+        let mut m_sparse = ndarray::Array1::zeros(100);
+        let mut m_dense = ndarray::Array1::zeros(100);
+        sparse.peek_into(0, 0, &mut m_sparse, &mut rng_sparse).unwrap();
+        dense.peek_into(0, 0, &mut m_dense, &mut rng_dense).unwrap();
+        assert_eq!(m_sparse, m_dense);
+    }
+
+    #[test]
+    fn test_sparse_fallback()
+    {
+        let z = crate::cmatrix::COMPLEX_ZERO;
+        let o = crate::cmatrix::COMPLEX_ONE;
+        let mut rng = rand::thread_rng();
+
+        // The state remains sparse while at most 1/16th of the amplitudes
+        // is non-zero
+        let nr_bits = 10;
+        let mut s = QuState::new_sparse(nr_bits, 100).unwrap();
+        for bit in 0..6
+        {
+            s.apply_gate(&H::new(), &[bit]).unwrap();
+        }
+        assert!(s.is_sparse());
+        s.apply_gate(&H::new(), &[6]).unwrap();
+        assert!(!s.is_sparse());
+        let x = num_complex::Complex::new(1.0 / ((1 << 7) as f64).sqrt(), 0.0);
+        for (idx, &c) in amplitudes(&s).column(0).iter().enumerate()
+        {
+            let expected = if idx & 0b111 == 0 { x } else { z };
+            assert!((c - expected).norm() < super::amplitude_tolerance(1.0e-12));
+        }
+
+        // After a full measurement, the state is sparse again
+        let res = s.measure_all(&mut rng).unwrap();
+        assert!(s.is_sparse());
+        assert!(res.iter().all(|&r| r & 0b1110000000 == 0));
+        assert_eq!(s.nr_branches(), s.sparse.as_ref().unwrap().iter().filter(|v| v.len() == 1).count());
+
+        s.apply_gate(&H::new(), &[0]).unwrap();
+        s.apply_gate(&H::new(), &[9]).unwrap();
+        s.reset_all();
+        assert!(s.is_sparse());
+        assert_eq!(s.counts, vec![100]);
+        let mut coefs = crate::cmatrix::CMatrix::zeros((1 << nr_bits, 1));
+        coefs[[0, 0]] = o;
+        assert_complex_matrix_eq!(&sparse_to_dense(&s), &coefs);
+
+        // Dense states remain dense
+        let mut s = QuState::new(2, 10).unwrap();
+        s.measure_all(&mut rng).unwrap();
+        assert!(!s.is_sparse());
+    }
+
+    #[test]
+    fn test_memory_budget()
+    {
+        let mut rng = rand::thread_rng();
+        let amp_size = ::std::mem::size_of::<crate::cmatrix::StateNumber>();
+        let branch_size = 16 * amp_size;
+
+        let mut s = QuState::new(4, 100).unwrap();
+        assert_eq!(s.memory_budget(), None);
+        assert_eq!(s.memory_usage(), branch_size);
+        s.set_memory_budget(Some(2 * branch_size));
+        assert_eq!(s.memory_budget(), Some(2 * branch_size));
+
+        // Measuring needs room for both the old and the new branches
+        s.apply_gate(&H::new(), &[0]).unwrap();
+        assert_eq!(s.measure(0, &mut rng),
+            Err(Error::MemoryBudgetExceeded(3 * branch_size, 2 * branch_size)));
+        assert_eq!(s.nr_branches(), 1);
+        assert_eq!(s.counts, vec![100]);
+        s.set_memory_budget(Some(3 * branch_size));
+        assert!(s.measure(0, &mut rng).is_ok());
+        assert_eq!(s.nr_branches(), 2);
+        assert_eq!(s.memory_usage(), 2 * branch_size);
+
+        // Collapsing the state to many basis states
+        let budget = 4 * branch_size;
+        s.set_memory_budget(Some(budget));
+        s.apply_unary_gate_all(&H::new()).unwrap();
+        assert!(matches!(s.measure_all(&mut rng), Err(Error::MemoryBudgetExceeded(_, b)) if b == budget));
+        s.set_memory_budget(None);
+        assert!(s.measure_all(&mut rng).is_ok());
+
+        // A sparse state that does not fit when converted to a dense state
+        let mut s = QuState::new_sparse(10, 100).unwrap();
+        let budget = 1024 * amp_size - 1;
+        s.set_memory_budget(Some(budget));
+        for bit in 0..6
+        {
+            s.apply_gate(&H::new(), &[bit]).unwrap();
         }
+        assert!(s.memory_usage() <= budget);
+        assert!(matches!(s.apply_gate(&H::new(), &[6]), Err(Error::MemoryBudgetExceeded(_, b)) if b == budget));
+        assert!(s.is_sparse());
+    }
 
-        let mut s = QuState::from_qubit_coefs(&[x, -x, o, z], nr_runs);
-        s.reset(1, &mut rng);
-        assert_complex_matrix_eq!(&s.states, &array![[x], [z], [-x], [z]]);
+    #[test]
+    fn test_errors()
+    {
+        let z = crate::cmatrix::COMPLEX_ZERO;
+        let o = crate::cmatrix::COMPLEX_ONE;
+        let mut rng = rand::thread_rng();
+
+        assert_eq!(QuState::from_qubit_coefs(&[o, z, z], 1).err(),
+            Some(Error::InvalidNrCoefficients(3)));
+        assert_eq!(QuState::new_sparse(65, 1).err(), Some(Error::TooManyQBits(65, 64)));
+        assert!(matches!(QuState::new(64, 1), Err(Error::TooManyQBits(64, _))));
+
+        let mut s = QuState::new(3, 10).unwrap();
+        assert_eq!(s.apply_gate(&H::new(), &[3]), Err(Error::InvalidQBit(3)));
+        assert!(matches!(s.apply_gate(&CX::new(), &[0]), Err(Error::InvalidNrBits(1, 2, _))));
+        assert_eq!(s.apply_gate(&CX::new(), &[1, 1]), Err(Error::DuplicateQBit(1)));
+        assert_eq!(s.apply_conditional_gate(&[true; 5], &X::new(), &[0]),
+            Err(Error::InvalidNrRuns(5, 10)));
+        assert_eq!(s.apply_conditional_gate(&[true; 10], &X::new(), &[5]),
+            Err(Error::InvalidQBit(5)));
+
+        let mut res = ndarray::Array1::zeros(10);
+        assert_eq!(s.measure_into(3, 0, &mut res, &mut rng), Err(Error::InvalidQBit(3)));
+        assert_eq!(s.measure_into(0, 64, &mut res, &mut rng), Err(Error::InvalidCBit(64)));
+        assert_eq!(s.peek_into(0, 0, &mut ndarray::Array1::zeros(9), &mut rng),
+            Err(Error::InvalidNrRuns(9, 10)));
+        assert!(matches!(s.measure_all_into(&[0, 1], &mut res, &mut rng),
+            Err(Error::InvalidNrBits(2, 3, _))));
+        assert_eq!(s.peek_all_into(&[0, 1, 70], &mut res, &mut rng), Err(Error::InvalidCBit(70)));
+        // Nothing was changed by the failed operations
+        assert_eq!(s.counts, vec![10]);
+
+        // A state with zero norm cannot be sampled
+        let mut s = QuState::from_qubit_coefs(&[z, z], 10).unwrap();
+        assert!(matches!(s.measure(0, &mut rng), Err(Error::SamplingFailed(_))));
+        assert!(matches!(s.measure_all(&mut rng), Err(Error::SamplingFailed(_))));
+        let mut s = QuState::new_sparse(2, 10).unwrap();
+        s.sparse.as_mut().unwrap()[0].clear();
+        assert!(matches!(s.measure_all(&mut rng), Err(Error::SamplingFailed(_))));
     }
 
     #[test]
@@ -848,15 +1757,93 @@ mod tests
         let nr_bits = 5;
         let nr_runs = 100;
 
-        let mut s = QuState::new(nr_bits, nr_runs);
-        s.apply_gate(&H::new(), &[2]);
-        s.apply_gate(&X::new(), &[0]);
-        s.apply_gate(&H::new(), &[4]);
+        let mut s = QuState::new(nr_bits, nr_runs).unwrap();
+        s.apply_gate(&H::new(), &[2]).unwrap();
+        s.apply_gate(&X::new(), &[0]).unwrap();
+        s.apply_gate(&H::new(), &[4]).unwrap();
 
         s.reset_all();
         assert_eq!(s.counts, vec![nr_runs]);
         let mut coefs = crate::cmatrix::CMatrix::zeros((1 << nr_bits, 1));
         coefs[[0, 0]] = crate::cmatrix::COMPLEX_ONE;
-        assert_complex_matrix_eq!(&s.states, &coefs);
+        assert_complex_matrix_eq!(&amplitudes(&s), &coefs, TOLERANCE);
+    }
+}
+
+#[cfg(all(test, feature = "single-precision"))]
+mod single_precision_tests
+{
+    use super::QuState;
+    use crate::cmatrix::Amplitude;
+    use crate::gates::{Gate, CX, H, RY, T};
+
+    #[test]
+    fn test_accuracy()
+    {
+        let nr_bits = 8;
+        let mut s = QuState::new(nr_bits, 1).unwrap();
+        let mut reference = crate::cmatrix::CVector::zeros(1 << nr_bits);
+        reference[0] = crate::cmatrix::COMPLEX_ONE;
+
+        let mut nr_gates = 0;
+        for round in 0..25
+        {
+            for bit in 0..nr_bits
+            {
+                let gates: Vec<(Box<dyn Gate>, Vec<usize>)> = vec![
+                    (Box::new(H::new()), vec![bit]),
+                    (Box::new(T::new()), vec![bit]),
+                    (Box::new(CX::new()), vec![bit, (bit + 3) % nr_bits]),
+                    (Box::new(RY::new(0.1 * round as f64)), vec![bit])
+                ];
+                for (gate, bits) in gates.iter()
+                {
+                    s.apply_gate(&**gate, bits).unwrap();
+                    crate::gates::apply_gate_slice(reference.view_mut(), &**gate, bits, nr_bits).unwrap();
+                    nr_gates += 1;
+                }
+            }
+        }
+
+        let err = s.states.column(0).iter().zip(reference.iter())
+            .map(|(&c, &r)| (c.to_complex() - r).norm_sqr())
+            .sum::<f64>()
+            .sqrt();
+        assert!(err < nr_gates as f64 * 1.0e-7, "error {} too large", err);
+    }
+
+    #[test]
+    fn test_measure()
+    {
+        let nr_shots = 4096;
+        let mut rng = <rand_hc::Hc128Rng as rand_core::SeedableRng>::seed_from_u64(0x5678);
+
+        // Outcomes that are impossible in exact arithmetic should not occur,
+        // even though rounding errors make their probability non-zero
+        let mut s = QuState::new(3, nr_shots).unwrap();
+        for _ in 0..10
+        {
+            s.apply_gate(&H::new(), &[1]).unwrap();
+            s.apply_gate(&RY::new(0.3), &[2]).unwrap();
+            s.apply_gate(&H::new(), &[1]).unwrap();
+            s.apply_gate(&RY::new(-0.3), &[2]).unwrap();
+        }
+        assert!(s.measure_all(&mut rng).unwrap().iter().all(|&m| m == 0));
+
+        // Probabilities should be sampled correctly
+        let mut s = QuState::new(2, nr_shots).unwrap();
+        s.apply_gate(&RY::new(1.0), &[0]).unwrap();
+        let p1 = (0.5f64).sin().powi(2);
+        let n1 = s.measure(0, &mut rng).unwrap().iter().filter(|&&m| m == 1).count();
+        let tol = 4.0 * (p1 * (1.0 - p1) / nr_shots as f64).sqrt();
+        assert!((n1 as f64 / nr_shots as f64 - p1).abs() < tol);
+
+        // Small, but non-zero, probabilities should not be rounded off
+        let nr_shots = 1_000_000;
+        let mut s = QuState::new(1, nr_shots).unwrap();
+        let p1: f64 = 1.0e-5;
+        s.apply_gate(&RY::new(2.0 * p1.sqrt().asin()), &[0]).unwrap();
+        let n1 = s.measure(0, &mut rng).unwrap().iter().filter(|&&m| m == 1).count();
+        assert!(n1 > 0 && n1 < 30, "{} outcomes 1", n1);
     }
 }
@@ -0,0 +1,190 @@
+// Copyright 2019 Q1t BV
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Named registers of quantum or classical bits.
+
+/// A named register
+///
+/// Struct Register describes a named register of quantum or classical bits
+/// in a circuit. The bits in a register are consecutive, starting at bit
+/// index `offset()` in the full list of bits of the circuit.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Register
+{
+    /// The name of the register
+    name: String,
+    /// The index of the first bit of the register in the circuit
+    offset: usize,
+    /// The number of bits in the register
+    size: usize
+}
+
+impl Register
+{
+    /// Create a new register.
+    ///
+    /// Create a new register called `name`, holding `size` bits starting at
+    /// bit index `offset`. Since the register name is used in exporting a
+    /// circuit, it should be a valid OpenQasm identifier, i.e. it should start
+    /// with a lower case letter, followed by letters, digits, or underscores.
+    /// If not, an `InvalidRegisterName` error is returned.
+    pub(crate) fn new(name: &str, offset: usize, size: usize) -> crate::error::Result<Self>
+    {
+        let mut chars = name.chars();
+        let valid = match chars.next()
+            {
+                Some(c) => c.is_ascii_lowercase()
+                    && chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+                None => false
+            };
+        if !valid
+        {
+            return Err(crate::error::Error::InvalidRegisterName(String::from(name)));
+        }
+
+        Ok(Register
+        {
+            name: String::from(name),
+            offset: offset,
+            size: size
+        })
+    }
+
+    /// The name of this register
+    pub fn name(&self) -> &str
+    {
+        &self.name
+    }
+
+    /// The index of the first bit of this register in the circuit
+    pub fn offset(&self) -> usize
+    {
+        self.offset
+    }
+
+    /// The number of bits in this register
+    pub fn size(&self) -> usize
+    {
+        self.size
+    }
+
+    /// The indices in the circuit of all bits in this register
+    pub fn bits(&self) -> Vec<usize>
+    {
+        (self.offset..self.offset+self.size).collect()
+    }
+
+    /// Return the index in the circuit of bit `idx` in this register, or
+    /// `None` if the register holds no such bit.
+    pub fn bit(&self, idx: usize) -> Option<usize>
+    {
+        if idx < self.size { Some(self.offset + idx) } else { None }
+    }
+
+    /// Check if the bits in `bits` are exactly the bits in this register, in
+    /// any order.
+    pub fn is_spanned_by(&self, bits: &[usize]) -> bool
+    {
+        let mut sorted = bits.to_vec();
+        sorted.sort();
+        sorted == self.bits()
+    }
+}
+
+/// Trait for bit addresses
+///
+/// Trait BitIndex describes values that can be used to address a quantum or
+/// classical bit in a circuit. A bit can be addressed either by its index in
+/// the circuit as a `usize`, or by a tuple of the name of a register and the
+/// index of the bit within that register, e.g. `("ancilla", 1)`.
+pub trait BitIndex
+{
+    /// Find the index in the circuit of this bit, using the registers in
+    /// `registers` to resolve register names.
+    fn bit_index(&self, registers: &[Register]) -> crate::error::Result<usize>;
+}
+
+impl BitIndex for usize
+{
+    fn bit_index(&self, _registers: &[Register]) -> crate::error::Result<usize>
+    {
+        Ok(*self)
+    }
+}
+
+impl BitIndex for (&str, usize)
+{
+    fn bit_index(&self, registers: &[Register]) -> crate::error::Result<usize>
+    {
+        let (name, idx) = *self;
+        let register = registers.iter().find(|reg| reg.name() == name)
+            .ok_or_else(|| crate::error::Error::UnknownRegister(String::from(name)))?;
+        register.bit(idx)
+            .ok_or_else(|| crate::error::Error::InvalidRegisterIndex(String::from(name), idx))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{BitIndex, Register};
+    use crate::error::Error;
+
+    #[test]
+    fn test_new()
+    {
+        let reg = Register::new("data", 3, 4).unwrap();
+        assert_eq!(reg.name(), "data");
+        assert_eq!(reg.offset(), 3);
+        assert_eq!(reg.size(), 4);
+        assert_eq!(reg.bits(), vec![3, 4, 5, 6]);
+        assert_eq!(reg.bit(1), Some(4));
+        assert_eq!(reg.bit(4), None);
+
+        assert!(Register::new("anc_1", 0, 1).is_ok());
+        assert_eq!(Register::new("", 0, 1), Err(Error::InvalidRegisterName(String::from(""))));
+        assert_eq!(Register::new("Data", 0, 1),
+            Err(Error::InvalidRegisterName(String::from("Data"))));
+        assert_eq!(Register::new("1q", 0, 1), Err(Error::InvalidRegisterName(String::from("1q"))));
+        assert_eq!(Register::new("a-b", 0, 1),
+            Err(Error::InvalidRegisterName(String::from("a-b"))));
+    }
+
+    #[test]
+    fn test_is_spanned_by()
+    {
+        let reg = Register::new("syndrome", 2, 3).unwrap();
+        assert!(reg.is_spanned_by(&[2, 3, 4]));
+        assert!(reg.is_spanned_by(&[4, 2, 3]));
+        assert!(!reg.is_spanned_by(&[2, 3]));
+        assert!(!reg.is_spanned_by(&[2, 3, 4, 5]));
+        assert!(!reg.is_spanned_by(&[2, 2, 3]));
+    }
+
+    #[test]
+    fn test_bit_index()
+    {
+        let regs = vec![
+            Register::new("data", 0, 4).unwrap(),
+            Register::new("ancilla", 4, 2).unwrap()
+        ];
+        assert_eq!(7.bit_index(&regs), Ok(7));
+        assert_eq!(("data", 3).bit_index(&regs), Ok(3));
+        assert_eq!(("ancilla", 1).bit_index(&regs), Ok(5));
+        assert_eq!(("ancilla", 2).bit_index(&regs),
+            Err(Error::InvalidRegisterIndex(String::from("ancilla"), 2)));
+        assert_eq!(("syndrome", 0).bit_index(&regs),
+            Err(Error::UnknownRegister(String::from("syndrome"))));
+    }
+}
@@ -0,0 +1,316 @@
+// Copyright 2019 Q1t BV
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Sparse quantum state vectors.
+//!
+//! Many circuits, like reversible arithmetic or permutation oracles, keep
+//! the quantum state in a superposition of only a handful of basis states.
+//! For these, storing all 2<sup>n</sup> amplitudes is wasteful. This module
+//! provides functions operating on sparse state vectors, which store only the
+//! non-zero amplitudes in a hash map, keyed by the index of the basis state.
+//! As in the dense state, qubit 0 corresponds to the most significant bit of
+//! the basis state index.
+
+use crate::cmatrix::Amplitude;
+
+/// Sparse state vector, mapping the index of a basis state to its amplitude
+pub type SparseVector = crate::idhash::U64HashMap<crate::cmatrix::StateNumber>;
+
+/// Create a sparse state vector for basis state `idx`.
+pub fn basis_state(idx: u64) -> SparseVector
+{
+    let mut vec = crate::idhash::new_u64_hash_map();
+    vec.insert(idx, crate::cmatrix::StateNumber::from_complex(crate::cmatrix::COMPLEX_ONE));
+    vec
+}
+
+/// Compute subspace offsets.
+///
+/// Compute the offsets of the basis states in the subspace spanned by qubits
+/// `bits` in a system of `nr_bits` qubits, in the order used by the gate
+/// matrix. The second return value is the mask of all bits affected by the
+/// gate.
+fn subspace_offsets(bits: &[usize], nr_bits: usize) -> (Vec<u64>, u64)
+{
+    let gate_bits = bits.len();
+    let offsets: Vec<u64> = (0..1 << gate_bits).map(|x| {
+            bits.iter().enumerate()
+                .filter(|&(i, _)| (x >> (gate_bits - 1 - i)) & 1 == 1)
+                .fold(0, |off, (_, &b)| off | (1 << (nr_bits - 1 - b)))
+        }).collect();
+    let affected_mask = offsets[offsets.len() - 1];
+
+    (offsets, affected_mask)
+}
+
+/// Compute the index within the subspace spanned by qubits `bits` of basis
+/// state `idx` in a system of `nr_bits` qubits.
+fn subspace_index(idx: u64, bits: &[usize], nr_bits: usize) -> usize
+{
+    bits.iter().fold(0, |x, &b| (x << 1) | ((idx >> (nr_bits - 1 - b)) & 1) as usize)
+}
+
+/// Apply a gate.
+///
+/// Apply gate `gate`, operating on the qubits in `bits`, to sparse state `vec`
+/// of `nr_bits` qubits. Only the subspaces of the affected qubits in which
+/// `vec` has a non-zero amplitude are computed. Resulting amplitudes with a
+/// norm smaller than `tolerance` are removed from the state. The bits are
+/// assumed to have been checked against the gate; an error can only result
+/// from applying the gate to the occupied subspaces.
+pub fn apply_gate<G>(vec: &mut SparseVector, gate: &G, bits: &[usize], nr_bits: usize,
+    tolerance: f64) -> crate::error::Result<()>
+where G: crate::gates::Gate + ?Sized
+{
+    if let Some(diagonal) = gate.diagonal()
+    {
+        let factors: Vec<crate::cmatrix::StateNumber> = diagonal.iter()
+            .map(|&d| crate::cmatrix::StateNumber::from_complex(d))
+            .collect();
+        for (&idx, c) in vec.iter_mut()
+        {
+            *c *= factors[subspace_index(idx, bits, nr_bits)];
+        }
+        return Ok(());
+    }
+
+    let (offsets, affected_mask) = subspace_offsets(bits, nr_bits);
+    if let Some(perm) = gate.permutation()
+    {
+        let inverse = perm.inverse();
+        let dest = inverse.indices();
+        let mut new_vec = crate::idhash::new_u64_hash_map();
+        new_vec.reserve(vec.len());
+        for (&idx, &c) in vec.iter()
+        {
+            let new_idx = (idx & !affected_mask) | offsets[dest[subspace_index(idx, bits, nr_bits)]];
+            new_vec.insert(new_idx, c);
+        }
+        *vec = new_vec;
+        return Ok(());
+    }
+
+    // Gather the occupied subspaces in the columns of a dense matrix, and
+    // apply the gate to these.
+    let mut columns = crate::idhash::new_u64_hash_map();
+    for &idx in vec.keys()
+    {
+        let nr_cols = columns.len();
+        columns.entry(idx & !affected_mask).or_insert(nr_cols);
+    }
+
+    let gate_bits = bits.len();
+    let mut work = crate::cmatrix::StateMatrix::zeros((1 << gate_bits, columns.len()));
+    for (&idx, &c) in vec.iter()
+    {
+        work[(subspace_index(idx, bits, nr_bits), columns[&(idx & !affected_mask)])] = c;
+    }
+    let work_bits: Vec<usize> = (0..gate_bits).collect();
+    crate::cmatrix::StateNumber::apply_gate(work.view_mut(), gate, &work_bits, gate_bits)?;
+
+    vec.clear();
+    for (&base, &icol) in columns.iter()
+    {
+        for (&off, &c) in offsets.iter().zip(work.column(icol).iter())
+        {
+            if c.to_complex().norm() >= tolerance
+            {
+                vec.insert(base | off, c);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Compute the probability of measuring 0 for qubit `qbit` in sparse state
+/// `vec` of `nr_bits` qubits.
+pub fn zero_probability(vec: &SparseVector, qbit: usize, nr_bits: usize) -> f64
+{
+    let mask = 1 << (nr_bits - 1 - qbit);
+    vec.iter()
+        .filter(|&(&idx, _)| idx & mask == 0)
+        .map(|(_, c)| c.to_complex().norm_sqr())
+        .sum()
+}
+
+/// Collapse a state.
+///
+/// Collapse sparse state `vec` of `nr_bits` qubits to the state where qubit
+/// `qbit` has value `value`, and renormalize it. The probability of measuring
+/// this value should be given in `norm_sq`.
+pub fn collapse(vec: &mut SparseVector, qbit: usize, nr_bits: usize, value: bool, norm_sq: f64)
+{
+    let mask = 1 << (nr_bits - 1 - qbit);
+    let expected = if value { mask } else { 0 };
+    vec.retain(|&idx, _| idx & mask == expected);
+
+    let factor = crate::cmatrix::StateNumber::from_complex(
+        num_complex::Complex::new(1.0 / norm_sq.sqrt(), 0.0));
+    for c in vec.values_mut()
+    {
+        *c *= factor;
+    }
+}
+
+/// Check if two sparse states are equal.
+///
+/// Check if the sparse states `a` and `b` are equal up to a global phase,
+/// within tolerance `tolerance`.
+pub fn equal_up_to_phase(a: &SparseVector, b: &SparseVector, tolerance: f64) -> bool
+{
+    let zero = crate::cmatrix::StateNumber::from_complex(crate::cmatrix::COMPLEX_ZERO);
+
+    // Determine the phase difference from the largest coefficient
+    let imax = match a.iter().max_by(|(_, ca), (_, cb)| {
+            ca.to_complex().norm_sqr().partial_cmp(&cb.to_complex().norm_sqr())
+                .unwrap_or(::std::cmp::Ordering::Equal)
+        })
+    {
+        Some((&imax, _)) => imax,
+        None => { return b.is_empty(); }
+    };
+    let phase = b.get(&imax).unwrap_or(&zero).to_complex() * a[&imax].to_complex().conj();
+    if phase.norm() < tolerance
+    {
+        return false;
+    }
+    let phase = phase / phase.norm();
+
+    a.iter().all(|(idx, &ca)| {
+            (ca.to_complex() * phase - b.get(idx).unwrap_or(&zero).to_complex()).norm() < tolerance
+        })
+        && b.iter().all(|(idx, &cb)| a.contains_key(idx) || cb.to_complex().norm() < tolerance)
+}
+
+/// Store sparse state `vec` in column `col` of a dense state matrix. The column
+/// is assumed to be zero initially.
+pub fn densify_into(vec: &SparseVector,
+    mut col: ndarray::ArrayViewMut1<crate::cmatrix::StateNumber>)
+{
+    for (&idx, &c) in vec.iter()
+    {
+        col[idx as usize] = c;
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{apply_gate, basis_state, collapse, densify_into, equal_up_to_phase,
+        zero_probability, SparseVector};
+    use crate::cmatrix::Amplitude;
+    use crate::gates::{CCX, CX, Gate, H, RY, S, Swap, T, U3, X};
+
+    /// Amplitudes smaller than this are removed from the state
+    const TOLERANCE: f64 = 100.0 * <crate::cmatrix::StateNumber as Amplitude>::EPSILON;
+
+    fn dense(vec: &SparseVector, nr_bits: usize) -> crate::cmatrix::CMatrix
+    {
+        let mut states = crate::cmatrix::StateMatrix::zeros((1 << nr_bits, 1));
+        densify_into(vec, states.column_mut(0));
+        states.mapv(|c| c.to_complex())
+    }
+
+    fn assert_close(a: &crate::cmatrix::CMatrix, b: &crate::cmatrix::CMatrix)
+    {
+        let err = (a - b).iter().map(|c| c.norm_sqr()).sum::<f64>().sqrt();
+        assert!(err < 1.0e-5, "{} does not match {}", a, b);
+    }
+
+    #[test]
+    fn test_apply_gate()
+    {
+        let nr_bits = 5;
+        let gates: Vec<(Box<dyn Gate>, Vec<usize>)> = vec![
+            (Box::new(X::new()), vec![3]),
+            (Box::new(H::new()), vec![1]),
+            (Box::new(CX::new()), vec![1, 4]),
+            (Box::new(T::new()), vec![4]),
+            (Box::new(RY::new(0.3)), vec![0]),
+            (Box::new(CCX::new()), vec![0, 4, 2]),
+            (Box::new(Swap::new()), vec![2, 1]),
+            (Box::new(S::new()), vec![3]),
+            (Box::new(U3::new(0.2, 1.3, -0.7)), vec![2]),
+            (Box::new(H::new()), vec![1]),
+        ];
+
+        let mut vec = basis_state(0);
+        let mut states = crate::cmatrix::StateMatrix::zeros((1 << nr_bits, 1));
+        states[(0, 0)] = crate::cmatrix::StateNumber::from_complex(crate::cmatrix::COMPLEX_ONE);
+        for (gate, bits) in gates.iter()
+        {
+            apply_gate(&mut vec, &**gate, bits, nr_bits, TOLERANCE).unwrap();
+            crate::cmatrix::StateNumber::apply_gate(states.view_mut(), &**gate, bits, nr_bits)
+                .unwrap();
+            assert_close(&dense(&vec, nr_bits), &states.mapv(|c| c.to_complex()));
+        }
+        assert_eq!(vec.len(), 16);
+
+        // Amplitudes that cancel are removed
+        let mut vec = basis_state(0b010);
+        apply_gate(&mut vec, &H::new(), &[2], 3, TOLERANCE).unwrap();
+        assert_eq!(vec.len(), 2);
+        apply_gate(&mut vec, &H::new(), &[2], 3, TOLERANCE).unwrap();
+        assert_eq!(vec.len(), 1);
+        assert!(vec.contains_key(&0b010));
+
+        // Indices beyond the range of a dense state
+        let mut vec = basis_state(1 << 63);
+        apply_gate(&mut vec, &CX::new(), &[0, 63], 64, TOLERANCE).unwrap();
+        assert_eq!(vec.keys().collect::<Vec<_>>(), vec![&((1 << 63) | 1)]);
+        apply_gate(&mut vec, &H::new(), &[40], 64, TOLERANCE).unwrap();
+        assert_eq!(vec.len(), 2);
+        assert!(vec.contains_key(&((1 << 63) | (1 << 23) | 1)));
+    }
+
+    #[test]
+    fn test_measure()
+    {
+        let mut vec = basis_state(0);
+        apply_gate(&mut vec, &RY::new(::std::f64::consts::FRAC_PI_3), &[1], 2, TOLERANCE)
+            .unwrap();
+        apply_gate(&mut vec, &CX::new(), &[1, 0], 2, TOLERANCE).unwrap();
+
+        assert!((zero_probability(&vec, 0, 2) - 0.75).abs() < 1.0e-6);
+        assert!((zero_probability(&vec, 1, 2) - 0.75).abs() < 1.0e-6);
+
+        let mut vec0 = vec.clone();
+        collapse(&mut vec0, 0, 2, false, 0.75);
+        assert_close(&dense(&vec0, 2), &dense(&basis_state(0), 2));
+
+        collapse(&mut vec, 1, 2, true, 0.25);
+        assert_close(&dense(&vec, 2), &dense(&basis_state(0b11), 2));
+    }
+
+    #[test]
+    fn test_equal_up_to_phase()
+    {
+        let mut a = basis_state(1);
+        apply_gate(&mut a, &H::new(), &[0], 2, TOLERANCE).unwrap();
+        let mut b = a.clone();
+        assert!(equal_up_to_phase(&a, &b, 1.0e-6));
+        apply_gate(&mut b, &crate::gates::RZ::new(0.4), &[1], 2, TOLERANCE).unwrap();
+        assert!(equal_up_to_phase(&a, &b, 1.0e-6));
+        apply_gate(&mut b, &T::new(), &[0], 2, TOLERANCE).unwrap();
+        assert!(!equal_up_to_phase(&a, &b, 1.0e-6));
+
+        let mut c = a.clone();
+        c.insert(0, crate::cmatrix::StateNumber::from_complex(crate::cmatrix::COMPLEX_ZERO));
+        assert!(equal_up_to_phase(&a, &c, 1.0e-6));
+        assert!(equal_up_to_phase(&c, &a, 1.0e-6));
+        assert!(!equal_up_to_phase(&a, &basis_state(1), 1.0e-6));
+        assert!(!equal_up_to_phase(&basis_state(1), &a, 1.0e-6));
+    }
+}